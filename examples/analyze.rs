@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+
+use gtt23::analyze::{self, STATS_GROUP_NAME};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Computes the main statistics of the circuits in an HDF5 file in a single
+/// pass (label statistics, port and day counts, length and inter-arrival time
+/// distributions, command counts, day volumes, and label days), and writes
+/// them with notes to the /stats group of the file
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let file = File::open_rw(&cli.input)?;
+    let size = file.dataset("/circuits")?.size();
+
+    log::info!("Writing statistics to {STATS_GROUP_NAME}");
+    let pb = pb_new(size, "Analyzing circuits".to_string());
+    let analysis = analyze::analyze(&file, |n| pb.inc(n as u64))?;
+    pb.finish_and_clear();
+    file.close()?;
+
+    log::info!(
+        "Wrote statistics of {} circuits with {} labels over {} days",
+        analysis.summary.total(),
+        analysis.labels.len(),
+        analysis.days.len()
+    );
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
@@ -0,0 +1,126 @@
+use std::fs::File as FsFile;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::bail;
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+
+use gtt23::anonymize::Pseudonymizer;
+use gtt23::index::IndexBuilder;
+use gtt23::writer::CircuitWriter;
+use gtt23::{Circuit, CircuitIndex};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Copy circuits into a new HDF5 file with domains replaced by keyed-hash
+/// pseudonyms, for sharing with collaborators who should not see raw domains
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Output path to write the anonymized HDF5 file
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        default_value = "./gtt23-anonymized.hdf5"
+    )]
+    pub output: PathBuf,
+    /// Path to a file holding the secret key (e.g., created with
+    /// `head -c 32 /dev/urandom > key`); reuse it to get the same pseudonyms
+    #[arg(short, long, value_name = "PATH", required = true)]
+    pub key_file: PathBuf,
+    /// Also replace all ports with 0
+    #[arg(short, long)]
+    pub strip_ports: bool,
+    /// Write a CSV mapping pseudonyms back to domains to this path. The mapping
+    /// is withheld unless requested, and must not be shared with the output.
+    #[arg(short, long, value_name = "PATH")]
+    pub mapping: Option<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let key = std::fs::read(&cli.key_file)?;
+    if key.len() < 16 {
+        bail!(
+            "Key in {} is only {} bytes; use at least 16 random bytes",
+            cli.key_file.display(),
+            key.len()
+        );
+    }
+    let mut pseudonymizer = Pseudonymizer::new(&key);
+
+    let in_file = File::open(&cli.input)?;
+    let in_ds = in_file.dataset("/circuits")?;
+    let size = in_ds.size();
+
+    let out_file = File::create(&cli.output)?;
+    let mut writer = CircuitWriter::<Circuit>::create_like(&out_file, "/circuits", &in_ds)?;
+    let mut index = IndexBuilder::new();
+
+    let pb = pb_new(size, "Anonymizing circuits".to_string());
+    let step = 1_000;
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        let circ_array: Array1<Circuit> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+        for circuit in circ_array.iter() {
+            let mut circuit = *circuit;
+            pseudonymizer.anonymize(&mut circuit, cli.strip_ports);
+
+            index.add(writer.len() as CircuitIndex, &circuit);
+            writer.push(circuit)?;
+        }
+
+        pb.inc((end - begin) as u64);
+    }
+
+    pb.finish();
+    writer.finish()?;
+    index.write_all(&out_file)?;
+
+    out_file.close()?;
+    in_file.close()?;
+
+    let mapping = pseudonymizer.mapping();
+    log::info!("Replaced {} distinct domains", mapping.len());
+
+    if let Some(path) = &cli.mapping {
+        let mut out = BufWriter::new(FsFile::create(path)?);
+        writeln!(out, "pseudonym,domain")?;
+        for (pseudonym, domain) in mapping {
+            writeln!(out, "{pseudonym},{domain}")?;
+        }
+        out.flush()?;
+        log::warn!("Wrote the private domain mapping to {}", path.display());
+    }
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
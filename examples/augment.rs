@@ -0,0 +1,497 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail};
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+
+use gtt23::augment::{
+    Augmenter, CircuitTransform, Compose, Decoy, DropMode, InjectPadding, Jitter, Noise,
+    PaddingTiming, RandomDrop, SplitStrategy, TraceMix, TrafficSplit, Truncations,
+};
+use gtt23::circpad::{self, Circpad, Machine, Next, Side, State};
+use gtt23::defense::{Front, Histogram, Tamaraw, WtfPad};
+use gtt23::rng::Rng;
+use gtt23::{Circuit, CircuitMeta, Direction};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None, args_override_self = true)]
+/// Create augmented copies of every circuit by applying randomized transforms,
+/// and write them to the /augmented dataset of a new HDF5 file, linked to their
+/// circuits by /index/uuid_gtt23
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Output path to write the augmented HDF5 file
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        default_value = "./gtt23-augmented.hdf5"
+    )]
+    pub output: PathBuf,
+    /// Read options from a TOML file whose keys are the long names of the
+    /// options below, e.g., `front = 1700` or `split-strategy = "batched"`,
+    /// where an array gives an option once per element, e.g.,
+    /// `decoy-label = ["a.com", "b.com"]`; options given on the command line
+    /// take precedence, except that repeated options are combined
+    #[arg(short, long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// The number of augmented copies of each circuit
+    #[arg(short = 'n', long, value_name = "N", default_value_t = 1)]
+    pub copies: u16,
+    /// Master seed from which the random number stream of each circuit is
+    /// derived
+    #[arg(short, long, value_name = "SEED", default_value_t = 0)]
+    pub seed: u64,
+    /// Simulate the WTF-PAD defense, with histograms fit to the first
+    /// --fit-circuits circuits
+    #[arg(long)]
+    pub wtfpad: bool,
+    /// The number of circuits to fit the WTF-PAD histograms to
+    #[arg(long, value_name = "N", default_value_t = 1_000)]
+    pub fit_circuits: usize,
+    /// The number of bins of each WTF-PAD histogram
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    pub bins: usize,
+    /// The tokens of the WTF-PAD infinity bins, relative to the other bins
+    #[arg(long, value_name = "RATIO", default_value_t = 1.0)]
+    pub infinity: f64,
+    /// Simulate the FRONT defense with at most this many dummy cells in each
+    /// direction
+    #[arg(long, value_name = "BUDGET")]
+    pub front: Option<u32>,
+    /// The smallest FRONT padding window, in seconds
+    #[arg(long, value_name = "SECONDS", default_value_t = 1.0)]
+    pub front_min_window: f64,
+    /// The largest FRONT padding window, in seconds
+    #[arg(long, value_name = "SECONDS", default_value_t = 14.0)]
+    pub front_max_window: f64,
+    /// Simulate the Tamaraw constant-rate defense, padding each direction to a
+    /// multiple of this many cells
+    #[arg(long, value_name = "L")]
+    pub tamaraw: Option<u32>,
+    /// The seconds between the cells that Tamaraw sends toward the server
+    #[arg(long, value_name = "SECONDS", default_value_t = 0.04)]
+    pub tamaraw_client_interval: f64,
+    /// The seconds between the cells that Tamaraw sends toward the client
+    #[arg(long, value_name = "SECONDS", default_value_t = 0.012)]
+    pub tamaraw_server_interval: f64,
+    /// Run the circuit padding machines described in this TOML file, with a
+    /// `[[machine]]` table per machine holding its `side` ("client" or
+    /// "relay") and a `[[machine.state]]` table per state holding the delay
+    /// histogram `edges` in seconds, `counts`, and `infinity` tokens, an
+    /// optional `length`, and `transitions` mapping event names such as
+    /// "nonpadding-sent" to a state index or "end"
+    #[arg(long, value_name = "PATH")]
+    pub circpad: Option<PathBuf>,
+    /// Insert this many padding cells at uniformly random times
+    #[arg(long, value_name = "N")]
+    pub pad: Option<u16>,
+    /// Insert the padding cells as a Poisson process with this many cells per
+    /// second instead
+    #[arg(long, value_name = "RATE", requires = "pad")]
+    pub pad_rate: Option<f64>,
+    /// Add Gaussian noise with this standard deviation in seconds to the time
+    /// of each cell
+    #[arg(long, value_name = "SECONDS", conflicts_with = "laplace_jitter")]
+    pub jitter: Option<f64>,
+    /// Add Laplace noise with this scale in seconds to the time of each cell
+    #[arg(long, value_name = "SECONDS")]
+    pub laplace_jitter: Option<f64>,
+    /// Add the same timing noise to all cells of each burst
+    #[arg(long)]
+    pub burst_jitter: bool,
+    /// Remove each cell with this probability
+    #[arg(long, value_name = "P", conflicts_with = "drop_spans")]
+    pub drop: Option<f64>,
+    /// Remove this many random spans of consecutive cells
+    #[arg(long, value_name = "N")]
+    pub drop_spans: Option<u16>,
+    /// The maximum number of cells in each removed span
+    #[arg(long, value_name = "N", default_value_t = 50)]
+    pub drop_span_len: u16,
+    /// Instead of other transforms, split each circuit across this many
+    /// sub-circuits, writing one augmented circuit per sub-circuit
+    #[arg(long, value_name = "PATHS")]
+    pub split: Option<u16>,
+    /// How cells are assigned to sub-circuits: round-robin, random, or batched
+    #[arg(long, value_name = "STRATEGY", value_parser = parse_split_strategy, default_value = "round-robin")]
+    pub split_strategy: SplitStrategy,
+    /// Instead of other transforms, overlay each circuit with one of a
+    /// different label from a pool of this many randomly chosen circuits
+    #[arg(long, value_name = "N", conflicts_with = "split")]
+    pub mix: Option<usize>,
+    /// The maximum delay in seconds of the overlaid circuit
+    #[arg(long, value_name = "SECONDS", default_value_t = 5.0)]
+    pub mix_offset: f64,
+    /// Instead of other transforms, truncate each circuit at each of these
+    /// percentages of its length, writing one augmented circuit per percentage
+    #[arg(long, value_name = "PERCENTS", value_delimiter = ',', conflicts_with_all = ["split", "mix"])]
+    pub truncate: Option<Vec<f64>>,
+    /// Restrict the transforms to the cells sent by this side, client or
+    /// server, e.g., to pad only the download direction
+    #[arg(long, value_name = "SIDE", value_parser = parse_direction, conflicts_with_all = ["split", "mix", "truncate"])]
+    pub only: Option<Direction>,
+    /// Overlay each circuit with cells of circuits from a pool of this many
+    /// circuits with a --decoy-label, applied before other transforms
+    #[arg(long, value_name = "N", requires = "decoy_labels")]
+    pub decoy: Option<usize>,
+    /// A label of the circuits to draw decoy cells from (may be repeated)
+    #[arg(long = "decoy-label", value_name = "LABEL")]
+    pub decoy_labels: Vec<String>,
+    /// The number of decoy cells added per cell of each circuit
+    #[arg(long, value_name = "RATIO", default_value_t = 1.0)]
+    pub decoy_ratio: f64,
+}
+
+fn parse_direction(side: &str) -> anyhow::Result<Direction> {
+    match side {
+        "client" => Ok(Direction::CLIENT_TO_SERVER),
+        "server" => Ok(Direction::SERVER_TO_CLIENT),
+        _ => Err(anyhow!("Unknown side '{side}'")),
+    }
+}
+
+fn parse_split_strategy(name: &str) -> anyhow::Result<SplitStrategy> {
+    match name {
+        "round-robin" => Ok(SplitStrategy::RoundRobin),
+        "random" => Ok(SplitStrategy::WeightedRandom),
+        "batched" => Ok(SplitStrategy::Batched { min: 50, max: 70 }),
+        _ => Err(anyhow!("Unknown split strategy '{name}'")),
+    }
+}
+
+/// Reads `n` circuits chosen uniformly at random, with replacement, from
+/// `dataset`, or from its circuits with one of `labels` if it is not empty.
+fn read_pool(
+    dataset: &hdf5::Dataset,
+    n: usize,
+    seed: u64,
+    labels: &[String],
+) -> anyhow::Result<Vec<Circuit>> {
+    let candidates = if labels.is_empty() {
+        (0..dataset.size()).collect()
+    } else {
+        labeled_indices(dataset, labels)?
+    };
+    if candidates.is_empty() {
+        bail!("Cannot draw a pool from no circuits");
+    }
+    // Use a different stream than those derived for each circuit.
+    let mut rng = Rng::new(!seed);
+    let mut indices: Vec<usize> = (0..n)
+        .map(|_| candidates[rng.below(candidates.len() as u64) as usize])
+        .collect();
+    indices.sort_unstable();
+
+    let mut pool = Vec::with_capacity(n);
+    for i in indices {
+        let circuit: Array1<Circuit> = dataset.read_slice(ndarray::s![i..i + 1])?;
+        pool.push(circuit[0]);
+    }
+    Ok(pool)
+}
+
+/// Returns the indices of the circuits in `dataset` with one of `labels`.
+fn labeled_indices(dataset: &hdf5::Dataset, labels: &[String]) -> anyhow::Result<Vec<usize>> {
+    let size = dataset.size();
+    let step = 10_000; // multiple of chunk size
+    let mut indices = Vec::new();
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+        let metas: Array1<CircuitMeta> = dataset.read_slice(ndarray::s![begin..end])?;
+        indices.extend(
+            metas
+                .iter()
+                .enumerate()
+                .filter(|(_, m)| labels.iter().any(|l| l == m.label().as_str()))
+                .map(|(i, _)| begin + i),
+        );
+    }
+    Ok(indices)
+}
+
+/// Reads the circuit padding machines described in the TOML file at `path`.
+fn read_machines(path: &Path) -> anyhow::Result<Vec<Machine>> {
+    let config: toml::Table = std::fs::read_to_string(path)?.parse()?;
+    let Some(toml::Value::Array(machines)) = config.get("machine") else {
+        bail!("{} has no [[machine]] tables", path.display());
+    };
+    machines
+        .iter()
+        .enumerate()
+        .map(|(i, machine)| read_machine(machine).map_err(|e| anyhow!("Machine {i}: {e}")))
+        .collect()
+}
+
+fn read_machine(machine: &toml::Value) -> anyhow::Result<Machine> {
+    let side = match machine.get("side").and_then(|v| v.as_str()) {
+        Some("client") => Side::Client,
+        Some("relay") => Side::Relay,
+        _ => bail!("side must be \"client\" or \"relay\""),
+    };
+    let Some(states) = machine.get("state").and_then(|v| v.as_array()) else {
+        bail!("no [[machine.state]] tables");
+    };
+    let states = states
+        .iter()
+        .enumerate()
+        .map(|(k, state)| read_state(state).map_err(|e| anyhow!("State {k}: {e}")))
+        .collect::<anyhow::Result<Vec<State>>>()?;
+    Machine::new(side, states).map_err(|e| anyhow!(e))
+}
+
+fn read_state(table: &toml::Value) -> anyhow::Result<State> {
+    let numbers = |key: &str| -> anyhow::Result<Vec<f64>> {
+        let values = table.get(key).and_then(|v| v.as_array());
+        let values = values.ok_or_else(|| anyhow!("{key} must be an array"))?;
+        values
+            .iter()
+            .map(|v| match v {
+                toml::Value::Float(f) => Ok(*f),
+                toml::Value::Integer(i) => Ok(*i as f64),
+                _ => Err(anyhow!("{key} must hold numbers")),
+            })
+            .collect()
+    };
+    let count = |key: &str| -> anyhow::Result<Option<u32>> {
+        match table.get(key) {
+            None => Ok(None),
+            Some(v) => match v.as_integer().map(u32::try_from) {
+                Some(Ok(n)) => Ok(Some(n)),
+                _ => Err(anyhow!("{key} must be a non-negative integer")),
+            },
+        }
+    };
+
+    let edges = numbers("edges")?;
+    let counts = numbers("counts")?.into_iter().map(|c| c as u32).collect();
+    let infinity = count("infinity")?.unwrap_or(0);
+    let mut state = State::new(Histogram::new(edges, counts, infinity).map_err(|e| anyhow!(e))?);
+    if let Some(length) = count("length")? {
+        state = state.with_length(length);
+    }
+
+    if let Some(transitions) = table.get("transitions") {
+        let Some(transitions) = transitions.as_table() else {
+            bail!("transitions must be a table");
+        };
+        for (name, next) in transitions {
+            let event = circpad::parse_event(name).map_err(|e| anyhow!(e))?;
+            let next = match next {
+                toml::Value::String(s) if s == "end" => Next::End,
+                toml::Value::Integer(k) if *k >= 0 => Next::State(*k as usize),
+                _ => bail!("transition on {name} must be a state index or \"end\""),
+            };
+            state = state.on(event, next);
+        }
+    }
+    Ok(state)
+}
+
+/// Returns the options in the TOML file at `path` as command line arguments.
+/// Arrays are expanded into a flag per element, e.g., for repeated
+/// `decoy_label` options or the list of `truncate` percentages.
+fn config_args(path: &Path) -> anyhow::Result<Vec<OsString>> {
+    let config: toml::Table = std::fs::read_to_string(path)?.parse()?;
+    let mut args = Vec::new();
+    for (key, value) in config {
+        if key == "input" || key == "config" {
+            bail!("Option '{key}' cannot be set in a config file")
+        }
+        let flag = format!("--{}", key.replace('_', "-"));
+        let values = match value {
+            toml::Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                toml::Value::Boolean(true) => args.push(flag.clone().into()),
+                toml::Value::Boolean(false) => {}
+                toml::Value::String(s) => args.extend([flag.clone().into(), s.into()]),
+                toml::Value::Integer(i) => args.extend([flag.clone().into(), i.to_string().into()]),
+                toml::Value::Float(f) => args.extend([flag.clone().into(), f.to_string().into()]),
+                _ => bail!(
+                    "Option '{key}' in {} has an unsupported type",
+                    path.display()
+                ),
+            }
+        }
+    }
+    Ok(args)
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let cli = match &cli.config {
+        Some(path) => {
+            // Insert the config options first so the command line overrides
+            // them.
+            let mut args: Vec<OsString> = std::env::args_os().collect();
+            args.splice(1..1, config_args(path)?);
+            Cli::parse_from(args)
+        }
+        None => cli,
+    };
+
+    let in_file = File::open(&cli.input)?;
+    let in_ds = in_file.dataset("/circuits")?;
+
+    let mut transforms = Compose::new();
+    if let Some(n) = cli.decoy {
+        if cli.decoy_ratio.is_nan() || cli.decoy_ratio < 0.0 {
+            bail!("Decoy ratio {} must not be negative", cli.decoy_ratio);
+        }
+        let pool = read_pool(&in_ds, n, cli.seed, &cli.decoy_labels)?;
+        log::info!("Drawing decoy cells from {} circuits", pool.len());
+        transforms = transforms.then(Decoy::new(pool, cli.decoy_ratio));
+    }
+    if cli.wtfpad {
+        if cli.bins == 0 {
+            bail!("WTF-PAD histograms need at least one bin");
+        }
+        let end = std::cmp::min(cli.fit_circuits, in_ds.size());
+        let sample: Array1<Circuit> = in_ds.read_slice(ndarray::s![0..end])?;
+        log::info!("Fitting WTF-PAD histograms to {end} circuits");
+        let sample = sample.into_raw_vec_and_offset().0;
+        transforms = transforms.then(WtfPad::fit(&sample, cli.bins, cli.infinity));
+    }
+    if let Some(budget) = cli.front {
+        if !(0.0 <= cli.front_min_window && cli.front_min_window <= cli.front_max_window) {
+            bail!("FRONT windows must satisfy 0 <= min <= max");
+        }
+        transforms = transforms.then(Front::new(
+            budget,
+            budget,
+            cli.front_min_window,
+            cli.front_max_window,
+        ));
+    }
+    if let Some(multiple) = cli.tamaraw {
+        if !(cli.tamaraw_client_interval > 0.0 && cli.tamaraw_server_interval > 0.0) {
+            bail!("Tamaraw intervals must be positive");
+        }
+        transforms = transforms.then(Tamaraw::new(
+            cli.tamaraw_client_interval,
+            cli.tamaraw_server_interval,
+            multiple,
+        ));
+    }
+    if let Some(path) = &cli.circpad {
+        let machines = read_machines(path)?;
+        log::info!("Running {} padding machines", machines.len());
+        transforms = transforms.then(Circpad::new(machines));
+    }
+    if let Some(n) = cli.pad {
+        let timing = match cli.pad_rate {
+            Some(rate) if rate > 0.0 => PaddingTiming::Poisson(rate),
+            Some(rate) => bail!("Padding rate {rate} must be positive"),
+            None => PaddingTiming::Uniform,
+        };
+        transforms = transforms.then(InjectPadding::new(n, timing));
+    }
+    let noise = match (cli.jitter, cli.laplace_jitter) {
+        (Some(sigma), _) => Some(Noise::Gaussian(sigma)),
+        (_, Some(scale)) => Some(Noise::Laplace(scale)),
+        (None, None) => None,
+    };
+    if let Some(noise) = noise {
+        transforms = transforms.then(Jitter::new(noise, cli.burst_jitter));
+    }
+    if let Some(p) = cli.drop {
+        if !(0.0..=1.0).contains(&p) {
+            bail!("Drop probability {p} must be between 0 and 1");
+        }
+        transforms = transforms.then(RandomDrop::new(DropMode::Independent(p)));
+    }
+    if let Some(count) = cli.drop_spans {
+        transforms = transforms.then(RandomDrop::new(DropMode::Spans {
+            count,
+            max_len: cli.drop_span_len,
+        }));
+    }
+
+    if (cli.split.is_some() || cli.mix.is_some() || cli.truncate.is_some())
+        && !transforms.0.is_empty()
+    {
+        bail!("Splitting, mixing, and truncating cannot be combined with other transforms");
+    }
+
+    let augmenter = match (cli.split, cli.mix, &cli.truncate) {
+        (_, _, Some(percents)) => {
+            if percents.is_empty() || !percents.iter().all(|p| (0.0..=100.0).contains(p)) {
+                bail!("Truncation percentages must be between 0 and 100");
+            }
+            log::info!(
+                "Writing {} truncations of each circuit to {}",
+                percents.len(),
+                cli.output.display()
+            );
+            Augmenter::multi(Truncations::new(percents), cli.seed)
+        }
+        (Some(0), _, _) => bail!("Circuits must be split across at least one path"),
+        (Some(paths), _, _) => {
+            log::info!(
+                "Writing {paths} sub-circuits of each circuit to {}",
+                cli.output.display()
+            );
+            Augmenter::multi(TrafficSplit::new(paths, cli.split_strategy), cli.seed)
+        }
+        (None, Some(n), _) => {
+            let pool = read_pool(&in_ds, n, cli.seed, &[])?;
+            log::info!(
+                "Writing each circuit mixed with one of {} circuits to {}",
+                pool.len(),
+                cli.output.display()
+            );
+            Augmenter::multi(TraceMix::new(pool, cli.mix_offset), cli.seed)
+        }
+        (None, None, None) if transforms.0.is_empty() => bail!("Specify at least one transform"),
+        (None, None, None) => {
+            log::info!(
+                "Writing {} augmented copies of each circuit to {}",
+                cli.copies,
+                cli.output.display()
+            );
+            match cli.only {
+                Some(direction) => Augmenter::new(transforms.only(direction), cli.copies, cli.seed),
+                None => Augmenter::new(transforms, cli.copies, cli.seed),
+            }
+        }
+    };
+
+    let out_file = File::create(&cli.output)?;
+    let pb = pb_new(in_ds.size(), "Augmenting circuits".to_string());
+    let written = augmenter.run_with_progress(&in_ds, &out_file, |n| pb.inc(n as u64))?;
+    pb.finish();
+    log::info!("Wrote {written} augmented circuits");
+
+    out_file.close()?;
+    in_file.close()?;
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
@@ -0,0 +1,154 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+
+use gtt23::balance::Balancer;
+use gtt23::index::IndexBuilder;
+use gtt23::rng::Rng;
+use gtt23::writer::{AugmentedWriter, CircuitWriter};
+use gtt23::{AugmentedCircuit, Circuit, CircuitIndex, CircuitMeta};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Write a dataset with the same number of circuits per label, downsampling
+/// large labels and optionally duplicating the circuits of small labels into
+/// an augmented dataset
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Output path to write the balanced HDF5 file
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        default_value = "./gtt23-balanced.hdf5"
+    )]
+    pub output: PathBuf,
+    /// Number of circuits per label [default: the size of the smallest label,
+    /// or of the largest label with --oversample]
+    #[arg(short = 'n', long, value_name = "N")]
+    pub target: Option<usize>,
+    /// Duplicate the circuits of labels with fewer than the target number of
+    /// circuits, writing the duplicates to the /augmented dataset
+    #[arg(long)]
+    pub oversample: bool,
+    /// Seed for the random number generator
+    #[arg(short, long, value_name = "SEED", default_value_t = 0)]
+    pub seed: u64,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let in_file = File::open(&cli.input)?;
+    let in_ds = in_file.dataset("/circuits")?;
+    let size = in_ds.size();
+    let step = 1_000; // multiple of chunk size
+
+    // Only the meta-data is needed to count the labels.
+    let mut balancer = Balancer::new();
+    let pb = pb_new(size, "Reading labels".to_string());
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        let meta_array: Array1<CircuitMeta> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+        for (i, meta) in meta_array.iter().enumerate() {
+            balancer.add((begin + i) as CircuitIndex, meta);
+        }
+
+        pb.inc((end - begin) as u64);
+    }
+
+    pb.finish();
+
+    let mut rng = Rng::new(cli.seed);
+    let plan = balancer.select(cli.target, cli.oversample, &mut rng);
+    log::info!(
+        "Keeping {} circuits and writing {} duplicates",
+        plan.keep.len(),
+        plan.duplicates.len()
+    );
+
+    let out_file = File::create(&cli.output)?;
+    let mut writer = CircuitWriter::<Circuit>::create_like(&out_file, "/circuits", &in_ds)?;
+    let mut aug_writer = if cli.oversample {
+        Some(AugmentedWriter::create_like(&out_file, &in_ds)?)
+    } else {
+        None
+    };
+    let mut index = IndexBuilder::new();
+
+    let pb = pb_new(
+        plan.keep.len() + plan.duplicates.len(),
+        "Writing circuits".to_string(),
+    );
+    let mut next_keep = plan.keep.iter().peekable();
+    let mut next_dup = plan.duplicates.iter().peekable();
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        // Skip chunks that contain no selected circuits.
+        if next_keep.peek().is_none_or(|&&i| i as usize >= end)
+            && next_dup.peek().is_none_or(|&&i| i as usize >= end)
+        {
+            continue;
+        }
+
+        let circ_array: Array1<Circuit> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+        while let Some(&i) = next_keep.next_if(|&&i| (i as usize) < end) {
+            let circuit = &circ_array[i as usize - begin];
+            index.add(writer.len() as CircuitIndex, circuit);
+            writer.push(*circuit)?;
+            pb.inc(1);
+        }
+
+        while let Some(&i) = next_dup.next_if(|&&i| (i as usize) < end) {
+            let circuit = &circ_array[i as usize - begin];
+            if let Some(aug_writer) = aug_writer.as_mut() {
+                // The writer numbers the duplicates of each circuit from 0.
+                aug_writer.push(AugmentedCircuit::from_circuit(circuit, rng.uuid(), 0))?;
+            }
+            pb.inc(1);
+        }
+    }
+
+    pb.finish();
+    writer.finish()?;
+    index.write_all(&out_file)?;
+    if let Some(aug_writer) = aug_writer {
+        aug_writer.finish()?;
+    }
+
+    out_file.close()?;
+    in_file.close()?;
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
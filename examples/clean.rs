@@ -0,0 +1,165 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+use serde_json::json;
+
+use gtt23::clean::{AnomalyReport, CleanReport};
+use gtt23::index::IndexBuilder;
+use gtt23::writer::CircuitWriter;
+use gtt23::{Circuit, CircuitIndex};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Copy the circuits into a new HDF5 file, dropping empty and degenerate
+/// circuits: those without cells, without any RELAY cells, or whose cells all
+/// share the same timestamp; optionally also check for protocol-inconsistent
+/// circuits
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Output path to write the cleaned HDF5 file
+    #[arg(short, long, value_name = "PATH", default_value = "./gtt23-clean.hdf5")]
+    pub output: PathBuf,
+    /// Also check for protocol-inconsistent circuits (relay commands on control
+    /// cells, or SIGNAL cells after the first DATA cell), and report, drop, or
+    /// move them into a /quarantine dataset of the output file
+    #[arg(short, long, value_name = "ACTION")]
+    pub anomalies: Option<AnomalyAction>,
+    /// Print the report as JSON to stdout
+    #[arg(short, long)]
+    pub json: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AnomalyAction {
+    /// Keep the circuits, only reporting them
+    Report,
+    /// Drop the circuits
+    Drop,
+    /// Move the circuits into the /quarantine dataset
+    Quarantine,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let in_file = File::open(&cli.input)?;
+    let in_ds = in_file.dataset("/circuits")?;
+    let size = in_ds.size();
+    let step = 1_000; // multiple of chunk size
+
+    let out_file = File::create(&cli.output)?;
+    let mut writer = CircuitWriter::create_like(&out_file, "/circuits", &in_ds)?;
+    let mut index = IndexBuilder::new();
+    let mut report = CleanReport::new();
+    let mut anomalies = AnomalyReport::new();
+    let mut quarantine = match cli.anomalies {
+        Some(AnomalyAction::Quarantine) => Some(CircuitWriter::create_like(
+            &out_file,
+            "/quarantine",
+            &in_ds,
+        )?),
+        _ => None,
+    };
+
+    let pb = pb_new(size, "Cleaning circuits".to_string());
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        let circ_array: Array1<Circuit> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+        for circuit in circ_array.iter().filter(|c| report.check(c)) {
+            if cli.anomalies.is_some() && !anomalies.check(circuit) {
+                if let Some(quarantine) = quarantine.as_mut() {
+                    quarantine.push(*circuit)?;
+                    continue;
+                }
+                if cli.anomalies == Some(AnomalyAction::Drop) {
+                    continue;
+                }
+            }
+
+            index.add(writer.len() as CircuitIndex, circuit);
+            writer.push(*circuit)?;
+        }
+
+        pb.inc((end - begin) as u64);
+    }
+
+    pb.finish();
+    let n_kept = writer.len();
+    writer.finish()?;
+    if let Some(quarantine) = quarantine {
+        quarantine.finish()?;
+    }
+    index.write_all(&out_file)?;
+
+    out_file.close()?;
+    in_file.close()?;
+
+    if cli.json {
+        let dropped: serde_json::Map<String, serde_json::Value> = report
+            .dropped()
+            .iter()
+            .map(|(kind, count)| (kind.to_string(), json!(count)))
+            .collect();
+        let flagged: serde_json::Map<String, serde_json::Value> = anomalies
+            .counts()
+            .iter()
+            .map(|(kind, count)| (kind.to_string(), json!(count)))
+            .collect();
+        let out = json!({
+            "circuits": size,
+            "kept": n_kept,
+            "dropped": dropped,
+            "anomalies": flagged,
+        });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+    } else {
+        for (kind, count) in report.dropped() {
+            log::info!("Dropped {count} {kind} circuits");
+        }
+        for (kind, count) in anomalies.counts() {
+            log::info!("Found {count} circuits with {kind} anomalies");
+        }
+    }
+
+    log::info!(
+        "Kept {n_kept}/{size} circuits, dropped {} degenerate circuits",
+        report.total_dropped()
+    );
+    if cli.anomalies.is_some() {
+        log::info!(
+            "Flagged {}/{} circuits with anomalies",
+            anomalies.flagged(),
+            anomalies.checked()
+        );
+    }
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
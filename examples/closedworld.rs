@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+
+use gtt23::index::IndexBuilder;
+use gtt23::rng::Rng;
+use gtt23::world::{self, ClosedWorld};
+use gtt23::writer::CircuitWriter;
+use gtt23::{Circuit, CircuitIndex, CircuitMeta};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Write a balanced closed-world dataset of the K most frequent labels that
+/// have at least M circuits each
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Output path to write the closed-world HDF5 file
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        default_value = "./gtt23-closedworld.hdf5"
+    )]
+    pub output: PathBuf,
+    /// Number of labels to keep
+    #[arg(short, long, value_name = "K", default_value_t = 100)]
+    pub k: usize,
+    /// Minimum number of circuits a label must have to be kept
+    #[arg(short, long, value_name = "M", default_value_t = 100)]
+    pub min_circuits: usize,
+    /// Number of circuits to keep per label, at most M [default: M]
+    #[arg(short, long, value_name = "N")]
+    pub per_label: Option<usize>,
+    /// Seed for the random number generator
+    #[arg(short, long, value_name = "SEED", default_value_t = 0)]
+    pub seed: u64,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let in_file = File::open(&cli.input)?;
+    let in_ds = in_file.dataset("/circuits")?;
+    let size = in_ds.size();
+    let step = 1_000; // multiple of chunk size
+
+    // Only the meta-data is needed to choose the labels.
+    let mut closed = ClosedWorld::new();
+    let pb = pb_new(size, "Reading labels".to_string());
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        let meta_array: Array1<CircuitMeta> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+        for (i, meta) in meta_array.iter().enumerate() {
+            closed.add((begin + i) as CircuitIndex, meta);
+        }
+
+        pb.inc((end - begin) as u64);
+    }
+
+    pb.finish();
+
+    let per_label = cli.per_label.unwrap_or(cli.min_circuits);
+    let mut rng = Rng::new(cli.seed);
+    let selection = closed.select(cli.k, cli.min_circuits, per_label, &mut rng);
+
+    if selection.labels.len() < cli.k {
+        log::warn!(
+            "Only {} labels have at least {} circuits",
+            selection.labels.len(),
+            cli.min_circuits
+        );
+    }
+    if selection.labels.is_empty() {
+        bail!("No labels to write");
+    }
+    log::info!(
+        "Selected {} circuits of {} labels",
+        selection.indices.len(),
+        selection.labels.len()
+    );
+
+    let out_file = File::create(&cli.output)?;
+    let mut writer = CircuitWriter::<Circuit>::create_like(&out_file, "/circuits", &in_ds)?;
+    let mut index = IndexBuilder::new();
+
+    let pb = pb_new(selection.indices.len(), "Writing circuits".to_string());
+    let mut next = selection.indices.iter().peekable();
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        // Skip chunks that contain no selected circuits.
+        if next.peek().is_none_or(|&&i| i as usize >= end) {
+            continue;
+        }
+
+        let circ_array: Array1<Circuit> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+        while let Some(&i) = next.next_if(|&&i| (i as usize) < end) {
+            let circuit = &circ_array[i as usize - begin];
+            index.add(writer.len() as CircuitIndex, circuit);
+            writer.push(*circuit)?;
+            pb.inc(1);
+        }
+    }
+
+    pb.finish();
+    let out_ds = writer.finish()?;
+    world::write_labels(&out_ds, &selection.labels)?;
+    index.write_all(&out_file)?;
+
+    out_file.close()?;
+    in_file.close()?;
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
@@ -0,0 +1,118 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+
+use gtt23::stats::{self, COMMAND_STATS_NAME, CommandCount, CommandStats};
+use gtt23::{CellCommand, Direction, RelayCommand};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Counts the cell and relay commands of the cells sent in each direction by
+/// the circuits in an HDF5 file, writes the counts to /stats/commands, and
+/// prints how much of the traffic is SENDME, DROP, and padding
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Also print the counts and the SENDME, DROP, and padding shares of each
+    /// day
+    #[arg(long)]
+    pub by_day: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let file = File::open_rw(&cli.input)?;
+    let size = file.dataset("/circuits")?.size();
+
+    log::info!("Writing command counts to {COMMAND_STATS_NAME}");
+    let pb = pb_new(size, "Counting commands".to_string());
+    let command_stats = stats::write_command_stats(&file, |n| pb.inc(n as u64))?;
+    pb.finish_and_clear();
+    file.close()?;
+
+    println!("All days:");
+    print_counts(&command_stats, &command_stats.totals(), None);
+
+    if cli.by_day {
+        let counts = command_stats.counts();
+        let mut days: Vec<u8> = counts.iter().map(|c| c.day).collect();
+        days.dedup();
+        for day in days {
+            let day_counts: Vec<CommandCount> =
+                counts.iter().filter(|c| c.day == day).copied().collect();
+            println!("Day {day}:");
+            print_counts(&command_stats, &day_counts, Some(day));
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints `counts` of one or all days grouped by direction, with their share
+/// of the cells sent in that direction.
+fn print_counts(stats: &CommandStats, counts: &[CommandCount], day: Option<u8>) {
+    for direction in [
+        Direction::CLIENT_TO_SERVER,
+        Direction::SERVER_TO_CLIENT,
+        Direction::PADDING,
+    ] {
+        let total = stats.cells(direction, day);
+        if total == 0 {
+            continue;
+        }
+        let share = |n: u64| 100.0 * n as f64 / total as f64;
+        let matching = |f: &dyn Fn(&CommandCount) -> bool| -> u64 {
+            counts
+                .iter()
+                .filter(|c| c.direction == direction && f(c))
+                .map(|c| c.count)
+                .sum()
+        };
+
+        println!("  {direction:?}: {total} cells");
+        for c in counts.iter().filter(|c| c.direction == direction) {
+            println!(
+                "    {:<20} {:<24} {:>12} {:>7.3}%",
+                format!("{:?}", c.cell_cmd),
+                format!("{:?}", c.relay_cmd),
+                c.count,
+                share(c.count)
+            );
+        }
+
+        let sendme = matching(&|c| c.relay_cmd == RelayCommand::SENDME);
+        let drop = matching(&|c| c.relay_cmd == RelayCommand::DROP);
+        let padding = matching(&|c| {
+            c.cell_cmd == CellCommand::PADDING || c.cell_cmd == CellCommand::VPADDING
+        });
+        println!(
+            "    SENDME {:.3}%, DROP {:.3}%, padding {:.3}%",
+            share(sendme),
+            share(drop),
+            share(padding)
+        );
+    }
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
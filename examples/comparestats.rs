@@ -0,0 +1,113 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+
+use gtt23::stats::{self, Stats, StatsDiff};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Computes summary statistics of the circuits in two HDF5 files and prints
+/// them side by side with their differences, e.g., to validate a converted or
+/// filtered dataset against its source
+pub struct Cli {
+    /// Path to the first hdf5 file containing a circuits dataset, e.g., the
+    /// source
+    #[arg(value_name = "A", required = true)]
+    pub a: PathBuf,
+    /// Path to the second hdf5 file containing a circuits dataset
+    #[arg(value_name = "B", required = true)]
+    pub b: PathBuf,
+    /// Number of labels with the largest differences to report
+    #[arg(short, long, value_name = "K", default_value_t = 20)]
+    pub top: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let a = read_stats(&cli.a)?;
+    let b = read_stats(&cli.b)?;
+    let diff = StatsDiff::new(&a, &b);
+
+    println!("A: {}", cli.a.display());
+    println!("B: {}", cli.b.display());
+    println!("{:>24} {:>12} {:>12} {:>12}", "", "A", "B", "B - A");
+    print_row("Total circuits", diff.totals.0, diff.totals.1);
+    print_row("Distinct labels", a.num_labels(), b.num_labels());
+
+    println!("Circuits per day:");
+    for &(day, x, y) in diff.days.iter() {
+        print_row(&format!("  {day}"), x, y);
+    }
+
+    println!("Circuits per port:");
+    for &(port, x, y) in diff.ports.iter() {
+        print_row(&format!("  {port}"), x, y);
+    }
+
+    println!("Length percentiles:");
+    for &(p, x, y) in diff.len_percentiles.iter() {
+        let (x, y) = (x.unwrap_or(0) as usize, y.unwrap_or(0) as usize);
+        print_row(&format!("  p{p}"), x, y);
+    }
+    println!("Length distribution shift (KS): {:.4}", diff.len_ks);
+
+    let (only_a, only_b) = diff.unique_labels();
+    let changed = diff.changed_labels().count();
+    println!("Labels with different counts: {changed}");
+    println!("Labels only in A: {only_a}");
+    println!("Labels only in B: {only_b}");
+    if changed > 0 {
+        println!("Top {} label differences:", cli.top.min(changed));
+        for (label, x, y) in diff.changed_labels().take(cli.top) {
+            print_row(&format!("  {label}"), *x, *y);
+        }
+    }
+
+    if diff.is_identical() {
+        println!("The statistics are identical");
+    } else {
+        println!("The statistics differ");
+    }
+
+    Ok(())
+}
+
+/// Computes the summary statistics of the circuits in the file at `path`.
+fn read_stats(path: &Path) -> anyhow::Result<Stats> {
+    let file = File::open(path)?;
+    let size = file.dataset("/circuits")?.size();
+    let pb = pb_new(size, format!("Computing stats of {}", path.display()));
+    let stats = stats::summary_stats(&file, |n| pb.inc(n as u64))?;
+    pb.finish_and_clear();
+    file.close()?;
+    Ok(stats)
+}
+
+/// Prints a row with the counts `a` and `b` and their signed difference.
+fn print_row(name: &str, a: usize, b: usize) {
+    let delta = b as i64 - a as i64;
+    println!("{name:<24} {a:>12} {b:>12} {delta:>+12}");
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
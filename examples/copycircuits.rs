@@ -59,7 +59,7 @@ fn main() -> anyhow::Result<()> {
         .create("circuits")?;
 
     // Track progress.
-    let pb = pb_new(n_tot_circs, format!("Copying circuits"));
+    let pb = pb_new(n_tot_circs, "Copying circuits".to_string());
     pb.tick();
 
     // Write in chunks for better progress info.
@@ -0,0 +1,82 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+
+use gtt23::stats::{self, DAY_VOLUME_NAME};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Reports the number of circuits, the number of cells, and the mean cells per
+/// second within circuits of each day of the circuits in an HDF5 file, writes
+/// them to /stats/days, and flags days with unusually low volume
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Flag days with fewer circuits or cells than this fraction of the median
+    /// day
+    #[arg(short, long, value_name = "FRACTION", default_value_t = 0.5)]
+    pub threshold: f64,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let file = File::open_rw(&cli.input)?;
+    let size = file.dataset("/circuits")?.size();
+
+    log::info!("Writing per-day volumes to {DAY_VOLUME_NAME}");
+    let pb = pb_new(size, "Reading circuits".to_string());
+    let volumes = stats::write_day_volumes(&file, |n| pb.inc(n as u64))?;
+    pb.finish_and_clear();
+    file.close()?;
+
+    let low = stats::low_volume_days(&volumes, cli.threshold);
+
+    println!(
+        "{:>4} {:>12} {:>14} {:>12} {:>12}",
+        "day", "circuits", "cells", "cells/s", "duration"
+    );
+    for v in volumes.iter() {
+        let flag = if low.contains(&v.day) {
+            " low volume"
+        } else {
+            ""
+        };
+        println!(
+            "{:>4} {:>12} {:>14} {:>12.2} {:>12.2}{flag}",
+            v.day, v.circuits, v.cells, v.mean_rate, v.mean_duration
+        );
+    }
+
+    if !low.is_empty() {
+        log::warn!(
+            "{} days have fewer circuits or cells than {} of the median day",
+            low.len(),
+            cli.threshold
+        );
+    }
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
@@ -0,0 +1,138 @@
+use std::fs::File as FsFile;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+
+use gtt23::clean::Deduplicator;
+use gtt23::index::IndexBuilder;
+use gtt23::writer::CircuitWriter;
+use gtt23::{Circuit, CircuitIndex};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Find circuits whose traces duplicate an earlier circuit's trace, and copy
+/// the circuits without duplicates into a new HDF5 file
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Output path to write the deduplicated HDF5 file
+    #[arg(short, long, value_name = "PATH", default_value = "./gtt23-dedup.hdf5")]
+    pub output: PathBuf,
+    /// Round cell times to multiples of this many seconds before comparing, to
+    /// also find near duplicates; only exact duplicates are found if not given
+    #[arg(short, long, value_name = "SECONDS")]
+    pub quantum: Option<f64>,
+    /// Write a CSV of each duplicate and the earlier circuit it duplicates
+    #[arg(short, long, value_name = "PATH")]
+    pub report: Option<PathBuf>,
+    /// Only report the duplicates without writing an output file
+    #[arg(long)]
+    pub report_only: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let in_file = File::open(&cli.input)?;
+    let in_ds = in_file.dataset("/circuits")?;
+    let size = in_ds.size();
+    let step = 1_000; // multiple of chunk size
+
+    let out_file = if cli.report_only {
+        None
+    } else {
+        Some(File::create(&cli.output)?)
+    };
+    let mut writer = match &out_file {
+        Some(out_file) => Some(CircuitWriter::create_like(out_file, "/circuits", &in_ds)?),
+        None => None,
+    };
+    let mut index = IndexBuilder::new();
+
+    let mut report = match &cli.report {
+        Some(path) => {
+            let mut report = BufWriter::new(FsFile::create(path)?);
+            writeln!(report, "index,uuid,duplicate_of_index,duplicate_of_uuid")?;
+            Some(report)
+        }
+        None => None,
+    };
+
+    let mut dedup = Deduplicator::new(cli.quantum);
+    let pb = pb_new(size, "Finding duplicates".to_string());
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        let circ_array: Array1<Circuit> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+        for (i, circuit) in circ_array.iter().enumerate() {
+            let circ_index = (begin + i) as CircuitIndex;
+
+            match dedup.check(circ_index, circuit) {
+                Some((first_index, first_uuid)) => {
+                    if let Some(report) = report.as_mut() {
+                        writeln!(
+                            report,
+                            "{circ_index},{},{first_index},{first_uuid}",
+                            circuit.uuid
+                        )?;
+                    }
+                }
+                None => {
+                    if let Some(writer) = writer.as_mut() {
+                        index.add(writer.len() as CircuitIndex, circuit);
+                        writer.push(*circuit)?;
+                    }
+                }
+            }
+        }
+
+        pb.inc((end - begin) as u64);
+    }
+
+    pb.finish();
+
+    if let Some(mut report) = report {
+        report.flush()?;
+    }
+    if let (Some(writer), Some(out_file)) = (writer, out_file) {
+        writer.finish()?;
+        index.write_all(&out_file)?;
+        out_file.close()?;
+    }
+    in_file.close()?;
+
+    log::info!(
+        "Found {} duplicates of {} unique traces in {size} circuits",
+        dedup.duplicates(),
+        dedup.unique()
+    );
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use log::{self, LevelFilter};
+
+use gtt23::diff;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Compare the circuits in two HDF5 files by uuid
+pub struct Cli {
+    /// Path to the first hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub first: PathBuf,
+    /// Path to the second hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub second: PathBuf,
+    /// Also compare the cells of circuits present in both files
+    #[arg(short, long)]
+    pub cells: bool,
+    /// Maximum number of example differences to report for each kind
+    #[arg(short, long, value_name = "N", default_value_t = 10)]
+    pub max_examples: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let first = File::open(&cli.first)?;
+    let second = File::open(&cli.second)?;
+    let first_ds = first.dataset("/circuits")?;
+    let second_ds = second.dataset("/circuits")?;
+
+    log::info!(
+        "Comparing {} circuits with {} circuits",
+        first_ds.size(),
+        second_ds.size()
+    );
+
+    let diff = diff::diff_datasets(&first_ds, &second_ds, cli.cells, cli.max_examples)?;
+
+    first.close()?;
+    second.close()?;
+
+    log::info!("{} uuids in common", diff.common());
+
+    for (kind, count) in diff.counts() {
+        log::warn!("{count} {kind}");
+        for d in diff.examples(*kind) {
+            log::warn!("  {}: {}", d.uuid, d.detail);
+        }
+    }
+
+    if !diff.is_empty() {
+        let total: usize = diff.counts().values().sum();
+        bail!("Found {total} differences");
+    }
+
+    log::info!("The files contain the same circuits");
+    Ok(())
+}
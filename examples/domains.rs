@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use log::{self, LevelFilter};
+
+use gtt23::stats;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Reports the most frequent labels, registrable domains (shortest private
+/// suffixes), and raw domains of the circuits in an HDF5 file, and the labels
+/// that collapse the most distinct domains
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Number of most frequent entries to report in each list
+    #[arg(short, long, value_name = "K", default_value_t = 10)]
+    pub top: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let file = File::open(&cli.input)?;
+    let domains = stats::domain_stats(&file)?;
+    file.close()?;
+
+    let (labels, suffixes, raw) = domains.distinct();
+    println!("Total circuits: {}", domains.total());
+    println!(
+        "Circuits without a shortest private suffix: {}",
+        domains.unsuffixed()
+    );
+    println!("Distinct labels: {labels}");
+    println!("Distinct registrable domains: {suffixes}");
+    println!(
+        "Distinct raw domains: {raw} ({:.2} per label)",
+        raw as f64 / labels.max(1) as f64
+    );
+
+    println!("Top {} labels:", cli.top);
+    for (label, count) in domains.top_labels(cli.top) {
+        println!("  {label}: {count}");
+    }
+
+    println!("Top {} registrable domains:", cli.top);
+    for (suffix, count) in domains.top_suffixes(cli.top) {
+        println!("  {suffix}: {count}");
+    }
+
+    println!("Top {} raw domains:", cli.top);
+    for (domain, count) in domains.top_domains(cli.top) {
+        println!("  {domain}: {count}");
+    }
+
+    println!("Top {} labels by distinct raw domains:", cli.top);
+    for (label, n_domains, count) in domains.top_collapsed(cli.top) {
+        println!("  {label}: {n_domains} domains, {count} circuits");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+
+use gtt23::drift::{self, DriftOptions, LabelDrift};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Compares the distributions of circuit lengths, burst counts, and CUMUL
+/// representations of each label between two day ranges of an HDF5 file, and
+/// reports how much they diverge, to quantify how quickly traces go stale
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// The first day range, as DAY or FIRST-LAST (e.g., 0-3)
+    #[arg(short = 'a', long, value_name = "DAYS", value_parser = parse_days)]
+    pub days_a: RangeInclusive<u8>,
+    /// The second day range, as DAY or FIRST-LAST (e.g., 4-7)
+    #[arg(short = 'b', long, value_name = "DAYS", value_parser = parse_days)]
+    pub days_b: RangeInclusive<u8>,
+    /// Only compare labels with at least this many circuits in each range
+    #[arg(long, value_name = "N", default_value_t = DriftOptions::default().min_circuits)]
+    pub min_circuits: usize,
+    /// Number of points of the CUMUL representations
+    #[arg(long, value_name = "N", default_value_t = DriftOptions::default().cumul_points)]
+    pub cumul_points: usize,
+    /// Number of labels with the most drift to print
+    #[arg(short, long, value_name = "K", default_value_t = 20)]
+    pub top: usize,
+    /// Also write the drift of every label to a CSV file at this path
+    #[arg(long, value_name = "PATH")]
+    pub csv: Option<PathBuf>,
+    /// Number of threads computing features
+    #[arg(short = 'j', long, value_name = "N", default_value_t = 1)]
+    pub threads: usize,
+}
+
+fn parse_days(days: &str) -> anyhow::Result<RangeInclusive<u8>> {
+    let (first, last) = days.split_once('-').unwrap_or((days, days));
+    let (first, last): (u8, u8) = (first.trim().parse()?, last.trim().parse()?);
+    if first > last {
+        return Err(anyhow!("Day range '{days}' is empty"));
+    }
+    Ok(first..=last)
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let file = File::open(&cli.input)?;
+    let size = file.dataset("/circuits")?.size();
+
+    let options = DriftOptions {
+        cumul_points: cli.cumul_points,
+        min_circuits: cli.min_circuits,
+        threads: cli.threads,
+        ..DriftOptions::default()
+    };
+    let pb = pb_new(size, "Reading circuits".to_string());
+    let (mut drifts, pooled) = drift::compare_days(
+        &file,
+        cli.days_a.clone(),
+        cli.days_b.clone(),
+        &options,
+        |n| pb.inc(n as u64),
+    )?;
+    pb.finish_and_clear();
+    file.close()?;
+
+    if let Some(path) = &cli.csv {
+        log::info!(
+            "Writing drift of {} labels to {}",
+            drifts.len(),
+            path.display()
+        );
+        drift::write_drift_csv(path, &drifts)?;
+    }
+
+    println!(
+        "Days {:?} vs {:?}, {} labels compared",
+        cli.days_a,
+        cli.days_b,
+        drifts.len()
+    );
+    println!(
+        "{:<44} {:>8} {:>8} {:>8} {:>8} {:>8} {:>10}",
+        "label", "count_a", "count_b", "len_ks", "len_js", "burst_ks", "cumul_rmsd"
+    );
+    println!("{}", row(&pooled));
+    drifts.sort_by(|x, y| y.cumul_rmsd.total_cmp(&x.cumul_rmsd));
+    for d in drifts.iter().take(cli.top) {
+        println!("{}", row(d));
+    }
+
+    Ok(())
+}
+
+fn row(d: &LabelDrift) -> String {
+    format!(
+        "{:<44} {:>8} {:>8} {:>8.4} {:>8.4} {:>8.4} {:>10.4}",
+        d.label.as_str(),
+        d.count_a,
+        d.count_b,
+        d.len_ks,
+        d.len_js,
+        d.burst_ks,
+        d.cumul_rmsd
+    )
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use log::{self, LevelFilter};
+
+use gtt23::Circuit;
+use gtt23::dtw::{self, DtwOptions};
+use gtt23::rng::Rng;
+use gtt23::sample;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Computes the dynamic time warping distance between every pair of a sample
+/// of circuits in an HDF5 file, and reports the accuracy of labeling each
+/// circuit with the label of its nearest neighbor and the pairs that are near
+/// duplicates
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Number of circuits drawn uniformly at random to compare
+    #[arg(short = 'n', long, value_name = "N", default_value_t = 1_000)]
+    pub count: usize,
+    /// Seed of the random number generator used to draw the circuits
+    #[arg(short, long, value_name = "SEED", default_value_t = 0)]
+    pub seed: u64,
+    /// Cost of a second of difference between the times of aligned cells,
+    /// relative to a cost of 2 for aligning cells in opposite directions
+    #[arg(long, value_name = "WEIGHT", default_value_t = 1.0)]
+    pub time_weight: f64,
+    /// Only align cells at most this many positions apart
+    #[arg(short, long, value_name = "CELLS")]
+    pub band: Option<usize>,
+    /// Compare only this many of the first cells of each circuit
+    #[arg(short = 'm', long, value_name = "CELLS")]
+    pub max_cells: Option<usize>,
+    /// Report pairs of circuits at most this distance apart as near duplicates
+    #[arg(short, long, value_name = "DISTANCE", default_value_t = 0.0)]
+    pub duplicate_threshold: f64,
+    /// Number of threads computing distances
+    #[arg(short = 'j', long, value_name = "N", default_value_t = 1)]
+    pub threads: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let file = File::open(&cli.input)?;
+    let dataset = file.dataset("/circuits")?;
+
+    let selected = sample::uniform(dataset.size(), cli.count, &mut Rng::new(cli.seed));
+    let indices: Vec<usize> = selected.iter().map(|&i| i as usize).collect();
+    let circuits: Vec<Circuit> = dataset.read_slice_1d::<Circuit, _>(indices)?.to_vec();
+    file.close()?;
+
+    let options = DtwOptions {
+        time_weight: cli.time_weight,
+        band: cli.band,
+        max_cells: cli.max_cells,
+    };
+    let n = circuits.len();
+    log::info!(
+        "Computing {} distances between {n} circuits",
+        n * n.saturating_sub(1) / 2
+    );
+    let condensed = dtw::pairwise(&circuits, &options, cli.threads);
+    let dist = |i: usize, j: usize| {
+        let (i, j) = (i.min(j), i.max(j));
+        condensed[n * i - i * (i + 1) / 2 + j - i - 1]
+    };
+
+    let mut correct = 0;
+    for i in 0..n {
+        let nearest = (0..n)
+            .filter(|&j| j != i)
+            .min_by(|&a, &b| dist(i, a).total_cmp(&dist(i, b)));
+        if nearest.is_some_and(|j| circuits[j].label() == circuits[i].label()) {
+            correct += 1;
+        }
+    }
+    println!(
+        "Nearest-neighbor label accuracy: {correct}/{n} ({:.2}%)",
+        100.0 * correct as f64 / n.max(1) as f64
+    );
+
+    println!("Near duplicates:");
+    for i in 0..n {
+        for j in i + 1..n {
+            if dist(i, j) <= cli.duplicate_threshold {
+                println!("  {} {} {}", circuits[i].uuid, circuits[j].uuid, dist(i, j));
+            }
+        }
+    }
+
+    Ok(())
+}
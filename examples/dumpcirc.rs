@@ -1,10 +1,11 @@
+use std::collections::HashSet;
 use std::path::PathBuf;
 
 use clap::{Args, Parser};
 use hdf5::{File, Result};
 use ndarray::{s, Array0};
 
-use gtt23::Circuit;
+use gtt23::{fixedascii_from_str, index, Circuit, Dataset};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -23,9 +24,9 @@ pub struct Cli {
 #[derive(Args)]
 #[group(required = true, multiple = false)]
 pub struct Selector {
-    /// Select circuit by uuid
+    /// Select circuit by uuid (may be given multiple times)
     #[arg(short, long)]
-    pub uuid: Option<String>,
+    pub uuid: Vec<String>,
     /// Select circuit by index
     #[arg(short, long)]
     pub index: Option<usize>,
@@ -40,21 +41,56 @@ fn main() -> Result<()> {
     // Open the circuit dataset
     let ds = file.dataset(cli.name.as_str())?;
 
-    // Get the index of the circuit
-    let index = if let Some(i) = cli.select.index {
-        i
-    } else if let Some(_) = cli.select.uuid {
-        unimplemented!()
+    // Get the indices of the circuits
+    let indices: Vec<(usize, String)> = if let Some(i) = cli.select.index {
+        vec![(i, format!("index {i}"))]
     } else {
-        panic!("No selector given")
+        // Longer strings would be silently truncated to 32 characters.
+        let mut uuids = Vec::new();
+        for u in cli.select.uuid.iter() {
+            if u.len() != 32 || !u.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(format!("Invalid uuid '{u}': expected 32 hex characters").into());
+            }
+            uuids.push(fixedascii_from_str::<32>(u).map_err(|e| e.to_string())?);
+        }
+
+        // The uuid index only covers the /circuits dataset; otherwise scan.
+        let uses_index = ds.name() == "/circuits" && file.link_exists("/index/uuid");
+        let found = if uses_index {
+            let dataset = Dataset::from_file(file.clone());
+            let mut found = Vec::new();
+            for uuid in uuids.iter() {
+                found.push(dataset.find_uuid(uuid)?);
+            }
+            found
+        } else {
+            let wanted: HashSet<_> = uuids.iter().copied().collect();
+            let map = index::scan_uuids(&ds, &wanted)?;
+            uuids.iter().map(|uuid| map.get(uuid).copied()).collect()
+        };
+
+        let mut indices = Vec::new();
+        let mut missing = Vec::new();
+        for (uuid, index) in uuids.iter().zip(found) {
+            match index {
+                Some(i) => indices.push((i as usize, format!("uuid {uuid}"))),
+                None => missing.push(uuid.to_string()),
+            }
+        }
+        if !missing.is_empty() {
+            return Err(format!("Circuit not found with uuid {}", missing.join(", ")).into());
+        }
+        indices
     };
 
-    // Grab a single circuit by its index in the circuit array
-    let arr: Array0<Circuit> = ds.read_slice(s![index])?;
+    for (index, desc) in indices {
+        // Grab a single circuit by its index in the circuit array
+        let arr: Array0<Circuit> = ds.read_slice(s![index])?;
 
-    match arr.first() {
-        Some(circ) => println!("{:?}", circ),
-        None => println!("Circuit not found at index {index}"),
+        match arr.first() {
+            Some(circ) => println!("{:?}", circ),
+            None => println!("Circuit not found at {desc}"),
+        }
     }
 
     // Note: we could dump multiple circuits like:
@@ -0,0 +1,142 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use hdf5::types::FixedAscii;
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+
+use gtt23::edit;
+use gtt23::index;
+use gtt23::npz::{NpyWriter, NpzWriter};
+use gtt23::rng::Rng;
+use gtt23::sample;
+use gtt23::{Circuit, CircuitIndex, CircuitMeta};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Computes the edit distance between the direction sequences of every pair of
+/// circuits with a label in an HDF5 file, and exports the N x N distance matrix
+/// to a NumPy file
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Label of the circuits to compare
+    #[arg(short, long, value_name = "LABEL", required = true)]
+    pub label: String,
+    /// Output path of the matrix, as a uint32 .npy array or, if the path ends
+    /// in .npz, an archive holding the matrix as `D` and the circuit uuids as
+    /// `uuid`
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        default_value = "./edit-distances.npy"
+    )]
+    pub output: PathBuf,
+    /// Compare at most this many circuits drawn uniformly at random from the
+    /// label
+    #[arg(short = 'n', long, value_name = "N")]
+    pub count: Option<usize>,
+    /// Seed of the random number generator used to draw the circuits
+    #[arg(short, long, value_name = "SEED", default_value_t = 0)]
+    pub seed: u64,
+    /// Compare only this many of the first cells of each circuit
+    #[arg(short = 'm', long, value_name = "CELLS")]
+    pub max_cells: Option<usize>,
+    /// Number of threads computing distances
+    #[arg(short = 'j', long, value_name = "N", default_value_t = 1)]
+    pub threads: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let file = File::open(&cli.input)?;
+    let dataset = file.dataset("/circuits")?;
+
+    let candidates: Vec<CircuitIndex> = label_indices(&file, &dataset, &cli.label)?
+        .into_iter()
+        .collect();
+    if candidates.is_empty() {
+        anyhow::bail!("No circuits have label '{}'", cli.label);
+    }
+    let selected = match cli.count {
+        Some(n) => sample::uniform_from(&candidates, n, &mut Rng::new(cli.seed)),
+        None => candidates,
+    };
+    let indices: Vec<usize> = selected.iter().map(|&i| i as usize).collect();
+    let circuits: Vec<Circuit> = dataset.read_slice_1d::<Circuit, _>(indices)?.to_vec();
+    file.close()?;
+
+    let n = circuits.len();
+    log::info!(
+        "Computing edit distances between {n} circuits with label {}",
+        cli.label
+    );
+    let matrix = edit::matrix(&circuits, cli.max_cells, cli.threads);
+    let rows = matrix.iter().map(|row| {
+        row.iter()
+            .flat_map(|&d| (d as u32).to_le_bytes())
+            .collect::<Vec<u8>>()
+    });
+
+    if cli.output.extension().is_some_and(|e| e == "npz") {
+        let mut npz = NpzWriter::create(&cli.output)?;
+        let d = npz.add_array("D", "<u4", &[n])?;
+        let uuid = npz.add_array("uuid", "|S32", &[])?;
+        for (row, circuit) in rows.zip(circuits.iter()) {
+            npz.append(d, &row)?;
+            let mut bytes = [0u8; 32];
+            bytes[..circuit.uuid.len()].copy_from_slice(circuit.uuid.as_bytes());
+            npz.append(uuid, &bytes)?;
+        }
+        npz.finish()?;
+    } else {
+        let mut npy = NpyWriter::create(&cli.output, "<u4", &[n])?;
+        for row in rows {
+            npy.append(&row)?;
+        }
+        npy.finish()?;
+    }
+
+    log::info!("Wrote {n} x {n} distances to {}", cli.output.display());
+
+    Ok(())
+}
+
+/// The sorted indices of the circuits with `label`, from the `/index/label`
+/// dataset if present and otherwise by scanning the circuits' meta-data.
+fn label_indices(
+    file: &File,
+    dataset: &hdf5::Dataset,
+    label: &str,
+) -> anyhow::Result<BTreeSet<CircuitIndex>> {
+    if file.link_exists("/index/label") {
+        return Ok(index::select_indices::<FixedAscii<44>, _>(
+            &file.dataset("/index/label")?,
+            |l| l.as_str() == label,
+        )?);
+    }
+
+    let size = dataset.size();
+    let step = 10_000;
+    let mut indices = BTreeSet::new();
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+        let metas: Array1<CircuitMeta> = dataset.read_slice(ndarray::s![begin..end])?;
+        for (i, meta) in metas.iter().enumerate() {
+            if meta.label().as_str() == label {
+                indices.insert((begin + i) as CircuitIndex);
+            }
+        }
+    }
+    Ok(indices)
+}
@@ -0,0 +1,213 @@
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+use regex::Regex;
+
+use gtt23::export::{CommandEncoding, DEFAULT_SHARD_SIZE, ExportFormat, ExportOptions};
+use gtt23::features::TimeNormalization;
+use gtt23::filter::Criteria;
+use gtt23::index::{LABELS_NAME, LabelVocabulary};
+use gtt23::transform::{CircuitTransform, TrimCells};
+use gtt23::{Circuit, CircuitIndex};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Export the circuits (or those matching some criteria) to another format
+pub struct Cli {
+    /// Input path to an HDF5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Output format: jsonl, csv, csv-cells, npz, df, df-h5, tiktok, tiktok-h5,
+    /// commands, wds (WebDataset tar shards), wang (a directory of Wang et al.
+    /// text files), or parquet
+    #[arg(short, long, value_name = "FORMAT", value_parser = parse_format)]
+    pub format: ExportFormat,
+    /// Output path [default: ./gtt23-export.<EXTENSION>]
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+    /// Export circuits with this label (may be repeated)
+    #[arg(short, long = "label", value_name = "LABEL")]
+    pub labels: Vec<String>,
+    /// Export circuits observed on or after this day
+    #[arg(long, value_name = "DAY")]
+    pub min_day: Option<u8>,
+    /// Export circuits observed on or before this day
+    #[arg(long, value_name = "DAY")]
+    pub max_day: Option<u8>,
+    /// Skip circuits observed on these days, separated by commas (e.g., 3,5)
+    #[arg(long, value_name = "DAYS", value_delimiter = ',')]
+    pub exclude_days: Vec<u8>,
+    /// Export circuits with this port (may be repeated)
+    #[arg(short, long = "port", value_name = "PORT")]
+    pub ports: Vec<u16>,
+    /// Skip circuits with this port (may be repeated)
+    #[arg(long = "deny-port", value_name = "PORT")]
+    pub deny_ports: Vec<u16>,
+    /// Export circuits with at least this many cells
+    #[arg(long, value_name = "LEN")]
+    pub min_len: Option<u16>,
+    /// Export circuits with at most this many cells
+    #[arg(long, value_name = "LEN")]
+    pub max_len: Option<u16>,
+    /// Export circuits whose domain has this suffix, e.g., "de" matches "de" and
+    /// "example.de" (may be repeated)
+    #[arg(long = "domain-suffix", value_name = "SUFFIX")]
+    pub domain_suffixes: Vec<String>,
+    /// Skip circuits whose domain has this suffix, e.g., "onion" (may be repeated)
+    #[arg(long = "deny-domain-suffix", value_name = "SUFFIX")]
+    pub deny_domain_suffixes: Vec<String>,
+    /// Export circuits whose domain matches this regex
+    #[arg(long, value_name = "REGEX")]
+    pub domain_regex: Option<Regex>,
+    /// Export circuits whose shortest private suffix matches this regex
+    #[arg(long, value_name = "REGEX")]
+    pub suffix_regex: Option<Regex>,
+    /// Export only the first N cells of each circuit
+    #[arg(long, value_name = "N")]
+    pub trim: Option<u16>,
+    /// Normalize the times of the tiktok formats: none (seconds since the first
+    /// cell), duration (divided by the time of the last cell), or log (ln(1 + t))
+    #[arg(long, value_name = "NORM", value_parser = parse_time_norm, default_value = "none")]
+    pub time_norm: TimeNormalization,
+    /// Encode the cell and relay commands of the commands format: index (ids in
+    /// separate arrays, for embeddings) or onehot (channels of X)
+    #[arg(long, value_name = "ENCODING", value_parser = parse_command_encoding, default_value = "index")]
+    pub commands: CommandEncoding,
+    /// The number of circuits in each shard of the wds format
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_SHARD_SIZE)]
+    pub shard_size: usize,
+}
+
+impl Cli {
+    fn criteria(&self) -> Criteria {
+        let days = match (self.min_day, self.max_day) {
+            (None, None) => None,
+            (min, max) => Some(min.unwrap_or(u8::MIN)..=max.unwrap_or(u8::MAX)),
+        };
+
+        Criteria {
+            labels: (!self.labels.is_empty()).then(|| self.labels.iter().cloned().collect()),
+            days,
+            exclude_days: (!self.exclude_days.is_empty())
+                .then(|| self.exclude_days.iter().copied().collect()),
+            ports: (!self.ports.is_empty()).then(|| self.ports.iter().copied().collect()),
+            deny_ports: (!self.deny_ports.is_empty())
+                .then(|| self.deny_ports.iter().copied().collect()),
+            min_len: self.min_len,
+            max_len: self.max_len,
+            domain_suffixes: (!self.domain_suffixes.is_empty())
+                .then(|| self.domain_suffixes.clone()),
+            deny_domain_suffixes: (!self.deny_domain_suffixes.is_empty())
+                .then(|| self.deny_domain_suffixes.clone()),
+            domain_regex: self.domain_regex.clone(),
+            suffix_regex: self.suffix_regex.clone(),
+            exclude_uuids: None,
+        }
+    }
+}
+
+fn parse_format(name: &str) -> anyhow::Result<ExportFormat> {
+    ExportFormat::from_name(name).ok_or_else(|| anyhow!("Unknown export format '{name}'"))
+}
+
+fn parse_time_norm(name: &str) -> anyhow::Result<TimeNormalization> {
+    match name {
+        "none" => Ok(TimeNormalization::None),
+        "duration" => Ok(TimeNormalization::Duration),
+        "log" => Ok(TimeNormalization::Log),
+        _ => Err(anyhow!("Unknown time normalization '{name}'")),
+    }
+}
+
+fn parse_command_encoding(name: &str) -> anyhow::Result<CommandEncoding> {
+    match name {
+        "index" => Ok(CommandEncoding::Index),
+        "onehot" => Ok(CommandEncoding::OneHot),
+        _ => Err(anyhow!("Unknown command encoding '{name}'")),
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let criteria = cli.criteria();
+    let trim = cli.trim.map(TrimCells::new);
+    let output = cli
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("./gtt23-export.{}", cli.format.extension())));
+
+    let in_file = File::open(&cli.input)?;
+    let in_ds = in_file.dataset("/circuits")?;
+    let size = in_ds.size();
+
+    let vocabulary = if in_file.link_exists(LABELS_NAME) {
+        Some(LabelVocabulary::read(&in_file)?)
+    } else {
+        None
+    };
+    let options = ExportOptions {
+        time_normalization: cli.time_norm,
+        vocabulary,
+        command_encoding: cli.commands,
+        shard_size: Some(cli.shard_size),
+    };
+    let mut exporter = cli.format.exporter(&output, &options)?;
+    let mut n_exported = 0;
+
+    let pb = pb_new(size, "Exporting circuits".to_string());
+    let step = 1_000;
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        let circ_array: Array1<Circuit> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+        for (i, circuit) in circ_array.iter().enumerate() {
+            if criteria.matches(circuit) {
+                let mut circuit = *circuit;
+                if let Some(trim) = &trim {
+                    trim.apply(&mut circuit);
+                }
+                exporter.write((begin + i) as CircuitIndex, &circuit)?;
+                n_exported += 1;
+            }
+        }
+
+        pb.inc((end - begin) as u64);
+    }
+
+    pb.finish();
+    exporter.finish()?;
+    in_file.close()?;
+
+    log::info!(
+        "Exported {n_exported}/{size} circuits to {}",
+        output.display()
+    );
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
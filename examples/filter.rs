@@ -0,0 +1,239 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+use regex::Regex;
+
+use gtt23::clean;
+use gtt23::filter::Criteria;
+use gtt23::index::IndexBuilder;
+use gtt23::query::Expr;
+use gtt23::writer::CircuitWriter;
+use gtt23::{Circuit, CircuitIndex, Dataset};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Copy the circuits matching some criteria into a new HDF5 file
+pub struct Cli {
+    /// Input path to an HDF5 file from which to copy circuits
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Output path to write the HDF5 file containing the matching circuits
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        default_value = "./gtt23-filtered.hdf5"
+    )]
+    pub output: PathBuf,
+    /// Keep circuits with this label (may be repeated)
+    #[arg(short, long = "label", value_name = "LABEL")]
+    pub labels: Vec<String>,
+    /// Keep circuits observed on or after this day
+    #[arg(long, value_name = "DAY")]
+    pub min_day: Option<u8>,
+    /// Keep circuits observed on or before this day
+    #[arg(long, value_name = "DAY")]
+    pub max_day: Option<u8>,
+    /// Drop circuits observed on these days, separated by commas (e.g., 3,5)
+    #[arg(long, value_name = "DAYS", value_delimiter = ',')]
+    pub exclude_days: Vec<u8>,
+    /// Keep circuits with this port (may be repeated)
+    #[arg(short, long = "port", value_name = "PORT")]
+    pub ports: Vec<u16>,
+    /// Drop circuits with this port (may be repeated)
+    #[arg(long = "deny-port", value_name = "PORT")]
+    pub deny_ports: Vec<u16>,
+    /// Keep circuits with at least this many cells
+    #[arg(long, value_name = "LEN")]
+    pub min_len: Option<u16>,
+    /// Keep circuits with at most this many cells
+    #[arg(long, value_name = "LEN")]
+    pub max_len: Option<u16>,
+    /// Keep circuits whose domain has this suffix, e.g., "de" matches "de" and
+    /// "example.de" (may be repeated)
+    #[arg(long = "domain-suffix", value_name = "SUFFIX")]
+    pub domain_suffixes: Vec<String>,
+    /// Drop circuits whose domain has this suffix, e.g., "onion" (may be repeated)
+    #[arg(long = "deny-domain-suffix", value_name = "SUFFIX")]
+    pub deny_domain_suffixes: Vec<String>,
+    /// Keep circuits whose domain matches this regex
+    #[arg(long, value_name = "REGEX")]
+    pub domain_regex: Option<Regex>,
+    /// Keep circuits whose shortest private suffix matches this regex
+    #[arg(long, value_name = "REGEX")]
+    pub suffix_regex: Option<Regex>,
+    /// Drop the outliers listed in the /outliers dataset of the input file, as
+    /// written by the outliers example
+    #[arg(long)]
+    pub exclude_outliers: bool,
+    /// Keep circuits matching this query expression, e.g.,
+    /// 'label == "example.com" && day >= 3 && len > 100'
+    #[arg(short, long = "where", value_name = "EXPR", value_parser = Expr::parse)]
+    pub query: Option<Expr>,
+    /// Evaluate the filter on this many threads while a single thread writes
+    /// the matching circuits in their original order
+    #[arg(short = 'j', long, value_name = "N")]
+    pub threads: Option<usize>,
+}
+
+impl Cli {
+    fn criteria(&self) -> Criteria {
+        let days = match (self.min_day, self.max_day) {
+            (None, None) => None,
+            (min, max) => Some(min.unwrap_or(u8::MIN)..=max.unwrap_or(u8::MAX)),
+        };
+
+        Criteria {
+            labels: (!self.labels.is_empty()).then(|| self.labels.iter().cloned().collect()),
+            days,
+            exclude_days: (!self.exclude_days.is_empty())
+                .then(|| self.exclude_days.iter().copied().collect()),
+            ports: (!self.ports.is_empty()).then(|| self.ports.iter().copied().collect()),
+            deny_ports: (!self.deny_ports.is_empty())
+                .then(|| self.deny_ports.iter().copied().collect()),
+            min_len: self.min_len,
+            max_len: self.max_len,
+            domain_suffixes: (!self.domain_suffixes.is_empty())
+                .then(|| self.domain_suffixes.clone()),
+            deny_domain_suffixes: (!self.deny_domain_suffixes.is_empty())
+                .then(|| self.deny_domain_suffixes.clone()),
+            domain_regex: self.domain_regex.clone(),
+            suffix_regex: self.suffix_regex.clone(),
+            exclude_uuids: None,
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let mut criteria = cli.criteria();
+
+    let in_file = File::open(&cli.input)?;
+    if cli.exclude_outliers {
+        let outliers = clean::read_outlier_uuids(&in_file)?;
+        log::info!("Excluding {} outliers", outliers.len());
+        criteria.exclude_uuids = Some(outliers);
+    }
+    let in_ds = in_file.dataset("/circuits")?;
+    let n_tot_circs = in_ds.size();
+
+    log::info!("Found {n_tot_circs} circuits");
+
+    if let Some(threads) = cli.threads {
+        in_file.close()?;
+        return filter_parallel(&cli, &criteria, n_tot_circs, threads);
+    }
+
+    let out_file = File::create(&cli.output)?;
+    let mut writer = CircuitWriter::<Circuit>::create_like(&out_file, "/circuits", &in_ds)?;
+    let mut index = IndexBuilder::new();
+
+    // Track progress.
+    let pb = pb_new(n_tot_circs, "Filtering circuits".to_string());
+    pb.tick();
+
+    let step = 1_000;
+
+    // The criteria and query may narrow the circuits to read using the stored
+    // indices.
+    let query_candidates = match &cli.query {
+        Some(query) => query.candidates(&in_file)?,
+        None => None,
+    };
+    let candidates = match (criteria.candidates(&in_file)?, query_candidates) {
+        (Some(mut a), Some(b)) => {
+            a.retain(|i| b.binary_search(i).is_ok());
+            Some(a)
+        }
+        (a, None) => a,
+        (None, b) => b,
+    };
+    if let Some(candidates) = &candidates {
+        log::info!(
+            "Indices narrowed the filter to {} candidates",
+            candidates.len()
+        );
+    }
+    let mut next = candidates.as_ref().map(|c| c.iter().peekable());
+
+    for begin in (0..n_tot_circs).step_by(step) {
+        let end = std::cmp::min(begin + step, n_tot_circs);
+
+        // Skip chunks that contain no candidates.
+        if let Some(next) = next.as_mut() {
+            if next.peek().is_none_or(|&&i| i as usize >= end) {
+                pb.inc((end - begin) as u64);
+                continue;
+            }
+            while next.next_if(|&&i| (i as usize) < end).is_some() {}
+        }
+
+        let circ_array: Array1<Circuit> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+        for circuit in circ_array
+            .iter()
+            .filter(|c| criteria.matches(c) && cli.query.as_ref().is_none_or(|q| q.matches(c)))
+        {
+            index.add(writer.len() as CircuitIndex, circuit);
+            writer.push(*circuit)?;
+        }
+
+        pb.inc((end - begin) as u64);
+    }
+
+    let n_kept = writer.len();
+    writer.finish()?;
+    pb.finish();
+
+    log::info!("Kept {n_kept}/{n_tot_circs} circuits, writing indices");
+    index.write_all(&out_file)?;
+
+    out_file.close()?;
+    in_file.close()?;
+
+    Ok(())
+}
+
+fn filter_parallel(
+    cli: &Cli,
+    criteria: &Criteria,
+    n_tot_circs: usize,
+    threads: usize,
+) -> anyhow::Result<()> {
+    log::info!("Filtering circuits on {threads} threads");
+
+    let dataset = Dataset::open(&cli.input)?;
+    let n_kept = dataset.filter_to_file_parallel(
+        |c| criteria.matches(c) && cli.query.as_ref().is_none_or(|q| q.matches(c)),
+        &cli.output,
+        threads,
+    )?;
+    dataset.close()?;
+
+    log::info!("Kept {n_kept}/{n_tot_circs} circuits");
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
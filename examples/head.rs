@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use hdf5::{File, Result};
+use ndarray::{self, Array1};
+
+use gtt23::Circuit;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Print the meta-data and first cells of the first N circuits in an HDF5 file
+pub struct Cli {
+    /// Path to the HDF5 database file
+    #[arg(value_name = "PATH")]
+    pub path: PathBuf,
+    /// HDF5 dataset name containing GTT23 circuits
+    #[arg(short, long, value_name = "NAME", default_value = "circuits")]
+    pub name: String,
+    /// Number of circuits to print
+    #[arg(short = 'n', long, value_name = "N", default_value_t = 10)]
+    pub count: usize,
+    /// Number of cells to print for each circuit
+    #[arg(short, long, value_name = "N", default_value_t = 5)]
+    pub cells: usize,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let file = File::open(cli.path)?;
+    let ds = file.dataset(cli.name.as_str())?;
+    let size = ds.size();
+
+    // Only the selected circuits are read, in bounded batches.
+    let range = 0..std::cmp::min(cli.count, size);
+    let step = 1_000;
+
+    for begin in range.clone().step_by(step) {
+        let end = std::cmp::min(begin + step, range.end);
+
+        let circ_array: Array1<Circuit> = ds.read_slice(ndarray::s![begin..end])?;
+
+        for (i, circuit) in circ_array.iter().enumerate() {
+            println!("{}: {}", begin + i, circuit.preview(cli.cells));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+
+use gtt23::rng::Rng;
+use gtt23::sample;
+use gtt23::stats::{self, IAT_PERCENTILES, IAT_STATS_NAME, IatSummary};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Computes log-binned histograms and percentiles of the times between cells
+/// overall and in each direction for the circuits in an HDF5 file, writes them
+/// to /stats/iat, and prints the percentiles
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Only read this many circuits drawn uniformly at random
+    #[arg(short = 'n', long, value_name = "N")]
+    pub sample: Option<usize>,
+    /// Seed of the random number generator used to draw the sample
+    #[arg(short, long, value_name = "SEED", default_value_t = 0)]
+    pub seed: u64,
+    /// Number of histogram bins per factor of 10 between 1 microsecond and 100
+    /// seconds
+    #[arg(short, long, value_name = "N", default_value_t = 10)]
+    pub bins_per_decade: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let file = File::open_rw(&cli.input)?;
+    let size = file.dataset("/circuits")?.size();
+
+    let selected = cli
+        .sample
+        .map(|n| sample::uniform(size, n, &mut Rng::new(cli.seed)));
+    let count = selected.as_ref().map_or(size, |s| s.len());
+
+    log::info!("Writing inter-arrival time statistics to {IAT_STATS_NAME}");
+    let pb = pb_new(count, "Reading circuits".to_string());
+    let iat_stats = stats::write_iat_stats(&file, cli.bins_per_decade, selected.as_deref(), |n| {
+        pb.inc(n as u64)
+    })?;
+    pb.finish_and_clear();
+    file.close()?;
+
+    log::info!("Read {} circuits", iat_stats.circuits);
+
+    let header: Vec<String> = IAT_PERCENTILES
+        .iter()
+        .map(|p| format!("{:>10}", format!("p{p}")))
+        .collect();
+    println!("{:<18} {:>12} {}", "cells", "count", header.join(" "));
+    let [all, client, server] = iat_stats.summaries();
+    for (name, summary) in [
+        ("all", all),
+        ("client_to_server", client),
+        ("server_to_client", server),
+    ] {
+        println!("{}", row(name, &summary));
+    }
+
+    Ok(())
+}
+
+fn row(name: &str, summary: &IatSummary) -> String {
+    let percentiles: Vec<String> = summary
+        .percentiles
+        .iter()
+        .map(|p| format!("{p:>10.6}"))
+        .collect();
+    format!("{name:<18} {:>12} {}", summary.count, percentiles.join(" "))
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
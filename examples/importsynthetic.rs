@@ -0,0 +1,178 @@
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+use anyhow::{Context, anyhow, bail};
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use serde_json::Value;
+
+use gtt23::import::SyntheticImporter;
+use gtt23::{Cell, CellCommand, Direction, RelayCommand};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Import traces produced by external generators from a jsonl file into the
+/// /augmented dataset of a new HDF5 file, linked to the GTT23 circuits they
+/// were generated from.
+///
+/// Each line is an object holding the uuid of the GTT23 circuit in
+/// "uuid_gtt23" (or "uuid") and the cells in "cells" as arrays of
+/// [time, direction, cell_cmd, relay_cmd], as written by the jsonl export. The
+/// commands may be omitted, in which case cells are relay data cells, and a
+/// direction is taken by its sign so that generators may output floats.
+pub struct Cli {
+    /// Input path to a jsonl file of generated traces
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Path to the hdf5 file containing the GTT23 circuits the traces were
+    /// generated from
+    #[arg(short, long, value_name = "PATH", required = true)]
+    pub gtt23: PathBuf,
+    /// Output path to write the augmented HDF5 file
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        default_value = "./gtt23-synthetic.hdf5"
+    )]
+    pub output: PathBuf,
+    /// Seed for the random number generator used to create the uuids of the
+    /// imported circuits
+    #[arg(short, long, value_name = "SEED", default_value_t = 0)]
+    pub seed: u64,
+    /// Fail on the first invalid trace instead of skipping it
+    #[arg(long)]
+    pub strict: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let gtt23_file = File::open(&cli.gtt23)?;
+    let out_file = File::create(&cli.output)?;
+    let mut importer = SyntheticImporter::create(&out_file, &gtt23_file, cli.seed)?;
+
+    let size = fs::metadata(&cli.input)?.len();
+    let reader = BufReader::new(fs::File::open(&cli.input)?);
+    let pb = pb_new(size as usize, "Importing traces".to_string());
+    let mut skipped = 0;
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        pb.inc(line.len() as u64 + 1);
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let result = parse_trace(&line).and_then(|(uuid_gtt23, cells)| {
+            importer
+                .import(&uuid_gtt23, &cells)?
+                .map_err(|e| anyhow!(e))
+        });
+        if let Err(e) = result {
+            let e = e.context(format!("Line {}", i + 1));
+            if cli.strict {
+                return Err(e);
+            }
+            log::warn!("{e:#}");
+            skipped += 1;
+        }
+    }
+
+    pb.finish();
+    let imported = importer.len();
+    importer.finish()?;
+
+    out_file.close()?;
+    gtt23_file.close()?;
+
+    log::info!("Imported {imported} traces and skipped {skipped} invalid traces");
+
+    Ok(())
+}
+
+/// Parses the GTT23 uuid and the cells of the trace on a jsonl `line`.
+fn parse_trace(line: &str) -> anyhow::Result<(String, Vec<Cell>)> {
+    let root: Value = serde_json::from_str(line).context("Parsing json")?;
+    let uuid = root
+        .get("uuid_gtt23")
+        .or_else(|| root.get("uuid"))
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("Missing uuid_gtt23"))?;
+    let cells = root
+        .get("cells")
+        .and_then(Value::as_array)
+        .ok_or_else(|| anyhow!("Missing cells array"))?;
+
+    let cells = cells
+        .iter()
+        .enumerate()
+        .map(|(i, cell)| parse_cell(cell).with_context(|| format!("Cell {i}")))
+        .collect::<anyhow::Result<Vec<Cell>>>()?;
+    Ok((uuid.to_string(), cells))
+}
+
+fn parse_cell(cell: &Value) -> anyhow::Result<Cell> {
+    let Some(fields) = cell.as_array() else {
+        bail!("Cell is not an array");
+    };
+    if !(2..=4).contains(&fields.len()) {
+        bail!("Cell has {} fields instead of 2 to 4", fields.len());
+    }
+    let number = |k: usize| {
+        fields[k]
+            .as_f64()
+            .ok_or_else(|| anyhow!("Field {k} is not a number"))
+    };
+    let command = |k: usize| match fields.get(k) {
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u8::try_from(v).ok())
+            .map(Some)
+            .ok_or_else(|| anyhow!("Field {k} is not a command")),
+        None => Ok(None),
+    };
+
+    let direction = match number(1)? {
+        d if d > 0.0 => Direction::CLIENT_TO_SERVER,
+        d if d < 0.0 => Direction::SERVER_TO_CLIENT,
+        _ => Direction::PADDING,
+    };
+    let cell_cmd = match command(2)? {
+        Some(v) => CellCommand::try_from(v).map_err(|e| anyhow!(e))?,
+        None => CellCommand::RELAY,
+    };
+    let relay_cmd = match command(3)? {
+        Some(v) => RelayCommand::try_from(v).map_err(|e| anyhow!(e))?,
+        None => RelayCommand::DATA,
+    };
+
+    Ok(Cell {
+        time: number(0)?,
+        direction,
+        cell_cmd,
+        relay_cmd,
+    })
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {bytes}/{total_bytes} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
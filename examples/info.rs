@@ -0,0 +1,123 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use hdf5::types::{TypeDescriptor, VarLenAscii, VarLenUnicode};
+use hdf5::{Attribute, Group, Location, Result};
+
+use gtt23::index::INDEX_NAMES;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Print the structure of a GTT23 HDF5 file: its groups, datasets, types,
+/// chunking, compression, attributes, and indices
+pub struct Cli {
+    /// Path to the HDF5 database file
+    #[arg(value_name = "PATH")]
+    pub path: PathBuf,
+    /// Print the full text of notes instead of only their first line
+    #[arg(short, long)]
+    pub notes: bool,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let file = hdf5::File::open(&cli.path)?;
+    let size = std::fs::metadata(&cli.path).map(|m| m.len()).unwrap_or(0);
+
+    println!("{} ({size} bytes)", cli.path.display());
+    print_attrs(&file, 1, cli.notes)?;
+    print_group(&file, 1, cli.notes)?;
+
+    println!("indices:");
+    let extra = ["uuid_filter", "uuid_gtt23"];
+    for name in INDEX_NAMES.iter().chain(extra.iter()) {
+        let present = file.link_exists(&format!("/index/{name}"));
+        println!("  {name}: {}", if present { "present" } else { "missing" });
+    }
+
+    file.close()?;
+    Ok(())
+}
+
+fn print_group(group: &Group, depth: usize, full_notes: bool) -> Result<()> {
+    let indent = "  ".repeat(depth);
+
+    for dataset in group.datasets()? {
+        println!("{indent}{} (dataset)", dataset.name());
+
+        let dtype = dataset.dtype()?.to_descriptor()?;
+        let chunk = match dataset.chunk() {
+            Some(chunk) => format!("{chunk:?}"),
+            None => "contiguous".to_string(),
+        };
+        println!("{indent}  shape: {:?}", dataset.shape());
+        println!("{indent}  dtype: {}", describe(&dtype));
+        println!("{indent}  chunk: {chunk}");
+        println!("{indent}  filters: {:?}", dataset.filters());
+        println!("{indent}  storage: {} bytes", dataset.storage_size());
+        print_attrs(&dataset, depth + 1, full_notes)?;
+    }
+
+    for child in group.groups()? {
+        println!("{indent}{} (group)", child.name());
+        print_attrs(&child, depth + 1, full_notes)?;
+        print_group(&child, depth + 1, full_notes)?;
+    }
+
+    Ok(())
+}
+
+fn print_attrs(loc: &Location, depth: usize, full_notes: bool) -> Result<()> {
+    let indent = "  ".repeat(depth);
+
+    for name in loc.attr_names()? {
+        let value = attr_value(&loc.attr(&name)?)?;
+        let value = if full_notes {
+            value
+        } else {
+            match value.split_once('\n') {
+                Some((first, _)) => format!("{first} ..."),
+                None => value,
+            }
+        };
+        println!("{indent}@{name}: {value}");
+    }
+
+    Ok(())
+}
+
+/// Formats scalar string and numeric attributes, and describes the type of
+/// any others.
+fn attr_value(attr: &Attribute) -> Result<String> {
+    let dtype = attr.dtype()?.to_descriptor()?;
+    if !attr.is_scalar() {
+        return Ok(format!("<{} {:?}>", describe(&dtype), attr.shape()));
+    }
+
+    Ok(match dtype {
+        TypeDescriptor::VarLenAscii => attr.read_scalar::<VarLenAscii>()?.to_string(),
+        TypeDescriptor::VarLenUnicode => attr.read_scalar::<VarLenUnicode>()?.to_string(),
+        TypeDescriptor::Unsigned(_) => attr.read_scalar::<u64>()?.to_string(),
+        TypeDescriptor::Integer(_) => attr.read_scalar::<i64>()?.to_string(),
+        TypeDescriptor::Float(_) => attr.read_scalar::<f64>()?.to_string(),
+        other => format!("<{}>", describe(&other)),
+    })
+}
+
+/// Describes a type, including the fields of compound types.
+fn describe(dtype: &TypeDescriptor) -> String {
+    match dtype {
+        TypeDescriptor::Compound(compound) => {
+            let fields: Vec<String> = compound
+                .fields
+                .iter()
+                .map(|f| format!("{}: {}", f.name, describe(&f.ty)))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        TypeDescriptor::FixedArray(ty, n) => format!("[{}; {n}]", describe(ty)),
+        TypeDescriptor::VarLenArray(ty) => format!("[{}] (var len)", describe(ty)),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use log::{self, LevelFilter};
+
+use gtt23::stats::{self, LABEL_DAYS_NAME, SINGLE_DAY_LABELS_NAME};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Computes the days on which each label of the circuits in an HDF5 file
+/// appears, writes them to /stats/label_days, and writes the labels appearing
+/// on a single day to /stats/single_day_labels
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Also print every label that appears on a single day and its day
+    #[arg(long)]
+    pub list: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let file = File::open_rw(&cli.input)?;
+
+    log::info!("Writing label days to {LABEL_DAYS_NAME} and {SINGLE_DAY_LABELS_NAME}");
+    let churn = stats::write_label_churn(&file)?;
+    file.close()?;
+
+    let days = churn.days();
+    let single: Vec<&(_, Vec<u8>)> = days.iter().filter(|(_, d)| d.len() == 1).collect();
+    println!("Labels: {}", days.len());
+    println!(
+        "Labels on a single day: {} ({:.2}%)",
+        single.len(),
+        100.0 * single.len() as f64 / days.len().max(1) as f64
+    );
+
+    println!("Labels only on each day:");
+    for (day, count) in churn.single_day_counts() {
+        println!("  {day}: {count}");
+    }
+
+    if cli.list {
+        println!("Single-day labels:");
+        for (label, days) in single {
+            println!("  {label}: {}", days[0]);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use log::{self, LevelFilter};
+
+use gtt23::stats::{self, LABEL_STATS_NAME};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Computes the number of circuits, the number of days, and length statistics
+/// of every label of the circuits in an HDF5 file, and writes them to
+/// /stats/labels
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Also write the statistics to a CSV file at this path
+    #[arg(long, value_name = "PATH")]
+    pub csv: Option<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let file = File::open_rw(&cli.input)?;
+
+    log::info!("Writing label statistics to {LABEL_STATS_NAME}");
+    let label_stats = stats::write_label_stats(&file)?;
+    file.close()?;
+
+    if let Some(path) = &cli.csv {
+        log::info!("Writing label statistics to {}", path.display());
+        stats::write_label_stats_csv(path, &label_stats)?;
+    }
+
+    log::info!("Wrote statistics for {} labels", label_stats.len());
+
+    Ok(())
+}
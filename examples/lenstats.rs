@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use log::{self, LevelFilter};
+
+use gtt23::stats::{self, LEN_PERCENTILES, LEN_STATS_NAME, LenPercentiles};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Computes histograms and percentiles of the lengths of the circuits in an
+/// HDF5 file, overall, per day, and per label, reading only the circuit
+/// lengths, writes them to /stats/len, and prints the percentiles
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Also print the percentiles of each label
+    #[arg(long)]
+    pub labels: bool,
+}
+
+fn row(name: &str, len: &LenPercentiles) -> String {
+    let percentiles: Vec<String> = len.percentiles.iter().map(|p| format!("{p:>5}")).collect();
+    format!("{name:<44} {:>10} {}", len.count, percentiles.join(" "))
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let file = File::open_rw(&cli.input)?;
+
+    log::info!("Writing length statistics to {LEN_STATS_NAME}");
+    let len_stats = stats::write_len_stats(&file)?;
+    file.close()?;
+
+    let header: Vec<String> = LEN_PERCENTILES
+        .iter()
+        .map(|p| format!("{:>5}", format!("p{p}")))
+        .collect();
+    println!("{:<44} {:>10} {}", "group", "count", header.join(" "));
+    println!("{}", row("all", &len_stats.percentiles()));
+    for day in len_stats.day_percentiles() {
+        println!("{}", row(&format!("day {}", day.day), &day.len));
+    }
+    if cli.labels {
+        for label in len_stats.label_percentiles() {
+            println!("{}", row(label.label.as_str(), &label.len));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+
+use gtt23::index::IndexBuilder;
+use gtt23::writer::CircuitWriter;
+use gtt23::{Circuit, CircuitIndex};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Concatenate the circuits of several HDF5 files into a new HDF5 file
+pub struct Cli {
+    /// Input paths to HDF5 files containing circuits datasets
+    #[arg(value_name = "PATH", required = true)]
+    pub input: Vec<PathBuf>,
+    /// Output path to write the merged HDF5 file
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        default_value = "./gtt23-merged.hdf5"
+    )]
+    pub output: PathBuf,
+    /// Offset added to the day of the circuits of each input, in input order
+    /// (e.g., --day-offset 0 --day-offset 7)
+    #[arg(short, long = "day-offset", value_name = "DAYS")]
+    pub day_offsets: Vec<u8>,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    if !cli.day_offsets.is_empty() && cli.day_offsets.len() != cli.input.len() {
+        bail!(
+            "Got {} day offsets for {} inputs",
+            cli.day_offsets.len(),
+            cli.input.len()
+        );
+    }
+
+    let out_file = File::create(&cli.output)?;
+    let mut writer: Option<CircuitWriter<Circuit>> = None;
+    let mut index = IndexBuilder::new();
+
+    let mpb = MultiProgress::new();
+    let pb_main = mpb.add(pb_new(cli.input.len(), "Merging files".to_string()));
+    pb_main.tick();
+
+    for (i, path) in cli.input.iter().enumerate() {
+        let offset = cli.day_offsets.get(i).copied().unwrap_or(0);
+
+        let in_file = File::open(path)?;
+        let in_ds = in_file.dataset("/circuits")?;
+        let size = in_ds.size();
+
+        // The first input determines the chunking and compression.
+        let writer = match writer.as_mut() {
+            Some(w) => w,
+            None => writer.insert(CircuitWriter::create_like(&out_file, "/circuits", &in_ds)?),
+        };
+
+        let pb = mpb.add(pb_new(size, format!("Copying ({})", path.display())));
+        let step = 1_000;
+
+        for begin in (0..size).step_by(step) {
+            let end = std::cmp::min(begin + step, size);
+
+            let circ_array: Array1<Circuit> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+            for circuit in circ_array.iter() {
+                let mut circuit = *circuit;
+                circuit.day = circuit
+                    .day
+                    .checked_add(offset)
+                    .with_context(|| format!("Day overflow in {}", path.display()))?;
+
+                index.add(writer.len() as CircuitIndex, &circuit);
+                writer.push(circuit)?;
+            }
+
+            pb.inc((end - begin) as u64);
+        }
+
+        pb.finish_and_clear();
+        in_file.close()?;
+        pb_main.inc(1);
+    }
+
+    let n_tot_circs = match writer {
+        Some(w) => w.finish()?.size(),
+        None => 0,
+    };
+    pb_main.finish();
+
+    let dups = index.duplicate_uuids();
+    if !dups.is_empty() {
+        out_file.close()?;
+        std::fs::remove_file(&cli.output)?;
+        bail!(
+            "Found {} uuids in more than one circuit, e.g., {}",
+            dups.len(),
+            dups[0]
+        );
+    }
+
+    log::info!("Merged {n_tot_circs} circuits, writing indices");
+    index.write_all(&out_file)?;
+    out_file.close()?;
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
@@ -0,0 +1,221 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::types::VarLenArray;
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+
+use gtt23::index::{self, IndexBuilder};
+use gtt23::rng::Rng;
+use gtt23::world::{self, OpenWorld};
+use gtt23::writer::CircuitWriter;
+use gtt23::{Circuit, CircuitIndex, CircuitMeta, IndexArrayEntry};
+
+/// Marks the circuits that belong to neither set.
+const NOT_SELECTED: u8 = u8::MAX;
+const UNMONITORED: u8 = 0;
+const MONITORED: u8 = 1;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Write an open-world dataset: monitored circuits from the K most frequent
+/// labels, and unmonitored circuits whose labels are never monitored
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Output directory for monitored.hdf5 and unmonitored.hdf5, or the output
+    /// file with --single
+    #[arg(short, long, value_name = "PATH", default_value = "./gtt23-openworld")]
+    pub output: PathBuf,
+    /// Write both sets into one file with a /index/monitored marker index
+    #[arg(long)]
+    pub single: bool,
+    /// Number of monitored labels
+    #[arg(short, long, value_name = "K", default_value_t = 100)]
+    pub k: usize,
+    /// Minimum number of circuits a monitored label must have
+    #[arg(short, long, value_name = "M", default_value_t = 100)]
+    pub min_circuits: usize,
+    /// Number of circuits to keep per monitored label, at most M [default: M]
+    #[arg(short, long, value_name = "N")]
+    pub per_label: Option<usize>,
+    /// Number of unmonitored circuits
+    #[arg(short, long, value_name = "N", default_value_t = 10_000)]
+    pub unmonitored: usize,
+    /// Maximum number of unmonitored circuits per label
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub unmonitored_per_label: usize,
+    /// Seed for the random number generator
+    #[arg(short, long, value_name = "SEED", default_value_t = 0)]
+    pub seed: u64,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let in_file = File::open(&cli.input)?;
+    let in_ds = in_file.dataset("/circuits")?;
+    let size = in_ds.size();
+    let step = 1_000; // multiple of chunk size
+
+    // Only the meta-data is needed to choose the labels.
+    let mut open = OpenWorld::new();
+    let pb = pb_new(size, "Reading labels".to_string());
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        let meta_array: Array1<CircuitMeta> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+        for (i, meta) in meta_array.iter().enumerate() {
+            open.add((begin + i) as CircuitIndex, meta);
+        }
+
+        pb.inc((end - begin) as u64);
+    }
+
+    pb.finish();
+
+    let mut rng = Rng::new(cli.seed);
+    let (monitored, unmonitored) = open.select(
+        cli.k,
+        cli.min_circuits,
+        cli.per_label.unwrap_or(cli.min_circuits),
+        cli.unmonitored,
+        cli.unmonitored_per_label,
+        &mut rng,
+    );
+
+    if monitored.labels.is_empty() {
+        bail!("No labels have at least {} circuits", cli.min_circuits);
+    }
+    log::info!(
+        "Selected {} monitored circuits of {} labels and {} unmonitored circuits of {} labels",
+        monitored.indices.len(),
+        monitored.labels.len(),
+        unmonitored.indices.len(),
+        unmonitored.labels.len()
+    );
+
+    let mut set_of = vec![NOT_SELECTED; size];
+    for &i in monitored.indices.iter() {
+        set_of[i as usize] = MONITORED;
+    }
+    for &i in unmonitored.indices.iter() {
+        set_of[i as usize] = UNMONITORED;
+    }
+
+    // Each set gets its own output, unless writing a single file.
+    let paths = if cli.single {
+        vec![cli.output.clone()]
+    } else {
+        fs::create_dir_all(&cli.output)?;
+        vec![
+            cli.output.join("unmonitored.hdf5"),
+            cli.output.join("monitored.hdf5"),
+        ]
+    };
+
+    let mut out_files = Vec::new();
+    let mut writers = Vec::new();
+    let mut indices = Vec::new();
+
+    for path in paths.iter() {
+        let out_file = File::create(path)?;
+        writers.push(CircuitWriter::<Circuit>::create_like(
+            &out_file,
+            "/circuits",
+            &in_ds,
+        )?);
+        indices.push(IndexBuilder::new());
+        out_files.push(out_file);
+    }
+
+    // The output indices of the circuits of each set, for the marker index.
+    let mut markers: [Vec<CircuitIndex>; 2] = [Vec::new(), Vec::new()];
+
+    let n_selected = monitored.indices.len() + unmonitored.indices.len();
+    let pb = pb_new(n_selected, "Writing circuits".to_string());
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        if set_of[begin..end].iter().all(|&s| s == NOT_SELECTED) {
+            continue;
+        }
+
+        let circ_array: Array1<Circuit> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+        for (i, circuit) in circ_array.iter().enumerate() {
+            let set = set_of[begin + i];
+            if set == NOT_SELECTED {
+                continue;
+            }
+
+            let out = if cli.single { 0 } else { set as usize };
+            let writer = &mut writers[out];
+
+            markers[set as usize].push(writer.len() as CircuitIndex);
+            indices[out].add(writer.len() as CircuitIndex, circuit);
+            writer.push(*circuit)?;
+            pb.inc(1);
+        }
+    }
+
+    pb.finish();
+
+    let labels = [&unmonitored.labels, &monitored.labels];
+    for (i, ((writer, index), out_file)) in writers
+        .into_iter()
+        .zip(indices)
+        .zip(out_files.iter())
+        .enumerate()
+    {
+        let out_ds = writer.finish()?;
+        index.write_all(out_file)?;
+
+        if cli.single {
+            // The vocabulary holds only the monitored labels; every other
+            // circuit belongs to the unmonitored class.
+            world::write_labels(&out_ds, &monitored.labels)?;
+            let marker = [UNMONITORED, MONITORED].map(|value| IndexArrayEntry {
+                value,
+                indexarr: VarLenArray::from_slice(&markers[value as usize]),
+            });
+            index::write_index(out_file, "/index/monitored", &marker)?;
+        } else {
+            world::write_labels(&out_ds, labels[i])?;
+        }
+    }
+
+    for out_file in out_files {
+        out_file.close()?;
+    }
+    in_file.close()?;
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+
+use gtt23::clean::{self, OUTLIERS_NAME, OutlierKind, OutlierOptions};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Flags statistically unusual circuits in an HDF5 file (extreme durations,
+/// many zero times between cells, all cells in one direction, or duplicated
+/// cells) and writes their indices and uuids to /outliers, from which the
+/// filter example can exclude them
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Flag durations more than this many interquartile ranges beyond the
+    /// quartiles of the log durations
+    #[arg(long, value_name = "K", default_value_t = OutlierOptions::default().duration_iqrs)]
+    pub duration_iqrs: f64,
+    /// Flag circuits with at least this fraction of zero times between cells
+    #[arg(long, value_name = "FRACTION", default_value_t = OutlierOptions::default().zero_gap_fraction)]
+    pub zero_gap_fraction: f64,
+    /// Flag circuits with at least this fraction of cells repeating the cell
+    /// before them
+    #[arg(long, value_name = "FRACTION", default_value_t = OutlierOptions::default().duplicate_fraction)]
+    pub duplicate_fraction: f64,
+    /// Only flag circuits with at least this many cells, except for their
+    /// durations
+    #[arg(long, value_name = "N", default_value_t = OutlierOptions::default().min_cells)]
+    pub min_cells: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let file = File::open_rw(&cli.input)?;
+    let size = file.dataset("/circuits")?.size();
+
+    let options = OutlierOptions {
+        duration_iqrs: cli.duration_iqrs,
+        zero_gap_fraction: cli.zero_gap_fraction,
+        duplicate_fraction: cli.duplicate_fraction,
+        min_cells: cli.min_cells,
+    };
+
+    let pb = pb_new(size, "Checking circuits".to_string());
+    let outliers = clean::write_outliers(&file, options, |n| pb.inc(n as u64))?;
+    pb.finish_and_clear();
+    file.close()?;
+
+    let mut counts: BTreeMap<OutlierKind, usize> = BTreeMap::new();
+    for outlier in outliers.iter() {
+        for kind in outlier.kinds() {
+            *counts.entry(kind).or_default() += 1;
+        }
+    }
+    for (kind, count) in counts {
+        log::info!("Found {count} circuits with {kind}");
+    }
+    log::info!(
+        "Wrote {}/{size} outliers to {OUTLIERS_NAME}",
+        outliers.len()
+    );
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
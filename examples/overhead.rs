@@ -0,0 +1,167 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use serde_json::json;
+
+use gtt23::defense::Overhead;
+use gtt23::overhead::{self, OVERHEAD_NAME, OverheadReport, OverheadWriter};
+
+const PERCENTILES: [f64; 5] = [5.0, 25.0, 50.0, 75.0, 95.0];
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Reports the bandwidth and latency overhead of a defended dataset over the
+/// original dataset its circuits were created from
+pub struct Cli {
+    /// Path to an hdf5 file containing the original circuits dataset
+    #[arg(value_name = "ORIGINAL", required = true)]
+    pub original: PathBuf,
+    /// Path to an hdf5 file containing an augmented dataset, or a circuits
+    /// dataset whose circuits keep the uuids of the original circuits
+    #[arg(value_name = "DEFENDED", required = true)]
+    pub defended: PathBuf,
+    /// Store the overhead of each defended circuit in the /overhead dataset of
+    /// the defended file, replacing any existing one
+    #[arg(long)]
+    pub store: bool,
+    /// Print the report as json instead of text
+    #[arg(long)]
+    pub json: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let original = File::open(&cli.original)?;
+    let defended = if cli.store {
+        File::open_rw(&cli.defended)?
+    } else {
+        File::open(&cli.defended)?
+    };
+    let name = if defended.link_exists("/augmented") {
+        "/augmented"
+    } else {
+        "/circuits"
+    };
+    let size = defended.dataset(name)?.size();
+
+    let pb = pb_new(size, "Comparing circuits".to_string());
+    let report = overhead::compare(&original, &defended, |n| pb.inc(n as u64))?;
+    pb.finish_and_clear();
+
+    if report.missing > 0 {
+        log::warn!(
+            "Skipped {} defended circuits whose original circuit was not found",
+            report.missing
+        );
+    }
+
+    if cli.store {
+        if defended.link_exists(OVERHEAD_NAME) {
+            // Note this unlinks but does not reclaim its storage space.
+            defended.unlink(OVERHEAD_NAME)?;
+        }
+        let mut writer = OverheadWriter::create(&defended)?;
+        for record in report.records.iter() {
+            writer.push(*record)?;
+        }
+        writer.finish()?;
+        log::info!("Stored the overhead of {} circuits", report.records.len());
+    }
+
+    original.close()?;
+    defended.close()?;
+
+    if cli.json {
+        print_json(&report)?;
+    } else {
+        print_text(&report);
+    }
+
+    Ok(())
+}
+
+/// Returns the `PERCENTILES` of the per-circuit overheads computed by `f`.
+fn percentiles<F>(report: &OverheadReport, f: F) -> Vec<(f64, f64)>
+where
+    F: Fn(&Overhead) -> f64,
+{
+    let mut values: Vec<f64> = report.records.iter().map(|r| f(&r.overhead())).collect();
+    values.sort_by(|a, b| a.total_cmp(b));
+    if values.is_empty() {
+        return Vec::new();
+    }
+    PERCENTILES
+        .iter()
+        .map(|&p| {
+            let rank = (p / 100.0 * (values.len() - 1) as f64).round() as usize;
+            (p, values[rank])
+        })
+        .collect()
+}
+
+fn print_text(report: &OverheadReport) {
+    println!("Circuits compared: {}", report.records.len());
+    println!("Circuits missing an original: {}", report.missing);
+    println!("Real cells: {}", report.total.real_cells);
+    println!("Dummy cells: {}", report.total.dummy_cells);
+    println!(
+        "Bandwidth overhead: {:.2}%",
+        100.0 * report.total.bandwidth()
+    );
+    println!("Latency overhead: {:.2}%", 100.0 * report.total.latency());
+
+    println!("Per-circuit bandwidth overhead percentiles:");
+    for (p, value) in percentiles(report, |o| o.bandwidth()) {
+        println!("  p{p}: {:.2}%", 100.0 * value);
+    }
+    println!("Per-circuit latency overhead percentiles:");
+    for (p, value) in percentiles(report, |o| o.latency()) {
+        println!("  p{p}: {:.2}%", 100.0 * value);
+    }
+}
+
+fn print_json(report: &OverheadReport) -> anyhow::Result<()> {
+    let to_map = |values: Vec<(f64, f64)>| -> serde_json::Map<String, serde_json::Value> {
+        values
+            .into_iter()
+            .map(|(p, value)| (format!("p{p}"), json!(value)))
+            .collect()
+    };
+
+    let root = json!({
+        "compared": report.records.len(),
+        "missing": report.missing,
+        "real_cells": report.total.real_cells,
+        "dummy_cells": report.total.dummy_cells,
+        "bandwidth": report.total.bandwidth(),
+        "latency": report.total.latency(),
+        "bandwidth_percentiles": to_map(percentiles(report, |o| o.bandwidth())),
+        "latency_percentiles": to_map(percentiles(report, |o| o.latency())),
+    });
+
+    println!("{}", serde_json::to_string_pretty(&root)?);
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use log::{self, LevelFilter};
+
+use gtt23::stats::{self, PORT_LABELS_NAME};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Counts the circuits of each label reached over ports other than 443 in an
+/// HDF5 file, writes the counts to /stats/port_labels, and reports the labels
+/// that are likely not websites
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Flag labels with at least this fraction of their circuits reached over
+    /// ports other than 443
+    #[arg(short, long, value_name = "FRACTION", default_value_t = 0.5)]
+    pub threshold: f64,
+    /// Print at most this many flagged labels
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    pub top: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let file = File::open_rw(&cli.input)?;
+
+    log::info!("Writing port and label counts to {PORT_LABELS_NAME}");
+    let port_labels = stats::write_port_labels(&file)?;
+    file.close()?;
+
+    let label_ports = port_labels.label_ports();
+    println!("Labels: {}", port_labels.labels);
+    println!(
+        "Labels reached over other ports: {} ({:.2}%)",
+        label_ports.len(),
+        100.0 * label_ports.len() as f64 / port_labels.labels.max(1) as f64
+    );
+
+    println!("Ports other than {}:", stats::HTTPS_PORT);
+    for (port, (circuits, labels)) in port_labels.ports() {
+        println!("  {port}: {circuits} circuits, {labels} labels");
+    }
+
+    let flagged = port_labels.flagged(cli.threshold);
+    println!(
+        "Labels with at least {:.0}% of circuits over other ports: {}",
+        100.0 * cli.threshold,
+        flagged.len()
+    );
+    for lp in flagged.iter().take(cli.top) {
+        let ports: Vec<String> = lp
+            .ports
+            .iter()
+            .map(|(port, count)| format!("{port}:{count}"))
+            .collect();
+        println!(
+            "  {}: {}/{} ({:.2}%) ports {}",
+            lp.label,
+            lp.non_https(),
+            lp.circuits,
+            100.0 * lp.non_https_fraction(),
+            ports.join(",")
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+use std::time::Instant;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use log::{self, LevelFilter};
+
+use gtt23::index;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Rebuilds all indices of a GTT23 HDF5 file in one pass and repacks the file
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let start = Instant::now();
+
+    log::info!("Rebuilding indices of {}", cli.input.display());
+    let sizes = index::reindex(&cli.input)?;
+
+    log::info!(
+        "Repacked from {} to {} bytes ({} bytes reclaimed)",
+        sizes.before,
+        sizes.after,
+        sizes.saved()
+    );
+    log::info!("All done in {:?}!", start.elapsed());
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+use std::time::Instant;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use log::{self, LevelFilter};
+
+use gtt23::repack::{self, RepackSizes};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Copies all datasets and attributes of an HDF5 file into a fresh file to
+/// reclaim the space left behind by unlinked datasets (e.g., replaced indices)
+pub struct Cli {
+    /// Input path to an hdf5 file
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Write the repacked file to this path instead of replacing the input
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let start = Instant::now();
+
+    let sizes = match &cli.output {
+        Some(output) => {
+            log::info!("Repacking {} to {}", cli.input.display(), output.display());
+            repack::repack_to(&cli.input, output)?;
+            RepackSizes {
+                before: std::fs::metadata(&cli.input)?.len(),
+                after: std::fs::metadata(output)?.len(),
+            }
+        }
+        None => {
+            log::info!("Repacking {} in place", cli.input.display());
+            repack::repack(&cli.input)?
+        }
+    };
+
+    log::info!(
+        "Repacked from {} to {} bytes ({} bytes reclaimed)",
+        sizes.before,
+        sizes.after,
+        sizes.saved()
+    );
+    log::info!("All done in {:?}!", start.elapsed());
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+
+use gtt23::index::IndexBuilder;
+use gtt23::rng::Rng;
+use gtt23::sample::{self, LabelCapSample, PerLabelSample};
+use gtt23::writer::{AugmentedWriter, CircuitWriter};
+use gtt23::{AugmentedCircuit, Circuit, CircuitIndex, CircuitMeta};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Write a random sample of the circuits in an HDF5 file to a new HDF5 file
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Output path to write the sampled HDF5 file
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        default_value = "./gtt23-sample.hdf5"
+    )]
+    pub output: PathBuf,
+    /// Number of circuits to sample uniformly
+    #[arg(short = 'n', long, value_name = "N", conflicts_with = "fraction")]
+    pub count: Option<usize>,
+    /// Fraction (0-1) of circuits to sample uniformly
+    #[arg(short, long, value_name = "F")]
+    pub fraction: Option<f64>,
+    /// Keep at most this many circuits per label; applied before uniform
+    /// sampling if both are given
+    #[arg(short = 'c', long, value_name = "K")]
+    pub per_label_cap: Option<usize>,
+    /// With --per-label-cap, keep the circuits of each label spread across days
+    /// rather than chosen uniformly at random
+    #[arg(long, requires = "per_label_cap")]
+    pub spread_days: bool,
+    /// Draw exactly this many circuits per label, spread across days, and skip
+    /// labels with fewer circuits
+    #[arg(
+        short = 'p',
+        long,
+        value_name = "N",
+        conflicts_with_all = ["count", "fraction", "per_label_cap"]
+    )]
+    pub per_label: Option<usize>,
+    /// With --per-label, keep labels with fewer circuits by sampling them with
+    /// replacement, writing the repeated circuits to the /augmented dataset
+    #[arg(short, long, requires = "per_label")]
+    pub replace: bool,
+    /// Seed for the random number generator
+    #[arg(short, long, value_name = "SEED", default_value_t = 0)]
+    pub seed: u64,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    if cli.count.is_none()
+        && cli.fraction.is_none()
+        && cli.per_label_cap.is_none()
+        && cli.per_label.is_none()
+    {
+        bail!("Specify at least one of --count, --fraction, --per-label-cap, or --per-label");
+    }
+    if let Some(f) = cli.fraction {
+        if !(0.0..=1.0).contains(&f) {
+            bail!("Fraction {f} is not between 0 and 1");
+        }
+    }
+
+    let in_file = File::open(&cli.input)?;
+    let in_ds = in_file.dataset("/circuits")?;
+    let size = in_ds.size();
+    let step = 1_000; // multiple of chunk size
+
+    let mut rng = Rng::new(cli.seed);
+
+    let (selected, duplicates) = match cli.per_label {
+        Some(n) => sample_per_label(&in_ds, n, cli.replace, &mut rng)?,
+        None => (sample_uniform(&cli, &in_ds, &mut rng)?, Vec::new()),
+    };
+    log::info!("Sampled {} of {size} circuits", selected.len());
+    if cli.replace {
+        log::info!("Writing {} repeated circuits", duplicates.len());
+    }
+
+    let out_file = File::create(&cli.output)?;
+    let mut writer = CircuitWriter::create_like(&out_file, "/circuits", &in_ds)?;
+    // Repeated circuits would repeat uuids in /circuits, so they are written as
+    // augmented copies instead.
+    let mut aug_writer = if cli.replace {
+        Some(AugmentedWriter::create_like(&out_file, &in_ds)?)
+    } else {
+        None
+    };
+    let mut index = IndexBuilder::new();
+
+    let pb = pb_new(
+        selected.len() + duplicates.len(),
+        "Writing sample".to_string(),
+    );
+    let mut next = selected.iter().peekable();
+    let mut next_dup = duplicates.iter().peekable();
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        // Skip chunks that contain no selected circuits.
+        if next.peek().is_none_or(|&&i| i as usize >= end)
+            && next_dup.peek().is_none_or(|&&i| i as usize >= end)
+        {
+            continue;
+        }
+
+        let circ_array: Array1<Circuit> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+        while let Some(&i) = next.next_if(|&&i| (i as usize) < end) {
+            let circuit = &circ_array[i as usize - begin];
+            index.add(writer.len() as CircuitIndex, circuit);
+            writer.push(*circuit)?;
+            pb.inc(1);
+        }
+
+        while let Some(&i) = next_dup.next_if(|&&i| (i as usize) < end) {
+            let circuit = &circ_array[i as usize - begin];
+            if let Some(aug_writer) = aug_writer.as_mut() {
+                // The writer numbers the repeats of each circuit from 0.
+                aug_writer.push(AugmentedCircuit::from_circuit(circuit, rng.uuid(), 0))?;
+            }
+            pb.inc(1);
+        }
+    }
+
+    pb.finish();
+    writer.finish()?;
+    index.write_all(&out_file)?;
+    if let Some(aug_writer) = aug_writer {
+        aug_writer.finish()?;
+    }
+
+    out_file.close()?;
+    in_file.close()?;
+
+    Ok(())
+}
+
+/// Samples uniformly, after applying the per-label cap if requested.
+fn sample_uniform(
+    cli: &Cli,
+    in_ds: &hdf5::Dataset,
+    rng: &mut Rng,
+) -> anyhow::Result<Vec<CircuitIndex>> {
+    let size = in_ds.size();
+    let step = 1_000;
+
+    // Apply the per-label cap first, if requested.
+    let candidates: Vec<CircuitIndex> = match cli.per_label_cap {
+        Some(cap) => {
+            let mut capped = LabelCapSample::new(cap, cli.spread_days);
+            let pb = pb_new(size, "Reading labels".to_string());
+
+            for begin in (0..size).step_by(step) {
+                let end = std::cmp::min(begin + step, size);
+
+                let meta_array: Array1<CircuitMeta> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+                for (i, meta) in meta_array.iter().enumerate() {
+                    capped.add((begin + i) as CircuitIndex, meta);
+                }
+
+                pb.inc((end - begin) as u64);
+            }
+
+            pb.finish();
+            capped.select(rng)
+        }
+        None => (0..size as CircuitIndex).collect(),
+    };
+
+    let n = match (cli.count, cli.fraction) {
+        (Some(n), _) => n,
+        (None, Some(f)) => (f * candidates.len() as f64).round() as usize,
+        (None, None) => candidates.len(),
+    };
+    Ok(sample::uniform_from(&candidates, n, rng))
+}
+
+/// Samples exactly `n` circuits per label, reading only the meta-data. Returns
+/// the distinct circuits and, if sampling with replacement, the repeats.
+fn sample_per_label(
+    in_ds: &hdf5::Dataset,
+    n: usize,
+    replace: bool,
+    rng: &mut Rng,
+) -> anyhow::Result<(Vec<CircuitIndex>, Vec<CircuitIndex>)> {
+    let size = in_ds.size();
+    let step = 1_000;
+
+    let mut per_label = PerLabelSample::new(n, replace);
+    let pb = pb_new(size, "Reading labels".to_string());
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        let meta_array: Array1<CircuitMeta> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+        for (i, meta) in meta_array.iter().enumerate() {
+            per_label.add((begin + i) as CircuitIndex, meta);
+        }
+
+        pb.inc((end - begin) as u64);
+    }
+
+    pb.finish();
+
+    let plan = per_label.select(rng);
+    if plan.skipped > 0 {
+        log::info!(
+            "Skipped {} labels with fewer than {n} circuits",
+            plan.skipped
+        );
+    }
+    Ok((plan.keep, plan.duplicates))
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
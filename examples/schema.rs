@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+
+use gtt23::schema::{self, SCHEMA_V1, SCHEMA_V2};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Prints the schema version of the circuits dataset of an HDF5 file, or copies
+/// its circuits to a new file as extended (version 2) or plain (version 1)
+/// records
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "INPUT", required = true)]
+    pub input: PathBuf,
+    /// Output path of the new hdf5 file; only the version is printed if absent
+    #[arg(value_name = "OUTPUT")]
+    pub output: Option<PathBuf>,
+    /// The schema version of the output circuits
+    #[arg(short = 'v', long, value_name = "VERSION", default_value_t = SCHEMA_V2)]
+    pub to_version: u32,
+    /// Set the measurement run ID of every extended circuit that has none
+    #[arg(long, value_name = "ID")]
+    pub run_id: Option<u32>,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let src = File::open(&cli.input)?;
+    let circuits = src.dataset("/circuits")?;
+    let version = schema::schema_version(&circuits)?;
+    println!("Schema version: {version}");
+
+    let Some(output) = &cli.output else {
+        return Ok(());
+    };
+
+    let dst = File::create(output)?;
+    let size = circuits.size();
+    log::info!(
+        "Writing {size} circuits as schema version {} to {}",
+        cli.to_version,
+        output.display()
+    );
+
+    let pb = pb_new(size, "Converting circuits".to_string());
+    match cli.to_version {
+        SCHEMA_V1 => schema::write_v1(&src, &dst, |n| pb.inc(n as u64))?,
+        SCHEMA_V2 => schema::write_extended(
+            &src,
+            &dst,
+            |c| {
+                let mut fields = c.fields();
+                fields.run_id = fields.run_id.or(cli.run_id);
+                fields
+            },
+            |n| pb.inc(n as u64),
+        )?,
+        v => anyhow::bail!("Unsupported schema version {v}"),
+    };
+    pb.finish();
+
+    src.close()?;
+    dst.close()?;
+    log::info!("The new file has no indices; run the writeindex example to index it");
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
@@ -0,0 +1,293 @@
+use std::fs::{self, File as FsFile};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+
+use gtt23::index::IndexBuilder;
+use gtt23::split::{self, DaySplit, LabelSplit, StratifiedSplit};
+use gtt23::writer::CircuitWriter;
+use gtt23::{Circuit, CircuitIndex, CircuitMeta, Dataset};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Split a circuits dataset into stratified-by-label parts (e.g., train,
+/// validation, and test), or into parts of whole days or whole labels, and
+/// write each part to its own HDF5 file
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Output directory for the part files and the manifest
+    #[arg(short, long, value_name = "DIR", default_value = "./gtt23-split")]
+    pub output: PathBuf,
+    /// Relative sizes of the parts, separated by colons
+    #[arg(short, long, value_name = "RATIOS", default_value = "8:1:1")]
+    pub ratios: String,
+    /// Names of the parts, separated by commas; must match the number of ratios
+    #[arg(
+        short,
+        long,
+        value_name = "NAMES",
+        default_value = "train,validation,test"
+    )]
+    pub names: String,
+    /// Instead of ratios, assign whole days to the parts: groups of days
+    /// separated by colons, each a comma separated list of days or day ranges
+    /// (e.g., 1-6:7-8); circuits of other days are left out
+    #[arg(short, long, value_name = "DAYS")]
+    pub days: Option<String>,
+    /// Split the labels rather than the circuits of each label by the ratios,
+    /// so that no label appears in more than one part; also writes a
+    /// labels.csv manifest of the part assigned to each label
+    #[arg(short = 'l', long, conflicts_with = "days")]
+    pub by_label: bool,
+    /// Seed for the random number generator
+    #[arg(short, long, value_name = "SEED", default_value_t = 0)]
+    pub seed: u64,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let ratios = cli
+        .ratios
+        .split(':')
+        .map(|r| r.trim().parse::<u32>())
+        .collect::<Result<Vec<u32>, _>>()
+        .with_context(|| format!("Invalid ratios '{}'", cli.ratios))?;
+    let names: Vec<&str> = cli.names.split(',').map(|n| n.trim()).collect();
+    let days = match &cli.days {
+        Some(days) => Some(
+            split::parse_day_groups(days)
+                .map_err(anyhow::Error::msg)
+                .with_context(|| format!("Invalid days '{days}'"))?,
+        ),
+        None => None,
+    };
+
+    if let Some(days) = &days {
+        if days.len() != names.len() {
+            bail!("Got {} day groups but {} names", days.len(), names.len());
+        }
+    } else if ratios.len() != names.len() {
+        bail!("Got {} ratios but {} names", ratios.len(), names.len());
+    } else if ratios.iter().all(|&r| r == 0) {
+        bail!("At least one ratio must be positive");
+    }
+
+    let in_file = File::open(&cli.input)?;
+    let in_ds = in_file.dataset("/circuits")?;
+    let size = in_ds.size();
+    let step = 1_000; // multiple of chunk size
+
+    let parts = match &days {
+        Some(days) => split_days(&in_file, &in_ds, days)?,
+        None if cli.by_label => split_labels(&cli, &in_file, &in_ds, &ratios, &names)?,
+        None => split_stratified(&in_file, &ratios, cli.seed)?,
+    };
+
+    let mut part_of = vec![None; size];
+    for (part, indices) in parts.iter().enumerate() {
+        log::info!("Part {}: {} circuits", names[part], indices.len());
+        for &index in indices {
+            part_of[index as usize] = Some(part);
+        }
+    }
+
+    // Second pass: copy each circuit into its part.
+    fs::create_dir_all(&cli.output)?;
+
+    let mut out_files = Vec::new();
+    let mut writers = Vec::new();
+    let mut indices = Vec::new();
+
+    for name in names.iter() {
+        let out_file = File::create(cli.output.join(format!("{name}.hdf5")))?;
+        writers.push(CircuitWriter::create_like(&out_file, "/circuits", &in_ds)?);
+        indices.push(IndexBuilder::new());
+        out_files.push(out_file);
+    }
+
+    let mut manifest = BufWriter::new(FsFile::create(cli.output.join("manifest.csv"))?);
+    writeln!(manifest, "uuid,label,split")?;
+
+    let pb = pb_new(size, "Writing parts".to_string());
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        let circ_array: Array1<Circuit> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+        for (i, circuit) in circ_array.iter().enumerate() {
+            let Some(part) = part_of[begin + i] else {
+                continue;
+            };
+            let writer = &mut writers[part];
+
+            indices[part].add(writer.len() as CircuitIndex, circuit);
+            writer.push(*circuit)?;
+            writeln!(
+                manifest,
+                "{},{},{}",
+                circuit.uuid,
+                circuit.label(),
+                names[part]
+            )?;
+        }
+
+        pb.inc((end - begin) as u64);
+    }
+
+    pb.finish();
+    manifest.flush()?;
+
+    for ((writer, index), out_file) in writers.into_iter().zip(indices).zip(out_files) {
+        writer.finish()?;
+        index.write_all(&out_file)?;
+        out_file.close()?;
+    }
+
+    in_file.close()?;
+    log::info!("Wrote {} parts to {}", names.len(), cli.output.display());
+
+    Ok(())
+}
+
+/// Groups the circuits by label and splits each label by the ratios, reading
+/// only the circuit meta-data.
+fn split_stratified(
+    in_file: &File,
+    ratios: &[u32],
+    seed: u64,
+) -> anyhow::Result<Vec<Vec<CircuitIndex>>> {
+    let dataset = Dataset::from_file(in_file.clone());
+    let size = dataset.circuits()?.size();
+
+    let mut split = StratifiedSplit::new(ratios, seed);
+    let pb = pb_new(size, "Reading labels".to_string());
+
+    for item in dataset.iter_meta()? {
+        let (index, meta) = item?;
+        split.add(index, &meta);
+        pb.inc(1);
+    }
+
+    pb.finish();
+    Ok(split.assign())
+}
+
+/// Assigns whole labels to parts by the ratios, using the label index if the
+/// file has one and reading the circuit meta-data otherwise, and writes the
+/// labels manifest.
+fn split_labels(
+    cli: &Cli,
+    in_file: &File,
+    in_ds: &hdf5::Dataset,
+    ratios: &[u32],
+    names: &[&str],
+) -> anyhow::Result<Vec<Vec<CircuitIndex>>> {
+    let mut split = LabelSplit::new(ratios, cli.seed);
+
+    if !split.add_from_index(in_file)? {
+        log::info!("No label index found; reading labels from the circuits");
+
+        let size = in_ds.size();
+        let step = 1_000;
+        let pb = pb_new(size, "Reading labels".to_string());
+
+        for begin in (0..size).step_by(step) {
+            let end = std::cmp::min(begin + step, size);
+
+            let meta_array: Array1<CircuitMeta> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+            for (i, meta) in meta_array.iter().enumerate() {
+                split.add((begin + i) as CircuitIndex, meta);
+            }
+
+            pb.inc((end - begin) as u64);
+        }
+
+        pb.finish();
+    }
+
+    let label_parts = split.label_parts();
+    fs::create_dir_all(&cli.output)?;
+    let mut manifest = BufWriter::new(FsFile::create(cli.output.join("labels.csv"))?);
+    writeln!(manifest, "label,split")?;
+    for (label, part) in label_parts.iter() {
+        writeln!(manifest, "{},{}", label, names[*part])?;
+    }
+    manifest.flush()?;
+
+    for (part, name) in names.iter().enumerate() {
+        let n_labels = label_parts.iter().filter(|(_, p)| *p == part).count();
+        log::info!("Part {name}: {n_labels} labels");
+    }
+
+    Ok(split.assign())
+}
+
+/// Assigns the circuits to parts by day, using the day index if the file has
+/// one and reading the circuit meta-data otherwise.
+fn split_days(
+    in_file: &File,
+    in_ds: &hdf5::Dataset,
+    days: &[Vec<u8>],
+) -> anyhow::Result<Vec<Vec<CircuitIndex>>> {
+    let mut split = DaySplit::new(days).map_err(anyhow::Error::msg)?;
+
+    if !split.add_from_index(in_file)? {
+        log::info!("No day index found; reading days from the circuits");
+
+        let size = in_ds.size();
+        let step = 1_000;
+        let pb = pb_new(size, "Reading days".to_string());
+
+        for begin in (0..size).step_by(step) {
+            let end = std::cmp::min(begin + step, size);
+
+            let meta_array: Array1<CircuitMeta> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+            for (i, meta) in meta_array.iter().enumerate() {
+                split.add((begin + i) as CircuitIndex, meta);
+            }
+
+            pb.inc((end - begin) as u64);
+        }
+
+        pb.finish();
+    }
+
+    if split.unassigned() > 0 {
+        log::info!(
+            "Leaving out {} circuits of days not in any part",
+            split.unassigned()
+        );
+    }
+    Ok(split.assign())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
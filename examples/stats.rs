@@ -0,0 +1,236 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use serde_json::json;
+
+use gtt23::stats::{self, IMBALANCE_HEAD_FRACTIONS, IMBALANCE_TAIL_COUNTS, Stats};
+
+const PERCENTILES: [f64; 7] = [1.0, 5.0, 25.0, 50.0, 75.0, 95.0, 99.0];
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Prints summary statistics of an HDF5 dataset of GTT23 circuits
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Number of most frequent labels to report
+    #[arg(short, long, value_name = "K", default_value_t = 10)]
+    pub top: usize,
+    /// Print the statistics as json instead of text
+    #[arg(long)]
+    pub json: bool,
+    /// Also break down the circuits per port and per service category,
+    /// cross-tabulated with day
+    #[arg(long)]
+    pub ports: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let file = File::open(&cli.input)?;
+    let size = file.dataset("/circuits")?.size();
+
+    let pb = pb_new(size, "Computing stats".to_string());
+    let stats = stats::summary_stats(&file, |n| pb.inc(n as u64))?;
+    pb.finish_and_clear();
+    file.close()?;
+
+    if cli.json {
+        print_json(&stats, cli.top, cli.ports)?;
+    } else {
+        print_text(&stats, cli.top);
+        if cli.ports {
+            print_ports(&stats);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_text(stats: &Stats, top: usize) {
+    println!("Total circuits: {}", stats.total());
+    println!("Distinct labels: {}", stats.num_labels());
+
+    println!("Circuits per day:");
+    for (day, count) in stats.days() {
+        println!("  {day}: {count}");
+    }
+
+    println!("Top {top} labels:");
+    for (label, count) in stats.top_labels(top) {
+        println!("  {label}: {count}");
+    }
+
+    let imbalance = stats.imbalance();
+    println!("Label imbalance:");
+    println!("  Gini coefficient: {:.4}", imbalance.gini);
+    println!("  Entropy: {:.4} bits", imbalance.entropy);
+    println!("  Effective classes: {:.2}", imbalance.effective_classes);
+    println!(
+        "  Circuits per label: {} to {}",
+        imbalance.min_count, imbalance.max_count
+    );
+    for (fraction, labels) in IMBALANCE_HEAD_FRACTIONS.iter().zip(imbalance.head) {
+        println!(
+            "  Labels holding {:.0}% of circuits: {labels}",
+            100.0 * fraction
+        );
+    }
+    for (count, labels) in IMBALANCE_TAIL_COUNTS.iter().zip(imbalance.tail) {
+        println!("  Labels with at most {count} circuits: {labels}");
+    }
+
+    println!("Length percentiles:");
+    for p in PERCENTILES {
+        if let Some(len) = stats.len_percentile(p) {
+            println!("  p{p}: {len}");
+        }
+    }
+
+    println!("Circuits per port:");
+    for (port, count) in stats.ports() {
+        println!("  {port}: {count}");
+    }
+}
+
+/// Prints the number of circuits of each port and service category on each
+/// day, one row per port or category and one column per day.
+fn print_ports(stats: &Stats) {
+    let days: Vec<u8> = stats.days().keys().copied().collect();
+    let header: Vec<String> = days
+        .iter()
+        .map(|d| format!("{:>10}", format!("day {d}")))
+        .collect();
+
+    println!("Circuits per port and day:");
+    println!("  {:>8} {:>10} {}", "port", "total", header.join(" "));
+    for (&port, &total) in stats.ports() {
+        let counts: Vec<String> = days
+            .iter()
+            .map(|&d| format!("{:>10}", stats.port_days().get(&(port, d)).unwrap_or(&0)))
+            .collect();
+        println!("  {port:>8} {total:>10} {}", counts.join(" "));
+    }
+
+    let service_days = stats.service_days();
+    println!("Circuits per service category and day:");
+    println!("  {:>8} {:>10} {}", "service", "total", header.join(" "));
+    for (service, total) in stats.services() {
+        let counts: Vec<String> = days
+            .iter()
+            .map(|&d| {
+                let count = service_days
+                    .iter()
+                    .find(|&&(s, day, _)| s == service && day == d)
+                    .map_or(0, |&(_, _, count)| count);
+                format!("{count:>10}")
+            })
+            .collect();
+        println!(
+            "  {:>8} {total:>10} {}",
+            format!("{service:?}"),
+            counts.join(" ")
+        );
+    }
+}
+
+fn print_json(stats: &Stats, top: usize, ports_by_day: bool) -> anyhow::Result<()> {
+    let days: serde_json::Map<String, serde_json::Value> = stats
+        .days()
+        .iter()
+        .map(|(day, count)| (day.to_string(), json!(count)))
+        .collect();
+    let labels: Vec<serde_json::Value> = stats
+        .top_labels(top)
+        .into_iter()
+        .map(|(label, count)| json!({"label": label, "count": count}))
+        .collect();
+    let percentiles: serde_json::Map<String, serde_json::Value> = PERCENTILES
+        .iter()
+        .map(|p| (format!("p{p}"), json!(stats.len_percentile(*p))))
+        .collect();
+    let imbalance = stats.imbalance();
+    let head: serde_json::Map<String, serde_json::Value> = IMBALANCE_HEAD_FRACTIONS
+        .iter()
+        .zip(imbalance.head)
+        .map(|(fraction, labels)| (fraction.to_string(), json!(labels)))
+        .collect();
+    let tail: serde_json::Map<String, serde_json::Value> = IMBALANCE_TAIL_COUNTS
+        .iter()
+        .zip(imbalance.tail)
+        .map(|(count, labels)| (count.to_string(), json!(labels)))
+        .collect();
+    let ports: serde_json::Map<String, serde_json::Value> = stats
+        .ports()
+        .iter()
+        .map(|(port, count)| (port.to_string(), json!(count)))
+        .collect();
+
+    let mut root = json!({
+        "total": stats.total(),
+        "num_labels": stats.num_labels(),
+        "days": days,
+        "top_labels": labels,
+        "imbalance": {
+            "min_count": imbalance.min_count,
+            "max_count": imbalance.max_count,
+            "gini": imbalance.gini,
+            "entropy": imbalance.entropy,
+            "effective_classes": imbalance.effective_classes,
+            "head": head,
+            "tail": tail,
+        },
+        "len_percentiles": percentiles,
+        "ports": ports,
+    });
+
+    if ports_by_day {
+        let mut port_days = serde_json::Map::new();
+        for (&(port, day), &count) in stats.port_days() {
+            let days = port_days.entry(port.to_string()).or_insert(json!({}));
+            days[day.to_string()] = json!(count);
+        }
+        let services: serde_json::Map<String, serde_json::Value> = stats
+            .services()
+            .into_iter()
+            .map(|(service, count)| (format!("{service:?}"), json!(count)))
+            .collect();
+        let mut service_days = serde_json::Map::new();
+        for (service, day, count) in stats.service_days() {
+            let days = service_days
+                .entry(format!("{service:?}"))
+                .or_insert(json!({}));
+            days[day.to_string()] = json!(count);
+        }
+        root["port_days"] = json!(port_days);
+        root["services"] = json!(services);
+        root["service_days"] = json!(service_days);
+    }
+
+    println!("{}", serde_json::to_string_pretty(&root)?);
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
@@ -0,0 +1,153 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+
+use gtt23::index::IndexBuilder;
+use gtt23::transform::{self, CircuitTransform, DropCells, TimeScale, TimeWindow, TrimCells};
+use gtt23::writer::CircuitWriter;
+use gtt23::{CellCommand, Circuit, CircuitIndex, RelayCommand};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Copy the circuits into a new HDF5 file after transforming their cells
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Output path to write the transformed HDF5 file
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        default_value = "./gtt23-transformed.hdf5"
+    )]
+    pub output: PathBuf,
+    /// Multiply the time between consecutive cells by this factor to simulate
+    /// a slower (> 1) or faster (< 1) path (applied before other transforms)
+    #[arg(long, value_name = "FACTOR")]
+    pub time_scale: Option<f64>,
+    /// Like --time-scale, but only for the gaps before client-to-server cells
+    #[arg(long, value_name = "FACTOR", conflicts_with = "time_scale")]
+    pub client_time_scale: Option<f64>,
+    /// Like --time-scale, but only for the gaps before server-to-client cells
+    #[arg(long, value_name = "FACTOR", conflicts_with = "time_scale")]
+    pub server_time_scale: Option<f64>,
+    /// Remove cells with this cell command, given by name or value (may be
+    /// repeated, e.g., --drop-cell PADDING --drop-cell VPADDING)
+    #[arg(long, value_name = "CMD", value_parser = transform::parse_cell_command)]
+    pub drop_cell: Vec<CellCommand>,
+    /// Remove cells with this relay command, given by name or value (may be
+    /// repeated, e.g., --drop-relay SENDME --drop-relay DROP)
+    #[arg(long, value_name = "CMD", value_parser = transform::parse_relay_command)]
+    pub drop_relay: Vec<RelayCommand>,
+    /// Truncate circuits to their first N cells (applied after dropping cells)
+    #[arg(long, value_name = "N")]
+    pub trim: Option<u16>,
+    /// Keep only the cells observed within this many seconds of the first cell
+    /// (applied after dropping cells and before trimming)
+    #[arg(long, value_name = "SECONDS")]
+    pub window: Option<f64>,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let mut transforms: Vec<Box<dyn CircuitTransform>> = Vec::new();
+    let scale = match (cli.time_scale, cli.client_time_scale, cli.server_time_scale) {
+        (Some(factor), _, _) => Some(TimeScale::new(factor)),
+        (None, None, None) => None,
+        (None, client, server) => Some(TimeScale::per_direction(
+            client.unwrap_or(1.0),
+            server.unwrap_or(1.0),
+        )),
+    };
+    if let Some(scale) = scale {
+        if !(scale.client > 0.0 && scale.server > 0.0) {
+            bail!("Time scale factors must be positive");
+        }
+        transforms.push(Box::new(scale));
+    }
+    if !cli.drop_cell.is_empty() || !cli.drop_relay.is_empty() {
+        transforms.push(Box::new(DropCells::new(
+            cli.drop_cell.clone(),
+            cli.drop_relay.clone(),
+        )));
+    }
+    if let Some(seconds) = cli.window {
+        if seconds.is_nan() || seconds < 0.0 {
+            bail!("Window {seconds} must not be negative");
+        }
+        transforms.push(Box::new(TimeWindow::new(seconds)));
+    }
+    if let Some(n) = cli.trim {
+        transforms.push(Box::new(TrimCells::new(n)));
+    }
+    if transforms.is_empty() {
+        bail!("Specify at least one transform");
+    }
+
+    let in_file = File::open(&cli.input)?;
+    let in_ds = in_file.dataset("/circuits")?;
+    let size = in_ds.size();
+    let step = 1_000; // multiple of chunk size
+
+    let out_file = File::create(&cli.output)?;
+    let mut writer = CircuitWriter::create_like(&out_file, "/circuits", &in_ds)?;
+    let mut index = IndexBuilder::new();
+    let (mut cells_before, mut cells_after) = (0u64, 0u64);
+
+    let pb = pb_new(size, "Transforming circuits".to_string());
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        let circ_array: Array1<Circuit> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+        for circuit in circ_array.iter() {
+            let mut circuit = *circuit;
+            cells_before += circuit.len as u64;
+            transforms.apply(&mut circuit);
+            cells_after += circuit.len as u64;
+
+            index.add(writer.len() as CircuitIndex, &circuit);
+            writer.push(circuit)?;
+        }
+
+        pb.inc((end - begin) as u64);
+    }
+
+    pb.finish();
+    writer.finish()?;
+    index.write_all(&out_file)?;
+
+    out_file.close()?;
+    in_file.close()?;
+
+    log::info!("Kept {cells_after}/{cells_before} cells in {size} circuits");
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
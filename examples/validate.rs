@@ -0,0 +1,107 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+use serde_json::json;
+
+use gtt23::validate::{self, RawCircuit, Report};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Checks that the circuits and indices in an HDF5 file satisfy the dataset
+/// invariants
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Maximum number of example violations to report for each kind
+    #[arg(short, long, value_name = "N", default_value_t = 10)]
+    pub max_examples: usize,
+    /// Print the report as JSON to stdout
+    #[arg(short, long)]
+    pub json: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let file = File::open(&cli.input)?;
+    let dataset = file.dataset("/circuits")?;
+    let size = dataset.size();
+    let step = 1_000; // multiple of chunk size
+
+    let mut report = Report::new(cli.max_examples);
+    let pb = pb_new(size, "Validating circuits".to_string());
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        let circuits: Array1<RawCircuit> = dataset.read_slice(ndarray::s![begin..end])?;
+
+        for (i, circuit) in circuits.iter().enumerate() {
+            report.extend(validate::check_circuit(begin + i, circuit));
+        }
+
+        pb.inc((end - begin) as u64);
+    }
+
+    pb.finish();
+
+    report.extend(validate::check_indices(&file, size)?);
+    file.close()?;
+
+    if cli.json {
+        let violations: Vec<_> = report
+            .counts()
+            .iter()
+            .map(|(kind, count)| {
+                let examples: Vec<_> = report
+                    .examples(*kind)
+                    .iter()
+                    .map(|v| json!({"index": v.index, "detail": v.detail}))
+                    .collect();
+                json!({"kind": kind.as_str(), "count": count, "examples": examples})
+            })
+            .collect();
+        let out = json!({"circuits": size, "violations": violations});
+        println!("{}", serde_json::to_string_pretty(&out)?);
+    } else {
+        for (kind, count) in report.counts() {
+            log::warn!("{count} {kind} violations");
+            for v in report.examples(*kind) {
+                log::warn!("  at {}: {}", v.index, v.detail);
+            }
+        }
+    }
+
+    if !report.is_empty() {
+        let total: usize = report.counts().values().sum();
+        bail!("Found {total} violations in {size} circuits");
+    }
+
+    log::info!("All {size} circuits and indices are valid");
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+
+use gtt23::variability::{self, LabelVariability, VARIABILITY_NAME, VariabilityOptions};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Measures how much the circuits of each label in an HDF5 file differ from
+/// each other (the mean pairwise edit distance of their direction sequences
+/// and the entropy of their burst patterns), writes the metrics to
+/// /stats/variability, and prints the most and least variable labels
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Sample at most this many circuits of each label
+    #[arg(short = 'n', long, value_name = "N", default_value_t = VariabilityOptions::default().max_circuits)]
+    pub max_circuits: usize,
+    /// Only measure labels with at least this many circuits
+    #[arg(long, value_name = "N", default_value_t = VariabilityOptions::default().min_circuits)]
+    pub min_circuits: usize,
+    /// Compare only this many of the first cells of each circuit, or all cells
+    /// if 0
+    #[arg(short = 'm', long, value_name = "CELLS", default_value_t = 500)]
+    pub max_cells: usize,
+    /// Number of bursts in each burst pattern
+    #[arg(long, value_name = "N", default_value_t = VariabilityOptions::default().pattern_bursts)]
+    pub pattern_bursts: usize,
+    /// Seed of the random number generator used to sample circuits
+    #[arg(short, long, value_name = "SEED", default_value_t = 0)]
+    pub seed: u64,
+    /// Number of most and least variable labels to print
+    #[arg(short, long, value_name = "K", default_value_t = 10)]
+    pub top: usize,
+    /// Number of threads computing the metrics
+    #[arg(short = 'j', long, value_name = "N", default_value_t = 1)]
+    pub threads: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let file = File::open_rw(&cli.input)?;
+
+    let options = VariabilityOptions {
+        max_circuits: cli.max_circuits,
+        min_circuits: cli.min_circuits,
+        max_cells: (cli.max_cells > 0).then_some(cli.max_cells),
+        pattern_bursts: cli.pattern_bursts,
+        threads: cli.threads,
+    };
+
+    log::info!("Writing label variability to {VARIABILITY_NAME}");
+    let pb = pb_new(0, "Measuring labels".to_string());
+    let mut metrics =
+        variability::write_label_variability(&file, &options, cli.seed, |done, total| {
+            pb.set_length(total as u64);
+            pb.set_position(done as u64);
+        })?;
+    pb.finish_and_clear();
+    file.close()?;
+
+    log::info!("Measured {} labels", metrics.len());
+
+    metrics.sort_by(|a, b| b.mean_distance.total_cmp(&a.mean_distance));
+    println!("Most variable labels:");
+    print_rows(metrics.iter().take(cli.top));
+    println!("Least variable labels:");
+    print_rows(metrics.iter().rev().take(cli.top));
+
+    Ok(())
+}
+
+fn print_rows<'a>(metrics: impl Iterator<Item = &'a LabelVariability>) {
+    println!(
+        "  {:<44} {:>8} {:>8} {:>10} {:>10} {:>10}",
+        "label", "count", "sampled", "distance", "entropy", "norm_ent"
+    );
+    for m in metrics {
+        println!(
+            "  {:<44} {:>8} {:>8} {:>10.4} {:>10.4} {:>10.4}",
+            m.label.as_str(),
+            m.count,
+            m.sampled,
+            m.mean_distance,
+            m.burst_entropy,
+            m.normalized_burst_entropy
+        );
+    }
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+
+use gtt23::varlen::{self, VARLEN_NAME};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Converts the fixed-length circuits of an HDF5 file to variable-length
+/// records holding only their valid cells, which are written to
+/// /circuits_varlen of a new file, or converts them back to /circuits
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset, or a
+    /// circuits_varlen dataset with --reverse
+    #[arg(value_name = "INPUT", required = true)]
+    pub input: PathBuf,
+    /// Output path of the new hdf5 file
+    #[arg(value_name = "OUTPUT", required = true)]
+    pub output: PathBuf,
+    /// Convert variable-length records back to fixed-length circuits
+    #[arg(short, long)]
+    pub reverse: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let src = File::open(&cli.input)?;
+    let dst = File::create(&cli.output)?;
+
+    let (from, to) = if cli.reverse {
+        (VARLEN_NAME, "/circuits")
+    } else {
+        ("/circuits", VARLEN_NAME)
+    };
+    let size = src.dataset(from)?.size();
+    log::info!(
+        "Converting {size} circuits from {from} of {} to {to} of {}",
+        cli.input.display(),
+        cli.output.display()
+    );
+
+    let pb = pb_new(size, "Converting circuits".to_string());
+    let n = if cli.reverse {
+        varlen::write_fixed(&src, &dst, |n| pb.inc(n as u64))?
+    } else {
+        varlen::write_varlen(&src, &dst, |n| pb.inc(n as u64))?
+    };
+    pb.finish();
+
+    src.close()?;
+    dst.close()?;
+
+    let before = std::fs::metadata(&cli.input)?.len();
+    let after = std::fs::metadata(&cli.output)?.len();
+    log::info!("Converted {n} circuits from {before} to {after} bytes");
+    if cli.reverse {
+        log::info!("The circuits are uncompressed; run the writeindex example to index them");
+    }
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
@@ -0,0 +1,176 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+
+use gtt23::index::{AugmentedIndexBuilder, INDEX_NAMES, IndexBuilder, IndexDiff, LABELS_NAME};
+use gtt23::{AugmentedCircuit, CircuitIndex, CircuitMeta};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Verifies that the indices stored in an HDF5 file match its circuits dataset
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset and indices
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Maximum number of example values to report for each kind of problem
+    #[arg(short, long, value_name = "N", default_value_t = 10)]
+    pub max_examples: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let file = File::open(&cli.input)?;
+    let dataset = file.dataset("/circuits")?;
+    let size = dataset.size();
+    let step = 1_000; // multiple of chunk size
+
+    // Recompute the indices in a streaming pass over the circuits.
+    let mut builder = IndexBuilder::new();
+    let pb = pb_new(size, "Recomputing index".to_string());
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        // Only the meta-data is needed, so skip reading the cells.
+        let metas: Array1<CircuitMeta> = dataset.read_slice(ndarray::s![begin..end])?;
+
+        for (i, meta) in metas.iter().enumerate() {
+            builder.add_meta((begin + i) as CircuitIndex, meta);
+        }
+
+        pb.inc((end - begin) as u64);
+    }
+
+    pb.finish();
+
+    // Files holding augmentations also have a back-reference index.
+    let aug_builder = if file.link_exists("/augmented") {
+        let dataset = file.dataset("/augmented")?;
+        let size = dataset.size();
+        let mut aug = AugmentedIndexBuilder::new();
+        let pb = pb_new(size, "Recomputing augmented index".to_string());
+
+        for begin in (0..size).step_by(step) {
+            let end = std::cmp::min(begin + step, size);
+
+            let circuits: Array1<AugmentedCircuit> = dataset.read_slice(ndarray::s![begin..end])?;
+
+            for (i, circuit) in circuits.iter().enumerate() {
+                aug.add((begin + i) as CircuitIndex, circuit);
+            }
+
+            pb.inc((end - begin) as u64);
+        }
+
+        pb.finish();
+        Some(aug)
+    } else {
+        None
+    };
+
+    // Compare against the stored copies.
+    let mut n_checked = 0;
+    let mut n_bad = 0;
+
+    for name in INDEX_NAMES {
+        let path = format!("/index/{name}");
+        let diff = if file.link_exists(&path) {
+            Some(builder.verify(&file, name)?)
+        } else {
+            None
+        };
+        n_checked += 1;
+        n_bad += check(&path, diff, cli.max_examples);
+    }
+
+    let diff = if file.link_exists(LABELS_NAME) {
+        Some(builder.verify_labels(&file)?)
+    } else {
+        None
+    };
+    n_checked += 1;
+    n_bad += check(LABELS_NAME, diff, cli.max_examples);
+
+    if let Some(aug) = aug_builder {
+        let path = "/index/uuid_gtt23";
+        let diff = if file.link_exists(path) {
+            Some(aug.verify(&file)?)
+        } else {
+            None
+        };
+        n_checked += 1;
+        n_bad += check(path, diff, cli.max_examples);
+    }
+
+    file.close()?;
+
+    if n_bad > 0 {
+        bail!("{n_bad}/{n_checked} indices failed verification");
+    }
+
+    log::info!("All {n_checked} indices verified");
+    Ok(())
+}
+
+/// Reports the differences of the stored index `path`, or that it is missing
+/// if `diff` is `None`. Returns 1 if the index failed verification, else 0.
+fn check(path: &str, diff: Option<IndexDiff>, max_examples: usize) -> usize {
+    let Some(diff) = diff else {
+        log::warn!("Index {path}: dataset is missing");
+        return 1;
+    };
+
+    if diff.is_empty() {
+        log::info!("Index {path}: ok");
+        return 0;
+    }
+
+    report(path, "missing", &diff.missing, max_examples);
+    report(path, "extra", &diff.extra, max_examples);
+    report(path, "mismatched", &diff.mismatched, max_examples);
+    let misordered: Vec<String> = diff.misordered.iter().map(|p| p.to_string()).collect();
+    report(path, "misordered at position", &misordered, max_examples);
+    1
+}
+
+fn report(name: &str, kind: &str, values: &[String], max_examples: usize) {
+    if values.is_empty() {
+        return;
+    }
+
+    let examples: Vec<&str> = values
+        .iter()
+        .take(max_examples)
+        .map(|s| s.as_str())
+        .collect();
+    log::warn!(
+        "Index {name}: {} {kind} entries, e.g.: {}",
+        values.len(),
+        examples.join(", ")
+    );
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
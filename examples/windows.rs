@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{self, LevelFilter};
+use ndarray::{self, Array1};
+
+use gtt23::Circuit;
+use gtt23::rng::Rng;
+use gtt23::window::SlidingWindow;
+use gtt23::writer::AugmentedWriter;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Slice each circuit into overlapping windows of consecutive cells and write
+/// them to the /augmented dataset of a new HDF5 file, linked to their circuits
+/// by /index/uuid_gtt23
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Output path to write the windows HDF5 file
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        default_value = "./gtt23-windows.hdf5"
+    )]
+    pub output: PathBuf,
+    /// The number of cells in each window
+    #[arg(short, long, value_name = "N")]
+    pub width: u16,
+    /// The number of cells between the starts of consecutive windows
+    /// [default: the window width, i.e., no overlap]
+    #[arg(short = 't', long, value_name = "N")]
+    pub stride: Option<u16>,
+    /// Add a final window holding the last cells of each circuit when the
+    /// stride would otherwise leave them out
+    #[arg(long)]
+    pub tail: bool,
+    /// Seed for the random number generator of the window uuids
+    #[arg(short, long, value_name = "SEED", default_value_t = 0)]
+    pub seed: u64,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let stride = cli.stride.unwrap_or(cli.width);
+    if cli.width == 0 || stride == 0 {
+        bail!("Window width and stride must be positive");
+    }
+    let mut window = SlidingWindow::new(cli.width, stride);
+    if cli.tail {
+        window = window.with_tail();
+    }
+
+    let in_file = File::open(&cli.input)?;
+    let in_ds = in_file.dataset("/circuits")?;
+    let size = in_ds.size();
+    let step = 1_000; // multiple of chunk size
+
+    let out_file = File::create(&cli.output)?;
+    let mut writer = AugmentedWriter::create_like(&out_file, &in_ds)?;
+    let mut rng = Rng::new(cli.seed);
+
+    let pb = pb_new(size, "Writing windows".to_string());
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        let circ_array: Array1<Circuit> = in_ds.read_slice(ndarray::s![begin..end])?;
+
+        for circuit in circ_array.iter() {
+            for augmented in window.windows(circuit, || rng.uuid()) {
+                writer.push(augmented)?;
+            }
+        }
+
+        pb.inc((end - begin) as u64);
+    }
+
+    pb.finish();
+    let n_windows = writer.len();
+    writer.finish()?;
+
+    out_file.close()?;
+    in_file.close()?;
+
+    log::info!("Wrote {n_windows} windows of {size} circuits");
+
+    Ok(())
+}
+
+fn pb_style() -> ProgressStyle {
+    ProgressStyle::with_template(
+        "{msg}: {wide_bar:.green} {pos}/{len} ({percent}%) [{elapsed_precise} (eta {eta_precise})]",
+    )
+    .unwrap_or(ProgressStyle::default_bar())
+}
+
+fn pb_new(count: usize, message: String) -> ProgressBar {
+    ProgressBar::new(count as u64)
+        .with_message(message)
+        .with_style(pb_style())
+}
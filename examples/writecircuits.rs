@@ -16,6 +16,8 @@ use serde_json::Value;
 use uuid::Uuid;
 use zstd::stream::read::Decoder;
 
+use gtt23::rng::Rng;
+use gtt23::sample::Reservoir;
 use gtt23::{self, Cell, CellCommand, Circuit, Direction, RelayCommand};
 
 #[derive(Parser)]
@@ -34,6 +36,13 @@ pub struct Cli {
     /// Ignore circuits that occurred after this time (e.g., yyyy-mm-ddT23:59:59Z)
     #[arg(short, long, value_name = "TIMESTAMP")]
     pub end: Option<Timestamp>,
+    /// Keep only a uniform random sample of N circuits, chosen with reservoir
+    /// sampling so that at most N circuits are held in memory
+    #[arg(long, value_name = "N")]
+    pub sample: Option<usize>,
+    /// Seed for the random number generator used with --sample
+    #[arg(short, long, value_name = "SEED", default_value_t = 0)]
+    pub seed: u64,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -63,22 +72,40 @@ fn main() -> anyhow::Result<()> {
 
     log::info!("Found {n_tot_circs} circuits in {} files", cli.input.len());
 
+    if let Some(n) = cli.sample {
+        let circuits = sample_files(&cli.input, n_tot_circs, n, cli.seed, &begin, &end)?;
+        log::info!("Sampled {} circuits", circuits.len());
+
+        let file = hdf5::File::create(&cli.output)?;
+        let ds = new_circuits_dataset(&file, circuits.len())?;
+        let pb = pb_new(circuits.len(), "Writing sample".to_string());
+
+        for begin in (0..circuits.len()).step_by(1_000) {
+            let end = std::cmp::min(begin + 1_000, circuits.len());
+            ds.write_slice(
+                &circuits.slice(ndarray::s![begin..end]),
+                ndarray::s![begin..end],
+            )?;
+            pb.inc((end - begin) as u64);
+        }
+
+        file.close()?;
+        pb.finish();
+
+        log::info!("All done in {:?}!", main_start.elapsed());
+        return Ok(());
+    }
+
     // Make an dataset with the known size.
     let file = hdf5::File::create(cli.output)?;
-    let ds = file
-        .new_dataset_builder()
-        .chunk(25)
-        .blosc_zstd(9, false) // level 9, no shuffle
-        .empty::<Circuit>()
-        .shape(n_tot_circs)
-        .create("/circuits")?;
+    let ds = new_circuits_dataset(&file, n_tot_circs)?;
 
     // Load and write circuits into the dataset
     let mut wr_cursor = 0;
 
     // Track progress.
     let mpb = MultiProgress::new();
-    let pb_main = mpb.add(pb_new(n_tot_circs, format!("Processing circuits")));
+    let pb_main = mpb.add(pb_new(n_tot_circs, "Processing circuits".to_string()));
     pb_main.tick();
 
     // Process all of the files.
@@ -144,6 +171,16 @@ fn pb_new(count: usize, message: String) -> ProgressBar {
         .with_style(pb_style())
 }
 
+fn new_circuits_dataset(file: &hdf5::File, size: usize) -> anyhow::Result<hdf5::Dataset> {
+    Ok(file
+        .new_dataset_builder()
+        .chunk(25)
+        .blosc_zstd(9, false) // level 9, no shuffle
+        .empty::<Circuit>()
+        .shape(size)
+        .create("/circuits")?)
+}
+
 fn count_circuits(paths: &Vec<PathBuf>) -> anyhow::Result<Vec<usize>> {
     let prog = ProgressBar::new(paths.len() as u64).with_style(pb_style());
 
@@ -170,7 +207,7 @@ fn count_lines(path: &PathBuf) -> anyhow::Result<usize> {
     let mut count = 0;
 
     // Only reallocates buffer if the next line does not fit.
-    while stream.read_line(&mut buffer).map_or(false, |n| n > 0) {
+    while stream.read_line(&mut buffer).is_ok_and(|n| n > 0) {
         count += 1;
         // Reclaim capacity.
         buffer.clear();
@@ -213,7 +250,7 @@ fn decode_file(
     let mut circuits = Vec::new();
 
     // Only reallocates buffer if the next line does not fit.
-    while stream.read_line(&mut buffer).map_or(false, |n| n > 0) {
+    while stream.read_line(&mut buffer).is_ok_and(|n| n > 0) {
         if let Some(circuit) = decode_circuit(&buffer, begin, end)? {
             circuits.push(circuit);
         }
@@ -225,6 +262,35 @@ fn decode_file(
     Ok(Array1::from_vec(circuits))
 }
 
+fn sample_files(
+    paths: &Vec<PathBuf>,
+    n_tot_circs: usize,
+    n: usize,
+    seed: u64,
+    begin: &Option<Duration>,
+    end: &Option<Duration>,
+) -> anyhow::Result<Array1<Circuit>> {
+    let mut rng = Rng::new(seed);
+    let mut reservoir = Reservoir::new(n);
+    let pb = pb_new(n_tot_circs, "Sampling circuits".to_string());
+
+    for path in paths.iter() {
+        let mut stream = open_input_stream(path)?;
+        let mut buffer = String::new();
+
+        while stream.read_line(&mut buffer).is_ok_and(|n| n > 0) {
+            if let Some(circuit) = decode_circuit(&buffer, begin, end)? {
+                reservoir.offer(circuit, &mut rng);
+            }
+            buffer.clear();
+            pb.inc(1);
+        }
+    }
+
+    pb.finish();
+    Ok(Array1::from_vec(reservoir.into_items()))
+}
+
 fn decode_circuit(
     jsonl: &String,
     begin: &Option<Duration>,
@@ -0,0 +1,108 @@
+use std::path::PathBuf;
+use std::thread;
+
+use clap::{Parser, ValueEnum};
+use env_logger::{Builder, Target};
+use hdf5::File;
+use log::{self, LevelFilter};
+
+use gtt23::features::{self, CUMUL_POINTS, FeatureSet, FeatureWriter};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Computes features of every circuit in an HDF5 file and writes them to a
+/// dataset alongside /circuits, or to a NumPy file, with one row per circuit
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// The features to compute
+    #[arg(short, long, value_name = "FEATURE", default_value = "cumul")]
+    pub feature: Feature,
+    /// The number of points in the CUMUL representation
+    #[arg(short = 'n', long, value_name = "N", default_value_t = CUMUL_POINTS)]
+    pub points: usize,
+    /// The number of bursts in the burst vector
+    #[arg(short, long, value_name = "N", default_value_t = 100)]
+    pub length: usize,
+    /// The number of bins in each timing histogram or cell rate series
+    #[arg(short, long, value_name = "N", default_value_t = 20)]
+    pub bins: usize,
+    /// The width in milliseconds of each bin of the cell rate series
+    #[arg(long, value_name = "MS", default_value_t = 100)]
+    pub bin_ms: u64,
+    /// Name of the dataset to write [default: /features/<FEATURE>]
+    #[arg(short, long, value_name = "NAME", conflicts_with = "numpy")]
+    pub dataset: Option<String>,
+    /// Write the features to this .npy file, or to array X of this .npz file
+    /// along with the label of each circuit, instead of to a dataset
+    #[arg(long, value_name = "PATH")]
+    pub numpy: Option<PathBuf>,
+    /// Compute the features on this many threads [default: all cores]
+    #[arg(short = 'j', long, value_name = "N")]
+    pub threads: Option<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Feature {
+    /// The interpolated cumulative sum of the cell directions
+    Cumul,
+    /// The k-fingerprinting hand-crafted statistical features
+    Kfp,
+    /// The signed number of cells in each burst
+    Bursts,
+    /// Histograms of inter-arrival times and of cells per second, per direction
+    Timing,
+    /// The number of cells per second in fixed-width time bins, per direction
+    Rates,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let threads = cli
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+
+    let set = match cli.feature {
+        Feature::Cumul => FeatureSet::Cumul(cli.points),
+        Feature::Kfp => FeatureSet::Kfp,
+        Feature::Bursts => FeatureSet::Bursts(cli.length),
+        Feature::Timing => FeatureSet::Timing(cli.bins),
+        Feature::Rates => FeatureSet::Rates {
+            bin_ms: cli.bin_ms,
+            bins: cli.bins,
+        },
+    };
+
+    if let Some(path) = &cli.numpy {
+        let file = File::open(&cli.input)?;
+        log::info!(
+            "Writing {set:?} features to {} on {threads} threads",
+            path.display()
+        );
+        let n_rows = features::save_numpy(&file, path, set, threads)?;
+        log::info!("Wrote features for {n_rows} circuits");
+        file.close()?;
+        return Ok(());
+    }
+
+    let file = File::open_rw(&cli.input)?;
+
+    let name = cli
+        .dataset
+        .clone()
+        .unwrap_or_else(|| FeatureWriter::dataset_name(set));
+    log::info!("Writing {set:?} features to {name} on {threads} threads");
+    let n_rows = FeatureWriter::new(&file, threads).write_to(&name, set)?;
+
+    log::info!("Wrote features for {n_rows} circuits");
+    file.close()?;
+
+    Ok(())
+}
@@ -1,17 +1,14 @@
-use std::collections::HashMap;
 use std::path::PathBuf;
 
 use clap::Parser;
 use env_logger::{Builder, Target};
-use hdf5::{
-    types::{FixedAscii, VarLenArray},
-    File, H5Type,
-};
+use hdf5::File;
 use indicatif::{ProgressBar, ProgressStyle};
 use log::{self, LevelFilter};
-use ndarray::{self, Array1, ArrayView};
+use ndarray::{self, Array1};
 
-use gtt23::{Circuit, CircuitIndex, IndexArrayEntry, IndexEntry};
+use gtt23::index::{self, AugmentedIndexBuilder, IndexBuilder, UuidFilter};
+use gtt23::{AugmentedCircuit, CircuitIndex, CircuitMeta};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -20,6 +17,9 @@ pub struct Cli {
     /// Input paths to an hdf5 file containing a circuits dataset
     #[arg(value_name = "PATH", required = true)]
     pub input: PathBuf,
+    /// Also write a uuid bloom filter with this false positive rate (e.g., 0.01)
+    #[arg(long, value_name = "RATE")]
+    pub uuid_filter: Option<f64>,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -30,11 +30,8 @@ fn main() -> anyhow::Result<()> {
 
     let cli = Cli::parse();
 
-    let mut ci_uuid = HashMap::<FixedAscii<32>, Vec<CircuitIndex>>::new();
-    let mut ci_label = HashMap::<FixedAscii<44>, Vec<CircuitIndex>>::new();
-    let mut ci_day = HashMap::<u8, Vec<CircuitIndex>>::new();
-    let mut ci_port = HashMap::<u16, Vec<CircuitIndex>>::new();
-    let mut ci_len = HashMap::<u16, Vec<CircuitIndex>>::new();
+    let mut builder = IndexBuilder::new();
+    let mut aug_builder = None;
 
     // Read the entire dataset to compute the index.
     {
@@ -44,53 +41,75 @@ fn main() -> anyhow::Result<()> {
         //let step = dataset.chunk().map_or(1_000, |v| *v.first().unwrap_or(&1_000));
         let step = 1_000; // multiple of chunk size
 
-        let pb = pb_new(size, format!("Computing index"));
+        let pb = pb_new(size, "Computing index".to_string());
 
         // Read from dataset in batches for better performance.
         for begin in (0..size).step_by(step) {
             let end = std::cmp::min(begin + step, size);
 
-            let circuits: Array1<Circuit> = dataset.read_slice(ndarray::s![begin..end])?;
+            // Only the meta-data is needed, so skip reading the cells.
+            let metas: Array1<CircuitMeta> = dataset.read_slice(ndarray::s![begin..end])?;
 
-            for (i, circuit) in circuits.iter().enumerate() {
+            for (i, meta) in metas.iter().enumerate() {
                 let index = (begin + i) as CircuitIndex;
 
-                ci_uuid.entry(circuit.uuid).or_default().push(index);
-                ci_label.entry(circuit.label()).or_default().push(index);
-                ci_day.entry(circuit.day).or_default().push(index);
-                ci_port.entry(circuit.port).or_default().push(index);
-                ci_len.entry(circuit.len).or_default().push(index);
+                builder.add_meta(index, meta);
             }
 
             pb.inc((end - begin) as u64);
         }
 
         pb.finish();
-        file.close()?;
-    }
 
-    // Write each index into the hdf5 database.
+        // Files holding augmentations also get a back-reference index.
+        if file.link_exists("/augmented") {
+            let dataset = file.dataset("/augmented")?;
+            let size = dataset.size();
+            let mut aug = AugmentedIndexBuilder::new();
+
+            let pb = pb_new(size, "Computing augmented index".to_string());
+
+            for begin in (0..size).step_by(step) {
+                let end = std::cmp::min(begin + step, size);
 
-    let mut index = create_index_entries(ci_uuid, "uuid")?;
-    index.sort_by_key(|v| v.value.to_string());
-    write_index(&cli.input, "/index/uuid", &Array1::from_vec(index))?;
+                let circuits: Array1<AugmentedCircuit> =
+                    dataset.read_slice(ndarray::s![begin..end])?;
+
+                for (i, circuit) in circuits.iter().enumerate() {
+                    aug.add((begin + i) as CircuitIndex, circuit);
+                }
+
+                pb.inc((end - begin) as u64);
+            }
 
-    let mut index = create_index_arr_entries(ci_label, "label")?;
-    index.sort_by_key(|v| v.value.to_string());
-    write_index(&cli.input, "/index/label", &Array1::from_vec(index))?;
+            pb.finish();
+            aug_builder = Some(aug);
+        }
 
-    let mut index = create_index_arr_entries(ci_day, "day")?;
-    index.sort_by_key(|v| v.value);
-    write_index(&cli.input, "/index/day", &Array1::from_vec(index))?;
+        file.close()?;
+    }
 
-    let mut index = create_index_arr_entries(ci_port, "port")?;
-    index.sort_by_key(|v| v.value);
-    write_index(&cli.input, "/index/port", &Array1::from_vec(index))?;
+    // Write each index into the hdf5 database. Replaced datasets are unlinked
+    // but their storage space is not reclaimed; run the repack example
+    // afterward to reclaim it.
+    log::info!("Writing indices");
+    let file = File::open_rw(&cli.input)?;
+    builder.write_all(&file)?;
+
+    if let Some(fp_rate) = cli.uuid_filter {
+        let uuid_index = builder.uuid_index();
+        let mut filter = UuidFilter::new(uuid_index.len(), fp_rate);
+        for entry in uuid_index.iter() {
+            filter.insert(&entry.value);
+        }
+        filter.write(&file, "/index/uuid_filter")?;
+    }
 
-    let mut index = create_index_arr_entries(ci_len, "len")?;
-    index.sort_by_key(|v| v.value);
-    write_index(&cli.input, "/index/len", &Array1::from_vec(index))?;
+    if let Some(aug) = aug_builder {
+        index::write_index(&file, "/index/uuid_gtt23", &aug.uuid_gtt23_index())?;
+    }
 
+    file.close()?;
     Ok(())
 }
 
@@ -106,59 +125,3 @@ fn pb_new(count: usize, message: String) -> ProgressBar {
         .with_message(message)
         .with_style(pb_style())
 }
-
-pub fn create_index_entries<T>(
-    index_map: HashMap<T, Vec<CircuitIndex>>,
-    name: &str,
-) -> anyhow::Result<Vec<IndexEntry<T>>>
-where
-    T: H5Type,
-{
-    Ok(create_index_arr_entries(index_map, name)?
-        .into_iter()
-        .map(|ent| IndexEntry {
-            value: ent.value,
-            index: *ent.indexarr.first().unwrap(),
-        })
-        .collect())
-}
-
-pub fn create_index_arr_entries<T>(
-    index_map: HashMap<T, Vec<CircuitIndex>>,
-    name: &str,
-) -> anyhow::Result<Vec<IndexArrayEntry<T>>>
-where
-    T: H5Type,
-{
-    let mut index = Vec::new();
-    let pb = pb_new(index_map.len(), format!("Preparing {name} index"));
-
-    for (value, mut indices) in index_map.into_iter() {
-        indices.sort();
-        let indexarr = VarLenArray::from_slice(&indices);
-        index.push(IndexArrayEntry { value, indexarr });
-        pb.inc(1);
-    }
-
-    pb.finish();
-    Ok(index)
-}
-
-pub fn write_index<'d, A, T, D>(path: &PathBuf, name: &str, data: A) -> anyhow::Result<()>
-where
-    A: Into<ArrayView<'d, T, D>>,
-    T: H5Type,
-    D: ndarray::Dimension,
-{
-    let file = File::open_rw(path)?;
-
-    if let Ok(_) = file.dataset(name) {
-        // Note this unlinks but does not reclaim its storage space.
-        file.unlink(name)?;
-    }
-
-    file.new_dataset_builder().with_data(data).create(name)?;
-
-    file.close()?;
-    Ok(())
-}
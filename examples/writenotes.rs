@@ -42,6 +42,20 @@ const PORT_NOTE: &str = "Provides a cached copy of the indices into the \
 const LEN_NOTE: &str = "Provides a cached copy of the indices into the \
     circuits dataset of those circuits with the given length.";
 
+const SERVICE_NOTE: &str = "Provides a cached copy of the indices into the \
+    circuits dataset of those circuits with the given service category. The \
+    category is derived from the circuit's port: HTTP (80, 8000, 8080), HTTPS \
+    (443, 8443), SMTP (25, 465, 587), IMAP (143, 993), or OTHER.";
+
+const UUID_GTT23_NOTE: &str = "Provides a cached copy of the indices into the \
+    augmented dataset of those augmented circuits that were created from the \
+    GTT23 circuit with the given uuid.";
+
+const UUID_FILTER_NOTE: &str = "Provides a bloom filter over the uuids of the \
+    circuits dataset, stored as 64-bit words of num_bits total bits and probed \
+    with num_hashes FNV-1a double hashes. A clear bit means the uuid is \
+    definitely absent from the circuits dataset.";
+
 fn main() -> anyhow::Result<()> {
     Builder::new()
         .target(Target::Stderr)
@@ -59,6 +73,15 @@ fn main() -> anyhow::Result<()> {
     write_dataset_note(&file, "/index/day", DAY_NOTE)?;
     write_dataset_note(&file, "/index/port", PORT_NOTE)?;
     write_dataset_note(&file, "/index/len", LEN_NOTE)?;
+    write_dataset_note(&file, "/index/service", SERVICE_NOTE)?;
+
+    if file.link_exists("/index/uuid_filter") {
+        write_dataset_note(&file, "/index/uuid_filter", UUID_FILTER_NOTE)?;
+    }
+
+    if file.link_exists("/index/uuid_gtt23") {
+        write_dataset_note(&file, "/index/uuid_gtt23", UUID_GTT23_NOTE)?;
+    }
 
     file.close()?;
     Ok(())
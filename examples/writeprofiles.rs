@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use log::{self, LevelFilter};
+
+use gtt23::profile::{self, LENGTHS_NAME};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Computes per-label trace-length statistics and mean and median feature
+/// vectors of the circuits in an HDF5 file, and writes them under /profiles
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Name of a features dataset to profile, e.g., /features/cumul; may be
+    /// given more than once
+    #[arg(short, long, value_name = "NAME")]
+    pub features: Vec<String>,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+    let file = File::open_rw(&cli.input)?;
+
+    let features: Vec<&str> = cli.features.iter().map(String::as_str).collect();
+    log::info!("Writing label profiles to {LENGTHS_NAME}");
+    for name in &features {
+        log::info!(
+            "Writing profiles of {name} to {}",
+            profile::profile_group_name(name)
+        );
+    }
+
+    let n_labels = profile::write_profiles(&file, &features)?;
+
+    log::info!("Wrote profiles for {n_labels} labels");
+    file.close()?;
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use std::fs::File as FsFile;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use clap::Parser;
+use env_logger::{Builder, Target};
+use hdf5::File;
+use log::{self, LevelFilter};
+
+use gtt23::CircuitIndex;
+use gtt23::index::{LABELS_NAME, LabelVocabulary};
+use gtt23::tensor::{self, TensorOptions};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+/// Writes the circuits of an HDF5 file in shuffled order to a single
+/// memory-mappable .npy file of direction and time channels plus class ids,
+/// along with a <OUTPUT>.labels.csv file mapping class ids to labels. Class ids
+/// are taken from the /labels dataset if the file has one
+pub struct Cli {
+    /// Input path to an hdf5 file containing a circuits dataset
+    #[arg(value_name = "PATH", required = true)]
+    pub input: PathBuf,
+    /// Output path of the .npy file
+    #[arg(short, long, value_name = "PATH", default_value = "./gtt23-tensor.npy")]
+    pub output: PathBuf,
+    /// Seed of the shuffle
+    #[arg(short, long, value_name = "SEED", default_value_t = 0)]
+    pub seed: u64,
+    /// Drop the circuits that do not fill a whole batch of this size
+    #[arg(short, long, value_name = "N")]
+    pub batch_size: Option<usize>,
+}
+
+fn main() -> anyhow::Result<()> {
+    Builder::new()
+        .target(Target::Stderr)
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let cli = Cli::parse();
+
+    let in_file = File::open(&cli.input)?;
+    let in_ds = in_file.dataset("/circuits")?;
+    let indices: Vec<CircuitIndex> = (0..in_ds.size() as CircuitIndex).collect();
+
+    let vocabulary = if in_file.link_exists(LABELS_NAME) {
+        Some(LabelVocabulary::read(&in_file)?)
+    } else {
+        None
+    };
+    let options = TensorOptions {
+        seed: cli.seed,
+        batch_size: cli.batch_size,
+        vocabulary,
+    };
+    let (n_records, labels) = tensor::write_tensor(&in_ds, &indices, &cli.output, &options)?;
+
+    let labels_path = cli.output.with_extension("labels.csv");
+    let mut out = BufWriter::new(FsFile::create(&labels_path)?);
+    writeln!(out, "class,label")?;
+    for (class, label) in labels.iter().enumerate() {
+        writeln!(out, "{class},{label}")?;
+    }
+    out.flush()?;
+
+    log::info!(
+        "Wrote {n_records} records of {} classes to {} and {}",
+        labels.len(),
+        cli.output.display(),
+        labels_path.display()
+    );
+    in_file.close()?;
+
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use hdf5::types::VarLenAscii;
+use hdf5::{File, Location};
+use ndarray::Array1;
+
+use crate::index::write_index;
+use crate::stats::{
+    COMMAND_STATS_NAME, CommandStats, DAY_VOLUME_NAME, DayVolume, DayVolumeBuilder, IAT_STATS_NAME,
+    IMBALANCE_NAME, IatStats, LABEL_DAYS_NAME, LABEL_STATS_NAME, LEN_STATS_NAME, LabelChurn,
+    LabelChurnBuilder, LabelStats, LabelStatsBuilder, LenStats, PORT_STATS_NAME,
+    SINGLE_DAY_LABELS_NAME, Stats,
+};
+use crate::{Circuit, CircuitMeta};
+
+/// The name of the group holding the statistics written by `analyze`.
+pub const STATS_GROUP_NAME: &str = "/stats";
+
+/// The number of log-spaced inter-arrival time bins per factor of 10.
+const IAT_BINS_PER_DECADE: usize = 10;
+
+const STATS_NOTE: &str = "Statistics of the circuits dataset computed in a \
+    single pass by the analyze example, so that they travel with the data. \
+    The number of circuits is stored in the circuits attribute.";
+
+const LABELS_NOTE: &str = "The number of circuits, the number of days, the \
+    first and last day, and the min, max, mean, median, and standard deviation \
+    of the lengths of the circuits with each label, sorted by label.";
+
+const IMBALANCE_NOTE: &str = "The number of labels and circuits, the min and \
+    max circuits per label, the Gini coefficient, Shannon entropy in bits, and \
+    effective number of classes of the labels, the number of most frequent \
+    labels holding 50%, 80%, 90%, and 99% of the circuits (head), and the \
+    number of labels with at most 1, 10, 100, and 1000 circuits (tail).";
+
+const PORTS_NOTE: &str = "The number of circuits with each port on each day, \
+    sorted by port and then day.";
+
+const LEN_HIST_NOTE: &str = "The number of circuits of each length, indexed \
+    by length.";
+
+const LEN_DAY_HIST_NOTE: &str = "The number of circuits of each length on \
+    each day, with one row per day in the order of the days dataset.";
+
+const LEN_PERCENTILES_NOTE: &str = "The number of circuits and the 1st, 5th, \
+    25th, 50th, 75th, 95th, and 99th nearest-rank percentiles of their \
+    lengths, overall, per day, and per label.";
+
+const COMMANDS_NOTE: &str = "The number of valid cells with each cell command \
+    and relay command sent in each direction on each day.";
+
+const IAT_EDGES_NOTE: &str = "The edges in seconds of the log-spaced bins of \
+    the inter-arrival time histograms, which also have an underflow bin before \
+    the first edge and an overflow bin after the last.";
+
+const IAT_HIST_NOTE: &str = "The number of times between consecutive valid \
+    cells in each inter-arrival time bin: all cells for all, and cells of one \
+    direction for client_to_server and server_to_client.";
+
+const IAT_PERCENTILES_NOTE: &str = "The number of inter-arrival times and \
+    their 1st, 5th, 25th, 50th, 75th, 95th, and 99th percentiles in seconds, \
+    resolved to the upper edge of their bin, for the all, client_to_server, \
+    and server_to_client histograms in that order.";
+
+const DAYS_NOTE: &str = "The number of circuits, the number of valid cells, \
+    and the mean cells per second and duration in seconds of the circuits \
+    observed on each day.";
+
+const LABEL_DAYS_NOTE: &str = "The number of circuits with each label on each \
+    day on which the label appears, sorted by label and then day.";
+
+const SINGLE_DAY_LABELS_NOTE: &str = "The labels whose circuits were all \
+    observed on a single day, which risk temporal leakage in splits.";
+
+/// The statistics written by `analyze`.
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    pub summary: Stats,
+    pub labels: Vec<LabelStats>,
+    pub len: LenStats,
+    pub commands: CommandStats,
+    pub iat: IatStats,
+    pub days: Vec<DayVolume>,
+    pub churn: LabelChurn,
+}
+
+/// Computes the main statistics of the circuits in the `/circuits` dataset of
+/// `file` in a single pass, and writes them as datasets of the `/stats` group
+/// with a `note` attribute describing each, replacing any existing datasets:
+/// the label statistics, port and day counts, length distributions, command
+/// counts, inter-arrival time distributions, day volumes, and label days, as
+/// written individually by the functions of the `stats` module. `progress` is
+/// called with the number of circuits read after each batch.
+pub fn analyze<F>(file: &File, mut progress: F) -> hdf5::Result<Analysis>
+where
+    F: FnMut(usize),
+{
+    let circuits = file.dataset("/circuits")?;
+    let size = circuits.size();
+    let step = 1_000; // multiple of chunk size
+
+    let mut summary = Stats::new();
+    let mut labels = LabelStatsBuilder::new();
+    let mut len = LenStats::new();
+    let mut commands = CommandStats::new();
+    let mut iat = IatStats::new(IAT_BINS_PER_DECADE);
+    let mut days = DayVolumeBuilder::new();
+    let mut churn = LabelChurnBuilder::new();
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+        let batch: Array1<Circuit> = circuits.read_slice(ndarray::s![begin..end])?;
+        for circuit in batch.iter() {
+            let meta = CircuitMeta::from(circuit);
+            summary.add(circuit);
+            labels.add(circuit);
+            len.add_meta(&meta);
+            commands.add(circuit);
+            iat.add(circuit);
+            days.add(circuit);
+            churn.add_meta(&meta);
+        }
+        progress(end - begin);
+    }
+
+    let analysis = Analysis {
+        summary,
+        labels: labels.finish(),
+        len,
+        commands,
+        iat,
+        days: days.finish(),
+        churn: churn.finish(),
+    };
+    analysis.write(file)?;
+    Ok(analysis)
+}
+
+impl Analysis {
+    /// Writes the statistics and their notes to the `/stats` group of `file`,
+    /// as described in `analyze`.
+    pub fn write(&self, file: &File) -> hdf5::Result<()> {
+        write_index(file, LABEL_STATS_NAME, &self.labels)?;
+        write_index(file, IMBALANCE_NAME, &[self.summary.imbalance()])?;
+        write_index(file, PORT_STATS_NAME, &self.summary.port_day_counts())?;
+        self.len.write(file)?;
+        write_index(file, COMMAND_STATS_NAME, &self.commands.counts())?;
+        self.iat.write(file)?;
+        write_index(file, DAY_VOLUME_NAME, &self.days)?;
+        self.churn.write(file)?;
+
+        let notes = [
+            (LABEL_STATS_NAME.to_string(), LABELS_NOTE),
+            (IMBALANCE_NAME.to_string(), IMBALANCE_NOTE),
+            (PORT_STATS_NAME.to_string(), PORTS_NOTE),
+            (format!("{LEN_STATS_NAME}/hist"), LEN_HIST_NOTE),
+            (format!("{LEN_STATS_NAME}/day_hist"), LEN_DAY_HIST_NOTE),
+            (
+                format!("{LEN_STATS_NAME}/percentiles"),
+                LEN_PERCENTILES_NOTE,
+            ),
+            (format!("{LEN_STATS_NAME}/days"), LEN_PERCENTILES_NOTE),
+            (format!("{LEN_STATS_NAME}/labels"), LEN_PERCENTILES_NOTE),
+            (COMMAND_STATS_NAME.to_string(), COMMANDS_NOTE),
+            (format!("{IAT_STATS_NAME}/edges"), IAT_EDGES_NOTE),
+            (format!("{IAT_STATS_NAME}/all"), IAT_HIST_NOTE),
+            (format!("{IAT_STATS_NAME}/client_to_server"), IAT_HIST_NOTE),
+            (format!("{IAT_STATS_NAME}/server_to_client"), IAT_HIST_NOTE),
+            (
+                format!("{IAT_STATS_NAME}/percentiles"),
+                IAT_PERCENTILES_NOTE,
+            ),
+            (DAY_VOLUME_NAME.to_string(), DAYS_NOTE),
+            (LABEL_DAYS_NAME.to_string(), LABEL_DAYS_NOTE),
+            (SINGLE_DAY_LABELS_NAME.to_string(), SINGLE_DAY_LABELS_NOTE),
+        ];
+        for (name, note) in notes {
+            write_note(&file.dataset(&name)?, note)?;
+        }
+
+        let group = file.group(STATS_GROUP_NAME)?;
+        write_note(&group, STATS_NOTE)?;
+        match group.attr("circuits") {
+            Ok(attr) => attr.write_scalar(&(self.summary.total() as u64))?,
+            Err(_) => group
+                .new_attr::<u64>()
+                .create("circuits")?
+                .write_scalar(&(self.summary.total() as u64))?,
+        }
+        Ok(())
+    }
+}
+
+/// Writes `note` to the `note` attribute of the dataset or group at
+/// `location`, replacing any existing note.
+pub fn write_note(location: &Location, note: &str) -> hdf5::Result<()> {
+    let note = VarLenAscii::from_ascii(note).map_err(|e| hdf5::Error::from(e.to_string()))?;
+    let note_data = ndarray::arr0(note);
+
+    if let Ok(attr) = location.attr("note") {
+        attr.write(&note_data)?;
+    } else {
+        location
+            .new_attr_builder()
+            .with_data(&note_data)
+            .create("note")?;
+    }
+
+    Ok(())
+}
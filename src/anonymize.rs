@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use hdf5::types::FixedAscii;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{fixedascii_from_str, Circuit};
+
+/// The prefix of every pseudonym, which keeps pseudonyms distinguishable from
+/// real domains.
+pub const PSEUDONYM_PREFIX: &str = "anon-";
+
+/// Replaces domains with keyed-hash pseudonyms.
+///
+/// A pseudonym is the first 128 bits of HMAC-SHA256(key, domain) in hex, so
+/// equal domains always get equal pseudonyms and circuit labels keep their
+/// equality, while recovering a domain requires the key. Empty fields stay
+/// empty so that `Circuit::label` picks the same field as before.
+#[derive(Clone)]
+pub struct Pseudonymizer {
+    mac: Hmac<Sha256>,
+    mapping: HashMap<FixedAscii<44>, FixedAscii<44>>,
+}
+
+impl Pseudonymizer {
+    /// Creates a pseudonymizer keyed with `key`, which should be at least 32
+    /// random bytes and must be kept private.
+    pub fn new(key: &[u8]) -> Self {
+        Self {
+            mac: Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length"),
+            mapping: HashMap::new(),
+        }
+    }
+
+    /// The pseudonym for `value`.
+    pub fn pseudonym(&mut self, value: &FixedAscii<44>) -> FixedAscii<44> {
+        if value.is_empty() {
+            return *value;
+        }
+
+        if let Some(pseudonym) = self.mapping.get(value) {
+            return *pseudonym;
+        }
+
+        let mut mac = self.mac.clone();
+        mac.update(value.as_bytes());
+        let digest = mac.finalize().into_bytes();
+        let hex: String = digest[..16].iter().map(|b| format!("{b:02x}")).collect();
+
+        let pseudonym = fixedascii_from_str::<44>(&format!("{PSEUDONYM_PREFIX}{hex}"))
+            .expect("pseudonyms are short ascii strings");
+        self.mapping.insert(*value, pseudonym);
+        pseudonym
+    }
+
+    /// Replaces the domain fields of `circuit` with pseudonyms, and also zeroes
+    /// its port if `strip_port` is set.
+    pub fn anonymize(&mut self, circuit: &mut Circuit, strip_port: bool) {
+        circuit.domain = self.pseudonym(&circuit.domain);
+        circuit.shortest_private_suffix = self.pseudonym(&circuit.shortest_private_suffix);
+        if strip_port {
+            circuit.port = 0;
+        }
+    }
+
+    /// The `(pseudonym, original)` pairs produced so far, sorted by pseudonym.
+    pub fn mapping(&self) -> Vec<(String, String)> {
+        let mut mapping: Vec<(String, String)> = self
+            .mapping
+            .iter()
+            .map(|(original, pseudonym)| (pseudonym.to_string(), original.to_string()))
+            .collect();
+        mapping.sort();
+        mapping
+    }
+}
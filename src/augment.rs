@@ -0,0 +1,891 @@
+use std::fmt;
+
+use hdf5::File;
+use hdf5::types::VarLenAscii;
+use ndarray::Array1;
+
+use crate::index::fnv1a;
+use crate::overhead::{OverheadRecord, OverheadWriter};
+use crate::rng::Rng;
+use crate::transform;
+use crate::writer::AugmentedWriter;
+use crate::{AugmentedCircuit, Cell, CellCommand, Circuit, Direction, RelayCommand};
+
+/// The number of circuits read at a time by `Augmenter`.
+const READ_BATCH: usize = 1_000;
+
+/// A possibly randomized transformation that creates an augmented circuit from
+/// a GTT23 circuit, e.g., to simulate a defense or perturb timing.
+///
+/// Every deterministic `transform::CircuitTransform` is also an augmentation
+/// that ignores the random number generator. The `Params` of an augmentation
+/// are stored with the circuits that `Augmenter` writes.
+pub trait CircuitTransform: fmt::Debug + Params {
+    /// Transforms the valid cells `cells[..*len]` in place using `rng`,
+    /// updating `len` to the new number of valid cells and zeroing the cells
+    /// beyond it.
+    fn augment_cells(&self, cells: &mut [Cell], len: &mut u16, rng: &mut Rng);
+
+    /// Returns an augmented copy of `circuit` with a random uuid, linked to
+    /// `circuit` with an `aug_index` of 0.
+    fn apply(&self, circuit: &Circuit, rng: &mut Rng) -> AugmentedCircuit {
+        let mut augmented = AugmentedCircuit::from_circuit(circuit, rng.uuid(), 0);
+        self.augment_cells(&mut augmented.cells, &mut augmented.len, rng);
+        augmented
+    }
+
+    /// Restricts this augmentation to the cells in `direction`, e.g., to pad
+    /// only `SERVER_TO_CLIENT`.
+    fn only(self, direction: Direction) -> Directed<Self>
+    where
+        Self: Sized,
+    {
+        Directed {
+            transform: self,
+            direction,
+        }
+    }
+}
+
+impl<T: transform::CircuitTransform + fmt::Debug + Params> CircuitTransform for T {
+    fn augment_cells(&self, cells: &mut [Cell], len: &mut u16, _rng: &mut Rng) {
+        self.apply_cells(cells, len);
+    }
+}
+
+/// The parameters of an augmentation in a stable text form, which `Augmenter`
+/// stores with the circuits it writes and mixes into their random number
+/// generators.
+///
+/// The form is `name(key=value,...)`, with lists in brackets, enums by name,
+/// and numbers in their shortest decimal form that parses back exactly. Unlike
+/// the `Debug` representation, it does not depend on the Rust version or on
+/// how a type is declared, so it can be compared across runs.
+pub trait Params {
+    /// Returns the parameters of this augmentation.
+    fn params(&self) -> String;
+}
+
+/// Formats `items` as a bracketed, comma-separated list.
+pub(crate) fn list<T: fmt::Display>(items: impl IntoIterator<Item = T>) -> String {
+    let items: Vec<String> = items.into_iter().map(|x| x.to_string()).collect();
+    format!("[{}]", items.join(","))
+}
+
+impl Params for transform::DropCells {
+    fn params(&self) -> String {
+        format!(
+            "drop_cells(cell_cmds={},relay_cmds={})",
+            list(self.cell_cmds.iter().map(|&c| c as u8)),
+            list(self.relay_cmds.iter().map(|&c| c as u8))
+        )
+    }
+}
+
+impl Params for transform::TrimCells {
+    fn params(&self) -> String {
+        format!("trim_cells(n={})", self.n)
+    }
+}
+
+impl Params for transform::TimeWindow {
+    fn params(&self) -> String {
+        format!("time_window(seconds={})", self.seconds)
+    }
+}
+
+impl Params for transform::TimeScale {
+    fn params(&self) -> String {
+        format!("time_scale(client={},server={})", self.client, self.server)
+    }
+}
+
+/// Applies each augmentation in turn.
+#[derive(Debug, Default)]
+pub struct Compose(pub Vec<Box<dyn CircuitTransform>>);
+
+impl Compose {
+    /// Creates an empty composition, which leaves circuits unchanged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends `transform` to the augmentations applied.
+    pub fn then<T: CircuitTransform + 'static>(mut self, transform: T) -> Self {
+        self.0.push(Box::new(transform));
+        self
+    }
+}
+
+impl Params for Compose {
+    fn params(&self) -> String {
+        format!("compose({})", list(self.0.iter().map(|t| t.params())))
+    }
+}
+
+impl CircuitTransform for Compose {
+    fn augment_cells(&self, cells: &mut [Cell], len: &mut u16, rng: &mut Rng) {
+        for t in self.0.iter() {
+            t.augment_cells(cells, len, rng);
+        }
+    }
+}
+
+/// Restricts an augmentation to the cells in one direction, as several
+/// defenses only act on the download direction.
+///
+/// The augmentation is applied to the valid cells in `direction` alone. Its
+/// resulting cells in `direction` are kept, with those in the `PADDING`
+/// direction assigned to `direction`, and its cells in the other direction are
+/// discarded. The cells in other directions are then merged back by time,
+/// dropping the latest cells if the circuit would exceed `MAX_CELLS` cells.
+#[derive(Clone, Debug)]
+pub struct Directed<T: CircuitTransform> {
+    pub transform: T,
+    pub direction: Direction,
+}
+
+impl<T: CircuitTransform> Params for Directed<T> {
+    fn params(&self) -> String {
+        format!(
+            "directed(transform={},direction={})",
+            self.transform.params(),
+            direction_name(self.direction)
+        )
+    }
+}
+
+impl<T: CircuitTransform> CircuitTransform for Directed<T> {
+    fn augment_cells(&self, cells: &mut [Cell], len: &mut u16, rng: &mut Rng) {
+        let valid = std::cmp::min(*len as usize, cells.len());
+        let (mut directed, others): (Vec<Cell>, Vec<Cell>) = cells[..valid]
+            .iter()
+            .partition(|c| c.direction == self.direction);
+
+        let mut directed_len = directed.len() as u16;
+        directed.resize(cells.len(), Cell::empty());
+        self.transform
+            .augment_cells(&mut directed, &mut directed_len, rng);
+        for cell in directed[..directed_len as usize].iter_mut() {
+            if cell.direction == Direction::PADDING {
+                cell.direction = self.direction;
+            }
+        }
+        transform::retain_cells(&mut directed, &mut directed_len, |c| {
+            c.direction == self.direction
+        });
+
+        cells.copy_from_slice(&directed);
+        *len = directed_len;
+        insert_cells(cells, len, others);
+    }
+}
+
+/// An augmentation that creates any number of augmented circuits from each
+/// GTT23 circuit, e.g., by splitting it into sub-circuits. Like
+/// `CircuitTransform`, its `Params` are stored with the circuits written.
+pub trait MultiTransform: fmt::Debug + Params {
+    /// Returns the augmented circuits created from `circuit`.
+    fn apply_all(&self, circuit: &Circuit, rng: &mut Rng) -> Vec<AugmentedCircuit>;
+}
+
+/// Applies an augmentation a number of times to create that many augmented
+/// circuits.
+#[derive(Debug)]
+pub struct Copies<T: CircuitTransform> {
+    pub transform: T,
+    pub copies: u16,
+}
+
+impl<T: CircuitTransform> Params for Copies<T> {
+    fn params(&self) -> String {
+        format!(
+            "copies(transform={},copies={})",
+            self.transform.params(),
+            self.copies
+        )
+    }
+}
+
+impl<T: CircuitTransform> MultiTransform for Copies<T> {
+    fn apply_all(&self, circuit: &Circuit, rng: &mut Rng) -> Vec<AugmentedCircuit> {
+        (0..self.copies)
+            .map(|_| self.transform.apply(circuit, rng))
+            .collect()
+    }
+}
+
+/// Creates augmented circuits from every circuit of a dataset and writes them
+/// to the `/augmented` dataset of a file, along with the `/index/uuid_gtt23`
+/// index that links them to their GTT23 circuits.
+///
+/// The augmentations of each circuit are created with a random number
+/// generator derived from the master seed, the transform's `Params`, and the
+/// circuit's uuid, so they do not depend on the order or the subset of circuits
+/// augmented, and runs of different transforms with the same seed do not share
+/// random streams, e.g., the uuids of their augmented circuits. The seed and
+/// the transform's parameters are stored in the `seed` and `transform`
+/// attributes of `/augmented`, so that it can be reproduced exactly. The
+/// overhead of each augmented circuit over its GTT23 circuit is stored in the
+/// same row of `/overhead`.
+pub struct Augmenter {
+    transform: Box<dyn MultiTransform>,
+    seed: u64,
+    /// The transform's `Params`, computed once.
+    params: String,
+}
+
+impl Augmenter {
+    /// Creates an augmenter that writes `copies` augmentations of each circuit
+    /// by applying `transform`, with random numbers derived from `seed`.
+    pub fn new<T: CircuitTransform + 'static>(transform: T, copies: u16, seed: u64) -> Self {
+        Self::multi(Copies { transform, copies }, seed)
+    }
+
+    /// Creates an augmenter that writes the augmented circuits that `transform`
+    /// creates from each circuit, with random numbers derived from `seed`.
+    pub fn multi<T: MultiTransform + 'static>(transform: T, seed: u64) -> Self {
+        let params = transform.params();
+        Self {
+            transform: Box::new(transform),
+            seed,
+            params,
+        }
+    }
+
+    /// Returns the augmented circuits created from `circuit`, which are the
+    /// same every time for the same seed and transform.
+    pub fn augment(&self, circuit: &Circuit) -> Vec<AugmentedCircuit> {
+        let salt = fnv1a(self.params.as_bytes(), 0xcbf29ce484222325);
+        let mut rng = Rng::derive(self.seed ^ salt, circuit.uuid.as_bytes());
+        self.transform.apply_all(circuit, &mut rng)
+    }
+
+    /// Applies the transform to every circuit in `circuits` and writes the
+    /// augmented circuits to a new `/augmented` dataset in `file` with an
+    /// `AugmentedWriter`, so that the augmentations of each circuit are
+    /// numbered from 0 by `aug_index` in the order the transform creates them.
+    /// Returns the number of augmented circuits written.
+    pub fn run(&self, circuits: &hdf5::Dataset, file: &File) -> hdf5::Result<usize> {
+        self.run_with_progress(circuits, file, |_| {})
+    }
+
+    /// Like `run`, but calls `progress` with the number of circuits augmented
+    /// after each batch, e.g., to update a progress bar.
+    pub fn run_with_progress<F>(
+        &self,
+        circuits: &hdf5::Dataset,
+        file: &File,
+        mut progress: F,
+    ) -> hdf5::Result<usize>
+    where
+        F: FnMut(usize),
+    {
+        let mut writer = AugmentedWriter::create_like(file, circuits)?;
+        let mut overhead = OverheadWriter::create(file)?;
+        let size = circuits.size();
+
+        for begin in (0..size).step_by(READ_BATCH) {
+            let end = std::cmp::min(begin + READ_BATCH, size);
+            let batch: Array1<Circuit> = circuits.read_slice(ndarray::s![begin..end])?;
+
+            for circuit in batch.iter() {
+                for augmented in self.augment(circuit) {
+                    overhead.push(OverheadRecord::augmented(circuit, &augmented))?;
+                    writer.push(augmented)?;
+                }
+            }
+            progress(end - begin);
+        }
+
+        let written = writer.len();
+        let dataset = writer.finish()?;
+        self.write_attrs(&dataset)?;
+        overhead.finish()?;
+        Ok(written)
+    }
+
+    /// Stores the seed and transform parameters in attributes of `dataset`.
+    fn write_attrs(&self, dataset: &hdf5::Dataset) -> hdf5::Result<()> {
+        dataset
+            .new_attr::<u64>()
+            .create("seed")?
+            .write_scalar(&self.seed)?;
+        let params = VarLenAscii::from_ascii(&self.params).map_err(|e| e.to_string())?;
+        dataset
+            .new_attr::<VarLenAscii>()
+            .create("transform")?
+            .write_scalar(&params)?;
+        Ok(())
+    }
+}
+
+/// How `TrafficSplit` assigns cells to sub-circuits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplitStrategy {
+    /// Cell `i` is sent on sub-circuit `i % paths`.
+    RoundRobin,
+    /// Each cell is sent on a random sub-circuit, chosen with weights sampled
+    /// uniformly for each circuit.
+    WeightedRandom,
+    /// Batches of consecutive cells, whose sizes are sampled uniformly from
+    /// `min..=max`, are each sent on a sub-circuit chosen as for
+    /// `WeightedRandom`.
+    Batched { min: u16, max: u16 },
+}
+
+/// Splits the cells of a circuit across simulated sub-circuits, as done by
+/// multipath defenses such as TrafficSliver (De la Cadena et al., CCS 2020),
+/// creating one augmented circuit per sub-circuit, in order. The cells keep
+/// their times.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrafficSplit {
+    /// The number of sub-circuits.
+    pub paths: u16,
+    pub strategy: SplitStrategy,
+}
+
+impl TrafficSplit {
+    /// Creates an augmentation splitting circuits across `paths` sub-circuits
+    /// using `strategy`. Panics if `paths` is 0.
+    pub fn new(paths: u16, strategy: SplitStrategy) -> Self {
+        assert!(paths > 0, "traffic must be split across at least one path");
+        Self { paths, strategy }
+    }
+
+    /// Returns the sub-circuit of each valid cell of `circuit`.
+    fn assign(&self, circuit: &Circuit, rng: &mut Rng) -> Vec<usize> {
+        let len = circuit.valid_cells().len();
+        let paths = self.paths as usize;
+
+        let weights: Vec<f64> = (0..paths).map(|_| rng.next_f64()).collect();
+        let total: f64 = weights.iter().sum();
+        let weighted = |rng: &mut Rng| {
+            let mut x = rng.next_f64() * total;
+            for (path, &w) in weights.iter().enumerate() {
+                if x < w {
+                    return path;
+                }
+                x -= w;
+            }
+            paths - 1
+        };
+
+        match self.strategy {
+            SplitStrategy::RoundRobin => (0..len).map(|i| i % paths).collect(),
+            SplitStrategy::WeightedRandom => (0..len).map(|_| weighted(rng)).collect(),
+            SplitStrategy::Batched { min, max } => {
+                let (min, max) = (min.max(1), max.max(min.max(1)));
+                let mut assigned = Vec::with_capacity(len);
+                while assigned.len() < len {
+                    let size = min as u64 + rng.below(u64::from(max - min) + 1);
+                    let path = weighted(rng);
+                    let n = std::cmp::min(size as usize, len - assigned.len());
+                    assigned.extend(std::iter::repeat_n(path, n));
+                }
+                assigned
+            }
+        }
+    }
+}
+
+impl Params for TrafficSplit {
+    fn params(&self) -> String {
+        let strategy = match self.strategy {
+            SplitStrategy::RoundRobin => "round_robin".to_string(),
+            SplitStrategy::WeightedRandom => "weighted_random".to_string(),
+            SplitStrategy::Batched { min, max } => format!("batched(min={min},max={max})"),
+        };
+        format!("traffic_split(paths={},strategy={strategy})", self.paths)
+    }
+}
+
+impl MultiTransform for TrafficSplit {
+    fn apply_all(&self, circuit: &Circuit, rng: &mut Rng) -> Vec<AugmentedCircuit> {
+        let assigned = self.assign(circuit, rng);
+
+        (0..self.paths as usize)
+            .map(|path| {
+                let mut augmented =
+                    AugmentedCircuit::from_circuit(circuit, rng.uuid(), path as u16);
+                let mut kept = assigned.iter();
+                transform::retain_cells(&mut augmented.cells, &mut augmented.len, |_| {
+                    kept.next() == Some(&path)
+                });
+                augmented
+            })
+            .collect()
+    }
+}
+
+/// Truncates each circuit at several fractions of its length, creating one
+/// augmented circuit per fraction in order, e.g., to evaluate classifiers on
+/// partial traces in a single run. The cells keep their times.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Truncations {
+    /// The fractions of the valid cells kept, each rounded up to a whole cell.
+    pub fractions: Vec<f64>,
+}
+
+impl Truncations {
+    /// Creates an augmentation keeping each of `percents` percent of the
+    /// valid cells.
+    pub fn new(percents: &[f64]) -> Self {
+        Self {
+            fractions: percents.iter().map(|p| p / 100.0).collect(),
+        }
+    }
+}
+
+impl Default for Truncations {
+    /// Truncates at 25%, 50%, and 75% of the valid cells.
+    fn default() -> Self {
+        Self::new(&[25.0, 50.0, 75.0])
+    }
+}
+
+impl Params for Truncations {
+    fn params(&self) -> String {
+        format!("truncations(fractions={})", list(&self.fractions))
+    }
+}
+
+impl MultiTransform for Truncations {
+    fn apply_all(&self, circuit: &Circuit, rng: &mut Rng) -> Vec<AugmentedCircuit> {
+        self.fractions
+            .iter()
+            .enumerate()
+            .map(|(k, fraction)| {
+                let mut augmented = AugmentedCircuit::from_circuit(circuit, rng.uuid(), k as u16);
+                let n = (fraction * f64::from(circuit.len)).ceil().max(0.0) as usize;
+                transform::truncate_cells(&mut augmented.cells, &mut augmented.len, n);
+                augmented
+            })
+            .collect()
+    }
+}
+
+/// When `InjectPadding` inserts its padding cells.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PaddingTiming {
+    /// Uniformly at random between the first and last valid cells.
+    Uniform,
+    /// As a Poisson process with this many cells per second, starting at the
+    /// first valid cell.
+    Poisson(f64),
+}
+
+/// Inserts `n` padding cells with the `PADDING` direction and cell command into
+/// each circuit, dropping the latest cells if the circuit would exceed
+/// `MAX_CELLS` cells, e.g., to augment training data with noise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InjectPadding {
+    pub n: u16,
+    pub timing: PaddingTiming,
+}
+
+impl InjectPadding {
+    /// Creates an augmentation inserting `n` padding cells at times sampled
+    /// according to `timing`.
+    pub fn new(n: u16, timing: PaddingTiming) -> Self {
+        Self { n, timing }
+    }
+}
+
+impl Params for InjectPadding {
+    fn params(&self) -> String {
+        let timing = match self.timing {
+            PaddingTiming::Uniform => "uniform".to_string(),
+            PaddingTiming::Poisson(rate) => format!("poisson({rate})"),
+        };
+        format!("inject_padding(n={},timing={timing})", self.n)
+    }
+}
+
+impl CircuitTransform for InjectPadding {
+    fn augment_cells(&self, cells: &mut [Cell], len: &mut u16, rng: &mut Rng) {
+        let valid = &cells[..std::cmp::min(*len as usize, cells.len())];
+        let (Some(first), Some(last)) = (valid.first(), valid.last()) else {
+            return;
+        };
+        let (start, end) = (first.time, last.time);
+
+        let mut time = start;
+        let padding = (0..self.n)
+            .map(|_| {
+                time = match self.timing {
+                    PaddingTiming::Uniform => start + (end - start) * rng.next_f64(),
+                    PaddingTiming::Poisson(rate) => time + exponential(rate, rng),
+                };
+                padding_cell(time, Direction::PADDING)
+            })
+            .collect();
+        insert_cells(cells, len, padding);
+    }
+}
+
+/// The distribution of the noise added to times by `Jitter`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Noise {
+    /// Normally distributed with mean 0 and this standard deviation in
+    /// seconds.
+    Gaussian(f64),
+    /// Laplace distributed with mean 0 and this scale in seconds.
+    Laplace(f64),
+}
+
+impl Noise {
+    /// Samples the noise in seconds.
+    pub fn sample(&self, rng: &mut Rng) -> f64 {
+        match *self {
+            Noise::Gaussian(sigma) => sigma * standard_normal(rng),
+            Noise::Laplace(scale) => {
+                // Sampled from the open interval (-0.5, 0.5), as the logarithm
+                // below is infinite at -0.5.
+                let u = (rng.next_f64() + f64::EPSILON).min(1.0 - f64::EPSILON) - 0.5;
+                -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+            }
+        }
+    }
+}
+
+/// Perturbs the time of each valid cell with random noise, to make models
+/// robust to variation in network timing. Times are not moved below 0, and
+/// cells keep their order: a cell whose perturbed time would precede the cell
+/// before it is moved to that cell's time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Jitter {
+    pub noise: Noise,
+    /// Add the same noise to all cells of a burst of consecutive cells sent in
+    /// the same direction, instead of to each cell independently.
+    pub per_burst: bool,
+}
+
+impl Jitter {
+    /// Creates an augmentation adding `noise` to each cell, or to each burst if
+    /// `per_burst` is true.
+    pub fn new(noise: Noise, per_burst: bool) -> Self {
+        Self { noise, per_burst }
+    }
+}
+
+impl Params for Jitter {
+    fn params(&self) -> String {
+        let noise = match self.noise {
+            Noise::Gaussian(sigma) => format!("gaussian({sigma})"),
+            Noise::Laplace(scale) => format!("laplace({scale})"),
+        };
+        format!("jitter(noise={noise},per_burst={})", self.per_burst)
+    }
+}
+
+impl CircuitTransform for Jitter {
+    fn augment_cells(&self, cells: &mut [Cell], len: &mut u16, rng: &mut Rng) {
+        let valid = std::cmp::min(*len as usize, cells.len());
+        let mut noise = 0.0;
+        let mut direction = None;
+
+        for cell in cells[..valid].iter_mut() {
+            if !self.per_burst || direction != Some(cell.direction) {
+                noise = self.noise.sample(rng);
+            }
+            direction = Some(cell.direction);
+            cell.time = (cell.time + noise).max(0.0);
+        }
+
+        for i in 1..valid {
+            cells[i].time = cells[i].time.max(cells[i - 1].time);
+        }
+    }
+}
+
+/// Samples the standard normal distribution (Box-Muller transform).
+fn standard_normal(rng: &mut Rng) -> f64 {
+    let u = 1.0 - rng.next_f64();
+    let v = rng.next_f64();
+    (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+}
+
+/// Which cells `RandomDrop` removes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DropMode {
+    /// Each cell independently with this probability.
+    Independent(f64),
+    /// This many spans of consecutive cells, whose lengths are sampled
+    /// uniformly from `1..=max_len` and whose starts are uniformly random.
+    Spans { count: u16, max_len: u16 },
+}
+
+/// Removes random cells, simulating cell loss and partial observation. If the
+/// first valid cell is removed, the times of the remaining cells are shifted
+/// so that the new first cell has the time of the original first cell.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RandomDrop {
+    pub mode: DropMode,
+}
+
+impl RandomDrop {
+    /// Creates an augmentation removing the cells selected by `mode`.
+    pub fn new(mode: DropMode) -> Self {
+        Self { mode }
+    }
+}
+
+impl Params for RandomDrop {
+    fn params(&self) -> String {
+        let mode = match self.mode {
+            DropMode::Independent(p) => format!("independent({p})"),
+            DropMode::Spans { count, max_len } => format!("spans(count={count},max_len={max_len})"),
+        };
+        format!("random_drop(mode={mode})")
+    }
+}
+
+impl CircuitTransform for RandomDrop {
+    fn augment_cells(&self, cells: &mut [Cell], len: &mut u16, rng: &mut Rng) {
+        let valid = std::cmp::min(*len as usize, cells.len());
+        if valid == 0 {
+            return;
+        }
+        let start = cells[0].time;
+
+        let drop: Vec<bool> = match self.mode {
+            DropMode::Independent(p) => (0..valid).map(|_| rng.next_f64() < p).collect(),
+            DropMode::Spans { count, max_len } => {
+                let mut drop = vec![false; valid];
+                for _ in 0..count {
+                    let span = 1 + rng.below(u64::from(max_len.max(1))) as usize;
+                    let begin = rng.below(valid as u64) as usize;
+                    let end = std::cmp::min(begin + span, valid);
+                    drop[begin..end].fill(true);
+                }
+                drop
+            }
+        };
+
+        let mut dropped = drop.iter();
+        transform::retain_cells(cells, len, |_| dropped.next() == Some(&false));
+
+        if *len > 0 {
+            let shift = cells[0].time - start;
+            for cell in cells[..*len as usize].iter_mut() {
+                cell.time -= shift;
+            }
+        }
+    }
+}
+
+/// Overlays each circuit with a circuit of a different label drawn from a
+/// pool, interleaving their cells by time, to simulate browsing in multiple
+/// tabs at once. The cells of the drawn circuit are delayed by an offset
+/// sampled uniformly from `0..max_offset` seconds, relative to the first cell
+/// of each circuit. The mixed circuit is linked to the original, dominant
+/// circuit with an `aug_index` of 0, and the latest cells are dropped if it
+/// would exceed `MAX_CELLS` cells. Circuits for which the pool has no circuit of a
+/// different label are written unmixed.
+#[derive(Clone)]
+pub struct TraceMix {
+    pool: Vec<Circuit>,
+    pub max_offset: f64,
+}
+
+impl TraceMix {
+    /// Creates an augmentation drawing the overlaid circuits from `pool`.
+    pub fn new(pool: Vec<Circuit>, max_offset: f64) -> Self {
+        Self { pool, max_offset }
+    }
+
+    /// Returns `dominant` overlaid with the cells of `other`, delayed by
+    /// `offset` seconds.
+    pub fn mix(
+        &self,
+        dominant: &Circuit,
+        other: &Circuit,
+        offset: f64,
+        rng: &mut Rng,
+    ) -> AugmentedCircuit {
+        let mut mixed = AugmentedCircuit::from_circuit(dominant, rng.uuid(), 0);
+        let start = mixed.cells.first().map_or(0.0, |c| c.time);
+
+        let other_cells = other.valid_cells();
+        let other_start = other_cells.first().map_or(0.0, |c| c.time);
+        let extra = other_cells
+            .iter()
+            .map(|c| Cell {
+                time: c.time - other_start + start + offset,
+                ..*c
+            })
+            .collect();
+        insert_cells(&mut mixed.cells, &mut mixed.len, extra);
+        mixed
+    }
+
+    /// Draws a circuit from the pool with a label other than `label`, trying
+    /// a limited number of times.
+    fn draw(&self, label: &str, rng: &mut Rng) -> Option<&Circuit> {
+        if self.pool.is_empty() {
+            return None;
+        }
+        (0..MIX_DRAWS)
+            .map(|_| &self.pool[rng.below(self.pool.len() as u64) as usize])
+            .find(|c| c.label().as_str() != label)
+    }
+}
+
+// The pool is summarized by its size, since listing its circuits would bury the
+// other parameters.
+impl fmt::Debug for TraceMix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TraceMix")
+            .field("pool", &self.pool.len())
+            .field("max_offset", &self.max_offset)
+            .finish()
+    }
+}
+
+/// The number of times `TraceMix` draws from its pool to find a circuit with a
+/// different label.
+const MIX_DRAWS: usize = 100;
+
+// As for `Debug`, the pool is summarized by its size.
+impl Params for TraceMix {
+    fn params(&self) -> String {
+        format!(
+            "trace_mix(pool={},max_offset={})",
+            self.pool.len(),
+            self.max_offset
+        )
+    }
+}
+
+impl MultiTransform for TraceMix {
+    fn apply_all(&self, circuit: &Circuit, rng: &mut Rng) -> Vec<AugmentedCircuit> {
+        let label = circuit.label();
+        let mixed = match self.draw(label.as_str(), rng) {
+            Some(other) => {
+                let offset = self.max_offset * rng.next_f64();
+                self.mix(circuit, other, offset, rng)
+            }
+            None => AugmentedCircuit::from_circuit(circuit, rng.uuid(), 0),
+        };
+        vec![mixed]
+    }
+}
+
+/// Overlays each circuit with the cells of background circuits, e.g., circuits
+/// with designated decoy labels, to simulate decoy-traffic defenses that load
+/// other pages alongside the real one (Panchenko et al., WPES 2011).
+///
+/// For a circuit with `len` valid cells, `ratio * len` decoy cells are added:
+/// circuits are drawn from the pool, and the cells of each, from its first
+/// cell, are interleaved by time starting at the first cell of the circuit,
+/// until enough cells have been added. The latest cells are dropped if the
+/// circuit would exceed `MAX_CELLS` cells.
+#[derive(Clone)]
+pub struct Decoy {
+    pool: Vec<Circuit>,
+    /// The number of decoy cells added per valid cell.
+    pub ratio: f64,
+}
+
+impl Decoy {
+    /// Creates an augmentation drawing the decoy circuits from the non-empty
+    /// circuits of `pool` and adding `ratio` decoy cells per cell.
+    pub fn new(mut pool: Vec<Circuit>, ratio: f64) -> Self {
+        pool.retain(|c| c.len > 0);
+        Self { pool, ratio }
+    }
+}
+
+// The pool is summarized by its size, as for `TraceMix`.
+impl fmt::Debug for Decoy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Decoy")
+            .field("pool", &self.pool.len())
+            .field("ratio", &self.ratio)
+            .finish()
+    }
+}
+
+impl Params for Decoy {
+    fn params(&self) -> String {
+        format!("decoy(pool={},ratio={})", self.pool.len(), self.ratio)
+    }
+}
+
+impl CircuitTransform for Decoy {
+    fn augment_cells(&self, cells: &mut [Cell], len: &mut u16, rng: &mut Rng) {
+        let valid = std::cmp::min(*len as usize, cells.len());
+        if valid == 0 || self.pool.is_empty() {
+            return;
+        }
+        let start = cells[0].time;
+
+        // Decoy cells beyond the capacity of the circuit would be dropped.
+        let wanted = std::cmp::min((self.ratio * valid as f64).round() as usize, cells.len());
+        let mut extra: Vec<Cell> = Vec::with_capacity(wanted);
+        while extra.len() < wanted {
+            let decoy = &self.pool[rng.below(self.pool.len() as u64) as usize];
+            let decoy_cells = decoy.valid_cells();
+            let decoy_start = decoy_cells[0].time;
+            let n = std::cmp::min(wanted - extra.len(), decoy_cells.len());
+            extra.extend(decoy_cells[..n].iter().map(|c| Cell {
+                time: c.time - decoy_start + start,
+                ..*c
+            }));
+        }
+        insert_cells(cells, len, extra);
+    }
+}
+
+/// The name of `direction` in `Params`.
+pub(crate) fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::CLIENT_TO_SERVER => "client_to_server",
+        Direction::SERVER_TO_CLIENT => "server_to_client",
+        Direction::PADDING => "padding",
+    }
+}
+
+/// Samples the exponential distribution with `rate`, i.e., the time between
+/// the events of a Poisson process.
+pub fn exponential(rate: f64, rng: &mut Rng) -> f64 {
+    -(1.0 - rng.next_f64()).ln() / rate
+}
+
+/// Returns a dummy cell sent in `direction` at `time`, as injected by padding
+/// defenses: a `PADDING` cell command without a relay command.
+pub fn padding_cell(time: f64, direction: Direction) -> Cell {
+    Cell {
+        time,
+        direction,
+        cell_cmd: CellCommand::PADDING,
+        relay_cmd: RelayCommand::NOT_PRESENT,
+    }
+}
+
+/// Inserts the `extra` cells among the valid cells `cells[..*len]` so that
+/// the cells stay in order of time, with existing cells before inserted cells
+/// of equal time, and then updates `len` and zeroes the rest. If there are more
+/// cells than fit in `cells`, the latest cells are dropped.
+pub fn insert_cells(cells: &mut [Cell], len: &mut u16, mut extra: Vec<Cell>) {
+    let valid = std::cmp::min(*len as usize, cells.len());
+    extra.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    let capacity = std::cmp::min(cells.len(), u16::MAX as usize);
+    let mut merged = Vec::with_capacity(std::cmp::min(valid + extra.len(), capacity));
+    let (mut i, mut j) = (0, 0);
+    while merged.len() < capacity && (i < valid || j < extra.len()) {
+        if j >= extra.len() || (i < valid && cells[i].time <= extra[j].time) {
+            merged.push(cells[i]);
+            i += 1;
+        } else {
+            merged.push(extra[j]);
+            j += 1;
+        }
+    }
+
+    cells[..merged.len()].copy_from_slice(&merged);
+    cells[merged.len()..].fill(Cell::empty());
+    *len = merged.len() as u16;
+}
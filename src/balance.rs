@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use hdf5::types::FixedAscii;
+
+use crate::rng::Rng;
+use crate::sample;
+use crate::{CircuitIndex, CircuitMeta};
+
+/// The circuits chosen to balance a dataset: the original circuits to keep,
+/// and the circuits to copy as augmented duplicates of small labels.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BalancePlan {
+    /// The indices of the circuits to keep, sorted.
+    pub keep: Vec<CircuitIndex>,
+    /// The indices of the circuits to duplicate, sorted, with an index
+    /// repeated once per duplicate of its circuit.
+    pub duplicates: Vec<CircuitIndex>,
+}
+
+/// Equalizes the number of circuits per label, either by downsampling the
+/// labels with more circuits than a target or by also oversampling the labels
+/// with fewer.
+#[derive(Clone, Debug, Default)]
+pub struct Balancer {
+    labels: HashMap<FixedAscii<44>, Vec<CircuitIndex>>,
+}
+
+impl Balancer {
+    /// Creates a balancer that has not yet seen any circuits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the circuit at position `index` in the dataset.
+    pub fn add(&mut self, index: CircuitIndex, meta: &CircuitMeta) {
+        self.labels.entry(meta.label()).or_default().push(index);
+    }
+
+    /// Chooses `target` circuits of each label. Labels with more circuits are
+    /// downsampled uniformly at random. If `oversample` is set, labels with
+    /// fewer circuits keep all of them and duplicate them as evenly as possible
+    /// to reach the target; otherwise they are kept as they are.
+    ///
+    /// The target defaults to the size of the smallest label when downsampling
+    /// and of the largest label when oversampling.
+    pub fn select(&self, target: Option<usize>, oversample: bool, rng: &mut Rng) -> BalancePlan {
+        let sizes = self.labels.values().map(|v| v.len());
+        let target = match (target, oversample) {
+            (Some(target), _) => target,
+            (None, false) => sizes.min().unwrap_or(0),
+            (None, true) => sizes.max().unwrap_or(0),
+        };
+
+        let mut labels: Vec<(&FixedAscii<44>, &Vec<CircuitIndex>)> = self.labels.iter().collect();
+        labels.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+
+        let mut plan = BalancePlan::default();
+        for (_, indices) in labels {
+            let mut indices = indices.clone();
+            indices.sort();
+
+            if indices.len() >= target {
+                plan.keep
+                    .extend(sample::uniform_from(&indices, target, rng));
+                continue;
+            }
+
+            plan.keep.extend_from_slice(&indices);
+            if oversample {
+                // Cycle through the circuits in a random order so that no
+                // circuit is duplicated more than once more than any other.
+                let mut order = indices.clone();
+                rng.shuffle(&mut order);
+                plan.duplicates
+                    .extend(order.iter().cycle().take(target - indices.len()));
+            }
+        }
+
+        plan.keep.sort();
+        plan.duplicates.sort();
+        plan
+    }
+}
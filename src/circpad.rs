@@ -0,0 +1,378 @@
+use crate::augment::{self, CircuitTransform, Params, list};
+use crate::defense::Histogram;
+use crate::rng::Rng;
+use crate::{Cell, Direction};
+
+/// The most states a machine may enter at one instant, which bounds chains of
+/// transitions on `InfinitySampled` events that never schedule padding.
+const MAX_CHAIN: usize = 100;
+
+/// The end of the circuit on which a padding machine runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    /// The client, which sends cells toward the server.
+    Client,
+    /// The relay, which sends cells toward the client.
+    Relay,
+}
+
+impl Side {
+    /// The direction of the cells sent from this side.
+    pub fn direction(&self) -> Direction {
+        match self {
+            Side::Client => Direction::CLIENT_TO_SERVER,
+            Side::Relay => Direction::SERVER_TO_CLIENT,
+        }
+    }
+}
+
+/// The events on which a padding machine changes state, named as in Tor's
+/// circuit padding framework.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The machine's side sent a real cell.
+    NonpaddingSent,
+    /// The machine's side received a real cell.
+    NonpaddingRecv,
+    /// The machine sent a padding cell.
+    PaddingSent,
+    /// The machine's side received a padding cell from the other side.
+    PaddingRecv,
+    /// The infinity bin of the state's histogram was sampled.
+    InfinitySampled,
+    /// The machine sent the maximum number of padding cells of the state.
+    LengthCount,
+}
+
+/// Where a padding machine goes on an event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Next {
+    /// Enter the state with this index, which restarts it if it is the
+    /// current state.
+    State(usize),
+    /// Stop the machine, which sends no more padding.
+    End,
+}
+
+/// A state of a padding machine.
+#[derive(Clone, Debug, PartialEq)]
+pub struct State {
+    /// The delays in seconds before each padding cell sent in this state.
+    pub delay: Histogram,
+    /// The maximum number of padding cells sent in this state, if limited.
+    pub length: Option<u32>,
+    /// The transitions taken on events in this state; the first transition
+    /// for an event is taken and events without one are ignored.
+    pub transitions: Vec<(Event, Next)>,
+}
+
+impl State {
+    /// Creates a state sampling delays from `delay`, with no length limit or
+    /// transitions.
+    pub fn new(delay: Histogram) -> Self {
+        Self {
+            delay,
+            length: None,
+            transitions: Vec::new(),
+        }
+    }
+
+    /// Limits the padding cells sent in this state to `length`.
+    pub fn with_length(mut self, length: u32) -> Self {
+        self.length = Some(length);
+        self
+    }
+
+    /// Adds a transition to `next` on `event`.
+    pub fn on(mut self, event: Event, next: Next) -> Self {
+        self.transitions.push((event, next));
+        self
+    }
+
+    fn next(&self, event: Event) -> Option<Next> {
+        self.transitions
+            .iter()
+            .find(|(e, _)| *e == event)
+            .map(|(_, next)| *next)
+    }
+}
+
+/// A padding machine in the style of Tor's circuit padding framework
+/// (`circpad`), which starts in state 0.
+///
+/// On entering a state, a delay is sampled from its histogram and a padding
+/// cell is scheduled after it, or the `InfinitySampled` event occurs if the
+/// infinity bin is sampled. After sending a padding cell, the machine samples
+/// another delay unless the `PaddingSent` event causes a transition or the
+/// state's length is reached, in which case the `LengthCount` event occurs and
+/// no more padding is sent in the state. Real cells do not cancel scheduled
+/// padding unless they cause a transition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Machine {
+    /// The side that runs the machine and sends its padding.
+    pub side: Side,
+    /// The states, indexed by `Next::State`.
+    pub states: Vec<State>,
+}
+
+impl Machine {
+    /// Creates a machine running on `side` with `states`, which must not be
+    /// empty and whose transitions must name existing states.
+    pub fn new(side: Side, states: Vec<State>) -> Result<Self, String> {
+        if states.is_empty() {
+            return Err("Padding machine has no states".to_string());
+        }
+        for (k, state) in states.iter().enumerate() {
+            for (event, next) in state.transitions.iter() {
+                match next {
+                    Next::State(to) if *to >= states.len() => {
+                        return Err(format!(
+                            "State {k} transitions to unknown state {to} on {event:?}"
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(Self { side, states })
+    }
+}
+
+/// The execution of a machine on a circuit.
+struct Run<'a> {
+    machine: &'a Machine,
+    /// The current state, or `None` once the machine has ended.
+    state: Option<usize>,
+    /// The time of the next padding cell, if scheduled.
+    scheduled: Option<f64>,
+    /// The number of padding cells that may still be sent in the state.
+    remaining: Option<u32>,
+}
+
+impl<'a> Run<'a> {
+    fn new(machine: &'a Machine, now: f64, rng: &mut Rng) -> Self {
+        let mut run = Self {
+            machine,
+            state: None,
+            scheduled: None,
+            remaining: None,
+        };
+        run.enter(Next::State(0), now, rng);
+        run
+    }
+
+    /// Goes to `next` at time `now`, following transitions on the
+    /// `InfinitySampled` event.
+    fn enter(&mut self, next: Next, now: f64, rng: &mut Rng) {
+        let mut next = next;
+        for _ in 0..MAX_CHAIN {
+            self.scheduled = None;
+            let Next::State(k) = next else {
+                self.state = None;
+                return;
+            };
+            let state = &self.machine.states[k];
+            self.state = Some(k);
+            self.remaining = state.length;
+            if self.remaining == Some(0) {
+                return;
+            }
+            match state.delay.sample(rng) {
+                Some(delay) => {
+                    self.scheduled = Some(now + delay);
+                    return;
+                }
+                None => match state.next(Event::InfinitySampled) {
+                    Some(n) => next = n,
+                    None => return,
+                },
+            }
+        }
+    }
+
+    /// Handles `event` at time `now`, and returns true if it caused a
+    /// transition.
+    fn handle(&mut self, event: Event, now: f64, rng: &mut Rng) -> bool {
+        let Some(k) = self.state else {
+            return false;
+        };
+        match self.machine.states[k].next(event) {
+            Some(next) => {
+                self.enter(next, now, rng);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Records a padding cell sent at time `now` and schedules the next one.
+    fn sent_padding(&mut self, now: f64, rng: &mut Rng) {
+        self.scheduled = None;
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining = remaining.saturating_sub(1);
+        }
+        if self.handle(Event::PaddingSent, now, rng) {
+            return;
+        }
+        if self.remaining == Some(0) {
+            self.handle(Event::LengthCount, now, rng);
+            return;
+        }
+        if let Some(k) = self.state {
+            self.scheduled = self.machine.states[k].delay.sample(rng).map(|d| now + d);
+            if self.scheduled.is_none() {
+                self.handle(Event::InfinitySampled, now, rng);
+            }
+        }
+    }
+}
+
+/// Runs circuit padding machines against the cells of a circuit, injecting the
+/// `PADDING` cells they send, so that padding machines proposed for Tor can be
+/// evaluated on real traces.
+///
+/// The machines start at the first valid cell and observe the real cells in
+/// order of time, as sent or received by their side, and the padding cells
+/// sent by the other machines, as received by machines on the other side.
+/// Cells are delivered without delay, padding is not sent after the last real
+/// cell so that the duration of the circuit is not changed, and real cells are
+/// never delayed. The latest cells are dropped if the circuit would exceed
+/// `MAX_CELLS` cells.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Circpad {
+    pub machines: Vec<Machine>,
+}
+
+impl Circpad {
+    /// Creates a defense running `machines`.
+    pub fn new(machines: Vec<Machine>) -> Self {
+        Self { machines }
+    }
+
+    /// Returns the padding cells that the machines send among the valid
+    /// `cells`, stopping after `max` cells.
+    pub fn padding(&self, cells: &[Cell], max: usize, rng: &mut Rng) -> Vec<Cell> {
+        let (Some(start), Some(end)) = (cells.first(), cells.last()) else {
+            return Vec::new();
+        };
+        let (start, end) = (start.time, end.time);
+        let mut runs: Vec<Run> = self
+            .machines
+            .iter()
+            .map(|m| Run::new(m, start, rng))
+            .collect();
+
+        let mut padding = Vec::new();
+        let mut real = cells.iter().peekable();
+        while padding.len() < max {
+            let due = runs
+                .iter()
+                .enumerate()
+                .filter_map(|(i, r)| r.scheduled.map(|t| (i, t)))
+                .filter(|&(_, t)| t <= end)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+
+            match (due, real.peek().copied()) {
+                (Some((i, time)), cell) if cell.is_none_or(|c| time < c.time) => {
+                    let side = runs[i].machine.side;
+                    padding.push(augment::padding_cell(time, side.direction()));
+                    runs[i].sent_padding(time, rng);
+                    for run in runs.iter_mut().filter(|r| r.machine.side != side) {
+                        run.handle(Event::PaddingRecv, time, rng);
+                    }
+                }
+                (_, Some(cell)) => {
+                    for run in runs.iter_mut() {
+                        let event = match cell.direction {
+                            d if d == run.machine.side.direction() => Event::NonpaddingSent,
+                            Direction::PADDING => continue,
+                            _ => Event::NonpaddingRecv,
+                        };
+                        run.handle(event, cell.time, rng);
+                    }
+                    real.next();
+                }
+                (None, None) => break,
+            }
+        }
+        padding
+    }
+}
+
+impl Params for State {
+    fn params(&self) -> String {
+        let length = self.length.map_or("none".to_string(), |n| n.to_string());
+        let transitions = self.transitions.iter().map(|(event, next)| {
+            let next = match next {
+                Next::State(k) => k.to_string(),
+                Next::End => "end".to_string(),
+            };
+            format!("{}:{next}", event_name(*event))
+        });
+        format!(
+            "state(delay={},length={length},transitions={})",
+            self.delay.params(),
+            list(transitions)
+        )
+    }
+}
+
+impl Params for Machine {
+    fn params(&self) -> String {
+        let side = match self.side {
+            Side::Client => "client",
+            Side::Relay => "relay",
+        };
+        format!(
+            "machine(side={side},states={})",
+            list(self.states.iter().map(|s| s.params()))
+        )
+    }
+}
+
+impl Params for Circpad {
+    fn params(&self) -> String {
+        format!(
+            "circpad(machines={})",
+            list(self.machines.iter().map(|m| m.params()))
+        )
+    }
+}
+
+impl CircuitTransform for Circpad {
+    fn augment_cells(&self, cells: &mut [Cell], len: &mut u16, rng: &mut Rng) {
+        let valid = &cells[..std::cmp::min(*len as usize, cells.len())];
+        let padding = self.padding(valid, cells.len() - valid.len(), rng);
+        augment::insert_cells(cells, len, padding);
+    }
+}
+
+/// The name of `event` as accepted by `parse_event`, e.g., `nonpadding-sent`.
+pub fn event_name(event: Event) -> &'static str {
+    match event {
+        Event::NonpaddingSent => "nonpadding-sent",
+        Event::NonpaddingRecv => "nonpadding-recv",
+        Event::PaddingSent => "padding-sent",
+        Event::PaddingRecv => "padding-recv",
+        Event::InfinitySampled => "infinity-sampled",
+        Event::LengthCount => "length-count",
+    }
+}
+
+/// Parses an event from its name in Tor's circuit padding framework, e.g.,
+/// `nonpadding-sent` or `CIRCPAD_EVENT_NONPADDING_SENT`, case insensitive.
+pub fn parse_event(name: &str) -> Result<Event, String> {
+    let normalized = name.trim().to_ascii_lowercase().replace('_', "-");
+    let normalized = normalized
+        .strip_prefix("circpad-event-")
+        .unwrap_or(&normalized);
+    match normalized {
+        "nonpadding-sent" => Ok(Event::NonpaddingSent),
+        "nonpadding-recv" => Ok(Event::NonpaddingRecv),
+        "padding-sent" => Ok(Event::PaddingSent),
+        "padding-recv" => Ok(Event::PaddingRecv),
+        "infinity" | "infinity-sampled" => Ok(Event::InfinitySampled),
+        "length-count" => Ok(Event::LengthCount),
+        _ => Err(format!("unknown padding event '{name}'")),
+    }
+}
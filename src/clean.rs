@@ -0,0 +1,530 @@
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use hdf5::types::FixedAscii;
+use hdf5::{File, H5Type};
+use ndarray::Array1;
+
+use crate::features::percentile;
+use crate::index::{fnv1a, write_index};
+use crate::{CellCommand, Circuit, CircuitIndex, CircuitMeta, Direction, RelayCommand};
+
+/// The reason a circuit is considered degenerate and dropped during cleanup.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum DegenerateKind {
+    /// The circuit has no cells.
+    Empty,
+    /// The circuit has no RELAY or RELAY_EARLY cells, only control cells.
+    ControlOnly,
+    /// Every cell of the circuit has the same timestamp.
+    ConstantTime,
+}
+
+impl DegenerateKind {
+    /// A short name for the reason, suitable for reports.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DegenerateKind::Empty => "empty",
+            DegenerateKind::ControlOnly => "control_only",
+            DegenerateKind::ConstantTime => "constant_time",
+        }
+    }
+}
+
+impl fmt::Display for DegenerateKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Returns the reason `circuit` is degenerate, or `None` if it should be kept.
+/// When several reasons apply, the first in the order of `DegenerateKind` is
+/// returned.
+pub fn degenerate(circuit: &Circuit) -> Option<DegenerateKind> {
+    let cells = circuit.valid_cells();
+
+    if cells.is_empty() {
+        return Some(DegenerateKind::Empty);
+    }
+    if !cells
+        .iter()
+        .any(|c| matches!(c.cell_cmd, CellCommand::RELAY | CellCommand::RELAY_EARLY))
+    {
+        return Some(DegenerateKind::ControlOnly);
+    }
+    if cells.len() > 1 && cells.iter().all(|c| c.time == cells[0].time) {
+        return Some(DegenerateKind::ConstantTime);
+    }
+    None
+}
+
+/// Counts the circuits kept and dropped during cleanup, by reason.
+#[derive(Clone, Debug, Default)]
+pub struct CleanReport {
+    kept: usize,
+    dropped: BTreeMap<DegenerateKind, usize>,
+}
+
+impl CleanReport {
+    /// Creates a report that has not yet seen any circuits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks `circuit`, records the outcome, and returns true if it should be
+    /// kept.
+    pub fn check(&mut self, circuit: &Circuit) -> bool {
+        match degenerate(circuit) {
+            Some(kind) => {
+                *self.dropped.entry(kind).or_default() += 1;
+                false
+            }
+            None => {
+                self.kept += 1;
+                true
+            }
+        }
+    }
+
+    /// The number of circuits kept.
+    pub fn kept(&self) -> usize {
+        self.kept
+    }
+
+    /// The number of circuits dropped for each reason.
+    pub fn dropped(&self) -> &BTreeMap<DegenerateKind, usize> {
+        &self.dropped
+    }
+
+    /// The total number of circuits dropped.
+    pub fn total_dropped(&self) -> usize {
+        self.dropped.values().sum()
+    }
+}
+
+/// The kind of protocol inconsistency found in a circuit.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum AnomalyKind {
+    /// A cell has a relay command but is not a RELAY or RELAY_EARLY cell.
+    RelayCmdOnControlCell,
+    /// A SIGNAL cell appears after the first DATA cell; the measurement only
+    /// sends signals while the circuit is being set up.
+    LateSignal,
+}
+
+impl AnomalyKind {
+    /// A short name for the kind of anomaly, suitable for reports.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnomalyKind::RelayCmdOnControlCell => "relay_cmd_on_control_cell",
+            AnomalyKind::LateSignal => "late_signal",
+        }
+    }
+}
+
+impl fmt::Display for AnomalyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An anomaly found at position `cell` of a circuit's cells.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    pub cell: usize,
+}
+
+/// Returns the first anomaly of each kind in the valid cells of `circuit`,
+/// ordered by kind. An empty result means the circuit is consistent.
+pub fn anomalies(circuit: &Circuit) -> Vec<Anomaly> {
+    let cells = circuit.valid_cells();
+    let mut found = Vec::new();
+
+    if let Some(cell) = cells.iter().position(|c| {
+        c.relay_cmd != RelayCommand::NOT_PRESENT
+            && !matches!(c.cell_cmd, CellCommand::RELAY | CellCommand::RELAY_EARLY)
+    }) {
+        found.push(Anomaly {
+            kind: AnomalyKind::RelayCmdOnControlCell,
+            cell,
+        });
+    }
+
+    let first_data = cells.iter().position(|c| c.relay_cmd == RelayCommand::DATA);
+    if let Some(cell) = first_data.and_then(|first| {
+        cells[first..]
+            .iter()
+            .position(|c| c.relay_cmd == RelayCommand::SIGNAL)
+            .map(|i| first + i)
+    }) {
+        found.push(Anomaly {
+            kind: AnomalyKind::LateSignal,
+            cell,
+        });
+    }
+
+    found
+}
+
+/// Counts the circuits with anomalies, by kind.
+#[derive(Clone, Debug, Default)]
+pub struct AnomalyReport {
+    checked: usize,
+    flagged: usize,
+    counts: BTreeMap<AnomalyKind, usize>,
+}
+
+impl AnomalyReport {
+    /// Creates a report that has not yet seen any circuits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks `circuit`, records its anomalies, and returns true if it is
+    /// consistent.
+    pub fn check(&mut self, circuit: &Circuit) -> bool {
+        let found = anomalies(circuit);
+        self.checked += 1;
+        if !found.is_empty() {
+            self.flagged += 1;
+        }
+        for anomaly in found.iter() {
+            *self.counts.entry(anomaly.kind).or_default() += 1;
+        }
+        found.is_empty()
+    }
+
+    /// The number of circuits checked.
+    pub fn checked(&self) -> usize {
+        self.checked
+    }
+
+    /// The number of circuits with at least one anomaly.
+    pub fn flagged(&self) -> usize {
+        self.flagged
+    }
+
+    /// The number of circuits with an anomaly of each kind.
+    pub fn counts(&self) -> &BTreeMap<AnomalyKind, usize> {
+        &self.counts
+    }
+}
+
+/// Hashes the sequence of (direction, cell command, relay command, time) of the
+/// valid cells of `circuit`, with times taken relative to the first cell. If
+/// `quantum` is given, times are rounded to multiples of it so that traces
+/// differing only by small timing jitter are near duplicates with the same
+/// fingerprint; otherwise only exact duplicates share a fingerprint.
+pub fn fingerprint(circuit: &Circuit, quantum: Option<f64>) -> u64 {
+    let cells = circuit.valid_cells();
+    let start = cells.first().map_or(0.0, |c| c.time);
+
+    let mut bytes = Vec::with_capacity(cells.len() * 11);
+    for cell in cells {
+        let time = cell.time - start;
+        let time = match quantum {
+            Some(q) if q > 0.0 => ((time / q).round() as i64).to_le_bytes(),
+            _ => time.to_bits().to_le_bytes(),
+        };
+        bytes.push(cell.direction as i8 as u8);
+        bytes.push(cell.cell_cmd as u8);
+        bytes.push(cell.relay_cmd as u8);
+        bytes.extend_from_slice(&time);
+    }
+
+    fnv1a(&bytes, 0xcbf29ce484222325)
+}
+
+/// Finds circuits whose fingerprints match that of an earlier circuit.
+#[derive(Clone, Debug)]
+pub struct Deduplicator {
+    quantum: Option<f64>,
+    seen: HashMap<u64, (CircuitIndex, FixedAscii<32>)>,
+    duplicates: usize,
+}
+
+impl Deduplicator {
+    /// Creates a deduplicator that compares fingerprints computed with
+    /// `quantum`, as in `fingerprint`.
+    pub fn new(quantum: Option<f64>) -> Self {
+        Self {
+            quantum,
+            seen: HashMap::new(),
+            duplicates: 0,
+        }
+    }
+
+    /// Checks the circuit at position `index` in the dataset. Returns the index
+    /// and uuid of the first circuit checked with the same fingerprint if it is
+    /// a duplicate, and `None` if it is the first of its kind.
+    pub fn check(
+        &mut self,
+        index: CircuitIndex,
+        circuit: &Circuit,
+    ) -> Option<(CircuitIndex, FixedAscii<32>)> {
+        match self.seen.entry(fingerprint(circuit, self.quantum)) {
+            Entry::Occupied(e) => {
+                self.duplicates += 1;
+                Some(*e.get())
+            }
+            Entry::Vacant(e) => {
+                e.insert((index, circuit.uuid));
+                None
+            }
+        }
+    }
+
+    /// The number of distinct fingerprints seen.
+    pub fn unique(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// The number of duplicates found.
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+}
+
+/// The name of the dataset listing the circuits flagged by an
+/// `OutlierDetector`, which may be excluded when filtering.
+pub const OUTLIERS_NAME: &str = "/outliers";
+
+/// The reason a circuit is flagged as a statistical outlier. Outliers are
+/// well-formed circuits that are unusual enough to possibly be measurement
+/// artifacts, so they are listed rather than dropped.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum OutlierKind {
+    /// The time from the first to the last cell is far outside the range of
+    /// durations of the other circuits.
+    ExtremeDuration,
+    /// Many cells have the same time as the cell before them.
+    ZeroGaps,
+    /// Every cell was sent in the same direction.
+    OneDirection,
+    /// Many cells repeat the time, direction, and commands of the cell before
+    /// them, as if recorded twice.
+    DuplicateTimestamps,
+}
+
+impl OutlierKind {
+    /// Every kind of outlier, in order.
+    pub const ALL: [OutlierKind; 4] = [
+        OutlierKind::ExtremeDuration,
+        OutlierKind::ZeroGaps,
+        OutlierKind::OneDirection,
+        OutlierKind::DuplicateTimestamps,
+    ];
+
+    /// A short name for the kind of outlier, suitable for reports.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutlierKind::ExtremeDuration => "extreme_duration",
+            OutlierKind::ZeroGaps => "zero_gaps",
+            OutlierKind::OneDirection => "one_direction",
+            OutlierKind::DuplicateTimestamps => "duplicate_timestamps",
+        }
+    }
+
+    /// The bit of the kind in `Outlier::kinds`.
+    pub fn bit(&self) -> u8 {
+        1 << *self as u8
+    }
+}
+
+impl fmt::Display for OutlierKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A circuit flagged by an `OutlierDetector`.
+#[derive(H5Type, Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub struct Outlier {
+    /// The position of the circuit in its dataset.
+    pub index: CircuitIndex,
+    pub uuid: FixedAscii<32>,
+    /// The `OutlierKind::bit` of each reason the circuit was flagged.
+    pub kinds: u8,
+}
+
+impl Outlier {
+    /// The reasons the circuit was flagged, in order.
+    pub fn kinds(&self) -> Vec<OutlierKind> {
+        OutlierKind::ALL
+            .into_iter()
+            .filter(|k| self.kinds & k.bit() != 0)
+            .collect()
+    }
+}
+
+/// The thresholds at which an `OutlierDetector` flags circuits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutlierOptions {
+    /// Flag durations more than this many interquartile ranges beyond the
+    /// quartiles of the logarithms of all positive durations.
+    pub duration_iqrs: f64,
+    /// Flag circuits in which at least this fraction of the times between
+    /// cells are zero.
+    pub zero_gap_fraction: f64,
+    /// Flag circuits in which at least this fraction of the cells repeat the
+    /// cell before them.
+    pub duplicate_fraction: f64,
+    /// Only flag circuits with at least this many cells for the kinds other
+    /// than `ExtremeDuration`, since short circuits are often legitimately
+    /// one-sided or bursty.
+    pub min_cells: usize,
+}
+
+impl Default for OutlierOptions {
+    fn default() -> Self {
+        Self {
+            duration_iqrs: 3.0,
+            zero_gap_fraction: 0.5,
+            duplicate_fraction: 0.1,
+            min_cells: 10,
+        }
+    }
+}
+
+/// Flags statistically unusual circuits while streaming through a dataset.
+/// Durations are only judged once all circuits have been checked, so the
+/// detector keeps the duration of every circuit.
+#[derive(Clone, Debug, Default)]
+pub struct OutlierDetector {
+    options: OutlierOptions,
+    durations: Vec<(CircuitIndex, f32)>,
+    flagged: BTreeMap<CircuitIndex, u8>,
+    checked: usize,
+}
+
+impl OutlierDetector {
+    /// Creates a detector that flags circuits at the thresholds of `options`.
+    pub fn new(options: OutlierOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// Checks the circuit at position `index` in the dataset.
+    pub fn check(&mut self, index: CircuitIndex, circuit: &Circuit) {
+        let cells = circuit.valid_cells();
+        self.checked += 1;
+
+        if let (Some(first), Some(last)) = (cells.first(), cells.last()) {
+            let duration = last.time - first.time;
+            if duration > 0.0 {
+                self.durations.push((index, duration as f32));
+            }
+        }
+        if cells.len() < self.options.min_cells.max(2) {
+            return;
+        }
+
+        let gaps = (cells.len() - 1) as f64;
+        let zero_gaps = cells.windows(2).filter(|w| w[1].time == w[0].time).count();
+        let duplicates = cells.windows(2).filter(|w| w[1] == w[0]).count();
+        let directions: Vec<Direction> = cells
+            .iter()
+            .map(|c| c.direction)
+            .filter(|&d| d != Direction::PADDING)
+            .collect();
+
+        let mut kinds = 0;
+        if zero_gaps as f64 >= self.options.zero_gap_fraction * gaps {
+            kinds |= OutlierKind::ZeroGaps.bit();
+        }
+        if directions
+            .first()
+            .is_some_and(|&d| directions.iter().all(|&o| o == d))
+        {
+            kinds |= OutlierKind::OneDirection.bit();
+        }
+        if duplicates as f64 >= self.options.duplicate_fraction * cells.len() as f64 {
+            kinds |= OutlierKind::DuplicateTimestamps.bit();
+        }
+        if kinds != 0 {
+            *self.flagged.entry(index).or_default() |= kinds;
+        }
+    }
+
+    /// The number of circuits checked.
+    pub fn checked(&self) -> usize {
+        self.checked
+    }
+
+    /// Judges the durations and returns the index of every flagged circuit and
+    /// the `OutlierKind::bit` of each reason it was flagged, sorted by index.
+    pub fn finish(mut self) -> Vec<(CircuitIndex, u8)> {
+        let logs: Vec<f64> = self
+            .durations
+            .iter()
+            .map(|&(_, d)| f64::from(d).log10())
+            .collect();
+        if !logs.is_empty() {
+            let (q1, q3) = (percentile(&logs, 25.0), percentile(&logs, 75.0));
+            let fence = self.options.duration_iqrs * (q3 - q1);
+            for (&(index, _), &log) in self.durations.iter().zip(logs.iter()) {
+                if log < q1 - fence || log > q3 + fence {
+                    *self.flagged.entry(index).or_default() |= OutlierKind::ExtremeDuration.bit();
+                }
+            }
+        }
+        self.flagged.into_iter().collect()
+    }
+}
+
+/// Checks every circuit in the `/circuits` dataset of `file` for outliers with
+/// `options`, and writes the flagged circuits to `/outliers`, replacing any
+/// existing dataset. `progress` is called with the number of circuits checked
+/// after each batch. Returns the flagged circuits.
+pub fn write_outliers<F>(
+    file: &File,
+    options: OutlierOptions,
+    mut progress: F,
+) -> hdf5::Result<Vec<Outlier>>
+where
+    F: FnMut(usize),
+{
+    let circuits = file.dataset("/circuits")?;
+    let size = circuits.size();
+    let step = 1_000; // multiple of chunk size
+    let mut detector = OutlierDetector::new(options);
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+        let batch: Array1<Circuit> = circuits.read_slice(ndarray::s![begin..end])?;
+        for (i, circuit) in batch.iter().enumerate() {
+            detector.check((begin + i) as CircuitIndex, circuit);
+        }
+        progress(end - begin);
+    }
+
+    // Duration outliers are only known at the end, so read the uuids of the
+    // flagged circuits by point selection.
+    let flagged = detector.finish();
+    let mut outliers = Vec::with_capacity(flagged.len());
+    for batch in flagged.chunks(step) {
+        let indices: Vec<usize> = batch.iter().map(|&(i, _)| i as usize).collect();
+        let metas = circuits.read_slice_1d::<CircuitMeta, _>(indices)?;
+        for (&(index, kinds), meta) in batch.iter().zip(metas.iter()) {
+            outliers.push(Outlier {
+                index,
+                uuid: meta.uuid,
+                kinds,
+            });
+        }
+    }
+
+    write_index(file, OUTLIERS_NAME, &outliers)?;
+    Ok(outliers)
+}
+
+/// Reads the uuids of the circuits listed in the `/outliers` dataset of `file`.
+pub fn read_outlier_uuids(file: &File) -> hdf5::Result<HashSet<FixedAscii<32>>> {
+    let outliers: Vec<Outlier> = file.dataset(OUTLIERS_NAME)?.read_raw()?;
+    Ok(outliers.into_iter().map(|o| o.uuid).collect())
+}
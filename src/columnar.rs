@@ -0,0 +1,204 @@
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow_array::builder::{
+    ArrayBuilder, Float64Builder, Int8Builder, StringBuilder, UInt8Builder, UInt16Builder,
+    UInt32Builder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+
+use crate::export::Exporter;
+use crate::{Circuit, CircuitIndex};
+
+/// The number of rows buffered in memory before they are written to a table.
+const BATCH_ROWS: usize = 65_536;
+
+/// Writes the circuits to two Parquet tables: one row of meta-data per circuit,
+/// and one row per valid cell that refers to its circuit by `circuit_index`.
+///
+/// The circuits table has the columns `index`, `uuid`, `domain`,
+/// `shortest_private_suffix`, `label`, `day`, `port`, and `len`, and the cells
+/// table has the columns `circuit_index`, `seq` (the position of the cell in
+/// the circuit), `time`, `direction`, `cell_cmd`, and `relay_cmd`.
+pub struct ParquetExporter {
+    circuits: ArrowWriter<File>,
+    cells: ArrowWriter<File>,
+    circuit_rows: CircuitColumns,
+    cell_rows: CellColumns,
+}
+
+impl ParquetExporter {
+    /// Creates an exporter writing the circuits table to `path` and the cells
+    /// table to `cells_path(path)`.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let props = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .build();
+
+        let circuits = ArrowWriter::try_new(
+            File::create(&path)?,
+            CircuitColumns::schema(),
+            Some(props.clone()),
+        )
+        .map_err(io::Error::other)?;
+        let cells = ArrowWriter::try_new(
+            File::create(cells_path(path))?,
+            CellColumns::schema(),
+            Some(props),
+        )
+        .map_err(io::Error::other)?;
+
+        Ok(Self {
+            circuits,
+            cells,
+            circuit_rows: CircuitColumns::default(),
+            cell_rows: CellColumns::default(),
+        })
+    }
+
+    fn flush_circuits(&mut self) -> io::Result<()> {
+        let batch = self.circuit_rows.finish().map_err(io::Error::other)?;
+        self.circuits.write(&batch).map_err(io::Error::other)
+    }
+
+    fn flush_cells(&mut self) -> io::Result<()> {
+        let batch = self.cell_rows.finish().map_err(io::Error::other)?;
+        self.cells.write(&batch).map_err(io::Error::other)
+    }
+}
+
+/// The path of the cells table written alongside the circuits table at `path`,
+/// e.g., `export.cells.parquet` for `export.parquet`.
+pub fn cells_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    path.as_ref().with_extension("cells.parquet")
+}
+
+impl Exporter for ParquetExporter {
+    fn write(&mut self, index: CircuitIndex, circuit: &Circuit) -> io::Result<()> {
+        let rows = &mut self.circuit_rows;
+        rows.index.append_value(index);
+        rows.uuid.append_value(circuit.uuid.as_str());
+        rows.domain.append_value(circuit.domain.as_str());
+        rows.shortest_private_suffix
+            .append_value(circuit.shortest_private_suffix.as_str());
+        rows.label.append_value(circuit.label().as_str());
+        rows.day.append_value(circuit.day);
+        rows.port.append_value(circuit.port);
+        rows.len.append_value(circuit.len);
+
+        for (seq, cell) in circuit.valid_cells().iter().enumerate() {
+            let rows = &mut self.cell_rows;
+            rows.circuit_index.append_value(index);
+            rows.seq.append_value(seq as u16);
+            rows.time.append_value(cell.time);
+            rows.direction.append_value(cell.direction as i8);
+            rows.cell_cmd.append_value(cell.cell_cmd as u8);
+            rows.relay_cmd.append_value(cell.relay_cmd as u8);
+
+            if rows.circuit_index.len() >= BATCH_ROWS {
+                self.flush_cells()?;
+            }
+        }
+
+        if self.circuit_rows.index.len() >= BATCH_ROWS {
+            self.flush_circuits()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.flush_circuits()?;
+        self.flush_cells()?;
+
+        let Self {
+            circuits, cells, ..
+        } = *self;
+        circuits.close().map_err(io::Error::other)?;
+        cells.close().map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct CircuitColumns {
+    index: UInt32Builder,
+    uuid: StringBuilder,
+    domain: StringBuilder,
+    shortest_private_suffix: StringBuilder,
+    label: StringBuilder,
+    day: UInt8Builder,
+    port: UInt16Builder,
+    len: UInt16Builder,
+}
+
+impl CircuitColumns {
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("index", DataType::UInt32, false),
+            Field::new("uuid", DataType::Utf8, false),
+            Field::new("domain", DataType::Utf8, false),
+            Field::new("shortest_private_suffix", DataType::Utf8, false),
+            Field::new("label", DataType::Utf8, false),
+            Field::new("day", DataType::UInt8, false),
+            Field::new("port", DataType::UInt16, false),
+            Field::new("len", DataType::UInt16, false),
+        ]))
+    }
+
+    /// Moves the buffered rows into a record batch.
+    fn finish(&mut self) -> Result<RecordBatch, arrow_schema::ArrowError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.index.finish()),
+            Arc::new(self.uuid.finish()),
+            Arc::new(self.domain.finish()),
+            Arc::new(self.shortest_private_suffix.finish()),
+            Arc::new(self.label.finish()),
+            Arc::new(self.day.finish()),
+            Arc::new(self.port.finish()),
+            Arc::new(self.len.finish()),
+        ];
+        RecordBatch::try_new(Self::schema(), columns)
+    }
+}
+
+#[derive(Default)]
+struct CellColumns {
+    circuit_index: UInt32Builder,
+    seq: UInt16Builder,
+    time: Float64Builder,
+    direction: Int8Builder,
+    cell_cmd: UInt8Builder,
+    relay_cmd: UInt8Builder,
+}
+
+impl CellColumns {
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("circuit_index", DataType::UInt32, false),
+            Field::new("seq", DataType::UInt16, false),
+            Field::new("time", DataType::Float64, false),
+            Field::new("direction", DataType::Int8, false),
+            Field::new("cell_cmd", DataType::UInt8, false),
+            Field::new("relay_cmd", DataType::UInt8, false),
+        ]))
+    }
+
+    /// Moves the buffered rows into a record batch.
+    fn finish(&mut self) -> Result<RecordBatch, arrow_schema::ArrowError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.circuit_index.finish()),
+            Arc::new(self.seq.finish()),
+            Arc::new(self.time.finish()),
+            Arc::new(self.direction.finish()),
+            Arc::new(self.cell_cmd.finish()),
+            Arc::new(self.relay_cmd.finish()),
+        ];
+        RecordBatch::try_new(Self::schema(), columns)
+    }
+}
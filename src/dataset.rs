@@ -0,0 +1,340 @@
+use std::cell::OnceCell;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use hdf5::types::FixedAscii;
+use hdf5::{File, H5Type};
+
+use crate::index::{self, IndexBuilder, LabelVocabulary, UuidFilter};
+use crate::writer::CircuitWriter;
+use crate::{fixedascii_from_str, Circuit, CircuitIndex, CircuitMeta, IndexEntry};
+
+/// The number of circuits read from the file at a time when iterating.
+const READ_BATCH: usize = 1_000;
+
+/// The number of circuits whose meta-data is read from the file at a time when
+/// iterating over meta-data only.
+const META_READ_BATCH: usize = 10_000;
+
+/// A GTT23 HDF5 file holding a `/circuits` dataset along with any of the
+/// cached indices stored under `/index`.
+pub struct Dataset {
+    file: File,
+    uuid_filter: OnceCell<Option<UuidFilter>>,
+    labels: OnceCell<LabelVocabulary>,
+}
+
+impl Dataset {
+    /// Opens the dataset at `path` for reading.
+    pub fn open<P: AsRef<Path>>(path: P) -> hdf5::Result<Self> {
+        Ok(Self::from_file(File::open(path)?))
+    }
+
+    /// Opens the dataset at `path` for reading and writing.
+    pub fn open_rw<P: AsRef<Path>>(path: P) -> hdf5::Result<Self> {
+        Ok(Self::from_file(File::open_rw(path)?))
+    }
+
+    /// Wraps an already opened HDF5 file.
+    pub fn from_file(file: File) -> Self {
+        Self {
+            file,
+            uuid_filter: OnceCell::new(),
+            labels: OnceCell::new(),
+        }
+    }
+
+    /// The underlying HDF5 file.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// The `/circuits` dataset.
+    pub fn circuits(&self) -> hdf5::Result<hdf5::Dataset> {
+        self.file.dataset("/circuits")
+    }
+
+    /// Returns false if the circuits dataset definitely does not contain a
+    /// circuit with `uuid`, and true if it may. This only consults the
+    /// `/index/uuid_filter` dataset, and always returns true if the file has
+    /// no uuid filter.
+    pub fn maybe_contains_uuid(&self, uuid: &FixedAscii<32>) -> hdf5::Result<bool> {
+        if self.uuid_filter.get().is_none() {
+            let filter = if self.file.link_exists("/index/uuid_filter") {
+                Some(UuidFilter::read(&self.file, "/index/uuid_filter")?)
+            } else {
+                None
+            };
+            let _ = self.uuid_filter.set(filter);
+        }
+
+        Ok(match self.uuid_filter.get() {
+            Some(Some(filter)) => filter.maybe_contains(uuid),
+            _ => true,
+        })
+    }
+
+    /// Finds the index of the circuit with `uuid` in the circuits dataset using
+    /// the uuid filter (if present) followed by a binary search of the
+    /// `/index/uuid` dataset.
+    pub fn find_uuid(&self, uuid: &FixedAscii<32>) -> hdf5::Result<Option<CircuitIndex>> {
+        if !self.maybe_contains_uuid(uuid)? {
+            return Ok(None);
+        }
+
+        let dataset = self.file.dataset("/index/uuid")?;
+        let entry = index::find_entry(
+            &dataset,
+            &uuid.to_string(),
+            |e: &IndexEntry<FixedAscii<32>>| e.value.to_string(),
+        )?;
+        Ok(entry.map(|e| e.index))
+    }
+
+    /// The label vocabulary stored in the `/labels` dataset, which is read from
+    /// the file on first use.
+    pub fn labels(&self) -> hdf5::Result<&LabelVocabulary> {
+        if self.labels.get().is_none() {
+            let _ = self.labels.set(LabelVocabulary::read(&self.file)?);
+        }
+        Ok(self.labels.get().unwrap())
+    }
+
+    /// The class id assigned to `label` by the label vocabulary, or `None` if
+    /// the dataset has no circuits with that label.
+    pub fn class_id(&self, label: &str) -> hdf5::Result<Option<u32>> {
+        if label.len() > 44 {
+            return Ok(None);
+        }
+        let label =
+            fixedascii_from_str::<44>(label).map_err(|e| hdf5::Error::from(e.to_string()))?;
+        Ok(self.labels()?.class_id(&label))
+    }
+
+    /// Iterates over the circuits in the circuits dataset along with their
+    /// indices, reading them from the file in batches as needed.
+    pub fn iter(&self) -> hdf5::Result<CircuitIter> {
+        Ok(CircuitIter::new(self.circuits()?))
+    }
+
+    /// Like `iter`, but only reads the meta-data fields of the circuits, which
+    /// is much faster for scans that do not need their cells.
+    pub fn iter_meta(&self) -> hdf5::Result<CircuitIter<CircuitMeta>> {
+        Ok(CircuitIter::meta(self.circuits()?))
+    }
+
+    /// Lazily iterates over the circuits for which `pred` returns true, along
+    /// with their indices in the circuits dataset.
+    pub fn filter<P>(
+        &self,
+        mut pred: P,
+    ) -> hdf5::Result<impl Iterator<Item = hdf5::Result<(CircuitIndex, Circuit)>>>
+    where
+        P: FnMut(&Circuit) -> bool,
+    {
+        Ok(self
+            .iter()?
+            .filter(move |item| item.as_ref().ok().is_none_or(|(_, c)| pred(c))))
+    }
+
+    /// Writes the circuits for which `pred` returns true to a new file at
+    /// `output`, using the same chunking and compression as this dataset, and
+    /// writes the indices of the new file. Returns the number of circuits
+    /// written.
+    pub fn filter_to_file<P, Q>(&self, pred: P, output: Q) -> hdf5::Result<usize>
+    where
+        P: FnMut(&Circuit) -> bool,
+        Q: AsRef<Path>,
+    {
+        let out_file = File::create(output)?;
+        let mut writer = CircuitWriter::create_like(&out_file, "/circuits", &self.circuits()?)?;
+        let mut index = IndexBuilder::new();
+
+        for item in self.filter(pred)? {
+            let (_, circuit) = item?;
+            index.add(writer.len() as CircuitIndex, &circuit);
+            writer.push(circuit)?;
+        }
+
+        let n_written = writer.len();
+        writer.finish()?;
+        index.write_all(&out_file)?;
+        out_file.close()?;
+
+        Ok(n_written)
+    }
+
+    /// Like `filter_to_file`, but evaluates `pred` on `threads` worker threads.
+    /// The circuits are read in batches on the calling thread, filtered by the
+    /// workers, and written in their original order by a single writer thread.
+    pub fn filter_to_file_parallel<P, Q>(
+        &self,
+        pred: P,
+        output: Q,
+        threads: usize,
+    ) -> hdf5::Result<usize>
+    where
+        P: Fn(&Circuit) -> bool + Sync,
+        Q: AsRef<Path>,
+    {
+        let circuits = self.circuits()?;
+        let size = circuits.size();
+
+        let out_file = File::create(output)?;
+        let writer = CircuitWriter::create_like(&out_file, "/circuits", &circuits)?;
+        let index = IndexBuilder::new();
+
+        let threads = threads.max(1);
+        let (batch_tx, batch_rx) = mpsc::sync_channel::<(usize, Vec<Circuit>)>(threads);
+        let batch_rx = Arc::new(Mutex::new(batch_rx));
+        let (kept_tx, kept_rx) = mpsc::sync_channel::<(usize, Vec<Circuit>)>(threads);
+
+        let (writer, index) = thread::scope(|scope| {
+            for _ in 0..threads {
+                let batch_rx = Arc::clone(&batch_rx);
+                let kept_tx = kept_tx.clone();
+                let pred = &pred;
+
+                scope.spawn(move || {
+                    loop {
+                        // Hold the lock only while waiting for the next batch.
+                        let Ok(Ok((n, batch))) = batch_rx.lock().map(|rx| rx.recv()) else {
+                            break;
+                        };
+                        let kept = batch.into_iter().filter(|c| pred(c)).collect();
+                        if kept_tx.send((n, kept)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            // The workers hold the only remaining handles, so the channels
+            // close once they are done or the writer stops.
+            drop(batch_rx);
+            drop(kept_tx);
+
+            let writer_thread = scope.spawn(move || -> hdf5::Result<_> {
+                let (mut writer, mut index) = (writer, index);
+                let mut pending = BTreeMap::new();
+                let mut next = 0;
+
+                for (n, kept) in kept_rx {
+                    pending.insert(n, kept);
+                    while let Some(kept) = pending.remove(&next) {
+                        for circuit in kept {
+                            index.add(writer.len() as CircuitIndex, &circuit);
+                            writer.push(circuit)?;
+                        }
+                        next += 1;
+                    }
+                }
+
+                Ok((writer, index))
+            });
+
+            for (n, begin) in (0..size).step_by(READ_BATCH).enumerate() {
+                let end = std::cmp::min(begin + READ_BATCH, size);
+                let batch = circuits
+                    .read_slice_1d::<Circuit, _>(begin..end)?
+                    .into_raw_vec_and_offset()
+                    .0;
+                if batch_tx.send((n, batch)).is_err() {
+                    // The writer failed; its error is returned below.
+                    break;
+                }
+            }
+            drop(batch_tx);
+
+            writer_thread
+                .join()
+                .unwrap_or_else(|e| std::panic::resume_unwind(e))
+        })?;
+
+        let n_written = writer.len();
+        writer.finish()?;
+        index.write_all(&out_file)?;
+        out_file.close()?;
+
+        Ok(n_written)
+    }
+
+    /// Closes the underlying HDF5 file.
+    pub fn close(self) -> hdf5::Result<()> {
+        self.file.close()
+    }
+}
+
+/// An iterator over the circuits in a dataset and their indices, which reads
+/// the circuits in batches. Circuits are read as `T`, which is either `Circuit`
+/// or a type with a subset of its fields, e.g., `CircuitMeta`. Yields an error
+/// if a read fails.
+pub struct CircuitIter<T = Circuit> {
+    dataset: hdf5::Dataset,
+    size: usize,
+    next_read: usize,
+    batch_size: usize,
+    batch: std::vec::IntoIter<T>,
+    next_index: usize,
+}
+
+impl CircuitIter {
+    /// Iterates over all circuits in `dataset`.
+    pub fn new(dataset: hdf5::Dataset) -> Self {
+        Self::with_batch_size(dataset, READ_BATCH)
+    }
+}
+
+impl CircuitIter<CircuitMeta> {
+    /// Iterates over the meta-data of all circuits in `dataset`.
+    pub fn meta(dataset: hdf5::Dataset) -> Self {
+        Self::with_batch_size(dataset, META_READ_BATCH)
+    }
+}
+
+impl<T: H5Type> CircuitIter<T> {
+    /// Iterates over all circuits in `dataset`, reading `batch_size` circuits
+    /// at a time.
+    pub fn with_batch_size(dataset: hdf5::Dataset, batch_size: usize) -> Self {
+        let size = dataset.size();
+        Self {
+            dataset,
+            size,
+            next_read: 0,
+            batch_size: batch_size.max(1),
+            batch: Vec::new().into_iter(),
+            next_index: 0,
+        }
+    }
+}
+
+impl<T: H5Type> Iterator for CircuitIter<T> {
+    type Item = hdf5::Result<(CircuitIndex, T)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.batch.as_slice().is_empty() {
+            if self.next_read >= self.size {
+                return None;
+            }
+
+            let begin = self.next_read;
+            let end = std::cmp::min(begin + self.batch_size, self.size);
+            self.next_read = end;
+
+            match self.dataset.read_slice_1d::<T, _>(begin..end) {
+                Ok(array) => self.batch = array.into_raw_vec_and_offset().0.into_iter(),
+                Err(e) => {
+                    // Stop after reporting the error.
+                    self.next_read = self.size;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        let circuit = self.batch.next()?;
+        let index = self.next_index as CircuitIndex;
+        self.next_index += 1;
+        Some(Ok((index, circuit)))
+    }
+}
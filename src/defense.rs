@@ -0,0 +1,511 @@
+use crate::augment::{self, CircuitTransform, Params, list};
+use crate::rng::Rng;
+use crate::{Cell, Circuit, Direction};
+
+/// A histogram of delays in seconds from which padding defenses sample, with an
+/// extra "infinity" bin whose samples mean that no dummy cell is sent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    /// The bin edges, in increasing order; bin `k` covers
+    /// `edges[k]..edges[k + 1]`.
+    edges: Vec<f64>,
+    /// The number of tokens in each bin.
+    counts: Vec<u32>,
+    /// The number of tokens in the infinity bin.
+    infinity: u32,
+}
+
+impl Histogram {
+    /// Creates a histogram with `counts[k]` tokens in the bin from `edges[k]`
+    /// to `edges[k + 1]`, and `infinity` tokens in the infinity bin. There must
+    /// be one more edge than counts, and the edges must be increasing and not
+    /// negative.
+    pub fn new(edges: Vec<f64>, counts: Vec<u32>, infinity: u32) -> Result<Self, String> {
+        if edges.len() != counts.len() + 1 {
+            return Err(format!(
+                "Histogram has {} edges for {} bins",
+                edges.len(),
+                counts.len()
+            ));
+        }
+        if !edges.windows(2).all(|w| w[0] < w[1]) || edges[0] < 0.0 {
+            return Err("Histogram edges must be increasing and not negative".to_string());
+        }
+        Ok(Self {
+            edges,
+            counts,
+            infinity,
+        })
+    }
+
+    /// Creates a histogram of the `samples` with `bins` bins whose edges are
+    /// spaced logarithmically from `min` to `max` seconds, where shorter and
+    /// longer samples are counted in the first and last bins, and with
+    /// `infinity` tokens in the infinity bin. Panics if `bins` is 0.
+    pub fn from_samples(samples: &[f64], min: f64, max: f64, bins: usize, infinity: u32) -> Self {
+        assert!(bins > 0, "histogram must have at least one bin");
+        let (lo, hi) = (min.ln(), max.ln());
+        let edges: Vec<f64> = (0..=bins)
+            .map(|k| (lo + (hi - lo) * k as f64 / bins as f64).exp())
+            .collect();
+
+        let mut counts = vec![0u32; bins];
+        for &sample in samples {
+            let bin = if sample > 0.0 {
+                ((sample.ln() - lo) / (hi - lo) * bins as f64).floor()
+            } else {
+                0.0
+            };
+            counts[(bin.max(0.0) as usize).min(bins - 1)] += 1;
+        }
+
+        Self {
+            edges,
+            counts,
+            infinity,
+        }
+    }
+
+    /// Samples a delay, choosing a bin with probability proportional to its
+    /// tokens and a delay uniformly within the bin. Returns `None` if the
+    /// infinity bin is chosen or the histogram has no tokens.
+    pub fn sample(&self, rng: &mut Rng) -> Option<f64> {
+        let total: u64 =
+            self.counts.iter().map(|&c| u64::from(c)).sum::<u64>() + u64::from(self.infinity);
+        if total == 0 {
+            return None;
+        }
+
+        let mut token = rng.below(total);
+        for (k, &count) in self.counts.iter().enumerate() {
+            if token < u64::from(count) {
+                let (lo, hi) = (self.edges[k], self.edges[k + 1]);
+                return Some(lo + (hi - lo) * rng.next_f64());
+            }
+            token -= u64::from(count);
+        }
+        None
+    }
+}
+
+impl Params for Histogram {
+    fn params(&self) -> String {
+        format!(
+            "histogram(edges={},counts={},infinity={})",
+            list(&self.edges),
+            list(&self.counts),
+            self.infinity
+        )
+    }
+}
+
+/// The histograms that WTF-PAD samples from for the cells sent in one
+/// direction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PadHistograms {
+    /// The delays after a real cell before a dummy cell starts a fake burst.
+    pub burst: Histogram,
+    /// The delays between the dummy cells of a fake burst.
+    pub gap: Histogram,
+}
+
+/// Simulates the WTF-PAD adaptive padding defense of Juarez et al. (ESORICS
+/// 2016) on the cells of a circuit, injecting `PADDING` cells toward the
+/// server and toward the client independently.
+///
+/// After each real cell in a direction, a delay is sampled from the burst
+/// histogram. If the next real cell in that direction is sent before the delay
+/// expires the state resets; otherwise a dummy cell is injected when it
+/// expires, and dummy cells continue to be injected after delays sampled from
+/// the gap histogram until a real cell is sent, the infinity bin is sampled, or
+/// the delay would extend past the last real cell of the circuit, so that the
+/// duration of the circuit is not changed. Real cells are never delayed.
+/// Tokens are sampled with replacement, unlike the token removal of the
+/// original design.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WtfPad {
+    /// The histograms of cells sent toward the server.
+    pub client: PadHistograms,
+    /// The histograms of cells sent toward the client.
+    pub server: PadHistograms,
+}
+
+impl WtfPad {
+    /// Creates a defense sampling from the `client` histograms for cells sent
+    /// toward the server and from the `server` histograms for cells sent toward
+    /// the client.
+    pub fn new(client: PadHistograms, server: PadHistograms) -> Self {
+        Self { client, server }
+    }
+
+    /// Creates a defense whose histograms are fit to the traffic of `circuits`,
+    /// as WTF-PAD derives them from undefended traffic: in each direction, the
+    /// burst histogram holds the delays from the last cell of a burst to the
+    /// first cell of the next burst in the same direction, and the gap
+    /// histogram the delays between consecutive cells of a burst. Each has
+    /// `bins` bins spaced logarithmically from 100 microseconds to 10 seconds,
+    /// and an infinity bin with `infinity` times the tokens of the other bins.
+    pub fn fit(circuits: &[Circuit], bins: usize, infinity: f64) -> Self {
+        let fit = |direction: Direction| {
+            let (mut bursts, mut gaps) = (Vec::new(), Vec::new());
+            for circuit in circuits {
+                burst_gaps(circuit.valid_cells(), direction, &mut bursts, &mut gaps);
+            }
+            let histogram = |samples: &[f64]| {
+                let tokens = (samples.len() as f64 * infinity).round() as u32;
+                Histogram::from_samples(samples, 1e-4, 10.0, bins, tokens)
+            };
+            PadHistograms {
+                burst: histogram(&bursts),
+                gap: histogram(&gaps),
+            }
+        };
+        Self::new(
+            fit(Direction::CLIENT_TO_SERVER),
+            fit(Direction::SERVER_TO_CLIENT),
+        )
+    }
+
+    /// Returns the dummy cells injected in `direction` among the valid `cells`.
+    fn dummies(&self, cells: &[Cell], direction: Direction, rng: &mut Rng) -> Vec<Cell> {
+        let histograms = match direction {
+            Direction::SERVER_TO_CLIENT => &self.server,
+            _ => &self.client,
+        };
+        let Some(end) = cells.last().map(|c| c.time) else {
+            return Vec::new();
+        };
+
+        let real: Vec<f64> = cells
+            .iter()
+            .filter(|c| c.direction == direction)
+            .map(|c| c.time)
+            .collect();
+
+        let mut dummies = Vec::new();
+        for (i, &time) in real.iter().enumerate() {
+            let next = real.get(i + 1).copied().unwrap_or(end);
+            let mut now = time;
+            let mut histogram = &histograms.burst;
+            while let Some(delay) = histogram.sample(rng) {
+                let at = now + delay;
+                if at >= next || at > end {
+                    break;
+                }
+                dummies.push(augment::padding_cell(at, direction));
+                now = at;
+                histogram = &histograms.gap;
+            }
+        }
+        dummies
+    }
+}
+
+impl Params for PadHistograms {
+    fn params(&self) -> String {
+        format!(
+            "pad_histograms(burst={},gap={})",
+            self.burst.params(),
+            self.gap.params()
+        )
+    }
+}
+
+impl Params for WtfPad {
+    fn params(&self) -> String {
+        format!(
+            "wtfpad(client={},server={})",
+            self.client.params(),
+            self.server.params()
+        )
+    }
+}
+
+impl CircuitTransform for WtfPad {
+    fn augment_cells(&self, cells: &mut [Cell], len: &mut u16, rng: &mut Rng) {
+        let valid = &cells[..std::cmp::min(*len as usize, cells.len())];
+        let mut dummies = self.dummies(valid, Direction::CLIENT_TO_SERVER, rng);
+        dummies.extend(self.dummies(valid, Direction::SERVER_TO_CLIENT, rng));
+        augment::insert_cells(cells, len, dummies);
+    }
+}
+
+/// Simulates the FRONT defense of Gong and Wang (USENIX Security 2020) on the
+/// cells of a circuit, injecting `PADDING` cells that are concentrated near the
+/// start of the circuit, where website fingerprints are most distinctive.
+///
+/// For each direction, the number of dummy cells is sampled uniformly from
+/// `1..=budget` and a window `w` uniformly from `min_window..max_window`
+/// seconds, and each dummy cell is sent at a time after the first valid cell
+/// sampled from the Rayleigh distribution with scale `w`. Real cells are never
+/// delayed, and dummy cells may extend past the last real cell.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Front {
+    /// The maximum number of dummy cells sent toward the server.
+    pub client_budget: u32,
+    /// The maximum number of dummy cells sent toward the client.
+    pub server_budget: u32,
+    /// The smallest padding window, in seconds.
+    pub min_window: f64,
+    /// The largest padding window, in seconds.
+    pub max_window: f64,
+}
+
+impl Front {
+    /// Creates a defense with the padding budgets of each direction and the
+    /// range of the padding window in seconds.
+    pub fn new(client_budget: u32, server_budget: u32, min_window: f64, max_window: f64) -> Self {
+        Self {
+            client_budget,
+            server_budget,
+            min_window,
+            max_window,
+        }
+    }
+
+    /// Returns the dummy cells injected in `direction` after time `start`.
+    fn dummies(&self, start: f64, direction: Direction, rng: &mut Rng) -> Vec<Cell> {
+        let budget = match direction {
+            Direction::SERVER_TO_CLIENT => self.server_budget,
+            _ => self.client_budget,
+        };
+        if budget == 0 {
+            return Vec::new();
+        }
+
+        let n = 1 + rng.below(u64::from(budget));
+        let window = self.min_window + (self.max_window - self.min_window) * rng.next_f64();
+        (0..n)
+            .map(|_| {
+                // Inverse transform sampling of the Rayleigh distribution.
+                let time = window * (-2.0 * (1.0 - rng.next_f64()).ln()).sqrt();
+                augment::padding_cell(start + time, direction)
+            })
+            .collect()
+    }
+}
+
+impl Params for Front {
+    fn params(&self) -> String {
+        format!(
+            "front(client_budget={},server_budget={},min_window={},max_window={})",
+            self.client_budget, self.server_budget, self.min_window, self.max_window
+        )
+    }
+}
+
+impl CircuitTransform for Front {
+    fn augment_cells(&self, cells: &mut [Cell], len: &mut u16, rng: &mut Rng) {
+        let valid = &cells[..std::cmp::min(*len as usize, cells.len())];
+        let Some(start) = valid.first().map(|c| c.time) else {
+            return;
+        };
+        let mut dummies = self.dummies(start, Direction::CLIENT_TO_SERVER, rng);
+        dummies.extend(self.dummies(start, Direction::SERVER_TO_CLIENT, rng));
+        augment::insert_cells(cells, len, dummies);
+    }
+}
+
+/// The bandwidth and latency overhead that a defense induces on a circuit.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Overhead {
+    /// The number of cells of the undefended circuit.
+    pub real_cells: usize,
+    /// The number of dummy cells added by the defense.
+    pub dummy_cells: usize,
+    /// The time in seconds from the first to the last cell of the undefended
+    /// circuit.
+    pub duration: f64,
+    /// The time in seconds from the first to the last cell of the defended
+    /// circuit.
+    pub defended_duration: f64,
+}
+
+impl Overhead {
+    /// The overhead of the `defended` cells of a circuit over its `original`
+    /// cells, where every cell beyond the number of original cells counts as a
+    /// dummy cell.
+    pub fn between(original: &[Cell], defended: &[Cell]) -> Self {
+        let duration = |cells: &[Cell]| match (cells.first(), cells.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        };
+        Self {
+            real_cells: original.len(),
+            dummy_cells: defended.len().saturating_sub(original.len()),
+            duration: duration(original),
+            defended_duration: duration(defended),
+        }
+    }
+
+    /// The dummy cells as a fraction of the real cells.
+    pub fn bandwidth(&self) -> f64 {
+        match self.real_cells {
+            0 => 0.0,
+            n => self.dummy_cells as f64 / n as f64,
+        }
+    }
+
+    /// The added time as a fraction of the undefended duration.
+    pub fn latency(&self) -> f64 {
+        if self.duration > 0.0 {
+            (self.defended_duration - self.duration) / self.duration
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Sums the cells and durations of circuits, so that the ratios of the sum are
+/// the aggregate overheads of the circuits.
+impl std::iter::Sum for Overhead {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |total, o| Self {
+            real_cells: total.real_cells + o.real_cells,
+            dummy_cells: total.dummy_cells + o.dummy_cells,
+            duration: total.duration + o.duration,
+            defended_duration: total.defended_duration + o.defended_duration,
+        })
+    }
+}
+
+/// Simulates a Tamaraw-style constant-rate defense (Cai et al., CCS 2014) on
+/// the cells of a circuit.
+///
+/// Starting at the first valid cell, each direction sends one cell every fixed
+/// interval: the next real cell if it is ready, delaying it until its slot, and
+/// otherwise a `PADDING` cell. After its last real cell, each direction keeps
+/// sending `PADDING` cells until the number of cells it sent is a multiple of
+/// `pad_multiple`. Cells in the `PADDING` direction, e.g., from an earlier
+/// augmentation, are sent toward the server like real cells, so that every
+/// input cell is sent. The defense is deterministic.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tamaraw {
+    /// The seconds between cells sent toward the server.
+    pub client_interval: f64,
+    /// The seconds between cells sent toward the client.
+    pub server_interval: f64,
+    /// The number of cells sent in each direction is padded to a multiple of
+    /// this.
+    pub pad_multiple: u32,
+}
+
+impl Tamaraw {
+    /// Creates a defense sending a cell every `client_interval` seconds toward
+    /// the server and every `server_interval` seconds toward the client, and
+    /// padding each direction to a multiple of `pad_multiple` cells. Panics if
+    /// an interval is not positive.
+    pub fn new(client_interval: f64, server_interval: f64, pad_multiple: u32) -> Self {
+        assert!(
+            client_interval > 0.0 && server_interval > 0.0,
+            "Tamaraw intervals must be positive"
+        );
+        Self {
+            client_interval,
+            server_interval,
+            pad_multiple,
+        }
+    }
+
+    /// Returns the defended version of the valid `cells` and the overhead of
+    /// the defense. The defended cells may outnumber the cells of a circuit,
+    /// in which case the latest are dropped when the defense is applied as a
+    /// `CircuitTransform`, but the overhead accounts for all of them.
+    pub fn defend(&self, cells: &[Cell]) -> (Vec<Cell>, Overhead) {
+        let Some(start) = cells.first().map(|c| c.time) else {
+            return (Vec::new(), Overhead::default());
+        };
+
+        let mut defended = self.schedule(cells, start, Direction::CLIENT_TO_SERVER);
+        defended.extend(self.schedule(cells, start, Direction::SERVER_TO_CLIENT));
+        defended.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let end = cells.last().map_or(start, |c| c.time);
+        let defended_end = defended.last().map_or(start, |c| c.time);
+        let overhead = Overhead {
+            real_cells: cells.len(),
+            dummy_cells: defended.len().saturating_sub(cells.len()),
+            duration: end - start,
+            defended_duration: defended_end - start,
+        };
+        (defended, overhead)
+    }
+
+    /// Returns the cells sent in `direction` at the constant rate, starting at
+    /// `start`.
+    fn schedule(&self, cells: &[Cell], start: f64, direction: Direction) -> Vec<Cell> {
+        let interval = match direction {
+            Direction::SERVER_TO_CLIENT => self.server_interval,
+            _ => self.client_interval,
+        };
+
+        let mut sent = Vec::new();
+        let mut slot = 0u64;
+        let time = |slot: u64| start + slot as f64 * interval;
+
+        let scheduled = |c: &&Cell| match c.direction {
+            Direction::PADDING => direction == Direction::CLIENT_TO_SERVER,
+            d => d == direction,
+        };
+        for cell in cells.iter().filter(scheduled) {
+            while time(slot) < cell.time {
+                sent.push(augment::padding_cell(time(slot), direction));
+                slot += 1;
+            }
+            sent.push(Cell {
+                time: time(slot),
+                direction,
+                ..*cell
+            });
+            slot += 1;
+        }
+
+        let multiple = u64::from(self.pad_multiple.max(1));
+        while slot % multiple != 0 {
+            sent.push(augment::padding_cell(time(slot), direction));
+            slot += 1;
+        }
+        sent
+    }
+}
+
+impl Params for Tamaraw {
+    fn params(&self) -> String {
+        format!(
+            "tamaraw(client_interval={},server_interval={},pad_multiple={})",
+            self.client_interval, self.server_interval, self.pad_multiple
+        )
+    }
+}
+
+impl CircuitTransform for Tamaraw {
+    fn augment_cells(&self, cells: &mut [Cell], len: &mut u16, _rng: &mut Rng) {
+        let valid = &cells[..std::cmp::min(*len as usize, cells.len())];
+        let (defended, _) = self.defend(valid);
+
+        let n = std::cmp::min(defended.len(), cells.len());
+        cells[..n].copy_from_slice(&defended[..n]);
+        cells[n..].fill(Cell::empty());
+        *len = n as u16;
+    }
+}
+
+/// Appends the delays between consecutive cells sent in `direction` to
+/// `bursts` if other cells were sent between them, and otherwise to `gaps`.
+fn burst_gaps(cells: &[Cell], direction: Direction, bursts: &mut Vec<f64>, gaps: &mut Vec<f64>) {
+    let mut prev: Option<(usize, f64)> = None;
+    for (i, cell) in cells.iter().enumerate() {
+        if cell.direction != direction {
+            continue;
+        }
+        if let Some((j, time)) = prev {
+            let delay = (cell.time - time).max(0.0);
+            if i == j + 1 {
+                gaps.push(delay);
+            } else {
+                bursts.push(delay);
+            }
+        }
+        prev = Some((i, cell.time));
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use hdf5::types::FixedAscii;
+
+use crate::{Circuit, CircuitIndex, CircuitMeta};
+
+/// The kind of difference between two circuits datasets.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum DiffKind {
+    /// A uuid that appears only in the first dataset.
+    OnlyInFirst,
+    /// A uuid that appears only in the second dataset.
+    OnlyInSecond,
+    /// A uuid whose circuits have different meta-data.
+    MetadataDiffers,
+    /// A uuid whose circuits have different cells.
+    CellsDiffer,
+}
+
+impl DiffKind {
+    /// A short name for the kind of difference, suitable for reports.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiffKind::OnlyInFirst => "only_in_first",
+            DiffKind::OnlyInSecond => "only_in_second",
+            DiffKind::MetadataDiffers => "metadata_differs",
+            DiffKind::CellsDiffer => "cells_differ",
+        }
+    }
+}
+
+impl fmt::Display for DiffKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A single difference between the datasets.
+#[derive(Clone, Debug)]
+pub struct Difference {
+    pub kind: DiffKind,
+    pub uuid: FixedAscii<32>,
+    /// A human-readable description of the difference.
+    pub detail: String,
+}
+
+/// The differences between two circuits datasets, matched by uuid. Counts every
+/// difference but keeps only the first few of each kind as examples.
+#[derive(Clone, Debug)]
+pub struct DatasetDiff {
+    max_examples: usize,
+    common: usize,
+    counts: BTreeMap<DiffKind, usize>,
+    examples: BTreeMap<DiffKind, Vec<Difference>>,
+}
+
+impl DatasetDiff {
+    fn new(max_examples: usize) -> Self {
+        Self {
+            max_examples,
+            common: 0,
+            counts: BTreeMap::new(),
+            examples: BTreeMap::new(),
+        }
+    }
+
+    fn add(&mut self, kind: DiffKind, uuid: FixedAscii<32>, detail: String) {
+        *self.counts.entry(kind).or_default() += 1;
+        let examples = self.examples.entry(kind).or_default();
+        if examples.len() < self.max_examples {
+            examples.push(Difference { kind, uuid, detail });
+        }
+    }
+
+    /// Returns true if the datasets contain the same circuits.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// The number of uuids that appear in both datasets.
+    pub fn common(&self) -> usize {
+        self.common
+    }
+
+    /// The number of differences of each kind.
+    pub fn counts(&self) -> &BTreeMap<DiffKind, usize> {
+        &self.counts
+    }
+
+    /// The kept examples of differences of `kind`.
+    pub fn examples(&self, kind: DiffKind) -> &[Difference] {
+        self.examples.get(&kind).map_or(&[], |v| v.as_slice())
+    }
+}
+
+/// Compares two circuits datasets by uuid. Only meta-data is read unless
+/// `compare_cells` is set, in which case the cells of circuits present in both
+/// datasets are also compared.
+pub fn diff_datasets(
+    first: &hdf5::Dataset,
+    second: &hdf5::Dataset,
+    compare_cells: bool,
+    max_examples: usize,
+) -> hdf5::Result<DatasetDiff> {
+    let mut diff = DatasetDiff::new(max_examples);
+    let step = 1_000;
+
+    let mut first_meta: HashMap<FixedAscii<32>, (CircuitIndex, CircuitMeta)> = HashMap::new();
+    let size = first.size();
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+        for (i, meta) in first
+            .read_slice_1d::<CircuitMeta, _>(begin..end)?
+            .iter()
+            .enumerate()
+        {
+            first_meta.insert(meta.uuid, ((begin + i) as CircuitIndex, *meta));
+        }
+    }
+
+    // Pairs of (second index, first index) with the same uuid.
+    let mut pairs: Vec<(CircuitIndex, CircuitIndex)> = Vec::new();
+    let size = second.size();
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+        for (i, meta) in second
+            .read_slice_1d::<CircuitMeta, _>(begin..end)?
+            .iter()
+            .enumerate()
+        {
+            let Some((first_index, first)) = first_meta.remove(&meta.uuid) else {
+                diff.add(
+                    DiffKind::OnlyInSecond,
+                    meta.uuid,
+                    format!("index {}", begin + i),
+                );
+                continue;
+            };
+
+            diff.common += 1;
+            let fields = meta_differences(&first, meta);
+            if !fields.is_empty() {
+                diff.add(DiffKind::MetadataDiffers, meta.uuid, fields.join(", "));
+            }
+            if compare_cells {
+                pairs.push(((begin + i) as CircuitIndex, first_index));
+            }
+        }
+    }
+
+    let mut only_first: Vec<(CircuitIndex, FixedAscii<32>)> = first_meta
+        .into_iter()
+        .map(|(uuid, (index, _))| (index, uuid))
+        .collect();
+    only_first.sort_by_key(|(index, _)| *index);
+    for (index, uuid) in only_first {
+        diff.add(DiffKind::OnlyInFirst, uuid, format!("index {index}"));
+    }
+
+    // Read the second dataset in order, and the matching first circuits by
+    // point selection.
+    for batch in pairs.chunks(step) {
+        let second_indices: Vec<usize> = batch.iter().map(|p| p.0 as usize).collect();
+        let first_indices: Vec<usize> = batch.iter().map(|p| p.1 as usize).collect();
+        let second_circs = second.read_slice_1d::<Circuit, _>(second_indices)?;
+        let first_circs = first.read_slice_1d::<Circuit, _>(first_indices)?;
+
+        for (a, b) in first_circs.iter().zip(second_circs.iter()) {
+            if let Some(pos) = first_cell_difference(a, b) {
+                diff.add(
+                    DiffKind::CellsDiffer,
+                    a.uuid,
+                    format!("first differs at cell {pos}"),
+                );
+            }
+        }
+    }
+
+    Ok(diff)
+}
+
+/// The names of the meta-data fields that differ between `a` and `b`.
+pub fn meta_differences(a: &CircuitMeta, b: &CircuitMeta) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if a.domain != b.domain {
+        fields.push("domain");
+    }
+    if a.shortest_private_suffix != b.shortest_private_suffix {
+        fields.push("shortest_private_suffix");
+    }
+    if a.day != b.day {
+        fields.push("day");
+    }
+    if a.port != b.port {
+        fields.push("port");
+    }
+    if a.len != b.len {
+        fields.push("len");
+    }
+    fields
+}
+
+/// The position of the first valid cell that differs between `a` and `b`, or
+/// `None` if their valid cells are identical.
+pub fn first_cell_difference(a: &Circuit, b: &Circuit) -> Option<usize> {
+    let (cells_a, cells_b) = (a.valid_cells(), b.valid_cells());
+    let (len_a, len_b) = (cells_a.len(), cells_b.len());
+
+    cells_a
+        .iter()
+        .zip(cells_b.iter())
+        .position(|(x, y)| x != y)
+        .or((len_a != len_b).then(|| std::cmp::min(len_a, len_b)))
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::fs::File as FsFile;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use hdf5::File;
+use hdf5::types::FixedAscii;
+use ndarray::Array1;
+
+use crate::Circuit;
+use crate::export::csv_field;
+use crate::features::{bursts, cumul, mean, par_map};
+
+/// The features of a circuit compared between day ranges.
+#[derive(Clone, Debug, PartialEq)]
+struct Summary {
+    len: f64,
+    bursts: f64,
+    cumul: Vec<f64>,
+}
+
+impl Summary {
+    fn new(circuit: &Circuit, cumul_points: usize) -> Self {
+        Self {
+            len: f64::from(circuit.len),
+            bursts: bursts(circuit).len() as f64,
+            cumul: cumul(circuit, cumul_points),
+        }
+    }
+}
+
+/// The features of the circuits with one label in one day range.
+#[derive(Clone, Debug, Default)]
+struct Group {
+    lens: Vec<f64>,
+    bursts: Vec<f64>,
+    /// The sum of the CUMUL representations of the circuits.
+    cumul_sum: Vec<f64>,
+}
+
+impl Group {
+    fn add(&mut self, summary: Summary) {
+        self.lens.push(summary.len);
+        self.bursts.push(summary.bursts);
+        if self.cumul_sum.is_empty() {
+            self.cumul_sum = vec![0.0; summary.cumul.len()];
+        }
+        for (sum, v) in self.cumul_sum.iter_mut().zip(summary.cumul) {
+            *sum += v;
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.lens.len()
+    }
+
+    fn mean_cumul(&self) -> Vec<f64> {
+        let n = self.count().max(1) as f64;
+        self.cumul_sum.iter().map(|s| s / n).collect()
+    }
+}
+
+/// How the features of the circuits with a label differ between two day
+/// ranges.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LabelDrift {
+    pub label: FixedAscii<44>,
+    /// The number of circuits with the label in the first day range.
+    pub count_a: usize,
+    /// The number of circuits with the label in the second day range.
+    pub count_b: usize,
+    /// The two-sample Kolmogorov-Smirnov statistic of the circuit lengths.
+    pub len_ks: f64,
+    /// The Jensen-Shannon divergence in bits of the histograms of the circuit
+    /// lengths.
+    pub len_js: f64,
+    /// The two-sample Kolmogorov-Smirnov statistic of the numbers of bursts.
+    pub burst_ks: f64,
+    /// The root mean square difference of the mean CUMUL representations,
+    /// relative to the largest absolute value of either mean.
+    pub cumul_rmsd: f64,
+}
+
+/// Options of `compare_days`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DriftOptions {
+    /// The number of points of the CUMUL representations.
+    pub cumul_points: usize,
+    /// The number of equal-width bins of the length histograms.
+    pub len_bins: usize,
+    /// Only compare labels with at least this many circuits in each range.
+    pub min_circuits: usize,
+    /// The number of threads computing features.
+    pub threads: usize,
+}
+
+impl Default for DriftOptions {
+    fn default() -> Self {
+        Self {
+            cumul_points: 100,
+            len_bins: 50,
+            min_circuits: 10,
+            threads: 1,
+        }
+    }
+}
+
+/// Compares the features of the circuits of each label in the `/circuits`
+/// dataset of `file` observed on the days in range `a` with those observed on
+/// the days in range `b`, to quantify how quickly traces go stale. Circuits
+/// on days in both ranges count toward both. `progress` is called with the
+/// number of circuits read after each batch. Returns the drift of every label
+/// with enough circuits in both ranges, sorted by label, and the drift of all
+/// circuits pooled under the label `*`.
+pub fn compare_days<F>(
+    file: &File,
+    a: RangeInclusive<u8>,
+    b: RangeInclusive<u8>,
+    options: &DriftOptions,
+    mut progress: F,
+) -> hdf5::Result<(Vec<LabelDrift>, LabelDrift)>
+where
+    F: FnMut(usize),
+{
+    let circuits = file.dataset("/circuits")?;
+    let size = circuits.size();
+    let step = 1_000; // multiple of chunk size
+    let mut groups: HashMap<FixedAscii<44>, (Group, Group)> = HashMap::new();
+    let mut all = (Group::default(), Group::default());
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+        let batch: Array1<Circuit> = circuits.read_slice(ndarray::s![begin..end])?;
+        let batch: Vec<&Circuit> = batch
+            .iter()
+            .filter(|c| a.contains(&c.day) || b.contains(&c.day))
+            .collect();
+        let summaries = par_map(&batch, options.threads, |c| {
+            Summary::new(c, options.cumul_points)
+        });
+
+        for (circuit, summary) in batch.iter().zip(summaries) {
+            let label = groups.entry(circuit.label()).or_default();
+            if a.contains(&circuit.day) {
+                label.0.add(summary.clone());
+                all.0.add(summary.clone());
+            }
+            if b.contains(&circuit.day) {
+                label.1.add(summary.clone());
+                all.1.add(summary);
+            }
+        }
+        progress(end - begin);
+    }
+
+    let mut drifts: Vec<LabelDrift> = groups
+        .iter()
+        .filter(|(_, (ga, gb))| ga.count().min(gb.count()) >= options.min_circuits.max(1))
+        .map(|(&label, (ga, gb))| drift(label, ga, gb, options))
+        .collect();
+    drifts.sort_by(|x, y| x.label.as_str().cmp(y.label.as_str()));
+
+    let pooled = FixedAscii::from_ascii(b"*").expect("ascii label");
+    Ok((drifts, drift(pooled, &all.0, &all.1, options)))
+}
+
+fn drift(label: FixedAscii<44>, a: &Group, b: &Group, options: &DriftOptions) -> LabelDrift {
+    LabelDrift {
+        label,
+        count_a: a.count(),
+        count_b: b.count(),
+        len_ks: ks_statistic(&a.lens, &b.lens),
+        len_js: js_divergence(&a.lens, &b.lens, options.len_bins),
+        burst_ks: ks_statistic(&a.bursts, &b.bursts),
+        cumul_rmsd: relative_rmsd(&a.mean_cumul(), &b.mean_cumul()),
+    }
+}
+
+/// Returns the two-sample Kolmogorov-Smirnov statistic of `a` and `b`: the
+/// largest difference between their empirical distribution functions, from 0
+/// for identical samples to 1 for disjoint ones. Returns 0 if either is empty.
+pub fn ks_statistic(a: &[f64], b: &[f64]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let (mut a, mut b) = (a.to_vec(), b.to_vec());
+    a.sort_by(f64::total_cmp);
+    b.sort_by(f64::total_cmp);
+
+    let (mut i, mut j, mut max) = (0, 0, 0.0f64);
+    while i < a.len() && j < b.len() {
+        let x = a[i].min(b[j]);
+        while i < a.len() && a[i] <= x {
+            i += 1;
+        }
+        while j < b.len() && b[j] <= x {
+            j += 1;
+        }
+        max = max.max((i as f64 / a.len() as f64 - j as f64 / b.len() as f64).abs());
+    }
+    max
+}
+
+/// Returns the Jensen-Shannon divergence in bits between the histograms of `a`
+/// and `b` over `bins` equal-width bins spanning both samples, from 0 for
+/// identical histograms to 1 for disjoint ones. Returns 0 if either is empty.
+pub fn js_divergence(a: &[f64], b: &[f64], bins: usize) -> f64 {
+    if a.is_empty() || b.is_empty() || bins == 0 {
+        return 0.0;
+    }
+    let values = a.iter().chain(b.iter());
+    let lo = values.clone().copied().fold(f64::INFINITY, f64::min);
+    let hi = values.copied().fold(f64::NEG_INFINITY, f64::max);
+    let width = (hi - lo) / bins as f64;
+
+    let hist = |values: &[f64]| {
+        let mut hist = vec![0.0; bins];
+        for v in values {
+            let bin = if width > 0.0 {
+                ((v - lo) / width) as usize
+            } else {
+                0
+            };
+            hist[bin.min(bins - 1)] += 1.0 / values.len() as f64;
+        }
+        hist
+    };
+    let (p, q) = (hist(a), hist(b));
+
+    let kl = |p: &[f64], m: &[f64]| -> f64 {
+        p.iter()
+            .zip(m)
+            .filter(|&(&p, _)| p > 0.0)
+            .map(|(p, m)| p * (p / m).log2())
+            .sum()
+    };
+    let m: Vec<f64> = p.iter().zip(q.iter()).map(|(p, q)| (p + q) / 2.0).collect();
+    (kl(&p, &m) + kl(&q, &m)) / 2.0
+}
+
+/// The root mean square difference of `a` and `b`, divided by the largest
+/// absolute value of either so that curves of different scales compare.
+fn relative_rmsd(a: &[f64], b: &[f64]) -> f64 {
+    let scale = a.iter().chain(b.iter()).fold(0.0f64, |m, v| m.max(v.abs()));
+    if scale == 0.0 {
+        return 0.0;
+    }
+    let squares: Vec<f64> = a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).collect();
+    mean(&squares).sqrt() / scale
+}
+
+/// Writes `drifts` to a CSV file at `path` with a header row.
+pub fn write_drift_csv<P: AsRef<Path>>(path: P, drifts: &[LabelDrift]) -> io::Result<()> {
+    let mut out = BufWriter::new(FsFile::create(path)?);
+    writeln!(
+        out,
+        "label,count_a,count_b,len_ks,len_js,burst_ks,cumul_rmsd"
+    )?;
+    for d in drifts {
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{}",
+            csv_field(d.label.as_str()),
+            d.count_a,
+            d.count_b,
+            d.len_ks,
+            d.len_js,
+            d.burst_ks,
+            d.cumul_rmsd
+        )?;
+    }
+    out.flush()
+}
@@ -0,0 +1,160 @@
+use crate::features::par_map;
+use crate::{Circuit, Direction};
+
+/// A cell of a trace as compared by dynamic time warping.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Point {
+    /// The time in seconds since the first valid cell.
+    pub time: f64,
+    /// 1 for cells sent toward the server, -1 for cells sent toward the
+    /// client, and 0 for padding.
+    pub direction: f64,
+}
+
+/// Options of the dynamic time warping distance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DtwOptions {
+    /// The cost of a second of difference between the times of two aligned
+    /// cells, relative to the cost of 2 of aligning cells sent in opposite
+    /// directions. Use 0 to compare only the direction sequences.
+    pub time_weight: f64,
+    /// The Sakoe-Chiba band: the farthest cell `i` of one trace may be aligned
+    /// from cell `i` of the other. The band is widened to the difference of the
+    /// lengths of the traces, so that an alignment always exists.
+    pub band: Option<usize>,
+    /// Compare only this many of the first valid cells of each circuit.
+    pub max_cells: Option<usize>,
+}
+
+impl Default for DtwOptions {
+    fn default() -> Self {
+        Self {
+            time_weight: 1.0,
+            band: None,
+            max_cells: None,
+        }
+    }
+}
+
+/// Returns the points of the first `max_cells` valid cells of `circuit`, or all
+/// of them if `None`.
+pub fn points(circuit: &Circuit, max_cells: Option<usize>) -> Vec<Point> {
+    let cells = circuit.valid_cells();
+    let cells = &cells[..max_cells.map_or(cells.len(), |max| std::cmp::min(cells.len(), max))];
+    let start = cells.first().map_or(0.0, |c| c.time);
+
+    cells
+        .iter()
+        .map(|c| Point {
+            time: c.time - start,
+            direction: match c.direction {
+                Direction::CLIENT_TO_SERVER => 1.0,
+                Direction::SERVER_TO_CLIENT => -1.0,
+                Direction::PADDING => 0.0,
+            },
+        })
+        .collect()
+}
+
+/// Returns the dynamic time warping distance between the point sequences `a`
+/// and `b`: the least total cost of aligning every point of each to at least
+/// one point of the other in order, where aligning two points costs the
+/// difference of their directions plus `time_weight` times the difference of
+/// their times. The distance between two empty sequences is 0, and between an
+/// empty and a non-empty sequence is infinite. Uses memory proportional to the
+/// length of `b`.
+pub fn distance_points(a: &[Point], b: &[Point], options: &DtwOptions) -> f64 {
+    let (n, m) = (a.len(), b.len());
+    if n == 0 || m == 0 {
+        return if n == m { 0.0 } else { f64::INFINITY };
+    }
+    let band = options.band.map(|w| w.max(n.abs_diff(m)));
+
+    // Row `i` holds the least cost of aligning the first `i` points of `a`
+    // with the first `j` points of `b` at position `j`. Only the positions
+    // read by the next row are kept valid, so the band bounds the work.
+    let mut prev = vec![f64::INFINITY; m + 1];
+    let mut cur = vec![f64::INFINITY; m + 1];
+    prev[0] = 0.0;
+
+    for i in 1..=n {
+        let (lo, hi) = match band {
+            Some(w) => (i.saturating_sub(w).max(1), std::cmp::min(i + w, m)),
+            None => (1, m),
+        };
+        cur[lo - 1] = f64::INFINITY;
+        if hi < m {
+            cur[hi + 1] = f64::INFINITY;
+        }
+        for j in lo..=hi {
+            let (p, q) = (&a[i - 1], &b[j - 1]);
+            let cost =
+                (p.direction - q.direction).abs() + options.time_weight * (p.time - q.time).abs();
+            cur[j] = cost + prev[j].min(prev[j - 1]).min(cur[j - 1]);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev[m]
+}
+
+/// Returns the dynamic time warping distance between circuits `a` and `b`, as
+/// by `distance_points` on their `points`.
+pub fn distance(a: &Circuit, b: &Circuit, options: &DtwOptions) -> f64 {
+    distance_points(
+        &points(a, options.max_cells),
+        &points(b, options.max_cells),
+        options,
+    )
+}
+
+/// Returns the distance between every pair of `circuits` computed on `threads`
+/// threads, in the condensed order of `scipy.spatial.distance.pdist`: the
+/// distance between circuits `i < j` is at position
+/// `n * i - i * (i + 1) / 2 + j - i - 1` for `n` circuits.
+pub fn pairwise(circuits: &[Circuit], options: &DtwOptions, threads: usize) -> Vec<f64> {
+    let points = par_map(circuits, threads, |c| points(c, options.max_cells));
+    let pairs: Vec<(usize, usize)> = (0..points.len())
+        .flat_map(|i| (i + 1..points.len()).map(move |j| (i, j)))
+        .collect();
+    par_map(&pairs, threads, |&(i, j)| {
+        distance_points(&points[i], &points[j], options)
+    })
+}
+
+/// Returns the distance from each of `queries` to each of `references`
+/// computed on `threads` threads, as one row per query.
+pub fn distances(
+    queries: &[Circuit],
+    references: &[Circuit],
+    options: &DtwOptions,
+    threads: usize,
+) -> Vec<Vec<f64>> {
+    let references = par_map(references, threads, |c| points(c, options.max_cells));
+    par_map(queries, threads, |q| {
+        let q = points(q, options.max_cells);
+        references
+            .iter()
+            .map(|r| distance_points(&q, r, options))
+            .collect()
+    })
+}
+
+/// Returns the position in `references` of the nearest reference to each of
+/// `queries` and its distance, computed on `threads` threads, or `None` if
+/// there are no references.
+pub fn nearest(
+    queries: &[Circuit],
+    references: &[Circuit],
+    options: &DtwOptions,
+    threads: usize,
+) -> Vec<Option<(usize, f64)>> {
+    distances(queries, references, options, threads)
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .enumerate()
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+        })
+        .collect()
+}
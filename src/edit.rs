@@ -0,0 +1,72 @@
+use crate::Circuit;
+use crate::features::{direction_sequence, par_map};
+
+/// Returns the Levenshtein distance between the direction sequences `a` and
+/// `b`: the least number of cells inserted, deleted, or flipped to turn one
+/// into the other. Uses memory proportional to the length of `b`.
+pub fn distance(a: &[i8], b: &[i8]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+
+    for (i, x) in a.iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(x != y);
+            cur[j + 1] = substitute.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev[b.len()]
+}
+
+/// Returns `distance` divided by the length of the longer sequence, so that
+/// identical sequences are 0 apart and entirely different ones 1 apart. Two
+/// empty sequences are 0 apart.
+pub fn normalized_distance(a: &[i8], b: &[i8]) -> f64 {
+    match a.len().max(b.len()) {
+        0 => 0.0,
+        n => distance(a, b) as f64 / n as f64,
+    }
+}
+
+/// Returns the `direction_sequence` of `circuit`, truncated to its first
+/// `max_cells` cells if given.
+pub fn sequence(circuit: &Circuit, max_cells: Option<usize>) -> Vec<i8> {
+    let mut sequence = direction_sequence(circuit);
+    if let Some(max) = max_cells {
+        sequence.truncate(max);
+    }
+    sequence
+}
+
+/// Returns the `distance` between the `sequence`s of every pair of `circuits`
+/// computed on `threads` threads, in the condensed order of
+/// `scipy.spatial.distance.pdist`, as by `dtw::pairwise`.
+pub fn pairwise(circuits: &[Circuit], max_cells: Option<usize>, threads: usize) -> Vec<usize> {
+    let sequences = par_map(circuits, threads, |c| sequence(c, max_cells));
+    let pairs: Vec<(usize, usize)> = (0..sequences.len())
+        .flat_map(|i| (i + 1..sequences.len()).map(move |j| (i, j)))
+        .collect();
+    par_map(&pairs, threads, |&(i, j)| {
+        distance(&sequences[i], &sequences[j])
+    })
+}
+
+/// Returns the symmetric matrix of the `distance` between the `sequence`s of
+/// every pair of `circuits` computed on `threads` threads, as one row per
+/// circuit, with zeros on the diagonal.
+pub fn matrix(circuits: &[Circuit], max_cells: Option<usize>, threads: usize) -> Vec<Vec<usize>> {
+    let n = circuits.len();
+    let condensed = pairwise(circuits, max_cells, threads);
+    let mut matrix = vec![vec![0; n]; n];
+    let mut k = 0;
+    for i in 0..n {
+        for j in i + 1..n {
+            matrix[i][j] = condensed[k];
+            matrix[j][i] = condensed[k];
+            k += 1;
+        }
+    }
+    matrix
+}
@@ -0,0 +1,1064 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use hdf5::Extent;
+use hdf5::types::FixedAscii;
+use ndarray::Array2;
+
+use crate::features::{self, TimeNormalization};
+use crate::index::LabelVocabulary;
+use crate::npz::{NpzWriter, npy_header};
+use crate::tar::TarWriter;
+use crate::writer::DEFAULT_CHUNK;
+use crate::{CellCommand, Circuit, CircuitIndex, Direction, MAX_CELLS, RelayCommand};
+
+/// Writes circuits to some non-HDF5 format, one circuit at a time.
+pub trait Exporter {
+    /// Writes `circuit`, which is at position `index` in the source dataset.
+    fn write(&mut self, index: CircuitIndex, circuit: &Circuit) -> io::Result<()>;
+
+    /// Flushes any buffered output and completes the export.
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+/// The formats that circuits can be exported to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExportFormat {
+    /// One JSON object per line.
+    Jsonl,
+    /// One CSV row per circuit, with the cells serialized in a single column.
+    Csv,
+    /// One CSV row per valid cell.
+    CsvCells,
+    /// A NumPy archive with one array per circuit field.
+    Npz,
+    /// A NumPy archive of Deep Fingerprinting style `X`/`y` arrays.
+    Df,
+    /// An HDF5 file of Deep Fingerprinting style `X`/`y` datasets.
+    DfHdf5,
+    /// Like `Df`, but with the Tik-Tok timing and direction representation.
+    TikTok,
+    /// Like `DfHdf5`, but with the Tik-Tok timing and direction representation.
+    TikTokHdf5,
+    /// Like `Df`, but with the time and cell and relay commands of each cell.
+    Commands,
+    /// WebDataset tar shards with one sample per circuit.
+    WebDataset,
+    /// A directory of Wang et al. style text files, one per circuit.
+    Wang,
+    /// A Parquet table of circuit meta-data and a second table of cells.
+    /// Requires the `parquet` feature.
+    Parquet,
+}
+
+/// Options for the exporters that support them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExportOptions {
+    /// How times are normalized by the Tik-Tok formats.
+    pub time_normalization: TimeNormalization,
+    /// The class ids used by the formats that write them, typically read from
+    /// the `/labels` dataset of the source file.
+    pub vocabulary: Option<LabelVocabulary>,
+    /// How the commands format encodes cell and relay commands.
+    pub command_encoding: CommandEncoding,
+    /// The number of samples per shard of the WebDataset format [default:
+    /// `DEFAULT_SHARD_SIZE`].
+    pub shard_size: Option<usize>,
+}
+
+impl ExportFormat {
+    /// All supported formats.
+    pub const ALL: [ExportFormat; 12] = [
+        ExportFormat::Jsonl,
+        ExportFormat::Csv,
+        ExportFormat::CsvCells,
+        ExportFormat::Npz,
+        ExportFormat::Df,
+        ExportFormat::DfHdf5,
+        ExportFormat::TikTok,
+        ExportFormat::TikTokHdf5,
+        ExportFormat::Commands,
+        ExportFormat::WebDataset,
+        ExportFormat::Wang,
+        ExportFormat::Parquet,
+    ];
+
+    /// The name of the format.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Csv => "csv",
+            ExportFormat::CsvCells => "csv-cells",
+            ExportFormat::Npz => "npz",
+            ExportFormat::Df => "df",
+            ExportFormat::DfHdf5 => "df-h5",
+            ExportFormat::TikTok => "tiktok",
+            ExportFormat::TikTokHdf5 => "tiktok-h5",
+            ExportFormat::Commands => "commands",
+            ExportFormat::WebDataset => "wds",
+            ExportFormat::Wang => "wang",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+
+    /// The extension of the output path of the format, which is a directory
+    /// for `Wang`.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Csv | ExportFormat::CsvCells => "csv",
+            ExportFormat::Npz
+            | ExportFormat::Df
+            | ExportFormat::TikTok
+            | ExportFormat::Commands => "npz",
+            ExportFormat::DfHdf5 | ExportFormat::TikTokHdf5 => "h5",
+            ExportFormat::WebDataset => "tar",
+            ExportFormat::Wang => "wang",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+
+    /// Looks up a format by name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.name() == name)
+    }
+
+    /// Creates an exporter that writes this format to `path` using `options`.
+    pub fn exporter<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &ExportOptions,
+    ) -> io::Result<Box<dyn Exporter>> {
+        let tik_tok = Representation::TikTok(options.time_normalization);
+        let vocabulary = || options.vocabulary.clone();
+
+        Ok(match self {
+            ExportFormat::Jsonl => Box::new(JsonlExporter::create(path)?),
+            ExportFormat::Csv => Box::new(CsvExporter::create(path)?),
+            ExportFormat::CsvCells => Box::new(CsvCellsExporter::create(path)?),
+            ExportFormat::Npz => Box::new(NpzExporter::create(path)?),
+            ExportFormat::Df => Box::new(
+                DfExporter::create_npz(path, Representation::Direction)?
+                    .with_vocabulary(vocabulary()),
+            ),
+            ExportFormat::DfHdf5 => Box::new(
+                DfExporter::create_hdf5(path, Representation::Direction)?
+                    .with_vocabulary(vocabulary()),
+            ),
+            ExportFormat::TikTok => {
+                Box::new(DfExporter::create_npz(path, tik_tok)?.with_vocabulary(vocabulary()))
+            }
+            ExportFormat::TikTokHdf5 => {
+                Box::new(DfExporter::create_hdf5(path, tik_tok)?.with_vocabulary(vocabulary()))
+            }
+            ExportFormat::Commands => Box::new(
+                CommandsExporter::create(path, options.command_encoding)?
+                    .with_vocabulary(vocabulary()),
+            ),
+            ExportFormat::WebDataset => Box::new(WebDatasetExporter::create(
+                path,
+                options.shard_size.unwrap_or(DEFAULT_SHARD_SIZE),
+            )?),
+            ExportFormat::Wang => {
+                Box::new(WangExporter::create(path)?.with_vocabulary(vocabulary()))
+            }
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => Box::new(crate::columnar::ParquetExporter::create(path)?),
+            #[cfg(not(feature = "parquet"))]
+            ExportFormat::Parquet => {
+                return Err(io::Error::other(
+                    "Parquet export requires building with the parquet feature",
+                ));
+            }
+        })
+    }
+}
+
+/// Writes each circuit as a JSON object on its own line. Only the valid cells
+/// are written, each as a `[time, direction, cell_cmd, relay_cmd]` array.
+pub struct JsonlExporter {
+    out: BufWriter<File>,
+    line: String,
+}
+
+impl JsonlExporter {
+    /// Creates an exporter writing to `path`.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self {
+            out: BufWriter::new(File::create(path)?),
+            line: String::new(),
+        })
+    }
+}
+
+impl Exporter for JsonlExporter {
+    fn write(&mut self, index: CircuitIndex, circuit: &Circuit) -> io::Result<()> {
+        let line = &mut self.line;
+        line.clear();
+
+        line.push('{');
+        write_json_meta(line, index, circuit);
+        line.push_str(",\"cells\":[");
+
+        for (i, cell) in circuit.valid_cells().iter().enumerate() {
+            if i > 0 {
+                line.push(',');
+            }
+            let time = if cell.time.is_finite() {
+                cell.time.to_string()
+            } else {
+                "null".to_string()
+            };
+            let _ = write!(
+                line,
+                "[{time},{},{},{}]",
+                cell.direction as i8, cell.cell_cmd as u8, cell.relay_cmd as u8
+            );
+        }
+
+        line.push_str("]}\n");
+        self.out.write_all(line.as_bytes())
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Writes each circuit as a CSV row with a header. The valid cells are written
+/// to the `cells` column as `time:direction:cell_cmd:relay_cmd` items
+/// separated by `;`.
+pub struct CsvExporter {
+    out: BufWriter<File>,
+    line: String,
+}
+
+impl CsvExporter {
+    /// Creates an exporter writing to `path`, starting with the header row.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(
+            out,
+            "index,uuid,domain,shortest_private_suffix,label,day,port,len,cells"
+        )?;
+        Ok(Self {
+            out,
+            line: String::new(),
+        })
+    }
+}
+
+impl Exporter for CsvExporter {
+    fn write(&mut self, index: CircuitIndex, circuit: &Circuit) -> io::Result<()> {
+        let line = &mut self.line;
+        line.clear();
+
+        let _ = write!(
+            line,
+            "{index},{},{},{},{},{},{},{},",
+            csv_field(circuit.uuid.as_str()),
+            csv_field(circuit.domain.as_str()),
+            csv_field(circuit.shortest_private_suffix.as_str()),
+            csv_field(circuit.label().as_str()),
+            circuit.day,
+            circuit.port,
+            circuit.len,
+        );
+
+        for (i, cell) in circuit.valid_cells().iter().enumerate() {
+            if i > 0 {
+                line.push(';');
+            }
+            let _ = write!(
+                line,
+                "{}:{}:{}:{}",
+                cell.time, cell.direction as i8, cell.cell_cmd as u8, cell.relay_cmd as u8
+            );
+        }
+
+        line.push('\n');
+        self.out.write_all(line.as_bytes())
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Writes each valid cell as a CSV row with a header, along with the index,
+/// uuid, and label of its circuit and its position `seq` in the circuit.
+pub struct CsvCellsExporter {
+    out: BufWriter<File>,
+    line: String,
+}
+
+impl CsvCellsExporter {
+    /// Creates an exporter writing to `path`, starting with the header row.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(
+            out,
+            "circuit_index,uuid,label,seq,time,direction,cell_cmd,relay_cmd"
+        )?;
+        Ok(Self {
+            out,
+            line: String::new(),
+        })
+    }
+}
+
+impl Exporter for CsvCellsExporter {
+    fn write(&mut self, index: CircuitIndex, circuit: &Circuit) -> io::Result<()> {
+        let line = &mut self.line;
+        let uuid = csv_field(circuit.uuid.as_str());
+        let label = csv_field(circuit.label().as_str());
+
+        for (seq, cell) in circuit.valid_cells().iter().enumerate() {
+            line.clear();
+            let _ = writeln!(
+                line,
+                "{index},{uuid},{label},{seq},{},{},{},{}",
+                cell.time, cell.direction as i8, cell.cell_cmd as u8, cell.relay_cmd as u8
+            );
+            self.out.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Writes the circuits to a NumPy archive with one array per field. The
+/// string fields are fixed-width byte strings, and the cell fields are
+/// `[N, MAX_CELLS]` arrays that include the zeroed padding beyond each `len`.
+pub struct NpzExporter {
+    npz: NpzWriter,
+    ids: NpzIds,
+}
+
+struct NpzIds {
+    index: usize,
+    uuid: usize,
+    domain: usize,
+    shortest_private_suffix: usize,
+    day: usize,
+    port: usize,
+    len: usize,
+    time: usize,
+    direction: usize,
+    cell_cmd: usize,
+    relay_cmd: usize,
+}
+
+impl NpzExporter {
+    /// Creates an exporter writing to `path`.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut npz = NpzWriter::create(path)?;
+        let n_cells = Circuit::empty().cells.len();
+
+        let ids = NpzIds {
+            index: npz.add_array("index", "<u4", &[])?,
+            uuid: npz.add_array("uuid", "|S32", &[])?,
+            domain: npz.add_array("domain", "|S44", &[])?,
+            shortest_private_suffix: npz.add_array("shortest_private_suffix", "|S44", &[])?,
+            day: npz.add_array("day", "|u1", &[])?,
+            port: npz.add_array("port", "<u2", &[])?,
+            len: npz.add_array("len", "<u2", &[])?,
+            time: npz.add_array("time", "<f8", &[n_cells])?,
+            direction: npz.add_array("direction", "|i1", &[n_cells])?,
+            cell_cmd: npz.add_array("cell_cmd", "|u1", &[n_cells])?,
+            relay_cmd: npz.add_array("relay_cmd", "|u1", &[n_cells])?,
+        };
+
+        Ok(Self { npz, ids })
+    }
+}
+
+impl Exporter for NpzExporter {
+    fn write(&mut self, index: CircuitIndex, circuit: &Circuit) -> io::Result<()> {
+        let ids = &self.ids;
+        let npz = &mut self.npz;
+
+        npz.append(ids.index, &index.to_le_bytes())?;
+        npz.append(ids.uuid, &fixed_bytes::<32>(circuit.uuid.as_bytes()))?;
+        npz.append(ids.domain, &fixed_bytes::<44>(circuit.domain.as_bytes()))?;
+        npz.append(
+            ids.shortest_private_suffix,
+            &fixed_bytes::<44>(circuit.shortest_private_suffix.as_bytes()),
+        )?;
+        npz.append(ids.day, &[circuit.day])?;
+        npz.append(ids.port, &circuit.port.to_le_bytes())?;
+        npz.append(ids.len, &circuit.len.to_le_bytes())?;
+
+        let cells = &circuit.cells;
+        let time: Vec<u8> = cells.iter().flat_map(|c| c.time.to_le_bytes()).collect();
+        let direction: Vec<u8> = cells.iter().map(|c| c.direction as i8 as u8).collect();
+        let cell_cmd: Vec<u8> = cells.iter().map(|c| c.cell_cmd as u8).collect();
+        let relay_cmd: Vec<u8> = cells.iter().map(|c| c.relay_cmd as u8).collect();
+
+        npz.append(ids.time, &time)?;
+        npz.append(ids.direction, &direction)?;
+        npz.append(ids.cell_cmd, &cell_cmd)?;
+        npz.append(ids.relay_cmd, &relay_cmd)
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.npz.finish()
+    }
+}
+
+/// Writes each circuit to its own text file in the format of Wang et al.'s
+/// attack code, as used by much of the website fingerprinting literature.
+///
+/// The files are written to the directory `path` and named
+/// `<class>-<instance>`, where `<class>` is the class id of the circuit's label
+/// and `<instance>` counts the circuits of that class from 0. Each valid cell
+/// with a direction is a `<time>\t<direction>` line, where `<time>` is in
+/// seconds since the first valid cell and `<direction>` is 1 toward the server
+/// and -1 toward the client. Class ids are assigned as by `DfExporter`, and
+/// `finish` writes the label of each class to `wang_labels_path(path)` as CSV.
+pub struct WangExporter {
+    dir: PathBuf,
+    classes: ClassIds,
+    /// The number of circuits written of each class.
+    instances: Vec<usize>,
+    text: String,
+}
+
+impl WangExporter {
+    /// Creates an exporter writing to the directory `path`, creating it if
+    /// needed.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        fs::create_dir_all(&path)?;
+        Ok(Self {
+            dir: path.as_ref().to_path_buf(),
+            classes: ClassIds::new(None),
+            instances: Vec::new(),
+            text: String::new(),
+        })
+    }
+
+    /// Takes the class ids from `vocabulary`, if given, instead of assigning
+    /// them in order of first appearance.
+    pub fn with_vocabulary(mut self, vocabulary: Option<LabelVocabulary>) -> Self {
+        self.classes = ClassIds::new(vocabulary);
+        self
+    }
+}
+
+/// The path of the CSV file mapping class ids to labels written alongside the
+/// directory `path` by `WangExporter`, e.g., `export.labels.csv` for
+/// `export.wang`.
+pub fn wang_labels_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    path.as_ref().with_extension("labels.csv")
+}
+
+impl Exporter for WangExporter {
+    fn write(&mut self, _index: CircuitIndex, circuit: &Circuit) -> io::Result<()> {
+        let class = self.classes.class(circuit)? as usize;
+        if class >= self.instances.len() {
+            self.instances.resize(class + 1, 0);
+        }
+        let instance = self.instances[class];
+        self.instances[class] += 1;
+
+        let cells = circuit.valid_cells();
+        let start = cells.first().map_or(0.0, |c| c.time);
+        let text = &mut self.text;
+        text.clear();
+        for cell in cells.iter().filter(|c| c.direction != Direction::PADDING) {
+            let _ = writeln!(
+                text,
+                "{}\t{}",
+                (cell.time - start).max(0.0),
+                cell.direction as i8
+            );
+        }
+
+        fs::write(
+            self.dir.join(format!("{class}-{instance}")),
+            text.as_bytes(),
+        )
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(wang_labels_path(&self.dir))?);
+        writeln!(out, "class,label")?;
+        for (class, label) in self.classes.labels.iter().enumerate() {
+            writeln!(out, "{class},{}", csv_field(label.as_str()))?;
+        }
+        out.flush()
+    }
+}
+
+/// The number of samples written to each shard by `WebDatasetExporter` unless
+/// configured otherwise.
+pub const DEFAULT_SHARD_SIZE: usize = 10_000;
+
+/// The NumPy dtype of the cells written by `WebDatasetExporter`.
+pub const WDS_CELLS_DESCR: &str =
+    "[('time', '<f8'), ('direction', '|i1'), ('cell_cmd', '|u1'), ('relay_cmd', '|u1')]";
+
+/// Writes the circuits as WebDataset tar shards, with one sample per circuit.
+///
+/// Each sample is keyed by the circuit's uuid and consists of
+/// `<uuid>.cells.npy`, a one dimensional array of the valid cells with the
+/// structured dtype `WDS_CELLS_DESCR`, and `<uuid>.json`, an object holding the
+/// circuit's meta-data as written by `JsonlExporter`. Shards hold at most
+/// `shard_size` samples and are written to `shard_path(path, k)` for the `k`th
+/// shard.
+pub struct WebDatasetExporter {
+    path: PathBuf,
+    shard_size: usize,
+    shard: Option<TarWriter>,
+    n_shards: usize,
+    n_samples: usize,
+}
+
+impl WebDatasetExporter {
+    /// Creates an exporter writing shards of `shard_size` samples, named after
+    /// `path`. Creates no file until the first circuit is written.
+    pub fn create<P: AsRef<Path>>(path: P, shard_size: usize) -> io::Result<Self> {
+        if shard_size == 0 {
+            return Err(io::Error::other("Shard size must be positive"));
+        }
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            shard_size,
+            shard: None,
+            n_shards: 0,
+            n_samples: 0,
+        })
+    }
+
+    /// Returns the shard to write the next sample to, starting a new shard if
+    /// the current one is full.
+    fn shard(&mut self) -> io::Result<&mut TarWriter> {
+        if self.n_samples % self.shard_size == 0 {
+            if let Some(full) = self.shard.take() {
+                full.finish()?;
+            }
+            self.shard = Some(TarWriter::create(shard_path(&self.path, self.n_shards))?);
+            self.n_shards += 1;
+        }
+        Ok(self.shard.as_mut().unwrap())
+    }
+}
+
+/// The path of shard `k` written by `WebDatasetExporter` for `path`, e.g.,
+/// `export-000003.tar` for shard 3 of `export.tar`, following the brace
+/// notation `export-{000000..000009}.tar` used to list shards.
+pub fn shard_path<P: AsRef<Path>>(path: P, k: usize) -> PathBuf {
+    let path = path.as_ref();
+    let stem = path
+        .file_stem()
+        .map_or_else(|| "shard".to_string(), |s| s.to_string_lossy().into_owned());
+    path.with_file_name(format!("{stem}-{k:06}.tar"))
+}
+
+impl Exporter for WebDatasetExporter {
+    fn write(&mut self, index: CircuitIndex, circuit: &Circuit) -> io::Result<()> {
+        let cells = circuit.valid_cells();
+        let mut npy = npy_header(WDS_CELLS_DESCR, cells.len(), &[], 0);
+        for cell in cells {
+            npy.extend_from_slice(&cell.time.to_le_bytes());
+            npy.extend_from_slice(&[
+                cell.direction as i8 as u8,
+                cell.cell_cmd as u8,
+                cell.relay_cmd as u8,
+            ]);
+        }
+
+        let mut json = String::from("{");
+        write_json_meta(&mut json, index, circuit);
+        json.push('}');
+
+        let key = circuit.uuid.as_str();
+        let shard = self.shard()?;
+        shard.append(&format!("{key}.cells.npy"), &npy)?;
+        shard.append(&format!("{key}.json"), json.as_bytes())?;
+        self.n_samples += 1;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        match self.shard {
+            Some(shard) => shard.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The number of elements in each row written by `DfExporter`.
+pub const DF_LENGTH: usize = MAX_CELLS;
+
+/// The number of rows buffered by `DfExporter` before writing them to HDF5.
+const DF_WRITE_BATCH: usize = 1_000;
+
+/// The representation of each circuit written as a row of `X` by `DfExporter`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Representation {
+    /// The direction of each cell, as in `features::direction_sequence`.
+    Direction,
+    /// The direction times the time of each cell, as in `features::tik_tok`.
+    TikTok(TimeNormalization),
+}
+
+/// Writes the circuits in the layout used to train the Deep Fingerprinting
+/// (DF) and Tik-Tok attacks: `X` is a float32 `[N, DF_LENGTH]` array holding the
+/// `Representation` of each circuit padded with zeros, and `y` is an int64
+/// `[N]` array of class ids. Class ids are taken from a `LabelVocabulary` if
+/// one is given, and are otherwise assigned to labels in order of first
+/// appearance. Row `k` of the `labels` array holds the label of class `k`.
+pub struct DfExporter {
+    out: DfOutput,
+    representation: Representation,
+    classes: ClassIds,
+}
+
+enum DfOutput {
+    Npz {
+        npz: NpzWriter,
+        x: usize,
+        y: usize,
+        labels: usize,
+    },
+    Hdf5(DfHdf5),
+}
+
+struct DfHdf5 {
+    file: hdf5::File,
+    x: hdf5::Dataset,
+    y: hdf5::Dataset,
+    x_buf: Vec<f32>,
+    y_buf: Vec<i64>,
+    written: usize,
+}
+
+impl DfExporter {
+    /// Creates an exporter writing `representation` to a NumPy archive at
+    /// `path`.
+    pub fn create_npz<P: AsRef<Path>>(path: P, representation: Representation) -> io::Result<Self> {
+        let mut npz = NpzWriter::create(path)?;
+        let out = DfOutput::Npz {
+            x: npz.add_array("X", "<f4", &[DF_LENGTH])?,
+            y: npz.add_array("y", "<i8", &[])?,
+            labels: npz.add_array("labels", "|S44", &[])?,
+            npz,
+        };
+        Ok(Self::new(out, representation))
+    }
+
+    /// Creates an exporter writing `representation` to an HDF5 file at `path`,
+    /// replacing any existing file.
+    pub fn create_hdf5<P: AsRef<Path>>(
+        path: P,
+        representation: Representation,
+    ) -> io::Result<Self> {
+        let h5 = DfHdf5::create(path).map_err(io::Error::other)?;
+        Ok(Self::new(DfOutput::Hdf5(h5), representation))
+    }
+
+    fn new(out: DfOutput, representation: Representation) -> Self {
+        Self {
+            out,
+            representation,
+            classes: ClassIds::new(None),
+        }
+    }
+
+    /// Takes the class ids from `vocabulary`, if given, instead of assigning
+    /// them in order of first appearance.
+    pub fn with_vocabulary(mut self, vocabulary: Option<LabelVocabulary>) -> Self {
+        self.classes = ClassIds::new(vocabulary);
+        self
+    }
+}
+
+impl Exporter for DfExporter {
+    fn write(&mut self, _index: CircuitIndex, circuit: &Circuit) -> io::Result<()> {
+        let class = self.classes.class(circuit)?;
+        let mut row = [0f32; DF_LENGTH];
+        match self.representation {
+            Representation::Direction => {
+                let directions = features::direction_sequence(circuit);
+                for (x, d) in row.iter_mut().zip(directions) {
+                    *x = d as f32;
+                }
+            }
+            Representation::TikTok(normalization) => {
+                let times = features::tik_tok(circuit, normalization);
+                for (x, t) in row.iter_mut().zip(times) {
+                    *x = t as f32;
+                }
+            }
+        }
+
+        match &mut self.out {
+            DfOutput::Npz { npz, x, y, .. } => {
+                let bytes: Vec<u8> = row.iter().flat_map(|v| v.to_le_bytes()).collect();
+                npz.append(*x, &bytes)?;
+                npz.append(*y, &class.to_le_bytes())
+            }
+            DfOutput::Hdf5(h5) => {
+                h5.x_buf.extend_from_slice(&row);
+                h5.y_buf.push(class);
+                if h5.y_buf.len() >= DF_WRITE_BATCH {
+                    h5.flush().map_err(io::Error::other)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        let Self { out, classes, .. } = *self;
+        let labels = classes.labels;
+        match out {
+            DfOutput::Npz {
+                mut npz,
+                labels: id,
+                ..
+            } => {
+                for label in &labels {
+                    npz.append(id, &fixed_bytes::<44>(label.as_bytes()))?;
+                }
+                npz.finish()
+            }
+            DfOutput::Hdf5(mut h5) => {
+                let finish = || -> hdf5::Result<()> {
+                    h5.flush()?;
+                    h5.file
+                        .new_dataset_builder()
+                        .with_data(&labels[..])
+                        .create("labels")?;
+                    h5.file.close()
+                };
+                finish().map_err(io::Error::other)
+            }
+        }
+    }
+}
+
+impl DfHdf5 {
+    fn create<P: AsRef<Path>>(path: P) -> hdf5::Result<Self> {
+        let file = hdf5::File::create(path)?;
+        let x = file
+            .new_dataset::<f32>()
+            .chunk((DEFAULT_CHUNK, DF_LENGTH))
+            .shape(vec![Extent::resizable(0), Extent::fixed(DF_LENGTH)])
+            .create("X")?;
+        let y = file
+            .new_dataset::<i64>()
+            .chunk(DEFAULT_CHUNK)
+            .shape(0..)
+            .create("y")?;
+
+        Ok(Self {
+            file,
+            x,
+            y,
+            x_buf: Vec::new(),
+            y_buf: Vec::new(),
+            written: 0,
+        })
+    }
+
+    /// Writes all buffered rows to the datasets.
+    fn flush(&mut self) -> hdf5::Result<()> {
+        let begin = self.written;
+        let end = begin + self.y_buf.len();
+        if begin == end {
+            return Ok(());
+        }
+
+        let x = Array2::from_shape_vec((end - begin, DF_LENGTH), std::mem::take(&mut self.x_buf))
+            .map_err(|e| hdf5::Error::from(e.to_string()))?;
+        self.x.resize((end, DF_LENGTH))?;
+        self.x.write_slice(&x, ndarray::s![begin..end, ..])?;
+
+        self.y.resize(end)?;
+        self.y.write_slice(&self.y_buf[..], begin..end)?;
+        self.y_buf.clear();
+
+        self.written = end;
+        Ok(())
+    }
+}
+
+/// Assigns class ids to labels, taking them from a `LabelVocabulary` if one is
+/// given and otherwise assigning them in order of first appearance.
+struct ClassIds {
+    vocabulary: Option<LabelVocabulary>,
+    classes: HashMap<FixedAscii<44>, i64>,
+    /// The label of each class id.
+    labels: Vec<FixedAscii<44>>,
+}
+
+impl ClassIds {
+    fn new(vocabulary: Option<LabelVocabulary>) -> Self {
+        let labels = match &vocabulary {
+            Some(vocabulary) => vocabulary.entries().iter().map(|e| e.label).collect(),
+            None => Vec::new(),
+        };
+        Self {
+            vocabulary,
+            classes: HashMap::new(),
+            labels,
+        }
+    }
+
+    /// Returns the class id of `circuit`'s label, assigning the next id to
+    /// labels not seen before if there is no vocabulary.
+    fn class(&mut self, circuit: &Circuit) -> io::Result<i64> {
+        let label = circuit.label();
+        if let Some(vocabulary) = &self.vocabulary {
+            return vocabulary.class_id(&label).map(i64::from).ok_or_else(|| {
+                io::Error::other(format!("Label '{label}' is not in the label vocabulary"))
+            });
+        }
+        Ok(*self.classes.entry(label).or_insert_with(|| {
+            self.labels.push(label);
+            self.labels.len() as i64 - 1
+        }))
+    }
+}
+
+/// How `CommandsExporter` encodes the cell and relay commands of each cell.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CommandEncoding {
+    /// Integer ids in the separate `cell_cmd` and `relay_cmd` arrays, e.g., as
+    /// the input of embedding layers.
+    #[default]
+    Index,
+    /// One-hot channels of `X`, following the direction and time channels.
+    OneHot,
+}
+
+/// Writes the circuits to a NumPy archive in the layout of `DfExporter`, but
+/// with the cell and relay commands that GTT23 records for each cell encoded
+/// alongside its direction and time.
+///
+/// `X` is a float32 `[N, DF_LENGTH, C]` array whose channel 0 holds the direction
+/// of each valid cell and channel 1 its time in seconds since the first valid
+/// cell, padded with zeros. The arrays `cell_cmds` and `relay_cmds` list the
+/// value of each known command in id order. With `CommandEncoding::Index`,
+/// `C` is 2 and the `|u1` `[N, DF_LENGTH]` arrays `cell_cmd` and `relay_cmd` hold
+/// one plus the id of each valid cell's commands, so that 0 marks padding.
+/// With `CommandEncoding::OneHot`, channel `2 + k` of `X` is 1 for the cells
+/// with cell command id `k`, and channel `2 + cell_cmds.len() + k` is 1 for
+/// those with relay command id `k`. `y` and `labels` are as in `DfExporter`.
+pub struct CommandsExporter {
+    npz: NpzWriter,
+    ids: CommandsIds,
+    encoding: CommandEncoding,
+    classes: ClassIds,
+    cell_cmds: Vec<u8>,
+    relay_cmds: Vec<u8>,
+    /// The id of each cell command value.
+    cell_cmd_ids: [u8; 256],
+    /// The id of each relay command value.
+    relay_cmd_ids: [u8; 256],
+}
+
+struct CommandsIds {
+    x: usize,
+    y: usize,
+    labels: usize,
+    cell_cmd: Option<usize>,
+    relay_cmd: Option<usize>,
+    cell_cmds: usize,
+    relay_cmds: usize,
+}
+
+impl CommandsExporter {
+    /// Creates an exporter writing `encoding` to a NumPy archive at `path`.
+    pub fn create<P: AsRef<Path>>(path: P, encoding: CommandEncoding) -> io::Result<Self> {
+        let cell_cmds = command_values(|v| CellCommand::try_from(v).is_ok());
+        let relay_cmds = command_values(|v| RelayCommand::try_from(v).is_ok());
+        let channels = match encoding {
+            CommandEncoding::Index => 2,
+            CommandEncoding::OneHot => 2 + cell_cmds.len() + relay_cmds.len(),
+        };
+
+        let mut npz = NpzWriter::create(path)?;
+        let ids = CommandsIds {
+            x: npz.add_array("X", "<f4", &[DF_LENGTH, channels])?,
+            y: npz.add_array("y", "<i8", &[])?,
+            labels: npz.add_array("labels", "|S44", &[])?,
+            cell_cmd: match encoding {
+                CommandEncoding::Index => Some(npz.add_array("cell_cmd", "|u1", &[DF_LENGTH])?),
+                CommandEncoding::OneHot => None,
+            },
+            relay_cmd: match encoding {
+                CommandEncoding::Index => Some(npz.add_array("relay_cmd", "|u1", &[DF_LENGTH])?),
+                CommandEncoding::OneHot => None,
+            },
+            cell_cmds: npz.add_array("cell_cmds", "|u1", &[])?,
+            relay_cmds: npz.add_array("relay_cmds", "|u1", &[])?,
+        };
+
+        Ok(Self {
+            npz,
+            ids,
+            encoding,
+            classes: ClassIds::new(None),
+            cell_cmd_ids: command_ids(&cell_cmds),
+            relay_cmd_ids: command_ids(&relay_cmds),
+            cell_cmds,
+            relay_cmds,
+        })
+    }
+
+    /// Takes the class ids from `vocabulary`, if given, instead of assigning
+    /// them in order of first appearance.
+    pub fn with_vocabulary(mut self, vocabulary: Option<LabelVocabulary>) -> Self {
+        self.classes = ClassIds::new(vocabulary);
+        self
+    }
+
+    /// The number of channels of each cell in `X`.
+    fn channels(&self) -> usize {
+        match self.encoding {
+            CommandEncoding::Index => 2,
+            CommandEncoding::OneHot => 2 + self.cell_cmds.len() + self.relay_cmds.len(),
+        }
+    }
+}
+
+impl Exporter for CommandsExporter {
+    fn write(&mut self, _index: CircuitIndex, circuit: &Circuit) -> io::Result<()> {
+        let class = self.classes.class(circuit)?;
+        let channels = self.channels();
+        let cells = circuit.valid_cells();
+        let start = cells.first().map_or(0.0, |c| c.time);
+
+        let mut x = vec![0f32; DF_LENGTH * channels];
+        let mut cell_cmd = vec![0u8; DF_LENGTH];
+        let mut relay_cmd = vec![0u8; DF_LENGTH];
+
+        for (i, cell) in cells.iter().enumerate() {
+            let row = &mut x[i * channels..(i + 1) * channels];
+            row[0] = cell.direction as i8 as f32;
+            row[1] = (cell.time - start).max(0.0) as f32;
+
+            let cell_id = self.cell_cmd_ids[cell.cell_cmd as usize];
+            let relay_id = self.relay_cmd_ids[cell.relay_cmd as usize];
+            match self.encoding {
+                CommandEncoding::Index => {
+                    cell_cmd[i] = cell_id + 1;
+                    relay_cmd[i] = relay_id + 1;
+                }
+                CommandEncoding::OneHot => {
+                    row[2 + cell_id as usize] = 1.0;
+                    row[2 + self.cell_cmds.len() + relay_id as usize] = 1.0;
+                }
+            }
+        }
+
+        let ids = &self.ids;
+        let bytes: Vec<u8> = x.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.npz.append(ids.x, &bytes)?;
+        self.npz.append(ids.y, &class.to_le_bytes())?;
+        if let Some(id) = ids.cell_cmd {
+            self.npz.append(id, &cell_cmd)?;
+        }
+        if let Some(id) = ids.relay_cmd {
+            self.npz.append(id, &relay_cmd)?;
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        let Self {
+            mut npz,
+            ids,
+            classes,
+            cell_cmds,
+            relay_cmds,
+            ..
+        } = *self;
+
+        for label in &classes.labels {
+            npz.append(ids.labels, &fixed_bytes::<44>(label.as_bytes()))?;
+        }
+        for value in cell_cmds {
+            npz.append(ids.cell_cmds, &[value])?;
+        }
+        for value in relay_cmds {
+            npz.append(ids.relay_cmds, &[value])?;
+        }
+        npz.finish()
+    }
+}
+
+/// The command values for which `known` returns true, in ascending order.
+fn command_values<F: Fn(u8) -> bool>(known: F) -> Vec<u8> {
+    (0..=u8::MAX).filter(|&v| known(v)).collect()
+}
+
+/// Maps each of the command `values` to its position, and all other values to
+/// 0.
+fn command_ids(values: &[u8]) -> [u8; 256] {
+    let mut ids = [0u8; 256];
+    for (id, &value) in values.iter().enumerate() {
+        ids[value as usize] = id as u8;
+    }
+    ids
+}
+
+/// Right-pads `bytes` with zeros to exactly `N` bytes.
+fn fixed_bytes<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    let mut fixed = [0u8; N];
+    let n = std::cmp::min(bytes.len(), N);
+    fixed[..n].copy_from_slice(&bytes[..n]);
+    fixed
+}
+
+/// Appends the meta-data fields of `circuit` at `index` to `line` as the
+/// members of a JSON object, without the enclosing braces.
+fn write_json_meta(line: &mut String, index: CircuitIndex, circuit: &Circuit) {
+    let _ = write!(
+        line,
+        "\"index\":{index},\"uuid\":{},\"domain\":{},\"shortest_private_suffix\":{},\"label\":{},\"day\":{},\"port\":{},\"len\":{}",
+        json_str(circuit.uuid.as_str()),
+        json_str(circuit.domain.as_str()),
+        json_str(circuit.shortest_private_suffix.as_str()),
+        json_str(circuit.label().as_str()),
+        circuit.day,
+        circuit.port,
+        circuit.len,
+    );
+}
+
+/// Quotes and escapes `s` as a JSON string.
+fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Quotes `s` as a CSV field if it contains a separator, quote, or newline.
+pub(crate) fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
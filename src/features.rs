@@ -0,0 +1,848 @@
+use std::io;
+use std::path::Path;
+use std::thread;
+
+use hdf5::File;
+use hdf5::types::VarLenAscii;
+use ndarray::Array2;
+
+use crate::npz::{NpyWriter, NpzWriter};
+use crate::{Cell, Circuit, Direction};
+
+/// The number of points in the CUMUL representation used by the attack.
+pub const CUMUL_POINTS: usize = 100;
+
+/// The number of circuits read at a time when writing features.
+const READ_BATCH: usize = 1_000;
+
+/// Returns the direction of each valid cell of `circuit`: +1 for a cell sent
+/// from the client toward the server and -1 for one sent from the server
+/// toward the client. The sequence has `len` elements.
+pub fn direction_sequence(circuit: &Circuit) -> Vec<i8> {
+    circuit
+        .valid_cells()
+        .iter()
+        .map(|c| c.direction as i8)
+        .collect()
+}
+
+/// Returns the `direction_sequence` of each of `circuits`, in order.
+pub fn direction_sequences<'a, I>(circuits: I) -> Vec<Vec<i8>>
+where
+    I: IntoIterator<Item = &'a Circuit>,
+{
+    circuits.into_iter().map(direction_sequence).collect()
+}
+
+/// Options applied to the times returned by `inter_arrival_times`, in the
+/// order of the fields.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TimingOptions {
+    /// Clamp each time to at most this many seconds.
+    pub clamp: Option<f64>,
+    /// Replace each time `t` with `ln(1 + t)`.
+    pub log_scale: bool,
+    /// Truncate or pad the sequence with zeros to exactly this many elements.
+    pub length: Option<usize>,
+}
+
+impl TimingOptions {
+    fn apply(&self, time: f64) -> f64 {
+        let time = match self.clamp {
+            Some(max) => time.min(max),
+            None => time,
+        };
+        if self.log_scale { time.ln_1p() } else { time }
+    }
+}
+
+/// Returns the time in seconds between each valid cell of `circuit` and the
+/// cell before it, with the first cell's time taken as 0, after applying
+/// `options`. Without a fixed length, the sequence has `len` elements.
+pub fn inter_arrival_times(circuit: &Circuit, options: &TimingOptions) -> Vec<f64> {
+    let cells = circuit.valid_cells();
+    let mut prev = cells.first().map_or(0.0, |c| c.time);
+
+    let times = cells.iter().map(|c| {
+        let time = options.apply((c.time - prev).max(0.0));
+        prev = c.time;
+        time
+    });
+    fit_length(times.collect(), options.length)
+}
+
+/// Like `inter_arrival_times`, but each time is multiplied by the direction of
+/// its cell as in `direction_sequence`, so that positive times precede cells
+/// sent toward the server and negative times cells sent toward the client.
+pub fn signed_inter_arrival_times(circuit: &Circuit, options: &TimingOptions) -> Vec<f64> {
+    let mut times = inter_arrival_times(
+        circuit,
+        &TimingOptions {
+            length: None,
+            ..*options
+        },
+    );
+    for (time, cell) in times.iter_mut().zip(circuit.valid_cells()) {
+        *time *= cell.direction as i8 as f64;
+    }
+    fit_length(times, options.length)
+}
+
+/// How the times in the `tik_tok` representation are normalized.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum TimeNormalization {
+    /// Seconds since the first valid cell.
+    #[default]
+    None,
+    /// Seconds since the first valid cell divided by the time of the last
+    /// valid cell, so that times are between 0 and 1.
+    Duration,
+    /// Seconds since the first valid cell `t`, replaced with `ln(1 + t)`.
+    Log,
+}
+
+/// Returns the Tik-Tok representation of `circuit`: the time of each valid cell
+/// since the first valid cell, normalized by `normalization`, and multiplied by
+/// the direction of the cell as in `direction_sequence`. The sequence has `len`
+/// elements.
+pub fn tik_tok(circuit: &Circuit, normalization: TimeNormalization) -> Vec<f64> {
+    let cells = circuit.valid_cells();
+    let start = cells.first().map_or(0.0, |c| c.time);
+    let duration = cells.last().map_or(0.0, |c| c.time - start);
+
+    cells
+        .iter()
+        .map(|c| {
+            let time = (c.time - start).max(0.0);
+            let time = match normalization {
+                TimeNormalization::None => time,
+                TimeNormalization::Duration if duration > 0.0 => time / duration,
+                TimeNormalization::Duration => 0.0,
+                TimeNormalization::Log => time.ln_1p(),
+            };
+            time * c.direction as i8 as f64
+        })
+        .collect()
+}
+
+/// A maximal run of consecutive valid cells sent in the same direction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Burst {
+    /// The direction of the cells, as in `direction_sequence`.
+    pub direction: i8,
+    /// The number of cells in the burst.
+    pub count: usize,
+    /// The time in seconds from the first to the last cell of the burst.
+    pub duration: f64,
+}
+
+/// Collapses the valid cells of `circuit` into bursts of consecutive cells sent
+/// in the same direction, in order.
+pub fn bursts(circuit: &Circuit) -> Vec<Burst> {
+    let mut bursts: Vec<Burst> = Vec::new();
+    let mut start = 0.0;
+
+    for cell in circuit.valid_cells() {
+        let direction = cell.direction as i8;
+        match bursts.last_mut() {
+            Some(burst) if burst.direction == direction => {
+                burst.count += 1;
+                burst.duration = cell.time - start;
+            }
+            _ => {
+                start = cell.time;
+                bursts.push(Burst {
+                    direction,
+                    count: 1,
+                    duration: 0.0,
+                });
+            }
+        }
+    }
+
+    bursts
+}
+
+/// Returns the number of cells in each burst of `circuit`, signed by the
+/// direction of the burst, truncated or padded with zeros to `length` bursts.
+pub fn burst_counts(circuit: &Circuit, length: usize) -> Vec<i32> {
+    let counts = bursts(circuit)
+        .iter()
+        .take(length)
+        .map(|b| b.direction as i32 * b.count as i32)
+        .collect();
+    fit_length(counts, Some(length))
+}
+
+/// Returns the CUMUL representation of `circuit`: the cumulative sum of the
+/// `direction_sequence`, linearly interpolated at `n` evenly spaced points from
+/// the first to the last valid cell. Circuits without cells are all zeros.
+pub fn cumul(circuit: &Circuit, n: usize) -> Vec<f64> {
+    let cells = circuit.valid_cells();
+    if cells.is_empty() {
+        return vec![0.0; n];
+    }
+
+    // The sum after each cell, preceded by the empty sum at position 0.
+    let mut sums = Vec::with_capacity(cells.len() + 1);
+    sums.push(0.0);
+    for cell in cells {
+        sums.push(sums[sums.len() - 1] + cell.direction as i8 as f64);
+    }
+
+    (1..=n)
+        .map(|k| {
+            let x = (k * cells.len()) as f64 / n as f64;
+            let i = (x.floor() as usize).min(cells.len() - 1);
+            sums[i] + (sums[i + 1] - sums[i]) * (x - i as f64)
+        })
+        .collect()
+}
+
+/// Returns the `cumul` representation of each of `circuits`, in order, computed
+/// on `threads` threads.
+pub fn cumul_batch(circuits: &[Circuit], n: usize, threads: usize) -> Vec<Vec<f64>> {
+    par_map(circuits, threads, |c| cumul(c, n))
+}
+
+/// Computes the `cumul` representation with `n` points of every circuit in the
+/// `/circuits` dataset of `file` on `threads` threads, and writes them as rows
+/// of the two dimensional dataset `name`, replacing any existing dataset with
+/// that name. Row `i` holds the features of the circuit at index `i`. Returns
+/// the number of rows written.
+pub fn write_cumul(file: &File, name: &str, n: usize, threads: usize) -> hdf5::Result<usize> {
+    FeatureWriter::new(file, threads).write_to(name, FeatureSet::Cumul(n))
+}
+
+/// The number of features returned by `kfp`.
+pub const KFP_FEATURES: usize = 145;
+
+/// The number of cells in each chunk of the k-FP concentration features.
+const KFP_CHUNK: usize = 20;
+
+/// The number of cells at each end of the circuit counted by k-FP.
+const KFP_ENDS: usize = 30;
+
+/// The number of sums in the k-FP alternative concentration features.
+const KFP_ALT_CONC: usize = 70;
+
+/// The number of sums in the k-FP alternative cells per second features.
+const KFP_ALT_PER_SEC: usize = 20;
+
+/// Returns the k-fingerprinting (k-FP) features of `circuit`, following the
+/// hand-crafted feature set of Hayes and Danezis (USENIX Security 2016).
+///
+/// Incoming cells are those sent from the server toward the client, outgoing
+/// cells those sent from the client toward the server, and times are relative
+/// to the first valid cell. Standard deviations are population deviations and
+/// percentiles interpolate linearly between the closest ranks, matching the
+/// defaults of `numpy.std` and `numpy.percentile`. Statistics of empty
+/// sequences are 0. The `KFP_FEATURES` features are, in order:
+///
+/// - `0..12`: the max, mean, standard deviation, and 75th percentile of the
+///   inter-arrival times between incoming cells, outgoing cells, and all cells.
+/// - `12..24`: the 25th, 50th, 75th, and 100th percentiles of the times of
+///   incoming cells, outgoing cells, and all cells.
+/// - `24..27`: the number of incoming cells, outgoing cells, and all cells.
+/// - `27..31`: the number of incoming and outgoing cells among the first 30
+///   cells, then among the last 30 cells.
+/// - `31..35`: the mean and standard deviation of the positions of outgoing
+///   cells in the circuit, then of incoming cells.
+/// - `35..39`: the standard deviation, mean, median, and max of the number of
+///   outgoing cells in each consecutive chunk of 20 cells.
+/// - `39..44`: the mean, standard deviation, median, min, and max of the number
+///   of cells in each second.
+/// - `44..46`: the fraction of cells that are incoming, then outgoing.
+/// - `46..116`: the outgoing cell counts of the chunks of 20 cells, split into
+///   70 contiguous groups as by `numpy.array_split` and summed.
+/// - `116..136`: the cells per second counts, split into 20 contiguous groups
+///   as by `numpy.array_split` and summed.
+/// - `136..142`: the number of outgoing bursts, the max and mean number of
+///   cells in an outgoing burst, and the number of outgoing bursts with more
+///   than 5, 10, and 20 cells.
+/// - `142..145`: the time of the last cell, the sum of the outgoing cell counts
+///   of the chunks of 20 cells, and the sum of the cells per second counts.
+pub fn kfp(circuit: &Circuit) -> Vec<f64> {
+    let cells = circuit.valid_cells();
+    let start = cells.first().map_or(0.0, |c| c.time);
+
+    let mut times_in = Vec::new();
+    let mut times_out = Vec::new();
+    let mut positions_in = Vec::new();
+    let mut positions_out = Vec::new();
+    for (i, cell) in cells.iter().enumerate() {
+        let time = cell.time - start;
+        match cell.direction {
+            Direction::SERVER_TO_CLIENT => {
+                times_in.push(time);
+                positions_in.push(i as f64);
+            }
+            Direction::CLIENT_TO_SERVER => {
+                times_out.push(time);
+                positions_out.push(i as f64);
+            }
+            Direction::PADDING => {}
+        }
+    }
+    let times_all: Vec<f64> = cells.iter().map(|c| c.time - start).collect();
+
+    let mut features = Vec::with_capacity(KFP_FEATURES);
+
+    for times in [&times_in, &times_out, &times_all] {
+        let gaps: Vec<f64> = times.windows(2).map(|w| (w[1] - w[0]).max(0.0)).collect();
+        features.extend([
+            max(&gaps),
+            mean(&gaps),
+            std_dev(&gaps),
+            percentile(&gaps, 75.0),
+        ]);
+    }
+
+    for times in [&times_in, &times_out, &times_all] {
+        features.extend([25.0, 50.0, 75.0, 100.0].map(|q| percentile(times, q)));
+    }
+
+    let n_in = times_in.len() as f64;
+    let n_out = times_out.len() as f64;
+    let n_all = cells.len() as f64;
+    features.extend([n_in, n_out, n_all]);
+
+    let first = &cells[..std::cmp::min(KFP_ENDS, cells.len())];
+    let last = &cells[cells.len().saturating_sub(KFP_ENDS)..];
+    for ends in [first, last] {
+        features.extend([
+            count_direction(ends, Direction::SERVER_TO_CLIENT),
+            count_direction(ends, Direction::CLIENT_TO_SERVER),
+        ]);
+    }
+
+    for positions in [&positions_out, &positions_in] {
+        features.extend([mean(positions), std_dev(positions)]);
+    }
+
+    let concentration: Vec<f64> = cells
+        .chunks(KFP_CHUNK)
+        .map(|chunk| count_direction(chunk, Direction::CLIENT_TO_SERVER))
+        .collect();
+    features.extend([
+        std_dev(&concentration),
+        mean(&concentration),
+        median(&concentration),
+        max(&concentration),
+    ]);
+
+    let mut per_sec = Vec::new();
+    if let Some(end) = times_all.last() {
+        per_sec = vec![0.0; end.max(0.0) as usize + 1];
+        for time in &times_all {
+            per_sec[(time.max(0.0) as usize).min(per_sec.len() - 1)] += 1.0;
+        }
+    }
+    features.extend([
+        mean(&per_sec),
+        std_dev(&per_sec),
+        median(&per_sec),
+        min(&per_sec),
+        max(&per_sec),
+    ]);
+
+    if cells.is_empty() {
+        features.extend([0.0, 0.0]);
+    } else {
+        features.extend([n_in / n_all, n_out / n_all]);
+    }
+
+    features.extend(split_sums(&concentration, KFP_ALT_CONC));
+    features.extend(split_sums(&per_sec, KFP_ALT_PER_SEC));
+
+    let outgoing: Vec<f64> = bursts(circuit)
+        .iter()
+        .filter(|b| b.direction == Direction::CLIENT_TO_SERVER as i8)
+        .map(|b| b.count as f64)
+        .collect();
+    features.extend([outgoing.len() as f64, max(&outgoing), mean(&outgoing)]);
+    features.extend([5.0, 10.0, 20.0].map(|n| outgoing.iter().filter(|&&c| c > n).count() as f64));
+
+    features.extend([
+        times_all.last().copied().unwrap_or(0.0),
+        concentration.iter().sum(),
+        per_sec.iter().sum(),
+    ]);
+
+    debug_assert_eq!(features.len(), KFP_FEATURES);
+    features
+}
+
+/// Returns the `kfp` features of each of `circuits`, in order, computed on
+/// `threads` threads.
+pub fn kfp_batch(circuits: &[Circuit], threads: usize) -> Vec<Vec<f64>> {
+    par_map(circuits, threads, kfp)
+}
+
+/// Like `write_cumul`, but writes the `kfp` features of every circuit as rows
+/// of `KFP_FEATURES` columns.
+pub fn write_kfp(file: &File, name: &str, threads: usize) -> hdf5::Result<usize> {
+    FeatureWriter::new(file, threads).write_to(name, FeatureSet::Kfp)
+}
+
+/// The upper edge, in microseconds, of the first inter-arrival time bin of
+/// `timing_histograms`.
+const TIMING_MIN_GAP_US: u64 = 100;
+
+/// The lower edge, in microseconds, of the last inter-arrival time bin of
+/// `timing_histograms`.
+const TIMING_MAX_GAP_US: u64 = 10_000_000;
+
+/// Returns fixed-bin timing histograms of `circuit`, with `bins` bins each.
+/// Outgoing cells are those sent from the client toward the server and
+/// incoming cells those sent from the server toward the client. The
+/// `4 * bins` features are, in order:
+///
+/// - `0..bins`: the fraction of the inter-arrival times between consecutive
+///   outgoing cells in each bin, where the bin edges are spaced
+///   logarithmically from 100 microseconds to 10 seconds, and shorter and
+///   longer times are counted in the first and last bins.
+/// - `bins..2 * bins`: the same for incoming cells.
+/// - `2 * bins..3 * bins`: the number of outgoing cells per second in each of
+///   `bins` equal slots of the time from the first to the last valid cell.
+/// - `3 * bins..4 * bins`: the same for incoming cells.
+///
+/// Histograms of circuits with fewer than two cells in a direction, and rates
+/// of circuits whose cells all have the same time, are all zeros.
+pub fn timing_histograms(circuit: &Circuit, bins: usize) -> Vec<f64> {
+    let cells = circuit.valid_cells();
+    let start = cells.first().map_or(0.0, |c| c.time);
+    let duration = cells.last().map_or(0.0, |c| c.time - start);
+
+    let mut features = Vec::with_capacity(4 * bins);
+    if bins == 0 {
+        return features;
+    }
+
+    let directions = [Direction::CLIENT_TO_SERVER, Direction::SERVER_TO_CLIENT];
+    let times: Vec<Vec<f64>> = directions
+        .iter()
+        .map(|&d| {
+            cells
+                .iter()
+                .filter(|c| c.direction == d)
+                .map(|c| c.time - start)
+                .collect()
+        })
+        .collect();
+
+    let (lo, hi) = (
+        (TIMING_MIN_GAP_US as f64 / 1e6).ln(),
+        (TIMING_MAX_GAP_US as f64 / 1e6).ln(),
+    );
+    for times in &times {
+        let mut hist = vec![0.0; bins];
+        for w in times.windows(2) {
+            let gap = (w[1] - w[0]).max(0.0);
+            let bin = if gap > 0.0 {
+                ((gap.ln() - lo) / (hi - lo) * bins as f64).floor()
+            } else {
+                0.0
+            };
+            hist[(bin.max(0.0) as usize).min(bins - 1)] += 1.0;
+        }
+        let total = times.len().saturating_sub(1) as f64;
+        if total > 0.0 {
+            hist.iter_mut().for_each(|h| *h /= total);
+        }
+        features.extend(hist);
+    }
+
+    let slot = duration / bins as f64;
+    for times in &times {
+        let mut rates = vec![0.0; bins];
+        if slot > 0.0 {
+            for time in times {
+                let bin = (time.max(0.0) / slot).floor() as usize;
+                rates[bin.min(bins - 1)] += 1.0 / slot;
+            }
+        }
+        features.extend(rates);
+    }
+
+    features
+}
+
+/// Returns the number of cells per second sent in each direction of `circuit`
+/// in consecutive bins of `bin_width` seconds starting at the first valid
+/// cell: first the series of cells sent from the client toward the server,
+/// then that of cells sent from the server toward the client. Both series have
+/// as many bins as needed to cover the last valid cell, and are empty for
+/// circuits without cells or a bin width that is not positive.
+pub fn cell_rates(circuit: &Circuit, bin_width: f64) -> (Vec<f64>, Vec<f64>) {
+    let cells = circuit.valid_cells();
+    let (Some(first), Some(last)) = (cells.first(), cells.last()) else {
+        return (Vec::new(), Vec::new());
+    };
+    if bin_width <= 0.0 || !bin_width.is_finite() {
+        return (Vec::new(), Vec::new());
+    }
+
+    let bins = ((last.time - first.time).max(0.0) / bin_width).floor() as usize + 1;
+    let mut rates = (vec![0.0; bins], vec![0.0; bins]);
+    for cell in cells {
+        let bin = (((cell.time - first.time).max(0.0) / bin_width).floor() as usize).min(bins - 1);
+        match cell.direction {
+            Direction::CLIENT_TO_SERVER => rates.0[bin] += 1.0 / bin_width,
+            Direction::SERVER_TO_CLIENT => rates.1[bin] += 1.0 / bin_width,
+            _ => {}
+        }
+    }
+    rates
+}
+
+/// Returns the `cell_rates` of `circuit` with bins of `bin_width` seconds,
+/// each series truncated or padded with zeros to `bins` bins, so that the
+/// `2 * bins` features are the rates toward the server followed by the rates
+/// toward the client.
+pub fn cell_rate_series(circuit: &Circuit, bin_width: f64, bins: usize) -> Vec<f64> {
+    let (outgoing, incoming) = cell_rates(circuit, bin_width);
+    let mut series = fit_length(outgoing, Some(bins));
+    series.extend(fit_length(incoming, Some(bins)));
+    series
+}
+
+/// A set of features computed for each circuit, with a fixed number of
+/// columns.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FeatureSet {
+    /// The `cumul` representation with this many points.
+    Cumul(usize),
+    /// The `kfp` features.
+    Kfp,
+    /// The `burst_counts` with this many bursts.
+    Bursts(usize),
+    /// The `timing_histograms` with this many bins each.
+    Timing(usize),
+    /// The `cell_rate_series` with bins of `bin_ms` milliseconds, with `bins`
+    /// bins per direction.
+    Rates { bin_ms: u64, bins: usize },
+}
+
+impl FeatureSet {
+    /// The name of the feature set.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FeatureSet::Cumul(_) => "cumul",
+            FeatureSet::Kfp => "kfp",
+            FeatureSet::Bursts(_) => "bursts",
+            FeatureSet::Timing(_) => "timing",
+            FeatureSet::Rates { .. } => "rates",
+        }
+    }
+
+    /// The number of features computed for each circuit.
+    pub fn width(&self) -> usize {
+        match self {
+            FeatureSet::Cumul(n) | FeatureSet::Bursts(n) => *n,
+            FeatureSet::Kfp => KFP_FEATURES,
+            FeatureSet::Timing(bins) => 4 * bins,
+            FeatureSet::Rates { bins, .. } => 2 * bins,
+        }
+    }
+
+    /// Computes the features of `circuit`.
+    pub fn compute(&self, circuit: &Circuit) -> Vec<f64> {
+        match self {
+            FeatureSet::Cumul(n) => cumul(circuit, *n),
+            FeatureSet::Kfp => kfp(circuit),
+            FeatureSet::Bursts(n) => burst_counts(circuit, *n)
+                .into_iter()
+                .map(f64::from)
+                .collect(),
+            FeatureSet::Timing(bins) => timing_histograms(circuit, *bins),
+            FeatureSet::Rates { bin_ms, bins } => {
+                cell_rate_series(circuit, *bin_ms as f64 / 1e3, *bins)
+            }
+        }
+    }
+
+    /// The parameters used to compute the features, by name.
+    pub fn params(&self) -> Vec<(&'static str, u64)> {
+        match self {
+            FeatureSet::Cumul(n) => vec![("points", *n as u64)],
+            FeatureSet::Kfp => vec![
+                ("chunk", KFP_CHUNK as u64),
+                ("ends", KFP_ENDS as u64),
+                ("alt_conc", KFP_ALT_CONC as u64),
+                ("alt_per_sec", KFP_ALT_PER_SEC as u64),
+            ],
+            FeatureSet::Bursts(n) => vec![("length", *n as u64)],
+            FeatureSet::Timing(bins) => vec![
+                ("bins", *bins as u64),
+                ("min_gap_us", TIMING_MIN_GAP_US),
+                ("max_gap_us", TIMING_MAX_GAP_US),
+            ],
+            FeatureSet::Rates { bin_ms, bins } => vec![("bin_ms", *bin_ms), ("bins", *bins as u64)],
+        }
+    }
+}
+
+/// Writes feature matrices computed from the `/circuits` dataset of a file to
+/// datasets of the same file, aligned index-for-index with `/circuits` so that
+/// row `i` holds the features of the circuit at index `i`. Each dataset records
+/// the name of its feature set in the `feature_set` attribute and each of the
+/// feature set's `params` in an attribute of the same name.
+pub struct FeatureWriter<'a> {
+    file: &'a File,
+    threads: usize,
+}
+
+impl<'a> FeatureWriter<'a> {
+    /// Creates a writer for `file` that computes features on `threads`
+    /// threads.
+    pub fn new(file: &'a File, threads: usize) -> Self {
+        Self { file, threads }
+    }
+
+    /// The name of the dataset that `write` uses for `features`, i.e.,
+    /// `/features/<name>`.
+    pub fn dataset_name(features: FeatureSet) -> String {
+        format!("/features/{}", features.name())
+    }
+
+    /// Computes `features` of every circuit and writes them to the dataset
+    /// named by `dataset_name`, replacing any existing dataset with that name.
+    /// Returns the number of rows written.
+    pub fn write(&self, features: FeatureSet) -> hdf5::Result<usize> {
+        self.write_to(&Self::dataset_name(features), features)
+    }
+
+    /// Like `write`, but writes to the dataset `name`.
+    pub fn write_to(&self, name: &str, features: FeatureSet) -> hdf5::Result<usize> {
+        let size = write_rows(self.file, name, features.width(), self.threads, |c| {
+            features.compute(c)
+        })?;
+
+        let dataset = self.file.dataset(name)?;
+        let set = VarLenAscii::from_ascii(features.name()).map_err(|e| e.to_string())?;
+        dataset
+            .new_attr::<VarLenAscii>()
+            .create("feature_set")?
+            .write_scalar(&set)?;
+        for (param, value) in features.params() {
+            dataset
+                .new_attr::<u64>()
+                .create(param)?
+                .write_scalar(&value)?;
+        }
+
+        Ok(size)
+    }
+}
+
+/// Computes `features` of every circuit in the `/circuits` dataset of `file` on
+/// `threads` threads, and saves them as a float64 `[N, width]` array to the
+/// NumPy file at `path`, with row `i` holding the features of the circuit at
+/// index `i`. If `path` ends in `.npz`, the file is an archive holding the
+/// array as `X` and the label of each circuit as `label`, and otherwise it is
+/// a `.npy` file holding only the array. Returns the number of rows written.
+pub fn save_numpy<P: AsRef<Path>>(
+    file: &File,
+    path: P,
+    features: FeatureSet,
+    threads: usize,
+) -> hdf5::Result<usize> {
+    let width = features.width();
+    let to_hdf5 = |e: io::Error| hdf5::Error::from(e.to_string());
+
+    if path.as_ref().extension().is_some_and(|e| e == "npz") {
+        let mut npz = NpzWriter::create(path).map_err(to_hdf5)?;
+        let x = npz.add_array("X", "<f8", &[width]).map_err(to_hdf5)?;
+        let label = npz.add_array("label", "|S44", &[]).map_err(to_hdf5)?;
+
+        let size = for_each_batch(
+            file,
+            threads,
+            |c| features.compute(c),
+            |batch, rows| {
+                for (circuit, row) in batch.iter().zip(rows) {
+                    npz.append(x, &le_bytes(&row)).map_err(to_hdf5)?;
+                    let mut bytes = [0u8; 44];
+                    let name = circuit.label();
+                    bytes[..name.len()].copy_from_slice(name.as_bytes());
+                    npz.append(label, &bytes).map_err(to_hdf5)?;
+                }
+                Ok(())
+            },
+        )?;
+        npz.finish().map_err(to_hdf5)?;
+        Ok(size)
+    } else {
+        let mut npy = NpyWriter::create(path, "<f8", &[width]).map_err(to_hdf5)?;
+
+        let size = for_each_batch(
+            file,
+            threads,
+            |c| features.compute(c),
+            |_, rows| {
+                for row in rows {
+                    npy.append(&le_bytes(&row)).map_err(to_hdf5)?;
+                }
+                Ok(())
+            },
+        )?;
+        npy.finish().map_err(to_hdf5)?;
+        Ok(size)
+    }
+}
+
+/// The little-endian bytes of `values`.
+fn le_bytes(values: &[f64]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// Applies `f` to every circuit in the `/circuits` dataset of `file` on
+/// `threads` threads, and writes the results as rows of `width` columns of the
+/// two dimensional dataset `name`, replacing any existing dataset with that
+/// name. Returns the number of rows written.
+fn write_rows<F>(file: &File, name: &str, width: usize, threads: usize, f: F) -> hdf5::Result<usize>
+where
+    F: Fn(&Circuit) -> Vec<f64> + Sync,
+{
+    let size = file.dataset("/circuits")?.size();
+
+    if file.link_exists(name) {
+        // Note this unlinks but does not reclaim its storage space.
+        file.unlink(name)?;
+    }
+    let features = file
+        .new_dataset::<f64>()
+        .shape((size, width))
+        .create(name)?;
+
+    let mut begin = 0;
+    for_each_batch(file, threads, f, |batch, rows| {
+        let end = begin + batch.len();
+        let rows = Array2::from_shape_vec((batch.len(), width), rows.concat())
+            .map_err(|e| hdf5::Error::from(e.to_string()))?;
+        features.write_slice(&rows, ndarray::s![begin..end, ..])?;
+        begin = end;
+        Ok(())
+    })
+}
+
+/// Reads the circuits in the `/circuits` dataset of `file` in batches, applies
+/// `f` to each circuit of a batch on `threads` threads, and passes the batch
+/// and its results to `sink`, in order. Returns the number of circuits read.
+fn for_each_batch<F, S>(file: &File, threads: usize, f: F, mut sink: S) -> hdf5::Result<usize>
+where
+    F: Fn(&Circuit) -> Vec<f64> + Sync,
+    S: FnMut(&[Circuit], Vec<Vec<f64>>) -> hdf5::Result<()>,
+{
+    let circuits = file.dataset("/circuits")?;
+    let size = circuits.size();
+
+    for begin in (0..size).step_by(READ_BATCH) {
+        let end = std::cmp::min(begin + READ_BATCH, size);
+        let batch = circuits
+            .read_slice_1d::<Circuit, _>(begin..end)?
+            .into_raw_vec_and_offset()
+            .0;
+        let rows = par_map(&batch, threads, &f);
+        sink(&batch, rows)?;
+    }
+
+    Ok(size)
+}
+
+/// Returns the number of `cells` sent in `direction`.
+fn count_direction(cells: &[Cell], direction: Direction) -> f64 {
+    cells.iter().filter(|c| c.direction == direction).count() as f64
+}
+
+fn max(values: &[f64]) -> f64 {
+    values.iter().copied().reduce(f64::max).unwrap_or(0.0)
+}
+
+fn min(values: &[f64]) -> f64 {
+    values.iter().copied().reduce(f64::min).unwrap_or(0.0)
+}
+
+pub(crate) fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        0.0
+    } else {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+}
+
+/// The population standard deviation of `values`.
+pub(crate) fn std_dev(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mean = mean(values);
+    let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+    var.sqrt()
+}
+
+pub(crate) fn median(values: &[f64]) -> f64 {
+    percentile(values, 50.0)
+}
+
+/// The `q`th percentile of `values`, interpolating linearly between the closest
+/// ranks.
+pub(crate) fn percentile(values: &[f64], q: f64) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let rank = q / 100.0 * (sorted.len() - 1) as f64;
+    let i = rank.floor() as usize;
+    let j = std::cmp::min(i + 1, sorted.len() - 1);
+    sorted[i] + (sorted[j] - sorted[i]) * (rank - i as f64)
+}
+
+/// Splits `values` into `n` contiguous groups whose sizes differ by at most one,
+/// with the larger groups first, and returns the sum of each group.
+fn split_sums(values: &[f64], n: usize) -> Vec<f64> {
+    let (size, extra) = (values.len() / n, values.len() % n);
+    let mut begin = 0;
+    (0..n)
+        .map(|i| {
+            let end = begin + size + usize::from(i < extra);
+            let sum = values[begin..end].iter().sum();
+            begin = end;
+            sum
+        })
+        .collect()
+}
+
+/// Applies `f` to each of `items` on up to `threads` threads, returning the
+/// results in order.
+pub(crate) fn par_map<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let chunk = items.len().div_ceil(threads.max(1)).max(1);
+    let f = &f;
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<R>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    })
+}
+
+/// Truncates or pads `values` with the default value to `length` elements, if
+/// given.
+pub fn fit_length<T: Default + Clone>(mut values: Vec<T>, length: Option<usize>) -> Vec<T> {
+    if let Some(length) = length {
+        values.resize(length, T::default());
+    }
+    values
+}
@@ -0,0 +1,161 @@
+use std::collections::{BTreeSet, HashSet};
+use std::ops::RangeInclusive;
+
+use hdf5::File;
+use hdf5::types::FixedAscii;
+use regex::Regex;
+
+use crate::index;
+use crate::{Circuit, CircuitIndex};
+
+/// Criteria that select a subset of circuits. A circuit matches if it meets
+/// every criterion that is set; unset criteria match all circuits.
+#[derive(Clone, Debug, Default)]
+pub struct Criteria {
+    /// Keep only circuits with one of these labels.
+    pub labels: Option<HashSet<String>>,
+    /// Keep only circuits observed on a day in this range.
+    pub days: Option<RangeInclusive<u8>>,
+    /// Drop circuits observed on any of these days (e.g., a day with a known
+    /// collection outage).
+    pub exclude_days: Option<HashSet<u8>>,
+    /// Keep only circuits with one of these ports.
+    pub ports: Option<HashSet<u16>>,
+    /// Drop circuits with any of these ports.
+    pub deny_ports: Option<HashSet<u16>>,
+    /// Keep only circuits with at least this many cells.
+    pub min_len: Option<u16>,
+    /// Keep only circuits with at most this many cells.
+    pub max_len: Option<u16>,
+    /// Keep only circuits whose domain has one of these suffixes (e.g., `de`
+    /// matches `de` and `example.de`, but not `code`).
+    pub domain_suffixes: Option<Vec<String>>,
+    /// Drop circuits whose domain has any of these suffixes.
+    pub deny_domain_suffixes: Option<Vec<String>>,
+    /// Keep only circuits whose domain matches this regex.
+    pub domain_regex: Option<Regex>,
+    /// Keep only circuits whose shortest private suffix matches this regex.
+    pub suffix_regex: Option<Regex>,
+    /// Drop circuits with any of these uuids (e.g., the outliers listed by
+    /// `clean::write_outliers`).
+    pub exclude_uuids: Option<HashSet<FixedAscii<32>>>,
+}
+
+impl Criteria {
+    /// Returns true if `circuit` meets all of the criteria.
+    pub fn matches(&self, circuit: &Circuit) -> bool {
+        self.labels
+            .as_ref()
+            .is_none_or(|labels| labels.contains(circuit.label().as_str()))
+            && self.day_matches(circuit.day)
+            && self.port_matches(circuit.port)
+            && self.len_matches(circuit.len)
+            && self.domain_matches(
+                circuit.domain.as_str(),
+                circuit.shortest_private_suffix.as_str(),
+            )
+            && self
+                .exclude_uuids
+                .as_ref()
+                .is_none_or(|uuids| !uuids.contains(&circuit.uuid))
+    }
+
+    /// Returns true if `domain` and `suffix` (the shortest private suffix) meet
+    /// the domain suffix and regex criteria that are set.
+    pub fn domain_matches(&self, domain: &str, suffix: &str) -> bool {
+        self.domain_suffixes
+            .as_ref()
+            .is_none_or(|suffixes| suffixes.iter().any(|s| has_domain_suffix(domain, s)))
+            && self
+                .deny_domain_suffixes
+                .as_ref()
+                .is_none_or(|suffixes| !suffixes.iter().any(|s| has_domain_suffix(domain, s)))
+            && self
+                .domain_regex
+                .as_ref()
+                .is_none_or(|re| re.is_match(domain))
+            && self
+                .suffix_regex
+                .as_ref()
+                .is_none_or(|re| re.is_match(suffix))
+    }
+
+    /// Returns true if `day` is within the day range and not excluded, if
+    /// either is set.
+    pub fn day_matches(&self, day: u8) -> bool {
+        self.days.as_ref().is_none_or(|days| days.contains(&day))
+            && self
+                .exclude_days
+                .as_ref()
+                .is_none_or(|days| !days.contains(&day))
+    }
+
+    /// Returns true if `port` is allowed and not denied, if either list is set.
+    pub fn port_matches(&self, port: u16) -> bool {
+        self.ports
+            .as_ref()
+            .is_none_or(|ports| ports.contains(&port))
+            && self
+                .deny_ports
+                .as_ref()
+                .is_none_or(|ports| !ports.contains(&port))
+    }
+
+    /// Returns true if `len` is within the length range, if one is set.
+    pub fn len_matches(&self, len: u16) -> bool {
+        self.min_len.is_none_or(|min| len >= min) && self.max_len.is_none_or(|max| len <= max)
+    }
+
+    /// Narrows the circuits that may match using the indices stored in `file`,
+    /// so that the others need not be read. Returns the sorted indices of the
+    /// candidates, or `None` if no criterion could be resolved by an index.
+    /// Candidates must still be checked with `matches`.
+    pub fn candidates(&self, file: &File) -> hdf5::Result<Option<Vec<CircuitIndex>>> {
+        let mut candidates: Option<BTreeSet<CircuitIndex>> = None;
+
+        if (self.days.is_some() || self.exclude_days.is_some()) && file.link_exists("/index/day") {
+            let by_day = index::select_indices::<u8, _>(&file.dataset("/index/day")?, |day| {
+                self.day_matches(*day)
+            })?;
+            candidates = Some(intersect(candidates, by_day));
+        }
+
+        if (self.ports.is_some() || self.deny_ports.is_some()) && file.link_exists("/index/port") {
+            let by_port = index::select_indices::<u16, _>(&file.dataset("/index/port")?, |port| {
+                self.port_matches(*port)
+            })?;
+            candidates = Some(intersect(candidates, by_port));
+        }
+
+        if (self.min_len.is_some() || self.max_len.is_some()) && file.link_exists("/index/len") {
+            let by_len = index::select_indices::<u16, _>(&file.dataset("/index/len")?, |len| {
+                self.len_matches(*len)
+            })?;
+            candidates = Some(intersect(candidates, by_len));
+        }
+
+        Ok(candidates.map(|set| set.into_iter().collect()))
+    }
+}
+
+/// Returns true if `domain` equals `suffix` or ends with `.suffix`, ignoring
+/// case and any leading dot or `*.` of the suffix.
+pub fn has_domain_suffix(domain: &str, suffix: &str) -> bool {
+    let suffix = suffix.trim_start_matches("*.").trim_start_matches('.');
+    let (domain, suffix) = (domain.as_bytes(), suffix.as_bytes());
+
+    domain.len() >= suffix.len()
+        && domain[domain.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+        && (domain.len() == suffix.len() || domain[domain.len() - suffix.len() - 1] == b'.')
+}
+
+/// Intersects `set` with the candidates found so far, if any.
+fn intersect(
+    candidates: Option<BTreeSet<CircuitIndex>>,
+    set: BTreeSet<CircuitIndex>,
+) -> BTreeSet<CircuitIndex> {
+    match candidates {
+        Some(candidates) => candidates.intersection(&set).copied().collect(),
+        None => set,
+    }
+}
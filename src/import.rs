@@ -0,0 +1,126 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use hdf5::File;
+use hdf5::types::FixedAscii;
+
+use crate::index;
+use crate::rng::Rng;
+use crate::writer::AugmentedWriter;
+use crate::{AugmentedCircuit, Cell, MAX_CELLS};
+
+/// Why a trace produced by an external generator could not be imported.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImportError {
+    /// The GTT23 uuid is not ASCII or is longer than 32 characters.
+    BadUuid(String),
+    /// No GTT23 circuit has the uuid.
+    UnknownUuid(String),
+    /// The trace has more cells than fit in a circuit.
+    TooLong(usize),
+    /// The time of the cell at this position is not finite.
+    NonFiniteTime(usize),
+    /// The cell at this position is earlier than the cell before it.
+    NotMonotonic(usize),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::BadUuid(uuid) => write!(f, "'{uuid}' is not a valid uuid"),
+            ImportError::UnknownUuid(uuid) => write!(f, "no GTT23 circuit has uuid {uuid}"),
+            ImportError::TooLong(len) => write!(f, "{len} cells exceed the {MAX_CELLS} cell limit"),
+            ImportError::NonFiniteTime(i) => write!(f, "cell {i} has a non-finite time"),
+            ImportError::NotMonotonic(i) => write!(f, "cell {i} is earlier than cell {}", i - 1),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Returns an augmented circuit with `uuid` holding `cells`, linked to the
+/// GTT23 circuit with `uuid_gtt23`, if the cells fit in a circuit and their
+/// times are finite and non-decreasing.
+pub fn synthetic_circuit(
+    uuid: FixedAscii<32>,
+    uuid_gtt23: FixedAscii<32>,
+    cells: &[Cell],
+) -> Result<AugmentedCircuit, ImportError> {
+    let mut circuit = AugmentedCircuit::empty();
+    if cells.len() > circuit.cells.len() {
+        return Err(ImportError::TooLong(cells.len()));
+    }
+    if let Some(i) = cells.iter().position(|c| !c.time.is_finite()) {
+        return Err(ImportError::NonFiniteTime(i));
+    }
+    if let Some(i) = cells.windows(2).position(|w| w[1].time < w[0].time) {
+        return Err(ImportError::NotMonotonic(i + 1));
+    }
+
+    circuit.uuid = uuid;
+    circuit.uuid_gtt23 = uuid_gtt23;
+    circuit.len = cells.len() as u16;
+    circuit.cells[..cells.len()].copy_from_slice(cells);
+    Ok(circuit)
+}
+
+/// Imports traces produced by external generators, such as GAN or diffusion
+/// trace synthesizers, into the `/augmented` dataset of a file, linked to the
+/// GTT23 circuits they were generated from. Each trace is validated with
+/// `synthetic_circuit` and its GTT23 uuid is checked against a circuits
+/// dataset; traces that fail are not written.
+pub struct SyntheticImporter {
+    writer: AugmentedWriter,
+    uuids: HashSet<FixedAscii<32>>,
+    rng: Rng,
+}
+
+impl SyntheticImporter {
+    /// Creates an empty `/augmented` dataset in `file`, with the chunking and
+    /// compression of the `/circuits` dataset of `gtt23`, accepting traces of
+    /// its circuits. The uuids of the imported circuits are drawn from a random
+    /// number generator seeded with `seed`.
+    pub fn create(file: &File, gtt23: &File, seed: u64) -> hdf5::Result<Self> {
+        let template = gtt23.dataset("/circuits")?;
+        Ok(Self {
+            writer: AugmentedWriter::create_like(file, &template)?,
+            uuids: index::read_uuid_indices(gtt23)?.into_keys().collect(),
+            rng: Rng::new(seed),
+        })
+    }
+
+    /// Validates the trace with `cells` generated from the GTT23 circuit with
+    /// `uuid_gtt23` and writes it, returning the reason if it is invalid.
+    pub fn import(
+        &mut self,
+        uuid_gtt23: &str,
+        cells: &[Cell],
+    ) -> hdf5::Result<Result<(), ImportError>> {
+        let Ok(uuid_gtt23) = FixedAscii::<32>::from_ascii(uuid_gtt23.as_bytes()) else {
+            return Ok(Err(ImportError::BadUuid(uuid_gtt23.to_string())));
+        };
+        if !self.uuids.contains(&uuid_gtt23) {
+            return Ok(Err(ImportError::UnknownUuid(uuid_gtt23.to_string())));
+        }
+        match synthetic_circuit(self.rng.uuid(), uuid_gtt23, cells) {
+            Ok(circuit) => self.writer.push(circuit).map(Ok),
+            Err(e) => Ok(Err(e)),
+        }
+    }
+
+    /// The number of traces imported.
+    pub fn len(&self) -> usize {
+        self.writer.len()
+    }
+
+    /// Returns true if no traces have been imported.
+    pub fn is_empty(&self) -> bool {
+        self.writer.is_empty()
+    }
+
+    /// Writes all buffered circuits and the `/index/uuid_gtt23` index, and
+    /// returns the augmented dataset.
+    pub fn finish(self) -> hdf5::Result<hdf5::Dataset> {
+        self.writer.finish()
+    }
+}
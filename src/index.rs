@@ -0,0 +1,998 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::path::Path;
+
+use hdf5::types::{FixedAscii, VarLenArray};
+use hdf5::{File, H5Type};
+use ndarray::Array1;
+
+use crate::repack::{self, RepackSizes};
+use crate::{
+    AugmentedCircuit, Circuit, CircuitIndex, CircuitMeta, IndexArrayEntry, IndexEntry, LabelEntry,
+    ServiceCategory,
+};
+
+/// The names of the index datasets that are stored in the `/index` group.
+pub const INDEX_NAMES: [&str; 6] = ["uuid", "label", "day", "port", "len", "service"];
+
+/// The name of the dataset holding the label vocabulary.
+pub const LABELS_NAME: &str = "/labels";
+
+/// The false positive rate used when `reindex` rebuilds a uuid filter.
+pub const DEFAULT_FP_RATE: f64 = 0.01;
+
+/// The maximum number of uuid entries that `reindex` holds in memory before
+/// spilling a sorted run to the file.
+const UUID_RUN_LEN: usize = 1 << 20;
+
+/// The number of entries read at a time from each sorted run while merging.
+const UUID_MERGE_BLOCK: usize = 4_096;
+
+/// Accumulates the circuit indices for each indexed value while streaming
+/// through a circuits dataset.
+#[derive(Default)]
+pub struct IndexBuilder {
+    uuid: Option<HashMap<FixedAscii<32>, Vec<CircuitIndex>>>,
+    label: HashMap<FixedAscii<44>, Vec<CircuitIndex>>,
+    day: HashMap<u8, Vec<CircuitIndex>>,
+    port: HashMap<u16, Vec<CircuitIndex>>,
+    len: HashMap<u16, Vec<CircuitIndex>>,
+    service: HashMap<ServiceCategory, Vec<CircuitIndex>>,
+}
+
+impl IndexBuilder {
+    /// Creates an `IndexBuilder` that has not yet seen any circuits.
+    pub fn new() -> Self {
+        Self {
+            uuid: Some(HashMap::new()),
+            ..Self::default()
+        }
+    }
+
+    /// Creates an `IndexBuilder` that skips the uuid index, whose map holds an
+    /// entry for every circuit, for callers that build it some other way.
+    pub fn without_uuid() -> Self {
+        Self::default()
+    }
+
+    /// Records that `circuit` is stored at `index` in the circuits dataset.
+    pub fn add<const N: usize>(&mut self, index: CircuitIndex, circuit: &Circuit<N>) {
+        self.add_meta(index, &CircuitMeta::from(circuit));
+    }
+
+    /// Like `add`, but for the meta-data of a circuit, which is all that the
+    /// indices need.
+    pub fn add_meta(&mut self, index: CircuitIndex, meta: &CircuitMeta) {
+        if let Some(uuid) = self.uuid.as_mut() {
+            uuid.entry(meta.uuid).or_default().push(index);
+        }
+        self.label.entry(meta.label()).or_default().push(index);
+        self.day.entry(meta.day).or_default().push(index);
+        self.port.entry(meta.port).or_default().push(index);
+        self.len.entry(meta.len).or_default().push(index);
+        self.service.entry(meta.service()).or_default().push(index);
+    }
+
+    /// The uuid index, sorted by uuid. Empty if the builder was created with
+    /// `without_uuid`.
+    pub fn uuid_index(&self) -> Vec<IndexEntry<FixedAscii<32>>> {
+        self.uuid
+            .as_ref()
+            .map_or(Vec::new(), |uuid| index_entries(uuid, |v| v.to_string()))
+    }
+
+    /// The uuids that were added for more than one circuit, sorted by uuid.
+    pub fn duplicate_uuids(&self) -> Vec<FixedAscii<32>> {
+        let mut dups: Vec<FixedAscii<32>> = self.uuid.as_ref().map_or(Vec::new(), |uuid| {
+            uuid.iter()
+                .filter(|(_, indices)| indices.len() > 1)
+                .map(|(value, _)| *value)
+                .collect()
+        });
+        dups.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        dups
+    }
+
+    /// The label index, sorted by label.
+    pub fn label_index(&self) -> Vec<IndexArrayEntry<FixedAscii<44>>> {
+        index_arr_entries(&self.label, |v| v.to_string())
+    }
+
+    /// The label vocabulary, assigning class ids to the labels in sorted order.
+    pub fn label_vocabulary(&self) -> Vec<LabelEntry> {
+        let mut labels: Vec<(&FixedAscii<44>, &Vec<CircuitIndex>)> = self.label.iter().collect();
+        labels.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+        labels
+            .into_iter()
+            .enumerate()
+            .map(|(id, (label, indices))| LabelEntry {
+                id: id as u32,
+                label: *label,
+                count: indices.len() as u32,
+            })
+            .collect()
+    }
+
+    /// The day index, sorted by day.
+    pub fn day_index(&self) -> Vec<IndexArrayEntry<u8>> {
+        index_arr_entries(&self.day, |v| *v)
+    }
+
+    /// The port index, sorted by port.
+    pub fn port_index(&self) -> Vec<IndexArrayEntry<u16>> {
+        index_arr_entries(&self.port, |v| *v)
+    }
+
+    /// The len index, sorted by len.
+    pub fn len_index(&self) -> Vec<IndexArrayEntry<u16>> {
+        index_arr_entries(&self.len, |v| *v)
+    }
+
+    /// The service index, sorted by service category.
+    pub fn service_index(&self) -> Vec<IndexArrayEntry<ServiceCategory>> {
+        index_arr_entries(&self.service, |v| *v as u8)
+    }
+
+    /// Writes every index to its `/index/<name>` dataset in `file`, and the
+    /// label vocabulary to `/labels`, replacing any existing datasets. The uuid
+    /// index is skipped if the builder was created with `without_uuid`.
+    pub fn write_all(&self, file: &File) -> hdf5::Result<()> {
+        if self.uuid.is_some() {
+            write_index(file, "/index/uuid", &self.uuid_index())?;
+        }
+        write_index(file, "/index/label", &self.label_index())?;
+        write_index(file, "/index/day", &self.day_index())?;
+        write_index(file, "/index/port", &self.port_index())?;
+        write_index(file, "/index/len", &self.len_index())?;
+        write_index(file, "/index/service", &self.service_index())?;
+        write_index(file, LABELS_NAME, &self.label_vocabulary())?;
+        Ok(())
+    }
+
+    /// Compares the index dataset `/index/<name>` stored in `file` against the
+    /// index recomputed by this builder.
+    pub fn verify(&self, file: &File, name: &str) -> hdf5::Result<IndexDiff> {
+        let dataset = file.dataset(&format!("/index/{name}"))?;
+
+        let diff = match name {
+            "uuid" => {
+                let stored = dataset.read_raw::<IndexEntry<FixedAscii<32>>>()?;
+                diff_index(
+                    stored.iter().map(|e| (e.value, vec![e.index])),
+                    self.uuid_index().iter().map(|e| (e.value, vec![e.index])),
+                    |v| v.to_string(),
+                )
+            }
+            "label" => diff_index(
+                arr_pairs(&dataset.read_raw::<IndexArrayEntry<FixedAscii<44>>>()?),
+                arr_pairs(&self.label_index()),
+                |v| v.to_string(),
+            ),
+            "day" => diff_index(
+                arr_pairs(&dataset.read_raw::<IndexArrayEntry<u8>>()?),
+                arr_pairs(&self.day_index()),
+                |v| *v,
+            ),
+            "port" => diff_index(
+                arr_pairs(&dataset.read_raw::<IndexArrayEntry<u16>>()?),
+                arr_pairs(&self.port_index()),
+                |v| *v,
+            ),
+            "len" => diff_index(
+                arr_pairs(&dataset.read_raw::<IndexArrayEntry<u16>>()?),
+                arr_pairs(&self.len_index()),
+                |v| *v,
+            ),
+            "service" => diff_index(
+                arr_pairs(&dataset.read_raw::<IndexArrayEntry<ServiceCategory>>()?),
+                arr_pairs(&self.service_index()),
+                |v| *v as u8,
+            ),
+            _ => return Err(format!("Unknown index name {name}").into()),
+        };
+
+        Ok(diff)
+    }
+
+    /// Compares the label vocabulary stored in the `/labels` dataset of `file`
+    /// against the vocabulary recomputed by this builder. An entry mismatches
+    /// if its label has a different class id or count.
+    pub fn verify_labels(&self, file: &File) -> hdf5::Result<IndexDiff> {
+        let stored = file.dataset(LABELS_NAME)?.read_raw::<LabelEntry>()?;
+        Ok(diff_index(
+            stored.iter().map(|e| (e.label, vec![e.id, e.count])),
+            self.label_vocabulary()
+                .iter()
+                .map(|e| (e.label, vec![e.id, e.count])),
+            |v| v.to_string(),
+        ))
+    }
+}
+
+/// Rebuilds all indices of the GTT23 file at `path` in a single streaming pass
+/// over its circuits dataset, and then repacks the file to reclaim the space
+/// held by the replaced indices.
+///
+/// Circuits are read in bounded chunks, but only the uuid index is built in
+/// bounded memory: it has one entry per circuit, so it is built by spilling
+/// sorted runs of bounded size into the file and merging them. The label, day,
+/// port, len, and service indices are held in memory until they are written,
+/// at one `CircuitIndex` per circuit each, since every entry stores the
+/// indices of all circuits with its value in one variable-length array that
+/// must be in memory to be written. Memory therefore still grows linearly with
+/// the number of circuits, though by far less than when the uuid index is held
+/// in memory. The uuid filter and the augmented back-reference index, which is
+/// also held in memory, are rebuilt if the file has them.
+pub fn reindex<P: AsRef<Path>>(path: P) -> hdf5::Result<RepackSizes> {
+    let path = path.as_ref();
+
+    let file = File::open_rw(path)?;
+    let circuits = file.dataset("/circuits")?;
+    let size = circuits.size();
+    let step = 1_000; // multiple of chunk size
+    let meta_step = 10 * step;
+
+    let mut builder = IndexBuilder::without_uuid();
+    let mut filter = if file.link_exists("/index/uuid_filter") {
+        Some(UuidFilter::new(size, DEFAULT_FP_RATE))
+    } else {
+        None
+    };
+    let mut runs = Vec::new();
+    let mut run = Vec::with_capacity(UUID_RUN_LEN.min(size));
+
+    // Only the meta-data is needed, so skip reading the cells.
+    for begin in (0..size).step_by(meta_step) {
+        let end = std::cmp::min(begin + meta_step, size);
+
+        for (i, meta) in circuits
+            .read_slice_1d::<CircuitMeta, _>(begin..end)?
+            .iter()
+            .enumerate()
+        {
+            let index = (begin + i) as CircuitIndex;
+            builder.add_meta(index, meta);
+
+            if let Some(filter) = filter.as_mut() {
+                filter.insert(&meta.uuid);
+            }
+
+            run.push(IndexEntry {
+                value: meta.uuid,
+                index,
+            });
+
+            if run.len() >= UUID_RUN_LEN {
+                runs.push(spill_uuid_run(&file, &mut run, runs.len())?);
+            }
+        }
+    }
+
+    if !run.is_empty() {
+        runs.push(spill_uuid_run(&file, &mut run, runs.len())?);
+    }
+
+    merge_uuid_runs(&file, &runs, "/index/uuid")?;
+    for name in runs.iter() {
+        file.unlink(name)?;
+    }
+
+    builder.write_all(&file)?;
+
+    if let Some(filter) = filter {
+        filter.write(&file, "/index/uuid_filter")?;
+    }
+
+    if file.link_exists("/augmented") {
+        let augmented = file.dataset("/augmented")?;
+        let size = augmented.size();
+        let mut aug = AugmentedIndexBuilder::new();
+
+        for begin in (0..size).step_by(step) {
+            let end = std::cmp::min(begin + step, size);
+
+            for (i, circuit) in augmented
+                .read_slice_1d::<AugmentedCircuit, _>(begin..end)?
+                .iter()
+                .enumerate()
+            {
+                aug.add((begin + i) as CircuitIndex, circuit);
+            }
+        }
+
+        write_index(&file, "/index/uuid_gtt23", &aug.uuid_gtt23_index())?;
+    }
+
+    file.close()?;
+    repack::repack(path)
+}
+
+/// The class id of each label, as stored in the `/labels` dataset, so that
+/// every exporter numbers the classes of a dataset the same way.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LabelVocabulary {
+    entries: Vec<LabelEntry>,
+    ids: HashMap<FixedAscii<44>, u32>,
+}
+
+impl LabelVocabulary {
+    /// Creates a vocabulary from `entries`, which are sorted by id.
+    pub fn new(entries: Vec<LabelEntry>) -> Self {
+        let ids = entries.iter().map(|e| (e.label, e.id)).collect();
+        Self { entries, ids }
+    }
+
+    /// Reads the vocabulary from the `/labels` dataset of `file`.
+    pub fn read(file: &File) -> hdf5::Result<Self> {
+        Ok(Self::new(
+            file.dataset(LABELS_NAME)?.read_raw::<LabelEntry>()?,
+        ))
+    }
+
+    /// The class id of `label`, if it is in the vocabulary.
+    pub fn class_id(&self, label: &FixedAscii<44>) -> Option<u32> {
+        self.ids.get(label).copied()
+    }
+
+    /// The label with class id `id`, if any.
+    pub fn label(&self, id: u32) -> Option<FixedAscii<44>> {
+        self.entries.get(id as usize).map(|e| e.label)
+    }
+
+    /// The entries of the vocabulary, sorted by id.
+    pub fn entries(&self) -> &[LabelEntry] {
+        &self.entries
+    }
+
+    /// The number of labels in the vocabulary.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the vocabulary has no labels.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Writes `entries` to the index dataset `name` in `file`, replacing any
+/// existing dataset with that name.
+pub fn write_index<T: H5Type>(file: &File, name: &str, entries: &[T]) -> hdf5::Result<()> {
+    if file.link_exists(name) {
+        // Note this unlinks but does not reclaim its storage space.
+        file.unlink(name)?;
+    }
+
+    file.new_dataset_builder().with_data(entries).create(name)?;
+    Ok(())
+}
+
+/// Sorts `run` by uuid and writes it to a temporary dataset, returning the name
+/// of the dataset. Leaves `run` empty.
+fn spill_uuid_run(
+    file: &File,
+    run: &mut Vec<IndexEntry<FixedAscii<32>>>,
+    run_id: usize,
+) -> hdf5::Result<String> {
+    let name = format!("/index/uuid_run_{run_id}");
+    run.sort_by(|a, b| a.value.as_str().cmp(b.value.as_str()));
+    write_index(file, &name, run)?;
+    run.clear();
+    Ok(name)
+}
+
+/// Merges sorted uuid runs into a single sorted index dataset `name`, holding
+/// at most one block of entries per run in memory. Like `IndexBuilder`, only
+/// the entry with the lowest circuit index is kept for a uuid that appears more
+/// than once, which requires that each run holds increasing circuit indices for
+/// equal uuids and that earlier runs hold lower circuit indices.
+fn merge_uuid_runs(file: &File, runs: &[String], name: &str) -> hdf5::Result<()> {
+    let mut cursors = runs
+        .iter()
+        .map(|run| Ok(RunCursor::new(file.dataset(run)?)))
+        .collect::<hdf5::Result<Vec<_>>>()?;
+
+    if file.link_exists(name) {
+        // Note this unlinks but does not reclaim its storage space.
+        file.unlink(name)?;
+    }
+
+    // Duplicate uuids are dropped, so the final size is only known at the end.
+    let out = file
+        .new_dataset_builder()
+        .chunk(UUID_MERGE_BLOCK)
+        .empty::<IndexEntry<FixedAscii<32>>>()
+        .shape(0..)
+        .create(name)?;
+
+    // The heap holds the next uuid of each run that has entries remaining.
+    let mut heap = BinaryHeap::new();
+    for (id, cursor) in cursors.iter_mut().enumerate() {
+        if let Some(entry) = cursor.peek()? {
+            heap.push(Reverse((entry.value.to_string(), id)));
+        }
+    }
+
+    let mut buffer = Vec::with_capacity(UUID_MERGE_BLOCK);
+    let mut written = 0;
+    let mut last: Option<FixedAscii<32>> = None;
+
+    while let Some(Reverse((_, id))) = heap.pop() {
+        if let Some(entry) = cursors[id].block.pop_front() {
+            // Ties are popped in run order, so the first entry of a uuid has
+            // its lowest circuit index.
+            if last != Some(entry.value) {
+                last = Some(entry.value);
+                buffer.push(entry);
+            }
+        }
+
+        if let Some(entry) = cursors[id].peek()? {
+            heap.push(Reverse((entry.value.to_string(), id)));
+        }
+
+        if !buffer.is_empty() && (buffer.len() >= UUID_MERGE_BLOCK || heap.is_empty()) {
+            out.resize(written + buffer.len())?;
+            out.write_slice(&buffer[..], written..written + buffer.len())?;
+            written += buffer.len();
+            buffer.clear();
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a sorted uuid run one block at a time.
+struct RunCursor {
+    dataset: hdf5::Dataset,
+    pos: usize,
+    block: VecDeque<IndexEntry<FixedAscii<32>>>,
+}
+
+impl RunCursor {
+    fn new(dataset: hdf5::Dataset) -> Self {
+        Self {
+            dataset,
+            pos: 0,
+            block: VecDeque::new(),
+        }
+    }
+
+    /// Returns the next entry of the run without consuming it, reading the
+    /// next block from the file if needed.
+    fn peek(&mut self) -> hdf5::Result<Option<IndexEntry<FixedAscii<32>>>> {
+        let size = self.dataset.size();
+
+        if self.block.is_empty() && self.pos < size {
+            let end = std::cmp::min(self.pos + UUID_MERGE_BLOCK, size);
+            let block = self
+                .dataset
+                .read_slice_1d::<IndexEntry<FixedAscii<32>>, _>(self.pos..end)?;
+            self.block.extend(block.iter().copied());
+            self.pos = end;
+        }
+
+        Ok(self.block.front().copied())
+    }
+}
+
+/// Accumulates the indices of augmented circuits by the uuid of the GTT23
+/// circuit from which they were created, while streaming through an augmented
+/// circuits dataset.
+#[derive(Default)]
+pub struct AugmentedIndexBuilder {
+    uuid_gtt23: HashMap<FixedAscii<32>, Vec<CircuitIndex>>,
+}
+
+impl AugmentedIndexBuilder {
+    /// Creates an `AugmentedIndexBuilder` that has not yet seen any circuits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that `circuit` is stored at `index` in the augmented dataset.
+    pub fn add(&mut self, index: CircuitIndex, circuit: &AugmentedCircuit) {
+        self.uuid_gtt23
+            .entry(circuit.uuid_gtt23)
+            .or_default()
+            .push(index);
+    }
+
+    /// The number of augmentations of the GTT23 circuit with `uuid` seen so
+    /// far.
+    pub fn augmentations(&self, uuid: &FixedAscii<32>) -> usize {
+        self.uuid_gtt23.get(uuid).map_or(0, Vec::len)
+    }
+
+    /// The back-reference index from GTT23 uuids to the indices of their
+    /// augmentations in the augmented dataset, sorted by uuid.
+    pub fn uuid_gtt23_index(&self) -> Vec<IndexArrayEntry<FixedAscii<32>>> {
+        index_arr_entries(&self.uuid_gtt23, |v| v.to_string())
+    }
+
+    /// Compares the `/index/uuid_gtt23` dataset stored in `file` against the
+    /// index recomputed by this builder.
+    pub fn verify(&self, file: &File) -> hdf5::Result<IndexDiff> {
+        let dataset = file.dataset("/index/uuid_gtt23")?;
+        Ok(diff_index(
+            arr_pairs(&dataset.read_raw::<IndexArrayEntry<FixedAscii<32>>>()?),
+            arr_pairs(&self.uuid_gtt23_index()),
+            |v| v.to_string(),
+        ))
+    }
+}
+
+/// Looks up the indices of all augmentations of the GTT23 circuit with `uuid`
+/// using the `/index/uuid_gtt23` dataset stored in `file`. Returns an empty
+/// vector if the circuit has no augmentations.
+pub fn find_augmentations(file: &File, uuid: &FixedAscii<32>) -> hdf5::Result<Vec<CircuitIndex>> {
+    let dataset = file.dataset("/index/uuid_gtt23")?;
+    let entry = find_entry(
+        &dataset,
+        &uuid.to_string(),
+        |e: &IndexArrayEntry<FixedAscii<32>>| e.value.to_string(),
+    )?;
+    Ok(entry.map_or(Vec::new(), |e| e.indexarr.to_vec()))
+}
+
+/// Binary searches a sorted index dataset for the entry whose key equals
+/// `target`, reading a single entry per probe so that only O(log n) entries
+/// are loaded from the file.
+pub fn find_entry<E, K, F>(dataset: &hdf5::Dataset, target: &K, key: F) -> hdf5::Result<Option<E>>
+where
+    E: H5Type,
+    K: Ord,
+    F: Fn(&E) -> K,
+{
+    let (mut lo, mut hi) = (0, dataset.size());
+
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        let entry = match dataset
+            .read_slice_1d::<E, _>(mid..mid + 1)?
+            .into_iter()
+            .next()
+        {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        match key(&entry).cmp(target) {
+            std::cmp::Ordering::Less => lo = mid + 1,
+            std::cmp::Ordering::Greater => hi = mid,
+            std::cmp::Ordering::Equal => return Ok(Some(entry)),
+        }
+    }
+
+    Ok(None)
+}
+
+/// Unions the circuit indices of the entries of an array index dataset (e.g.,
+/// `/index/len`) whose values satisfy `pred`.
+pub fn select_indices<T, F>(
+    dataset: &hdf5::Dataset,
+    pred: F,
+) -> hdf5::Result<BTreeSet<CircuitIndex>>
+where
+    T: H5Type,
+    F: Fn(&T) -> bool,
+{
+    Ok(dataset
+        .read_raw::<IndexArrayEntry<T>>()?
+        .iter()
+        .filter(|e| pred(&e.value))
+        .flat_map(|e| e.indexarr.iter().copied())
+        .collect())
+}
+
+/// Maps the uuid of every circuit in `file` to its index, using the
+/// `/index/uuid` dataset if present and otherwise scanning the circuits.
+pub fn read_uuid_indices(file: &File) -> hdf5::Result<HashMap<FixedAscii<32>, CircuitIndex>> {
+    if file.link_exists("/index/uuid") {
+        let entries = file
+            .dataset("/index/uuid")?
+            .read_raw::<IndexEntry<FixedAscii<32>>>()?;
+        return Ok(entries.into_iter().map(|e| (e.value, e.index)).collect());
+    }
+
+    let circuits = file.dataset("/circuits")?;
+    let size = circuits.size();
+    let mut indices = HashMap::with_capacity(size);
+    let step = 1_000;
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+        let metas: Array1<CircuitMeta> = circuits.read_slice(ndarray::s![begin..end])?;
+        for (i, meta) in metas.iter().enumerate() {
+            indices.insert(meta.uuid, (begin + i) as CircuitIndex);
+        }
+    }
+    Ok(indices)
+}
+
+/// Only the uuid field of a circuit, so that scans need not convert cells.
+#[derive(H5Type, Clone, Copy, Debug)]
+#[repr(C)]
+struct UuidField {
+    uuid: FixedAscii<32>,
+}
+
+/// Finds the indices of the circuits with the given `uuids` by scanning the
+/// uuid field of every circuit in `dataset`. This is the fallback for files
+/// without a `/index/uuid` dataset. Uuids that are not found are absent from
+/// the returned map.
+pub fn scan_uuids(
+    dataset: &hdf5::Dataset,
+    uuids: &HashSet<FixedAscii<32>>,
+) -> hdf5::Result<HashMap<FixedAscii<32>, CircuitIndex>> {
+    let mut found = HashMap::new();
+    let size = dataset.size();
+    let step = 1_000;
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+
+        for (i, field) in dataset
+            .read_slice_1d::<UuidField, _>(begin..end)?
+            .iter()
+            .enumerate()
+        {
+            if uuids.contains(&field.uuid) {
+                found
+                    .entry(field.uuid)
+                    .or_insert((begin + i) as CircuitIndex);
+            }
+        }
+
+        if found.len() == uuids.len() {
+            break;
+        }
+    }
+
+    Ok(found)
+}
+
+/// A bloom filter over circuit uuids that answers "definitely absent" or
+/// "maybe present" without reading the uuid index. It is stored as an array of
+/// `u64` bit words with `num_bits` and `num_hashes` attributes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UuidFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+}
+
+impl UuidFilter {
+    /// Creates an empty filter sized to hold `num_items` uuids with the given
+    /// false positive rate.
+    pub fn new(num_items: usize, fp_rate: f64) -> Self {
+        let n = num_items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-n * fp_rate.ln() / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let num_hashes = ((num_bits as f64 / n) * ln2).round().max(1.0) as u32;
+
+        Self {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes,
+        }
+    }
+
+    /// Adds `uuid` to the filter.
+    pub fn insert(&mut self, uuid: &FixedAscii<32>) {
+        let positions: Vec<u64> = self.bit_positions(uuid).collect();
+        for bit in positions {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Returns false if `uuid` was definitely never inserted, and true if it
+    /// may have been inserted.
+    pub fn maybe_contains(&self, uuid: &FixedAscii<32>) -> bool {
+        self.bit_positions(uuid)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Writes the filter to the dataset `name` in `file`, replacing any
+    /// existing dataset with that name.
+    pub fn write(&self, file: &File, name: &str) -> hdf5::Result<()> {
+        if file.link_exists(name) {
+            // Note this unlinks but does not reclaim its storage space.
+            file.unlink(name)?;
+        }
+
+        let dataset = file
+            .new_dataset_builder()
+            .with_data(&self.bits[..])
+            .create(name)?;
+        dataset
+            .new_attr::<u64>()
+            .create("num_bits")?
+            .write_scalar(&self.num_bits)?;
+        dataset
+            .new_attr::<u32>()
+            .create("num_hashes")?
+            .write_scalar(&self.num_hashes)?;
+
+        Ok(())
+    }
+
+    /// Reads a filter previously written to the dataset `name` in `file`.
+    pub fn read(file: &File, name: &str) -> hdf5::Result<Self> {
+        let dataset = file.dataset(name)?;
+        let bits = dataset.read_raw::<u64>()?;
+        let num_bits = dataset.attr("num_bits")?.read_scalar::<u64>()?;
+        let num_hashes = dataset.attr("num_hashes")?.read_scalar::<u32>()?;
+
+        if num_bits == 0 || num_bits.div_ceil(64) as usize != bits.len() {
+            return Err(format!("Inconsistent uuid filter size in {name}").into());
+        }
+
+        Ok(Self {
+            bits,
+            num_bits,
+            num_hashes,
+        })
+    }
+
+    /// Computes the bit positions for `uuid` using double hashing.
+    fn bit_positions(&self, uuid: &FixedAscii<32>) -> impl Iterator<Item = u64> + '_ {
+        let h1 = fnv1a(uuid.as_bytes(), 0xcbf29ce484222325);
+        let h2 = fnv1a(uuid.as_bytes(), 0x84222325cbf29ce4) | 1;
+        (0..self.num_hashes as u64)
+            .map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % self.num_bits)
+    }
+}
+
+/// A 64-bit FNV-1a hash, used because its output is stable across platforms
+/// and Rust versions.
+pub(crate) fn fnv1a(bytes: &[u8], basis: u64) -> u64 {
+    bytes.iter().fold(basis, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The differences between a stored index and the index recomputed from the
+/// circuits dataset. Values are formatted with `Debug` for reporting.
+#[derive(Clone, Debug, Default)]
+pub struct IndexDiff {
+    /// Values in the recomputed index that are absent from the stored index.
+    pub missing: Vec<String>,
+    /// Values in the stored index that are absent from the recomputed index.
+    pub extra: Vec<String>,
+    /// Values in both indices that map to different circuit indices.
+    pub mismatched: Vec<String>,
+    /// Positions in the stored index whose entry does not sort strictly after
+    /// the entry at the previous position.
+    pub misordered: Vec<usize>,
+}
+
+impl IndexDiff {
+    /// Returns true if the stored index matches the recomputed index.
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty()
+            && self.extra.is_empty()
+            && self.mismatched.is_empty()
+            && self.misordered.is_empty()
+    }
+}
+
+fn arr_pairs<T: H5Type + Copy>(
+    entries: &[IndexArrayEntry<T>],
+) -> impl Iterator<Item = (T, Vec<CircuitIndex>)> + '_ {
+    entries.iter().map(|e| (e.value, e.indexarr.to_vec()))
+}
+
+fn diff_index<T, K, F>(
+    stored: impl Iterator<Item = (T, Vec<CircuitIndex>)>,
+    expected: impl Iterator<Item = (T, Vec<CircuitIndex>)>,
+    key: F,
+) -> IndexDiff
+where
+    T: Eq + Hash + Debug,
+    K: Ord,
+    F: Fn(&T) -> K,
+{
+    let mut diff = IndexDiff::default();
+    let mut stored_map = HashMap::new();
+    let mut prev_key = None;
+
+    for (pos, (value, indices)) in stored.enumerate() {
+        let k = key(&value);
+        if prev_key.as_ref().is_some_and(|prev| *prev >= k) {
+            diff.misordered.push(pos);
+        }
+        prev_key = Some(k);
+        stored_map.insert(value, indices);
+    }
+
+    let mut seen = HashSet::new();
+    for (value, indices) in expected {
+        match stored_map.get(&value) {
+            Some(stored_indices) if *stored_indices != indices => {
+                diff.mismatched.push(format!("{value:?}"))
+            }
+            Some(_) => {}
+            None => diff.missing.push(format!("{value:?}")),
+        }
+        seen.insert(value);
+    }
+
+    for value in stored_map.keys() {
+        if !seen.contains(value) {
+            diff.extra.push(format!("{value:?}"));
+        }
+    }
+
+    diff
+}
+
+fn index_entries<T, K, F>(index_map: &HashMap<T, Vec<CircuitIndex>>, key: F) -> Vec<IndexEntry<T>>
+where
+    T: H5Type + Copy,
+    K: Ord,
+    F: Fn(&T) -> K,
+{
+    index_arr_entries(index_map, key)
+        .into_iter()
+        .map(|ent| IndexEntry {
+            value: ent.value,
+            index: *ent.indexarr.first().unwrap(),
+        })
+        .collect()
+}
+
+fn index_arr_entries<T, K, F>(
+    index_map: &HashMap<T, Vec<CircuitIndex>>,
+    key: F,
+) -> Vec<IndexArrayEntry<T>>
+where
+    T: H5Type + Copy,
+    K: Ord,
+    F: Fn(&T) -> K,
+{
+    let mut index: Vec<IndexArrayEntry<T>> = index_map
+        .iter()
+        .map(|(value, indices)| {
+            let mut indices = indices.clone();
+            indices.sort();
+            IndexArrayEntry {
+                value: *value,
+                indexarr: VarLenArray::from_slice(&indices),
+            }
+        })
+        .collect();
+    index.sort_by_key(|v| key(&v.value));
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates an empty HDF5 file named after `name` in the temporary
+    /// directory, returning it with its path.
+    fn temp_file(name: &str) -> (File, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("gtt23-{}-{name}.h5", std::process::id()));
+        (File::create(&path).unwrap(), path)
+    }
+
+    fn uuid(s: &str) -> FixedAscii<32> {
+        crate::fixedascii_from_str::<32>(&format!("{s:0>32}")).unwrap()
+    }
+
+    fn entry(s: &str, index: CircuitIndex) -> IndexEntry<FixedAscii<32>> {
+        IndexEntry {
+            value: uuid(s),
+            index,
+        }
+    }
+
+    /// Spills `runs` to the file `name` and merges them, returning the merged
+    /// entries.
+    fn merge(
+        name: &str,
+        runs: Vec<Vec<IndexEntry<FixedAscii<32>>>>,
+    ) -> Vec<(FixedAscii<32>, CircuitIndex)> {
+        let (file, path) = temp_file(name);
+        let names: Vec<String> = runs
+            .into_iter()
+            .enumerate()
+            .map(|(id, mut run)| spill_uuid_run(&file, &mut run, id).unwrap())
+            .collect();
+        merge_uuid_runs(&file, &names, "/index/uuid").unwrap();
+        let merged = file
+            .dataset("/index/uuid")
+            .unwrap()
+            .read_raw::<IndexEntry<FixedAscii<32>>>()
+            .unwrap();
+        file.close().unwrap();
+        std::fs::remove_file(path).unwrap();
+        merged.into_iter().map(|e| (e.value, e.index)).collect()
+    }
+
+    #[test]
+    fn merge_uuid_runs_keeps_lowest_index() {
+        let merged = merge(
+            "merge-lowest",
+            vec![
+                vec![entry("b", 0), entry("a", 1), entry("c", 2), entry("a", 3)],
+                vec![entry("a", 4), entry("d", 5), entry("b", 6)],
+            ],
+        );
+        assert_eq!(
+            merged,
+            vec![
+                (uuid("a"), 1),
+                (uuid("b"), 0),
+                (uuid("c"), 2),
+                (uuid("d"), 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_uuid_runs_spans_blocks() {
+        // Interleaved runs longer than a block, so that blocks are refilled and
+        // the output is written in several pieces.
+        let n = 2 * UUID_MERGE_BLOCK + 1;
+        let runs = (0..2)
+            .map(|r| {
+                (0..n)
+                    .map(|i| entry(&format!("{:x}", 2 * i + r), (2 * i + r) as CircuitIndex))
+                    .collect()
+            })
+            .collect();
+        let merged = merge("merge-blocks", runs);
+
+        assert_eq!(merged.len(), 2 * n);
+        assert!(merged.windows(2).all(|w| w[0].0.as_str() < w[1].0.as_str()));
+        assert!(merged.iter().all(|(u, i)| *u == uuid(&format!("{i:x}"))));
+    }
+
+    #[test]
+    fn merge_uuid_runs_of_nothing() {
+        assert!(merge("merge-empty", vec![Vec::new(), Vec::new()]).is_empty());
+    }
+
+    #[test]
+    fn uuid_filter_has_no_false_negatives() {
+        let mut filter = UuidFilter::new(1_000, 0.01);
+        for i in 0..1_000 {
+            filter.insert(&uuid(&format!("{i:x}")));
+        }
+        assert!((0..1_000).all(|i| filter.maybe_contains(&uuid(&format!("{i:x}")))));
+    }
+
+    #[test]
+    fn uuid_filter_false_positive_rate() {
+        let mut filter = UuidFilter::new(1_000, 0.01);
+        for i in 0..1_000 {
+            filter.insert(&uuid(&format!("{i:x}")));
+        }
+        let false_positives = (1_000..101_000)
+            .filter(|i| filter.maybe_contains(&uuid(&format!("{i:x}"))))
+            .count();
+        // About 1,000 are expected.
+        assert!(false_positives < 2_000, "{false_positives} false positives");
+    }
+
+    #[test]
+    fn uuid_filter_round_trip() {
+        let mut filter = UuidFilter::new(10, 0.01);
+        filter.insert(&uuid("a"));
+        filter.insert(&uuid("b"));
+
+        let (file, path) = temp_file("uuid-filter");
+        filter.write(&file, "/index/uuid_filter").unwrap();
+        let read = UuidFilter::read(&file, "/index/uuid_filter").unwrap();
+        file.close().unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(read.num_bits, filter.num_bits);
+        assert_eq!(read.num_hashes, filter.num_hashes);
+        assert_eq!(read.bits, filter.bits);
+        assert!(read.maybe_contains(&uuid("a")) && read.maybe_contains(&uuid("b")));
+    }
+}
@@ -1,6 +1,48 @@
 use hdf5::types::{FixedAscii, StringError, VarLenArray};
 use hdf5::H5Type;
 
+pub mod analyze;
+#[cfg(feature = "anonymize")]
+pub mod anonymize;
+pub mod augment;
+pub mod balance;
+pub mod circpad;
+pub mod clean;
+#[cfg(feature = "parquet")]
+pub mod columnar;
+pub mod dataset;
+pub mod defense;
+pub mod diff;
+pub mod drift;
+pub mod dtw;
+pub mod edit;
+pub mod export;
+pub mod features;
+pub mod filter;
+pub mod import;
+pub mod index;
+pub mod npz;
+pub mod overhead;
+pub mod profile;
+pub mod query;
+pub mod repack;
+pub mod rng;
+pub mod sample;
+pub mod schema;
+pub mod split;
+pub mod stats;
+pub mod tar;
+pub mod tensor;
+pub mod transform;
+pub mod validate;
+pub mod variability;
+pub mod varlen;
+pub mod window;
+pub mod world;
+pub mod writer;
+
+pub use dataset::Dataset;
+
 /// The direction that the cell was traveling.
 #[derive(H5Type, Clone, Copy, Debug, Eq, PartialEq)]
 #[allow(non_camel_case_types)]
@@ -165,6 +207,48 @@ impl TryFrom<u8> for RelayCommand {
     }
 }
 
+/// A coarse category of the service reached by a circuit, derived from the
+/// port number used to connect to the external server.
+#[derive(H5Type, Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[allow(non_camel_case_types)]
+#[repr(u8)]
+pub enum ServiceCategory {
+    OTHER = 0,
+    HTTP = 1,
+    HTTPS = 2,
+    SMTP = 3,
+    IMAP = 4,
+}
+
+impl ServiceCategory {
+    /// Buckets a port number into a service category using the well-known
+    /// ports of each service.
+    pub fn from_port(port: u16) -> Self {
+        match port {
+            80 | 8000 | 8080 => ServiceCategory::HTTP,
+            443 | 8443 => ServiceCategory::HTTPS,
+            25 | 465 | 587 => ServiceCategory::SMTP,
+            143 | 993 => ServiceCategory::IMAP,
+            _ => ServiceCategory::OTHER,
+        }
+    }
+}
+
+impl TryFrom<u8> for ServiceCategory {
+    type Error = String;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        match v {
+            v if v == ServiceCategory::OTHER as u8 => Ok(ServiceCategory::OTHER),
+            v if v == ServiceCategory::HTTP as u8 => Ok(ServiceCategory::HTTP),
+            v if v == ServiceCategory::HTTPS as u8 => Ok(ServiceCategory::HTTPS),
+            v if v == ServiceCategory::SMTP as u8 => Ok(ServiceCategory::SMTP),
+            v if v == ServiceCategory::IMAP as u8 => Ok(ServiceCategory::IMAP),
+            _ => Err(format!("Unexpected service category value {v}").to_string()),
+        }
+    }
+}
+
 /// The meta-data associated with a Cell observed by a Tor relay.
 #[derive(H5Type, Clone, Copy, Debug)]
 #[repr(C)]
@@ -210,10 +294,21 @@ impl Ord for Cell {
     }
 }
 
+/// The maximum number of cells of a GTT23 circuit, which is the default size
+/// of the `cells` array of `Circuit` and `AugmentedCircuit`.
+pub const MAX_CELLS: usize = 5000;
+
 /// The meta-data associated with a Circuit observed by a Tor relay.
+///
+/// The circuit holds up to `N` cells, which is `MAX_CELLS` for GTT23, so that
+/// measurements with a different cap can use the same schema, e.g., by reading
+/// and writing `Circuit<10000>` records with `CircuitWriter` and `CircuitIter`.
+/// The statistics, features, augmentations, cleaning, filtering, exports, and
+/// schema conversions of this crate only accept circuits with the default
+/// `MAX_CELLS` cells.
 #[derive(H5Type, Clone, Copy, PartialEq, Debug)]
 #[repr(C)]
-pub struct Circuit {
+pub struct Circuit<const N: usize = MAX_CELLS> {
     /// A unique ID.
     pub uuid: FixedAscii<32>,
     /// The initial first-party domain looked up on the circuit.
@@ -233,12 +328,19 @@ pub struct Circuit {
     pub len: u16,
     /// The cells observed on the circuit. Only `cells[0..len]` are valid, the
     /// rest are padding.
-    pub cells: [Cell; 5000],
+    pub cells: [Cell; N],
 }
 
 impl Circuit {
-    /// Creates an empty `Circuit` with all meta-data zeroed out.
+    /// Creates an empty `Circuit` with all meta-data zeroed out. Use
+    /// `Circuit::<N>::default` for circuits with a different number of cells.
     pub fn empty() -> Self {
+        Self::default()
+    }
+}
+
+impl<const N: usize> Default for Circuit<N> {
+    fn default() -> Self {
         Self {
             uuid: fixedascii_null::<32>().unwrap(),
             domain: fixedascii_null::<44>().unwrap(),
@@ -246,10 +348,12 @@ impl Circuit {
             day: 0,
             port: 0,
             len: 0,
-            cells: [Cell::empty(); 5000],
+            cells: [Cell::empty(); N],
         }
     }
+}
 
+impl<const N: usize> Circuit<N> {
     /// A string that can be used as a label for this circuit.
     pub fn label(&self) -> FixedAscii<44> {
         if self.shortest_private_suffix.is_empty() {
@@ -258,12 +362,302 @@ impl Circuit {
             self.shortest_private_suffix
         }
     }
+
+    /// The category of service reached by this circuit, based on its port.
+    pub fn service(&self) -> ServiceCategory {
+        ServiceCategory::from_port(self.port)
+    }
+
+    /// The valid cells of the circuit, i.e., `cells[0..len]`.
+    pub fn valid_cells(&self) -> &[Cell] {
+        &self.cells[..std::cmp::min(self.len as usize, self.cells.len())]
+    }
+
+    /// The time of the first valid cell, or `None` if there are no cells.
+    pub fn first_cell_time(&self) -> Option<f64> {
+        self.valid_cells().first().map(|c| c.time)
+    }
+
+    /// The time of the last valid cell, or `None` if there are no cells.
+    pub fn last_cell_time(&self) -> Option<f64> {
+        self.valid_cells().last().map(|c| c.time)
+    }
+
+    /// The time in seconds from the first to the last valid cell, or 0 if
+    /// there are fewer than two cells.
+    pub fn duration(&self) -> f64 {
+        match (self.first_cell_time(), self.last_cell_time()) {
+            (Some(first), Some(last)) => (last - first).max(0.0),
+            _ => 0.0,
+        }
+    }
+
+    /// The longest silence between consecutive valid cells, or `None` if there
+    /// are fewer than two cells. The earliest gap is returned if several are
+    /// equally long.
+    pub fn longest_idle_gap(&self) -> Option<IdleGap> {
+        self.valid_cells()
+            .windows(2)
+            .enumerate()
+            .map(|(i, w)| IdleGap {
+                after: i,
+                start: w[0].time,
+                duration: w[1].time - w[0].time,
+            })
+            .reduce(|longest, gap| {
+                if gap.duration > longest.duration {
+                    gap
+                } else {
+                    longest
+                }
+            })
+    }
+
+    /// A one-line summary of the circuit's meta-data and its first `n_cells`
+    /// valid cells, for quick inspection.
+    pub fn preview(&self, n_cells: usize) -> String {
+        let valid = self.valid_cells();
+        let len = valid.len();
+        let cells: Vec<String> = valid[..std::cmp::min(n_cells, len)]
+            .iter()
+            .map(|c| {
+                format!(
+                    "({:.6} {:?} {:?} {:?})",
+                    c.time, c.direction, c.cell_cmd, c.relay_cmd
+                )
+            })
+            .collect();
+        let more = if len > n_cells { ", ..." } else { "" };
+
+        format!(
+            "uuid={} label={} day={} port={} len={} cells=[{}{more}]",
+            self.uuid,
+            self.label(),
+            self.day,
+            self.port,
+            self.len,
+            cells.join(", ")
+        )
+    }
+}
+
+/// A silence between two consecutive valid cells of a circuit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IdleGap {
+    /// The position of the cell before the silence.
+    pub after: usize,
+    /// The time of the cell before the silence.
+    pub start: f64,
+    /// The time in seconds until the next cell.
+    pub duration: f64,
+}
+
+/// The meta-data fields of a `Circuit` without its cells. The field names match
+/// `Circuit`, so it can be read directly from a circuits dataset while skipping
+/// the conversion of the cells array, which makes up nearly all of the 100KB of
+/// each circuit record. Scans that only need the meta-data, such as computing
+/// statistics or indices, should read this type instead of `Circuit`.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct CircuitMeta {
+    pub uuid: FixedAscii<32>,
+    pub domain: FixedAscii<44>,
+    pub shortest_private_suffix: FixedAscii<44>,
+    pub day: u8,
+    pub port: u16,
+    pub len: u16,
+}
+
+impl CircuitMeta {
+    /// The same as `Circuit::label`.
+    pub fn label(&self) -> FixedAscii<44> {
+        if self.shortest_private_suffix.is_empty() {
+            self.domain
+        } else {
+            self.shortest_private_suffix
+        }
+    }
+
+    /// The same as `Circuit::service`.
+    pub fn service(&self) -> ServiceCategory {
+        ServiceCategory::from_port(self.port)
+    }
+}
+
+impl<const N: usize> From<&Circuit<N>> for CircuitMeta {
+    fn from(circuit: &Circuit<N>) -> Self {
+        Self {
+            uuid: circuit.uuid,
+            domain: circuit.domain,
+            shortest_private_suffix: circuit.shortest_private_suffix,
+            day: circuit.day,
+            port: circuit.port,
+            len: circuit.len,
+        }
+    }
+}
+
+/// A `Circuit` that stores only its valid cells, in a variable-length array,
+/// instead of a fixed array of `MAX_CELLS` cells. The other field names match
+/// `Circuit`, so `CircuitMeta` can also be read from a dataset of this type.
+/// See the `varlen` module for the tradeoffs of storing circuits this way.
+#[derive(H5Type, Clone, PartialEq, Debug)]
+#[repr(C)]
+pub struct CircuitVarLen {
+    pub uuid: FixedAscii<32>,
+    pub domain: FixedAscii<44>,
+    pub shortest_private_suffix: FixedAscii<44>,
+    pub day: u8,
+    pub port: u16,
+    /// The number of cells observed on the circuit, i.e., the length of
+    /// `cells`.
+    pub len: u16,
+    /// The valid cells observed on the circuit.
+    pub cells: VarLenArray<Cell>,
+}
+
+impl CircuitVarLen {
+    /// Returns the `Circuit` with the same meta-data and valid cells, padded
+    /// with empty cells to `N` cells, or truncated to `N` cells if longer.
+    pub fn to_circuit<const N: usize>(&self) -> Circuit<N> {
+        let mut circuit = Circuit::<N>::default();
+        let len = std::cmp::min(self.cells.len(), circuit.cells.len());
+        circuit.uuid = self.uuid;
+        circuit.domain = self.domain;
+        circuit.shortest_private_suffix = self.shortest_private_suffix;
+        circuit.day = self.day;
+        circuit.port = self.port;
+        circuit.len = len as u16;
+        circuit.cells[..len].copy_from_slice(&self.cells[..len]);
+        circuit
+    }
+}
+
+impl<const N: usize> From<&Circuit<N>> for CircuitVarLen {
+    fn from(circuit: &Circuit<N>) -> Self {
+        let cells = circuit.valid_cells();
+        Self {
+            uuid: circuit.uuid,
+            domain: circuit.domain,
+            shortest_private_suffix: circuit.shortest_private_suffix,
+            day: circuit.day,
+            port: circuit.port,
+            len: cells.len() as u16,
+            cells: VarLenArray::from_slice(cells),
+        }
+    }
+}
+
+/// The fields of an `ExtendedCircuit` that a `Circuit` does not have, each of
+/// which is unknown for circuits measured before they were collected.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ExtendedFields {
+    /// The hex-encoded fingerprint of the relay that observed the circuit.
+    pub relay_fingerprint: Option<FixedAscii<40>>,
+    /// The ID of the measurement run during which the circuit was observed.
+    pub run_id: Option<u32>,
+    /// A coarse bucket of the location of the client that built the circuit.
+    pub geo_bucket: Option<u16>,
 }
 
-/// A modified version of a Tor circuit used for augmentation purposes.
+/// A `Circuit` with the additional meta-data of later measurement campaigns.
+/// An unknown fingerprint is stored as an empty string, which no relay has, and
+/// the numeric fields have flags recording whether they are known, so that 0
+/// remains a valid run ID and bucket. The other field names match
+/// `Circuit`, so `Circuit` and `CircuitMeta` can also be read from a dataset
+/// of this type. See the `schema` module for how such datasets are versioned.
 #[derive(H5Type, Clone, Copy, PartialEq, Debug)]
 #[repr(C)]
-pub struct AugmentedCircuit {
+pub struct ExtendedCircuit<const N: usize = MAX_CELLS> {
+    pub uuid: FixedAscii<32>,
+    pub domain: FixedAscii<44>,
+    pub shortest_private_suffix: FixedAscii<44>,
+    pub day: u8,
+    pub port: u16,
+    pub len: u16,
+    /// The hex-encoded fingerprint of the relay that observed the circuit, or
+    /// empty if unknown.
+    pub relay_fingerprint: FixedAscii<40>,
+    /// The ID of the measurement run, or 0 if unknown.
+    pub run_id: u32,
+    /// Whether `run_id` is known.
+    pub has_run_id: bool,
+    /// The bucket of the client's location, or 0 if unknown.
+    pub geo_bucket: u16,
+    /// Whether `geo_bucket` is known.
+    pub has_geo_bucket: bool,
+    /// The same meaning as `Circuit.cells`.
+    pub cells: [Cell; N],
+}
+
+impl<const N: usize> ExtendedCircuit<N> {
+    /// Creates an `ExtendedCircuit` with the meta-data and cells of `circuit`
+    /// and the additional `fields`.
+    pub fn from_circuit(circuit: &Circuit<N>, fields: ExtendedFields) -> Self {
+        let mut extended = Self {
+            uuid: circuit.uuid,
+            domain: circuit.domain,
+            shortest_private_suffix: circuit.shortest_private_suffix,
+            day: circuit.day,
+            port: circuit.port,
+            len: circuit.len,
+            relay_fingerprint: fixedascii_null::<40>().unwrap(),
+            run_id: 0,
+            has_run_id: false,
+            geo_bucket: 0,
+            has_geo_bucket: false,
+            cells: circuit.cells,
+        };
+        extended.set_fields(fields);
+        extended
+    }
+
+    /// Replaces the additional fields of the circuit with `fields`.
+    pub fn set_fields(&mut self, fields: ExtendedFields) {
+        self.relay_fingerprint = fields
+            .relay_fingerprint
+            .unwrap_or_else(|| fixedascii_null::<40>().unwrap());
+        self.run_id = fields.run_id.unwrap_or(0);
+        self.has_run_id = fields.run_id.is_some();
+        self.geo_bucket = fields.geo_bucket.unwrap_or(0);
+        self.has_geo_bucket = fields.geo_bucket.is_some();
+    }
+
+    /// The additional fields of the circuit, with unknown fields as `None`.
+    pub fn fields(&self) -> ExtendedFields {
+        ExtendedFields {
+            relay_fingerprint: Some(self.relay_fingerprint).filter(|f| !f.is_empty()),
+            run_id: Some(self.run_id).filter(|_| self.has_run_id),
+            geo_bucket: Some(self.geo_bucket).filter(|_| self.has_geo_bucket),
+        }
+    }
+
+    /// Returns the `Circuit` with the same meta-data and cells, dropping the
+    /// additional fields.
+    pub fn to_circuit(&self) -> Circuit<N> {
+        Circuit {
+            uuid: self.uuid,
+            domain: self.domain,
+            shortest_private_suffix: self.shortest_private_suffix,
+            day: self.day,
+            port: self.port,
+            len: self.len,
+            cells: self.cells,
+        }
+    }
+}
+
+impl<const N: usize> From<&Circuit<N>> for ExtendedCircuit<N> {
+    fn from(circuit: &Circuit<N>) -> Self {
+        Self::from_circuit(circuit, ExtendedFields::default())
+    }
+}
+
+/// A modified version of a Tor circuit used for augmentation purposes, holding
+/// up to `N` cells like `Circuit`.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct AugmentedCircuit<const N: usize = MAX_CELLS> {
     pub uuid: FixedAscii<32>,
     /// The UUID of the `Circuit` from which this `AugmentedCircuit` was created.
     pub uuid_gtt23: FixedAscii<32>,
@@ -272,22 +666,49 @@ pub struct AugmentedCircuit {
     /// The same meaning as `Circuit.len`.
     pub len: u16,
     /// The same meaning as `Circuit.cells`.
-    pub cells: [Cell; 5000],
+    pub cells: [Cell; N],
 }
 
 impl AugmentedCircuit {
-    /// Creates an empty `AugmentedCircuit` with all meta-data zeroed out.
+    /// Creates an empty `AugmentedCircuit` with all meta-data zeroed out. Use
+    /// `AugmentedCircuit::<N>::default` for circuits with a different number
+    /// of cells.
     pub fn empty() -> Self {
+        Self::default()
+    }
+}
+
+impl<const N: usize> Default for AugmentedCircuit<N> {
+    fn default() -> Self {
         Self {
             uuid: fixedascii_null::<32>().unwrap(),
             uuid_gtt23: fixedascii_null::<32>().unwrap(),
             aug_index: 0,
             len: 0,
-            cells: [Cell::empty(); 5000],
+            cells: [Cell::empty(); N],
         }
     }
 }
 
+impl<const N: usize> AugmentedCircuit<N> {
+    /// Creates an `AugmentedCircuit` with `uuid` that copies the cells of the
+    /// GTT23 `circuit`, linked to it as augmentation number `aug_index`.
+    pub fn from_circuit(circuit: &Circuit<N>, uuid: FixedAscii<32>, aug_index: u16) -> Self {
+        Self {
+            uuid,
+            uuid_gtt23: circuit.uuid,
+            aug_index,
+            len: circuit.len,
+            cells: circuit.cells,
+        }
+    }
+
+    /// The valid cells of the circuit, i.e., `cells[0..len]`.
+    pub fn valid_cells(&self) -> &[Cell] {
+        &self.cells[..std::cmp::min(self.len as usize, self.cells.len())]
+    }
+}
+
 /// An integer index into an array of Circuits. Requires that the length of the
 /// Circuit array is less than 2**32.
 pub type CircuitIndex = u32;
@@ -317,6 +738,20 @@ pub struct IndexArrayEntry<T: H5Type> {
     pub indexarr: VarLenArray<CircuitIndex>,
 }
 
+/// An entry of the label vocabulary stored in the `/labels` dataset, which
+/// assigns each label an integer class id.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct LabelEntry {
+    /// The class id of the label. Ids are assigned to the labels in sorted
+    /// order starting from 0, so the entry with id `k` is at position `k`.
+    pub id: u32,
+    /// The label, as returned by `Circuit::label`.
+    pub label: FixedAscii<44>,
+    /// The number of circuits with the label.
+    pub count: u32,
+}
+
 /// A helper to converts `s` to a FixedAscii type, truncating `s` or
 /// right-padding with 0x0 to meet the desired fixed length.
 pub fn fixedascii_from_str<const N: usize>(s: &str) -> Result<FixedAscii<N>, StringError> {
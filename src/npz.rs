@@ -0,0 +1,519 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Writes a NumPy `.npz` archive whose arrays are appended to one row at a
+/// time, so that arrays larger than memory can be written.
+///
+/// Rows are spooled to temporary files in a `<path>.spool` directory, and the
+/// archive is assembled by `finish` once the number of rows is known. Arrays
+/// are stored uncompressed (as `np.savez` does) using zip64 so that they may
+/// exceed 4 GiB.
+pub struct NpzWriter {
+    path: PathBuf,
+    spool_dir: PathBuf,
+    arrays: Vec<SpooledArray>,
+}
+
+struct SpooledArray {
+    name: String,
+    descr: String,
+    row_shape: Vec<usize>,
+    row_bytes: usize,
+    rows: usize,
+    spool_path: PathBuf,
+    spool: BufWriter<File>,
+}
+
+impl NpzWriter {
+    /// Creates a writer for the archive at `path`.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut spool_dir = path.clone().into_os_string();
+        spool_dir.push(".spool");
+        let spool_dir = PathBuf::from(spool_dir);
+        fs::create_dir_all(&spool_dir)?;
+
+        Ok(Self {
+            path,
+            spool_dir,
+            arrays: Vec::new(),
+        })
+    }
+
+    /// Adds an array named `name` whose elements have the NumPy type string
+    /// `descr` (e.g., `<f8` or `|S32`) and whose rows have shape `row_shape`.
+    /// Returns an id to pass to `append`.
+    pub fn add_array(&mut self, name: &str, descr: &str, row_shape: &[usize]) -> io::Result<usize> {
+        let item_size = item_size(descr)?;
+        let spool_path = self.spool_dir.join(format!("{}.bin", self.arrays.len()));
+        let spool = BufWriter::new(File::create(&spool_path)?);
+
+        self.arrays.push(SpooledArray {
+            name: name.to_string(),
+            descr: descr.to_string(),
+            row_shape: row_shape.to_vec(),
+            row_bytes: item_size * row_shape.iter().product::<usize>(),
+            rows: 0,
+            spool_path,
+            spool,
+        });
+
+        Ok(self.arrays.len() - 1)
+    }
+
+    /// Appends one row to array `id`. `row` holds the little-endian bytes of
+    /// the row's elements in C order.
+    pub fn append(&mut self, id: usize, row: &[u8]) -> io::Result<()> {
+        let array = &mut self.arrays[id];
+        if row.len() != array.row_bytes {
+            return Err(io::Error::other(format!(
+                "Row of {} bytes does not match array '{}' with {} bytes per row",
+                row.len(),
+                array.name,
+                array.row_bytes
+            )));
+        }
+
+        array.spool.write_all(row)?;
+        array.rows += 1;
+        Ok(())
+    }
+
+    /// Assembles the archive and removes the spooled rows.
+    pub fn finish(self) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(&self.path)?);
+        let mut central = Vec::new();
+        let mut offset = 0u64;
+
+        for mut array in self.arrays {
+            array.spool.flush()?;
+            drop(array.spool);
+
+            let header = npy_header(&array.descr, array.rows, &array.row_shape, 0);
+            let size = header.len() as u64 + fs::metadata(&array.spool_path)?.len();
+
+            // One pass to checksum, and one to copy.
+            let mut crc = Crc32::new();
+            crc.update(&header);
+            copy_spool(&array.spool_path, |buf| {
+                crc.update(buf);
+                Ok(())
+            })?;
+            let crc = crc.finish();
+
+            let name = format!("{}.npy", array.name);
+            let header_offset = offset;
+            offset += write_local_header(&mut out, &name, crc, size)?;
+            out.write_all(&header)?;
+            copy_spool(&array.spool_path, |buf| out.write_all(buf))?;
+            offset += size;
+
+            central.push((name, crc, size, header_offset));
+            fs::remove_file(&array.spool_path)?;
+        }
+
+        write_central_directory(&mut out, &central, offset)?;
+        out.flush()?;
+
+        fs::remove_dir(&self.spool_dir)
+    }
+}
+
+/// Writes a NumPy `.npy` file holding a single array whose rows are appended
+/// one at a time, so that arrays larger than memory can be written.
+///
+/// The header is written with room for any number of rows and is rewritten
+/// by `finish` once the number of rows is known.
+pub struct NpyWriter {
+    out: BufWriter<File>,
+    descr: String,
+    row_shape: Vec<usize>,
+    row_bytes: usize,
+    rows: usize,
+    header_len: usize,
+}
+
+impl NpyWriter {
+    /// Creates a writer for the file at `path` holding an array whose elements
+    /// have the NumPy type string `descr` (e.g., `<f8`) and whose rows have
+    /// shape `row_shape`.
+    pub fn create<P: AsRef<Path>>(path: P, descr: &str, row_shape: &[usize]) -> io::Result<Self> {
+        let row_bytes = item_size(descr)? * row_shape.iter().product::<usize>();
+        let header = npy_header(descr, usize::MAX, row_shape, 0);
+
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&header)?;
+
+        Ok(Self {
+            out,
+            descr: descr.to_string(),
+            row_shape: row_shape.to_vec(),
+            row_bytes,
+            rows: 0,
+            header_len: header.len(),
+        })
+    }
+
+    /// Appends one row to the array. `row` holds the little-endian bytes of
+    /// the row's elements in C order.
+    pub fn append(&mut self, row: &[u8]) -> io::Result<()> {
+        if row.len() != self.row_bytes {
+            return Err(io::Error::other(format!(
+                "Row of {} bytes does not match array with {} bytes per row",
+                row.len(),
+                self.row_bytes
+            )));
+        }
+
+        self.out.write_all(row)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Rewrites the header with the final number of rows.
+    pub fn finish(mut self) -> io::Result<()> {
+        let header = npy_header(&self.descr, self.rows, &self.row_shape, self.header_len);
+        self.out.seek(SeekFrom::Start(0))?;
+        self.out.write_all(&header)?;
+        self.out.flush()
+    }
+}
+
+/// The size in bytes of the elements of NumPy type string `descr`.
+fn item_size(descr: &str) -> io::Result<usize> {
+    descr
+        .get(2..)
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| io::Error::other(format!("Unsupported npy descr '{descr}'")))
+}
+
+/// Builds the `.npy` v1.0 header for an array of `rows` rows of `row_shape`,
+/// padded to at least `min_len` bytes. A `descr` starting with `[` is written
+/// as a structured dtype list, e.g., `[('x', '<f4', (2,)), ('y', '<i8')]`.
+pub(crate) fn npy_header(descr: &str, rows: usize, row_shape: &[usize], min_len: usize) -> Vec<u8> {
+    let mut shape: Vec<String> = vec![rows.to_string()];
+    shape.extend(row_shape.iter().map(|d| d.to_string()));
+    let shape = match shape.len() {
+        1 => format!("({},)", shape[0]),
+        _ => format!("({})", shape.join(", ")),
+    };
+
+    let descr = if descr.starts_with('[') {
+        descr.to_string()
+    } else {
+        format!("'{descr}'")
+    };
+
+    let mut dict = format!("{{'descr': {descr}, 'fortran_order': False, 'shape': {shape}, }}");
+    // Pad so that the data starts on a 64-byte boundary.
+    let unpadded = 10 + dict.len() + 1;
+    let padded = std::cmp::max(unpadded.next_multiple_of(64), min_len);
+    dict.push_str(&" ".repeat(padded - unpadded));
+    dict.push('\n');
+
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header
+}
+
+fn copy_spool<F: FnMut(&[u8]) -> io::Result<()>>(path: &Path, mut f: F) -> io::Result<()> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buf = vec![0u8; 1 << 16];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        f(&buf[..n])?;
+    }
+}
+
+const ZIP_VERSION: u16 = 45; // zip64
+const ZIP64_MARKER: u32 = u32::MAX;
+
+/// Writes a zip64 local file header for a stored entry, returning its length.
+fn write_local_header<W: Write>(out: &mut W, name: &str, crc: u32, size: u64) -> io::Result<u64> {
+    out.write_all(&0x04034b50u32.to_le_bytes())?;
+    out.write_all(&ZIP_VERSION.to_le_bytes())?;
+    out.write_all(&0u16.to_le_bytes())?; // flags
+    out.write_all(&0u16.to_le_bytes())?; // method: stored
+    out.write_all(&0u16.to_le_bytes())?; // mod time
+    out.write_all(&0x21u16.to_le_bytes())?; // mod date: 1980-01-01
+    out.write_all(&crc.to_le_bytes())?;
+    out.write_all(&ZIP64_MARKER.to_le_bytes())?;
+    out.write_all(&ZIP64_MARKER.to_le_bytes())?;
+    out.write_all(&(name.len() as u16).to_le_bytes())?;
+    out.write_all(&20u16.to_le_bytes())?; // extra length
+    out.write_all(name.as_bytes())?;
+    // zip64 extra field: uncompressed and compressed sizes.
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+    out.write_all(&size.to_le_bytes())?;
+    out.write_all(&size.to_le_bytes())?;
+    Ok(30 + name.len() as u64 + 20)
+}
+
+/// Writes the central directory and the zip64 end records.
+fn write_central_directory<W: Write>(
+    out: &mut W,
+    entries: &[(String, u32, u64, u64)],
+    cd_offset: u64,
+) -> io::Result<()> {
+    let mut cd_size = 0u64;
+
+    for (name, crc, size, offset) in entries {
+        out.write_all(&0x02014b50u32.to_le_bytes())?;
+        out.write_all(&ZIP_VERSION.to_le_bytes())?; // made by
+        out.write_all(&ZIP_VERSION.to_le_bytes())?; // needed
+        out.write_all(&0u16.to_le_bytes())?; // flags
+        out.write_all(&0u16.to_le_bytes())?; // method: stored
+        out.write_all(&0u16.to_le_bytes())?; // mod time
+        out.write_all(&0x21u16.to_le_bytes())?; // mod date
+        out.write_all(&crc.to_le_bytes())?;
+        out.write_all(&ZIP64_MARKER.to_le_bytes())?;
+        out.write_all(&ZIP64_MARKER.to_le_bytes())?;
+        out.write_all(&(name.len() as u16).to_le_bytes())?;
+        out.write_all(&28u16.to_le_bytes())?; // extra length
+        out.write_all(&0u16.to_le_bytes())?; // comment length
+        out.write_all(&0u16.to_le_bytes())?; // disk number
+        out.write_all(&0u16.to_le_bytes())?; // internal attributes
+        out.write_all(&0u32.to_le_bytes())?; // external attributes
+        out.write_all(&ZIP64_MARKER.to_le_bytes())?; // local header offset
+        out.write_all(name.as_bytes())?;
+        // zip64 extra field: sizes and local header offset.
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&24u16.to_le_bytes())?;
+        out.write_all(&size.to_le_bytes())?;
+        out.write_all(&size.to_le_bytes())?;
+        out.write_all(&offset.to_le_bytes())?;
+        cd_size += 46 + name.len() as u64 + 28;
+    }
+
+    let n = entries.len() as u64;
+    let eocd64_offset = cd_offset + cd_size;
+
+    // zip64 end of central directory record
+    out.write_all(&0x06064b50u32.to_le_bytes())?;
+    out.write_all(&44u64.to_le_bytes())?;
+    out.write_all(&ZIP_VERSION.to_le_bytes())?;
+    out.write_all(&ZIP_VERSION.to_le_bytes())?;
+    out.write_all(&0u32.to_le_bytes())?;
+    out.write_all(&0u32.to_le_bytes())?;
+    out.write_all(&n.to_le_bytes())?;
+    out.write_all(&n.to_le_bytes())?;
+    out.write_all(&cd_size.to_le_bytes())?;
+    out.write_all(&cd_offset.to_le_bytes())?;
+
+    // zip64 end of central directory locator
+    out.write_all(&0x07064b50u32.to_le_bytes())?;
+    out.write_all(&0u32.to_le_bytes())?;
+    out.write_all(&eocd64_offset.to_le_bytes())?;
+    out.write_all(&1u32.to_le_bytes())?;
+
+    // end of central directory record, deferring to the zip64 record
+    out.write_all(&0x06054b50u32.to_le_bytes())?;
+    out.write_all(&0u16.to_le_bytes())?;
+    out.write_all(&0u16.to_le_bytes())?;
+    out.write_all(&u16::MAX.to_le_bytes())?;
+    out.write_all(&u16::MAX.to_le_bytes())?;
+    out.write_all(&ZIP64_MARKER.to_le_bytes())?;
+    out.write_all(&ZIP64_MARKER.to_le_bytes())?;
+    out.write_all(&0u16.to_le_bytes())?;
+    Ok(())
+}
+
+/// The CRC-32 checksum used by zip.
+struct Crc32 {
+    table: [u32; 256],
+    value: u32,
+}
+
+impl Crc32 {
+    fn new() -> Self {
+        let mut table = [0u32; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut c = i as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 {
+                    0xedb88320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+            }
+            *entry = c;
+        }
+        Self {
+            table,
+            value: u32::MAX,
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.value = self.table[((self.value ^ b as u32) & 0xff) as usize] ^ (self.value >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        self.value ^ u32::MAX
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = Crc32::new();
+        crc.update(bytes);
+        crc.finish()
+    }
+
+    #[test]
+    fn crc32_matches_known_vectors() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414fa339
+        );
+
+        let mut crc = Crc32::new();
+        crc.update(b"12345");
+        crc.update(b"6789");
+        assert_eq!(crc.finish(), 0xcbf43926);
+    }
+
+    #[test]
+    fn npy_header_matches_numpy() {
+        // The header that `np.save` writes for `np.zeros(3)`.
+        let mut expected = b"\x93NUMPY\x01\x00\x76\x00".to_vec();
+        expected.extend_from_slice(b"{'descr': '<f8', 'fortran_order': False, 'shape': (3,), }");
+        expected.resize(127, b' ');
+        expected.push(b'\n');
+        assert_eq!(npy_header("<f8", 3, &[], 0), expected);
+    }
+
+    #[test]
+    fn npy_header_shapes_and_padding() {
+        let header = npy_header("[('x', '<f4', (2,)), ('y', '<i8')]", 5, &[4, 2], 256);
+        assert_eq!(header.len(), 256);
+        assert_eq!(u16_at(&header, 8) as usize, 256 - 10);
+        let dict = std::str::from_utf8(&header[10..]).unwrap();
+        assert!(dict.starts_with("{'descr': [('x', '<f4', (2,)), ('y', '<i8')], "));
+        assert!(dict.contains("'shape': (5, 4, 2), }"));
+        assert!(dict.ends_with(" \n"));
+    }
+
+    #[test]
+    fn npz_round_trip() {
+        let path = std::env::temp_dir().join(format!("gtt23-{}-npz-test.npz", std::process::id()));
+        let mut npz = NpzWriter::create(&path).unwrap();
+        let x = npz.add_array("x", "<f8", &[2]).unwrap();
+        let y = npz.add_array("y", "|u1", &[]).unwrap();
+        let mut x_data = Vec::new();
+        for row in 0..3u8 {
+            let bytes: Vec<u8> = [f64::from(row), -f64::from(row)]
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect();
+            npz.append(x, &bytes).unwrap();
+            npz.append(y, &[row]).unwrap();
+            x_data.extend(bytes);
+        }
+        assert!(npz.append(y, &[0, 0]).is_err());
+        npz.finish().unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let entries = [
+            ("x.npy", [npy_header("<f8", 3, &[2], 0), x_data].concat()),
+            (
+                "y.npy",
+                [npy_header("|u1", 3, &[], 0), vec![0, 1, 2]].concat(),
+            ),
+        ];
+
+        // The local file headers, each followed by the stored entry.
+        let mut at = 0;
+        let mut offsets = Vec::new();
+        for (name, contents) in entries.iter() {
+            assert_eq!(u32_at(&bytes, at), 0x04034b50);
+            assert_eq!(u16_at(&bytes, at + 8), 0);
+            assert_eq!(u32_at(&bytes, at + 14), crc32(contents));
+            let name_len = u16_at(&bytes, at + 26) as usize;
+            let extra_len = u16_at(&bytes, at + 28) as usize;
+            assert_eq!(&bytes[at + 30..at + 30 + name_len], name.as_bytes());
+            let extra = at + 30 + name_len;
+            assert_eq!(u16_at(&bytes, extra), 1);
+            assert_eq!(u64_at(&bytes, extra + 4), contents.len() as u64);
+            assert_eq!(u64_at(&bytes, extra + 12), contents.len() as u64);
+            let data = extra + extra_len;
+            assert_eq!(&bytes[data..data + contents.len()], &contents[..]);
+            offsets.push(at as u64);
+            at = data + contents.len();
+        }
+
+        // The central directory, pointing back at the local headers.
+        let cd_offset = at as u64;
+        for ((name, contents), offset) in entries.iter().zip(offsets) {
+            assert_eq!(u32_at(&bytes, at), 0x02014b50);
+            assert_eq!(u32_at(&bytes, at + 16), crc32(contents));
+            assert_eq!(u32_at(&bytes, at + 42), u32::MAX);
+            let name_len = u16_at(&bytes, at + 28) as usize;
+            let extra_len = u16_at(&bytes, at + 30) as usize;
+            assert_eq!(&bytes[at + 46..at + 46 + name_len], name.as_bytes());
+            let extra = at + 46 + name_len;
+            assert_eq!(u16_at(&bytes, extra), 1);
+            assert_eq!(u64_at(&bytes, extra + 4), contents.len() as u64);
+            assert_eq!(u64_at(&bytes, extra + 20), offset);
+            at = extra + extra_len;
+        }
+        let cd_size = at as u64 - cd_offset;
+
+        // The zip64 end records and the end of central directory record.
+        let eocd64 = at;
+        assert_eq!(u32_at(&bytes, eocd64), 0x06064b50);
+        assert_eq!(u64_at(&bytes, eocd64 + 32), entries.len() as u64);
+        assert_eq!(u64_at(&bytes, eocd64 + 40), cd_size);
+        assert_eq!(u64_at(&bytes, eocd64 + 48), cd_offset);
+        let locator = eocd64 + 56;
+        assert_eq!(u32_at(&bytes, locator), 0x07064b50);
+        assert_eq!(u64_at(&bytes, locator + 8), eocd64 as u64);
+        let eocd = locator + 20;
+        assert_eq!(u32_at(&bytes, eocd), 0x06054b50);
+        assert_eq!(bytes.len(), eocd + 22);
+    }
+
+    #[test]
+    fn npy_round_trip() {
+        let path = std::env::temp_dir().join(format!("gtt23-{}-npy-test.npy", std::process::id()));
+        let mut npy = NpyWriter::create(&path, "<i8", &[]).unwrap();
+        for value in [1i64, -2, 3] {
+            npy.append(&value.to_le_bytes()).unwrap();
+        }
+        npy.finish().unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // The header keeps the room reserved for any number of rows.
+        let header = npy_header("<i8", 3, &[], npy_header("<i8", usize::MAX, &[], 0).len());
+        assert_eq!(&bytes[..header.len()], &header[..]);
+        let data: Vec<u8> = [1i64, -2, 3].iter().flat_map(|v| v.to_le_bytes()).collect();
+        assert_eq!(&bytes[header.len()..], &data[..]);
+    }
+}
@@ -0,0 +1,184 @@
+use hdf5::types::FixedAscii;
+use hdf5::{File, H5Type};
+use ndarray::Array1;
+
+use crate::defense::Overhead;
+use crate::index;
+use crate::writer::CircuitWriter;
+use crate::{AugmentedCircuit, Cell, Circuit};
+
+/// The name of the dataset holding the overhead of each defended circuit.
+pub const OVERHEAD_NAME: &str = "/overhead";
+
+/// The number of circuits read at a time when comparing datasets.
+const READ_BATCH: usize = 1_000;
+
+/// The overhead of a defended or augmented circuit over the GTT23 circuit it
+/// was created from.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct OverheadRecord {
+    /// The uuid of the defended circuit.
+    pub uuid: FixedAscii<32>,
+    /// The uuid of the original GTT23 circuit.
+    pub uuid_gtt23: FixedAscii<32>,
+    pub real_cells: u32,
+    pub dummy_cells: u32,
+    pub duration: f64,
+    pub defended_duration: f64,
+}
+
+impl OverheadRecord {
+    /// Creates the record of the circuit with `uuid` and valid cells `defended`
+    /// that was created from `original`.
+    pub fn new(uuid: FixedAscii<32>, original: &Circuit, defended: &[Cell]) -> Self {
+        let overhead = Overhead::between(original.valid_cells(), defended);
+        Self {
+            uuid,
+            uuid_gtt23: original.uuid,
+            real_cells: overhead.real_cells as u32,
+            dummy_cells: overhead.dummy_cells as u32,
+            duration: overhead.duration,
+            defended_duration: overhead.defended_duration,
+        }
+    }
+
+    /// The record of `augmented`, which was created from `original`.
+    pub fn augmented(original: &Circuit, augmented: &AugmentedCircuit) -> Self {
+        Self::new(augmented.uuid, original, augmented.valid_cells())
+    }
+
+    pub fn overhead(&self) -> Overhead {
+        Overhead {
+            real_cells: self.real_cells as usize,
+            dummy_cells: self.dummy_cells as usize,
+            duration: self.duration,
+            defended_duration: self.defended_duration,
+        }
+    }
+}
+
+/// Appends overhead records to the `/overhead` dataset of a file, and stores
+/// their aggregate overhead in the `real_cells`, `dummy_cells`, `bandwidth`,
+/// and `latency` attributes of the dataset when finished.
+pub struct OverheadWriter {
+    writer: CircuitWriter<OverheadRecord>,
+    total: Overhead,
+}
+
+impl OverheadWriter {
+    /// Creates an empty `/overhead` dataset in `file`.
+    pub fn create(file: &File) -> hdf5::Result<Self> {
+        Ok(Self {
+            writer: CircuitWriter::create(file, OVERHEAD_NAME)?,
+            total: Overhead::default(),
+        })
+    }
+
+    /// Appends `record`.
+    pub fn push(&mut self, record: OverheadRecord) -> hdf5::Result<()> {
+        self.total = [self.total, record.overhead()].into_iter().sum();
+        self.writer.push(record)
+    }
+
+    /// Writes all buffered records and the aggregate attributes, and returns
+    /// the aggregate overhead.
+    pub fn finish(self) -> hdf5::Result<Overhead> {
+        let dataset = self.writer.finish()?;
+        for (name, value) in [
+            ("real_cells", self.total.real_cells as u64),
+            ("dummy_cells", self.total.dummy_cells as u64),
+        ] {
+            dataset
+                .new_attr::<u64>()
+                .create(name)?
+                .write_scalar(&value)?;
+        }
+        for (name, value) in [
+            ("bandwidth", self.total.bandwidth()),
+            ("latency", self.total.latency()),
+        ] {
+            dataset
+                .new_attr::<f64>()
+                .create(name)?
+                .write_scalar(&value)?;
+        }
+        Ok(self.total)
+    }
+}
+
+/// The overhead of the circuits of a defended dataset over the original
+/// circuits they were created from.
+#[derive(Clone, Debug, Default)]
+pub struct OverheadReport {
+    /// The overhead of each defended circuit whose original circuit was found.
+    pub records: Vec<OverheadRecord>,
+    /// The aggregate overhead of the records.
+    pub total: Overhead,
+    /// The number of defended circuits whose original circuit was not found.
+    pub missing: usize,
+}
+
+/// Compares every circuit of the defended dataset in `defended` with the
+/// circuit in the `/circuits` dataset of `original` it was created from. The
+/// defended circuits are those of the `/augmented` dataset, linked to their
+/// originals by `uuid_gtt23`, or, if there is none, those of the `/circuits`
+/// dataset, linked by `uuid`, e.g., as written by the transform example.
+/// `progress` is called with the number of defended circuits compared after
+/// each batch.
+pub fn compare<F>(original: &File, defended: &File, mut progress: F) -> hdf5::Result<OverheadReport>
+where
+    F: FnMut(usize),
+{
+    let originals = original.dataset("/circuits")?;
+    let indices = index::read_uuid_indices(original)?;
+    let mut report = OverheadReport::default();
+    let mut cached: Option<Circuit> = None;
+
+    let mut add = |uuid: FixedAscii<32>, uuid_gtt23: FixedAscii<32>, cells: &[Cell]| {
+        if cached.as_ref().is_none_or(|c| c.uuid != uuid_gtt23) {
+            cached = match indices.get(&uuid_gtt23) {
+                Some(&i) => {
+                    let i = i as usize;
+                    let circuit: Array1<Circuit> = originals.read_slice(ndarray::s![i..i + 1])?;
+                    circuit.into_iter().next()
+                }
+                None => None,
+            };
+        }
+        match &cached {
+            Some(circuit) => report
+                .records
+                .push(OverheadRecord::new(uuid, circuit, cells)),
+            None => report.missing += 1,
+        }
+        hdf5::Result::Ok(())
+    };
+
+    if defended.link_exists("/augmented") {
+        let dataset = defended.dataset("/augmented")?;
+        let size = dataset.size();
+        for begin in (0..size).step_by(READ_BATCH) {
+            let end = std::cmp::min(begin + READ_BATCH, size);
+            let batch: Array1<AugmentedCircuit> = dataset.read_slice(ndarray::s![begin..end])?;
+            for c in batch.iter() {
+                add(c.uuid, c.uuid_gtt23, c.valid_cells())?;
+            }
+            progress(end - begin);
+        }
+    } else {
+        let dataset = defended.dataset("/circuits")?;
+        let size = dataset.size();
+        for begin in (0..size).step_by(READ_BATCH) {
+            let end = std::cmp::min(begin + READ_BATCH, size);
+            let batch: Array1<Circuit> = dataset.read_slice(ndarray::s![begin..end])?;
+            for c in batch.iter() {
+                add(c.uuid, c.uuid, c.valid_cells())?;
+            }
+            progress(end - begin);
+        }
+    }
+
+    report.total = report.records.iter().map(|r| r.overhead()).sum();
+    Ok(report)
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use hdf5::types::FixedAscii;
+use hdf5::{File, H5Type};
+use ndarray::{Array1, Array2};
+
+use crate::features::{mean, median, std_dev};
+use crate::index::write_index;
+use crate::{CircuitIndex, CircuitMeta};
+
+/// The name of the dataset holding the trace-length profile of each label.
+pub const LENGTHS_NAME: &str = "/profiles/lengths";
+
+/// The number of circuits read at a time when grouping circuits by label.
+const READ_BATCH: usize = 10_000;
+
+/// Statistics of the lengths of the circuits with one label.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct LengthProfile {
+    pub label: FixedAscii<44>,
+    pub count: u32,
+    pub min: u16,
+    pub max: u16,
+    pub mean: f64,
+    pub median: f64,
+    /// The population standard deviation.
+    pub std: f64,
+}
+
+impl LengthProfile {
+    fn new(label: FixedAscii<44>, lens: &[u16]) -> Self {
+        let values: Vec<f64> = lens.iter().map(|&l| f64::from(l)).collect();
+        Self {
+            label,
+            count: lens.len() as u32,
+            min: lens.iter().copied().min().unwrap_or(0),
+            max: lens.iter().copied().max().unwrap_or(0),
+            mean: mean(&values),
+            median: median(&values),
+            std: std_dev(&values),
+        }
+    }
+}
+
+/// The circuits with one label.
+struct LabelGroup {
+    label: FixedAscii<44>,
+    indices: Vec<CircuitIndex>,
+    lens: Vec<u16>,
+}
+
+/// The name of the group holding the profiles of the features dataset `name`,
+/// i.e., `/profiles/<base>` where `<base>` is the last component of `name`.
+pub fn profile_group_name(name: &str) -> String {
+    let base = name.rsplit('/').find(|s| !s.is_empty()).unwrap_or(name);
+    format!("/profiles/{base}")
+}
+
+/// Computes per-label profiles of the circuits in the `/circuits` dataset of
+/// `file` and writes them under `/profiles`, replacing any existing profiles
+/// with the same names. Returns the number of labels.
+///
+/// `/profiles/lengths` holds the `LengthProfile` of each label. For each of
+/// the two dimensional features datasets in `features`, e.g., `/features/cumul`
+/// as written by `FeatureWriter`, `profile_group_name(name)` holds the datasets
+/// `mean` and `median`, whose rows are the element-wise mean and median of the
+/// feature vectors of the circuits with each label. Row `k` of every profile
+/// belongs to the `k`th label in sorted order, which matches the class ids of
+/// the `/labels` vocabulary when it was written from the same circuits.
+pub fn write_profiles(file: &File, features: &[&str]) -> hdf5::Result<usize> {
+    let groups = group_by_label(file)?;
+
+    let lengths: Vec<LengthProfile> = groups
+        .iter()
+        .map(|g| LengthProfile::new(g.label, &g.lens))
+        .collect();
+    write_index(file, LENGTHS_NAME, &lengths)?;
+
+    for name in features {
+        let (means, medians) = feature_profiles(file, name, &groups)?;
+        let group = profile_group_name(name);
+        write_matrix(file, &format!("{group}/mean"), &means)?;
+        write_matrix(file, &format!("{group}/median"), &medians)?;
+    }
+
+    Ok(groups.len())
+}
+
+/// Reads the label and length of every circuit in `file`, and returns the
+/// circuits grouped by label, sorted by label.
+fn group_by_label(file: &File) -> hdf5::Result<Vec<LabelGroup>> {
+    let circuits = file.dataset("/circuits")?;
+    let size = circuits.size();
+    let mut groups: HashMap<FixedAscii<44>, LabelGroup> = HashMap::new();
+
+    for begin in (0..size).step_by(READ_BATCH) {
+        let end = std::cmp::min(begin + READ_BATCH, size);
+        let metas: Array1<CircuitMeta> = circuits.read_slice(ndarray::s![begin..end])?;
+        for (i, meta) in metas.iter().enumerate() {
+            let label = meta.label();
+            let group = groups.entry(label).or_insert_with(|| LabelGroup {
+                label,
+                indices: Vec::new(),
+                lens: Vec::new(),
+            });
+            group.indices.push((begin + i) as CircuitIndex);
+            group.lens.push(meta.len);
+        }
+    }
+
+    let mut groups: Vec<LabelGroup> = groups.into_values().collect();
+    groups.sort_by(|a, b| a.label.as_str().cmp(b.label.as_str()));
+    Ok(groups)
+}
+
+/// Returns the per-label mean and median rows of the features dataset `name`.
+fn feature_profiles(
+    file: &File,
+    name: &str,
+    groups: &[LabelGroup],
+) -> hdf5::Result<(Array2<f64>, Array2<f64>)> {
+    let dataset = file.dataset(name)?;
+    let shape = dataset.shape();
+    if shape.len() != 2 {
+        return Err(format!("Features dataset {name} is not two dimensional").into());
+    }
+    let width = shape[1];
+
+    let mut means = Array2::zeros((groups.len(), width));
+    let mut medians = Array2::zeros((groups.len(), width));
+
+    for (k, group) in groups.iter().enumerate() {
+        // The indices are ascending, so read each run of consecutive circuits
+        // with a single selection.
+        let mut rows: Vec<f64> = Vec::with_capacity(group.indices.len() * width);
+        for (begin, end) in runs(&group.indices) {
+            let slice = dataset.read_slice_2d::<f64, _>(ndarray::s![begin..end, ..])?;
+            rows.extend(slice.iter());
+        }
+
+        let count = group.indices.len();
+        let mut column = Vec::with_capacity(count);
+        for j in 0..width {
+            column.clear();
+            column.extend((0..count).map(|r| rows[r * width + j]));
+            means[[k, j]] = mean(&column);
+            medians[[k, j]] = median(&column);
+        }
+    }
+
+    Ok((means, medians))
+}
+
+/// Returns the half-open ranges of consecutive values in the ascending
+/// `indices`.
+fn runs(indices: &[CircuitIndex]) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for &i in indices {
+        let i = i as usize;
+        match runs.last_mut() {
+            Some((_, end)) if *end == i => *end += 1,
+            _ => runs.push((i, i + 1)),
+        }
+    }
+    runs
+}
+
+/// Writes `values` to the dataset `name` in `file`, replacing any existing
+/// dataset with that name.
+fn write_matrix(file: &File, name: &str, values: &Array2<f64>) -> hdf5::Result<()> {
+    if file.link_exists(name) {
+        // Note this unlinks but does not reclaim its storage space.
+        file.unlink(name)?;
+    }
+
+    file.new_dataset_builder().with_data(values).create(name)?;
+    Ok(())
+}
@@ -0,0 +1,621 @@
+//! A small expression language for selecting circuits, e.g.:
+//!
+//! ```text
+//! label == "example.com" && day >= 3 && len > 100
+//! (port == 80 || port == 443) && !(service == "https")
+//! ```
+//!
+//! Expressions compare a field (`uuid`, `label`, `domain`, `suffix`, `day`,
+//! `port`, `len`, or `service`) to a literal with `==`, `!=`, `<`, `<=`, `>`,
+//! or `>=`, and combine comparisons with `&&`, `||`, `!`, and parentheses.
+//! String literals are double-quoted; `service` accepts a category name (e.g.,
+//! `"https"`) or its integer value.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use hdf5::types::FixedAscii;
+use hdf5::File;
+
+use crate::index;
+use crate::{Circuit, CircuitIndex, CircuitMeta, IndexEntry, ServiceCategory};
+
+/// A circuit field that can appear in an expression.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Field {
+    Uuid,
+    Label,
+    Domain,
+    Suffix,
+    Day,
+    Port,
+    Len,
+    Service,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "uuid" => Field::Uuid,
+            "label" => Field::Label,
+            "domain" => Field::Domain,
+            "suffix" | "shortest_private_suffix" => Field::Suffix,
+            "day" => Field::Day,
+            "port" => Field::Port,
+            "len" => Field::Len,
+            "service" => Field::Service,
+            _ => return None,
+        })
+    }
+
+    fn is_string(&self) -> bool {
+        matches!(
+            self,
+            Field::Uuid | Field::Label | Field::Domain | Field::Suffix
+        )
+    }
+}
+
+/// A comparison operator.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    fn eval<T: PartialOrd + ?Sized>(&self, a: &T, b: &T) -> bool {
+        match self {
+            CmpOp::Eq => a == b,
+            CmpOp::Ne => a != b,
+            CmpOp::Lt => a < b,
+            CmpOp::Le => a <= b,
+            CmpOp::Gt => a > b,
+            CmpOp::Ge => a >= b,
+        }
+    }
+}
+
+/// A literal value in a comparison.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Value {
+    Str(String),
+    Int(u64),
+}
+
+/// A parsed query expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Cmp {
+        field: Field,
+        op: CmpOp,
+        value: Value,
+    },
+}
+
+/// An error in the syntax or types of a query expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// The byte offset in the expression where the error was detected.
+    pub pos: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.msg, self.pos)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Expr {
+    /// Parses a query expression.
+    pub fn parse(s: &str) -> Result<Self, ParseError> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: s.len(),
+        };
+        let expr = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some((pos, tok)) => Err(ParseError {
+                pos,
+                msg: format!("Unexpected {tok}"),
+            }),
+        }
+    }
+
+    /// Returns true if `circuit` satisfies the expression.
+    pub fn matches(&self, circuit: &Circuit) -> bool {
+        self.matches_meta(&CircuitMeta::from(circuit))
+    }
+
+    /// Returns true if a circuit with meta-data `meta` satisfies the
+    /// expression. Every field in the language is meta-data, so the cells
+    /// never need to be read.
+    pub fn matches_meta(&self, meta: &CircuitMeta) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches_meta(meta) && b.matches_meta(meta),
+            Expr::Or(a, b) => a.matches_meta(meta) || b.matches_meta(meta),
+            Expr::Not(a) => !a.matches_meta(meta),
+            Expr::Cmp { field, op, value } => match (field, value) {
+                (Field::Uuid, Value::Str(s)) => op.eval(meta.uuid.as_str(), s),
+                (Field::Label, Value::Str(s)) => op.eval(meta.label().as_str(), s),
+                (Field::Domain, Value::Str(s)) => op.eval(meta.domain.as_str(), s),
+                (Field::Suffix, Value::Str(s)) => op.eval(meta.shortest_private_suffix.as_str(), s),
+                (Field::Day, Value::Int(v)) => op.eval(&(meta.day as u64), v),
+                (Field::Port, Value::Int(v)) => op.eval(&(meta.port as u64), v),
+                (Field::Len, Value::Int(v)) => op.eval(&(meta.len as u64), v),
+                (Field::Service, Value::Int(v)) => {
+                    op.eval(&(ServiceCategory::from_port(meta.port) as u64), v)
+                }
+                // Mismatched types are rejected by the parser.
+                _ => false,
+            },
+        }
+    }
+
+    /// Uses the indices stored in `file` to compute the sorted indices of the
+    /// circuits that may satisfy the expression. Returns `None` if some part of
+    /// the expression cannot be answered from the indices, in which case the
+    /// whole dataset must be scanned. Candidates should still be checked with
+    /// `matches`.
+    pub fn candidates(&self, file: &File) -> hdf5::Result<Option<Vec<CircuitIndex>>> {
+        Ok(self
+            .candidate_set(file)?
+            .map(|set| set.into_iter().collect()))
+    }
+
+    fn candidate_set(&self, file: &File) -> hdf5::Result<Option<BTreeSet<CircuitIndex>>> {
+        Ok(match self {
+            Expr::And(a, b) => match (a.candidate_set(file)?, b.candidate_set(file)?) {
+                (Some(a), Some(b)) => Some(a.intersection(&b).copied().collect()),
+                (Some(a), None) => Some(a),
+                (None, b) => b,
+            },
+            Expr::Or(a, b) => match (a.candidate_set(file)?, b.candidate_set(file)?) {
+                (Some(mut a), Some(b)) => {
+                    a.extend(b);
+                    Some(a)
+                }
+                _ => None,
+            },
+            Expr::Not(_) => None,
+            Expr::Cmp { field, op, value } => {
+                let name = match field {
+                    Field::Uuid => "uuid",
+                    Field::Label => "label",
+                    Field::Day => "day",
+                    Field::Port => "port",
+                    Field::Len => "len",
+                    Field::Service => "service",
+                    Field::Domain | Field::Suffix => return Ok(None),
+                };
+                if !file.link_exists(&format!("/index/{name}")) {
+                    return Ok(None);
+                }
+                let dataset = file.dataset(&format!("/index/{name}"))?;
+
+                match (field, value) {
+                    (Field::Uuid, Value::Str(s)) => Some(
+                        dataset
+                            .read_raw::<IndexEntry<FixedAscii<32>>>()?
+                            .into_iter()
+                            .filter(|e| op.eval(e.value.as_str(), s))
+                            .map(|e| e.index)
+                            .collect(),
+                    ),
+                    (Field::Label, Value::Str(s)) => {
+                        Some(index::select_indices::<FixedAscii<44>, _>(&dataset, |v| {
+                            op.eval(v.as_str(), s)
+                        })?)
+                    }
+                    (Field::Day, Value::Int(n)) => {
+                        Some(index::select_indices::<u8, _>(&dataset, |v| {
+                            op.eval(&(*v as u64), n)
+                        })?)
+                    }
+                    (Field::Port, Value::Int(n)) | (Field::Len, Value::Int(n)) => {
+                        Some(index::select_indices::<u16, _>(&dataset, |v| {
+                            op.eval(&(*v as u64), n)
+                        })?)
+                    }
+                    (Field::Service, Value::Int(n)) => {
+                        Some(index::select_indices::<ServiceCategory, _>(
+                            &dataset,
+                            |v| op.eval(&(*v as u64), n),
+                        )?)
+                    }
+                    _ => None,
+                }
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Int(u64),
+    Op(CmpOp),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(s) => write!(f, "identifier '{s}'"),
+            Token::Str(s) => write!(f, "string \"{s}\""),
+            Token::Int(n) => write!(f, "integer {n}"),
+            Token::Op(op) => write!(f, "operator {op:?}"),
+            Token::And => write!(f, "'&&'"),
+            Token::Or => write!(f, "'||'"),
+            Token::Not => write!(f, "'!'"),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let bytes = s.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        let two = bytes.get(i..i + 2).unwrap_or(&[]);
+        let start = i;
+
+        let token = match c {
+            b' ' | b'\t' | b'\n' | b'\r' => {
+                i += 1;
+                continue;
+            }
+            b'(' => Token::LParen,
+            b')' => Token::RParen,
+            _ if two == b"&&" => Token::And,
+            _ if two == b"||" => Token::Or,
+            _ if two == b"==" => Token::Op(CmpOp::Eq),
+            _ if two == b"!=" => Token::Op(CmpOp::Ne),
+            _ if two == b"<=" => Token::Op(CmpOp::Le),
+            _ if two == b">=" => Token::Op(CmpOp::Ge),
+            b'<' => Token::Op(CmpOp::Lt),
+            b'>' => Token::Op(CmpOp::Gt),
+            b'!' => Token::Not,
+            b'"' => {
+                let end = s[i + 1..].find('"').ok_or(ParseError {
+                    pos: i,
+                    msg: "Unterminated string".to_string(),
+                })?;
+                tokens.push((start, Token::Str(s[i + 1..i + 1 + end].to_string())));
+                i += end + 2;
+                continue;
+            }
+            c if c.is_ascii_digit() => {
+                let len = bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+                let n = s[i..i + len].parse().map_err(|_| ParseError {
+                    pos: i,
+                    msg: "Integer too large".to_string(),
+                })?;
+                tokens.push((start, Token::Int(n)));
+                i += len;
+                continue;
+            }
+            c if c.is_ascii_alphabetic() || c == b'_' => {
+                let len = bytes[i..]
+                    .iter()
+                    .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+                    .count();
+                tokens.push((start, Token::Ident(s[i..i + len].to_string())));
+                i += len;
+                continue;
+            }
+            _ => {
+                return Err(ParseError {
+                    pos: i,
+                    msg: format!("Unexpected character '{}'", c as char),
+                });
+            }
+        };
+
+        i += match token {
+            Token::LParen | Token::RParen | Token::Not => 1,
+            Token::Op(CmpOp::Lt) | Token::Op(CmpOp::Gt) => 1,
+            _ => 2,
+        };
+        tokens.push((start, token));
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<(usize, &Token)> {
+        self.tokens.get(self.pos).map(|(p, t)| (*p, t))
+    }
+
+    fn next(&mut self) -> Result<(usize, Token), ParseError> {
+        let tok = self.tokens.get(self.pos).cloned().ok_or(ParseError {
+            pos: self.end,
+            msg: "Unexpected end of expression".to_string(),
+        })?;
+        self.pos += 1;
+        Ok(tok)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_and()?;
+        while let Some((_, Token::Or)) = self.peek() {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_unary()?;
+        while let Some((_, Token::And)) = self.peek() {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        match self.next()? {
+            (_, Token::Not) => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            (_, Token::LParen) => {
+                let expr = self.parse_or()?;
+                match self.next()? {
+                    (_, Token::RParen) => Ok(expr),
+                    (pos, tok) => Err(ParseError {
+                        pos,
+                        msg: format!("Expected ')' but found {tok}"),
+                    }),
+                }
+            }
+            (pos, Token::Ident(name)) => self.parse_cmp(pos, &name),
+            (pos, tok) => Err(ParseError {
+                pos,
+                msg: format!("Expected a field name but found {tok}"),
+            }),
+        }
+    }
+
+    fn parse_cmp(&mut self, field_pos: usize, name: &str) -> Result<Expr, ParseError> {
+        let field = Field::from_name(name).ok_or(ParseError {
+            pos: field_pos,
+            msg: format!("Unknown field '{name}'"),
+        })?;
+
+        let op = match self.next()? {
+            (_, Token::Op(op)) => op,
+            (pos, tok) => {
+                return Err(ParseError {
+                    pos,
+                    msg: format!("Expected a comparison operator but found {tok}"),
+                });
+            }
+        };
+
+        let (pos, literal) = self.next()?;
+        let value = match (field, literal) {
+            (Field::Service, Token::Str(s)) => {
+                Value::Int(service_from_name(&s).ok_or(ParseError {
+                    pos,
+                    msg: format!("Unknown service '{s}'"),
+                })? as u64)
+            }
+            (f, Token::Str(s)) if f.is_string() => Value::Str(s),
+            (f, Token::Int(n)) if !f.is_string() => Value::Int(n),
+            (_, tok) => {
+                return Err(ParseError {
+                    pos,
+                    msg: format!("Field '{name}' cannot be compared with {tok}"),
+                });
+            }
+        };
+
+        Ok(Expr::Cmp { field, op, value })
+    }
+}
+
+fn service_from_name(name: &str) -> Option<ServiceCategory> {
+    Some(match name.to_ascii_uppercase().as_str() {
+        "OTHER" => ServiceCategory::OTHER,
+        "HTTP" => ServiceCategory::HTTP,
+        "HTTPS" => ServiceCategory::HTTPS,
+        "SMTP" => ServiceCategory::SMTP,
+        "IMAP" => ServiceCategory::IMAP,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixedascii_from_str;
+    use crate::index::IndexBuilder;
+
+    fn cmp(field: Field, op: CmpOp, value: u64) -> Expr {
+        Expr::Cmp {
+            field,
+            op,
+            value: Value::Int(value),
+        }
+    }
+
+    fn day(n: u64) -> Expr {
+        cmp(Field::Day, CmpOp::Eq, n)
+    }
+
+    fn port(n: u64) -> Expr {
+        cmp(Field::Port, CmpOp::Eq, n)
+    }
+
+    fn and(a: Expr, b: Expr) -> Expr {
+        Expr::And(Box::new(a), Box::new(b))
+    }
+
+    fn or(a: Expr, b: Expr) -> Expr {
+        Expr::Or(Box::new(a), Box::new(b))
+    }
+
+    fn not(a: Expr) -> Expr {
+        Expr::Not(Box::new(a))
+    }
+
+    fn error_pos(s: &str) -> usize {
+        Expr::parse(s).unwrap_err().pos
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            Expr::parse("day == 1 || day == 2 && port == 80").unwrap(),
+            or(day(1), and(day(2), port(80)))
+        );
+        assert_eq!(
+            Expr::parse("day == 1 && day == 2 || port == 80").unwrap(),
+            or(and(day(1), day(2)), port(80))
+        );
+        assert_eq!(
+            Expr::parse("day == 1 && (day == 2 || port == 80)").unwrap(),
+            and(day(1), or(day(2), port(80)))
+        );
+    }
+
+    #[test]
+    fn operators_associate_left() {
+        assert_eq!(
+            Expr::parse("day == 1 || day == 2 || day == 3").unwrap(),
+            or(or(day(1), day(2)), day(3))
+        );
+    }
+
+    #[test]
+    fn not_binds_tightest() {
+        assert_eq!(
+            Expr::parse("!day == 1 && port == 80").unwrap(),
+            and(not(day(1)), port(80))
+        );
+        assert_eq!(
+            Expr::parse("!(day == 1 && port == 80)").unwrap(),
+            not(and(day(1), port(80)))
+        );
+        assert_eq!(Expr::parse("!!day == 1").unwrap(), not(not(day(1))));
+    }
+
+    #[test]
+    fn literals_and_operators() {
+        assert_eq!(
+            Expr::parse("len >= 10").unwrap(),
+            cmp(Field::Len, CmpOp::Ge, 10)
+        );
+        assert_eq!(
+            Expr::parse("service == \"https\"").unwrap(),
+            cmp(Field::Service, CmpOp::Eq, ServiceCategory::HTTPS as u64)
+        );
+        assert_eq!(
+            Expr::parse("suffix != \"a.com\"").unwrap(),
+            Expr::Cmp {
+                field: Field::Suffix,
+                op: CmpOp::Ne,
+                value: Value::Str("a.com".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn trailing_tokens_are_errors() {
+        assert_eq!(error_pos("day == 1 )"), 9);
+        assert_eq!(error_pos("day == 1 port == 2"), 9);
+        assert_eq!(error_pos("(day == 1) (port == 2)"), 11);
+    }
+
+    #[test]
+    fn syntax_and_type_errors() {
+        assert_eq!(error_pos("day =="), 6);
+        assert_eq!(error_pos("(day == 1"), 9);
+        assert_eq!(error_pos("color == 1"), 0);
+        assert_eq!(error_pos("day == \"1\""), 7);
+        assert_eq!(error_pos("label == 1"), 9);
+        assert_eq!(error_pos("label == \"a.com"), 9);
+        assert_eq!(error_pos("service == \"gopher\""), 11);
+        assert_eq!(error_pos("day = 1"), 4);
+    }
+
+    /// Writes the indices of circuits with the given `(label, day, port)` to a
+    /// new file, returning it with its path.
+    fn indexed_file(name: &str, circuits: &[(&str, u8, u16)]) -> (File, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("gtt23-{}-{name}.h5", std::process::id()));
+        let file = File::create(&path).unwrap();
+        let mut builder = IndexBuilder::new();
+        for (i, &(label, day, port)) in circuits.iter().enumerate() {
+            let meta = CircuitMeta {
+                uuid: fixedascii_from_str(&format!("{i:032x}")).unwrap(),
+                domain: fixedascii_from_str(label).unwrap(),
+                shortest_private_suffix: fixedascii_from_str(label).unwrap(),
+                day,
+                port,
+                len: 0,
+            };
+            builder.add_meta(i as CircuitIndex, &meta);
+        }
+        builder.write_all(&file).unwrap();
+        (file, path)
+    }
+
+    #[test]
+    fn candidates_narrow_with_indices() {
+        let (file, path) = indexed_file(
+            "query-candidates",
+            &[
+                ("a.com", 1, 443),
+                ("a.com", 2, 80),
+                ("b.com", 1, 80),
+                ("b.com", 3, 443),
+            ],
+        );
+        let candidates = |s: &str| Expr::parse(s).unwrap().candidates(&file).unwrap();
+
+        assert_eq!(candidates("day == 1"), Some(vec![0, 2]));
+        assert_eq!(candidates("day == 1 && port == 443"), Some(vec![0]));
+        assert_eq!(candidates("day == 1 || port == 443"), Some(vec![0, 2, 3]));
+        assert_eq!(candidates("label == \"b.com\" && day > 1"), Some(vec![3]));
+        assert_eq!(candidates("service == \"http\""), Some(vec![1, 2]));
+        // Unindexed parts narrow nothing on their own.
+        assert_eq!(
+            candidates("day == 1 && domain == \"b.com\""),
+            Some(vec![0, 2])
+        );
+        assert_eq!(candidates("day == 1 || domain == \"b.com\""), None);
+        assert_eq!(candidates("!(day == 1)"), None);
+        assert_eq!(candidates("!(day == 1) && port == 80"), Some(vec![1, 2]));
+
+        file.close().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+}
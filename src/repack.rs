@@ -0,0 +1,105 @@
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+
+use hdf5::types::{VarLenAscii, VarLenUnicode};
+use hdf5::File;
+use hdf5_sys::h5o::H5Ocopy;
+use hdf5_sys::h5p::H5P_DEFAULT;
+
+/// The on-disk size of a file before and after it was repacked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RepackSizes {
+    pub before: u64,
+    pub after: u64,
+}
+
+impl RepackSizes {
+    /// The number of bytes reclaimed by repacking.
+    pub fn saved(&self) -> u64 {
+        self.before.saturating_sub(self.after)
+    }
+}
+
+/// Repacks the HDF5 file at `path` in place to reclaim the storage space left
+/// behind by unlinked objects (e.g., indices that were replaced). The file is
+/// first copied next to `path` and then moved over the original.
+pub fn repack<P: AsRef<Path>>(path: P) -> hdf5::Result<RepackSizes> {
+    let path = path.as_ref();
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".repack");
+    let tmp = PathBuf::from(tmp);
+
+    let before = file_size(path)?;
+    repack_to(path, &tmp)?;
+    std::fs::rename(&tmp, path).map_err(|e| e.to_string())?;
+
+    Ok(RepackSizes {
+        before,
+        after: file_size(path)?,
+    })
+}
+
+/// Copies every object and root attribute of the HDF5 file at `src` into a new
+/// file at `dst`. Datasets keep their datatypes, chunking, compression filters,
+/// and attributes, but storage that is no longer reachable in `src` is not
+/// carried over.
+pub fn repack_to<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> hdf5::Result<()> {
+    let src = File::open(src)?;
+    let dst = File::create(dst)?;
+
+    for name in src.member_names()? {
+        copy_object(&src, &dst, &name)?;
+    }
+
+    // Root attributes are not covered by copying the root's members.
+    for name in src.attr_names()? {
+        copy_string_attr(&src, &dst, &name)?;
+    }
+
+    dst.close()?;
+    src.close()?;
+    Ok(())
+}
+
+fn copy_object(src: &File, dst: &File, name: &str) -> hdf5::Result<()> {
+    let c_name = CString::new(name).map_err(|e| e.to_string())?;
+
+    let ret = hdf5::sync::sync(|| unsafe {
+        H5Ocopy(
+            src.id(),
+            c_name.as_ptr(),
+            dst.id(),
+            c_name.as_ptr(),
+            H5P_DEFAULT,
+            H5P_DEFAULT,
+        )
+    });
+
+    if ret < 0 {
+        return Err(format!("Unable to copy object {name}").into());
+    }
+
+    Ok(())
+}
+
+/// Copies a scalar string attribute, which is the only kind of attribute (e.g.,
+/// the file note) that GTT23 files store on the root group.
+fn copy_string_attr(src: &File, dst: &File, name: &str) -> hdf5::Result<()> {
+    let attr = src.attr(name)?;
+
+    if let Ok(value) = attr.read_scalar::<VarLenAscii>() {
+        dst.new_attr::<VarLenAscii>()
+            .create(name)?
+            .write_scalar(&value)
+    } else {
+        let value = attr.read_scalar::<VarLenUnicode>()?;
+        dst.new_attr::<VarLenUnicode>()
+            .create(name)?
+            .write_scalar(&value)
+    }
+}
+
+fn file_size(path: &Path) -> hdf5::Result<u64> {
+    Ok(std::fs::metadata(path).map_err(|e| e.to_string())?.len())
+}
@@ -0,0 +1,69 @@
+use hdf5::types::FixedAscii;
+
+use crate::index::fnv1a;
+
+/// A small seeded pseudo-random number generator (SplitMix64).
+///
+/// We use our own generator rather than an external crate so that the stream
+/// produced by a given seed never changes across dependency upgrades, which
+/// keeps splits and samples reproducible.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Creates a generator whose output is fully determined by `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Creates a generator for the stream of `key`, e.g., a circuit uuid,
+    /// derived from the master `seed` as `seed ^ hash(key)`, so that the
+    /// stream of each key does not depend on the streams of other keys.
+    pub fn derive(seed: u64, key: &[u8]) -> Self {
+        Self::new(seed ^ fnv1a(key, 0xcbf29ce484222325))
+    }
+
+    /// Returns the next 64 random bits.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a uniformly random float in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns a uniformly random integer in `[0, n)`. Panics if `n` is 0.
+    pub fn below(&mut self, n: u64) -> u64 {
+        assert!(n > 0, "empty range");
+        // Lemire's multiply-shift with rejection to avoid modulo bias.
+        let threshold = n.wrapping_neg() % n;
+        loop {
+            let m = (self.next_u64() as u128) * (n as u128);
+            if (m as u64) >= threshold {
+                return (m >> 64) as u64;
+            }
+        }
+    }
+
+    /// Shuffles `items` in place (Fisher-Yates).
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+
+    /// Returns a random uuid of 32 lowercase hex characters, e.g., for newly
+    /// created augmented circuits.
+    pub fn uuid(&mut self) -> FixedAscii<32> {
+        let uuid = format!("{:016x}{:016x}", self.next_u64(), self.next_u64());
+        FixedAscii::from_ascii(uuid.as_bytes()).unwrap()
+    }
+}
@@ -0,0 +1,401 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use hdf5::types::FixedAscii;
+
+use crate::rng::Rng;
+use crate::{CircuitIndex, CircuitMeta};
+
+/// Draws `n` distinct positions uniformly at random from `0..size`, returned in
+/// increasing order. Returns all positions if `n >= size`.
+pub fn uniform(size: usize, n: usize, rng: &mut Rng) -> Vec<CircuitIndex> {
+    if n >= size {
+        return (0..size as CircuitIndex).collect();
+    }
+
+    // Floyd's algorithm, so memory is proportional to `n` rather than `size`.
+    let mut chosen = HashSet::with_capacity(n);
+    for j in (size - n)..size {
+        let t = rng.below(j as u64 + 1) as CircuitIndex;
+        if !chosen.insert(t) {
+            chosen.insert(j as CircuitIndex);
+        }
+    }
+
+    let mut sample: Vec<CircuitIndex> = chosen.into_iter().collect();
+    sample.sort();
+    sample
+}
+
+/// Draws `n` distinct elements uniformly at random from `candidates`, returned
+/// in increasing order.
+pub fn uniform_from(candidates: &[CircuitIndex], n: usize, rng: &mut Rng) -> Vec<CircuitIndex> {
+    let mut sample: Vec<CircuitIndex> = uniform(candidates.len(), n, rng)
+        .into_iter()
+        .map(|i| candidates[i as usize])
+        .collect();
+    sample.sort();
+    sample
+}
+
+/// Draws `n` distinct circuits from `circuits`, given as `(index, day)` pairs,
+/// spreading the draws across days as evenly as possible: days are visited
+/// round-robin in a random order, taking a random circuit from each until `n`
+/// are drawn. Returns the drawn indices in increasing order.
+pub fn spread_across_days(
+    circuits: &[(CircuitIndex, u8)],
+    n: usize,
+    rng: &mut Rng,
+) -> Vec<CircuitIndex> {
+    let mut days: BTreeMap<u8, Vec<CircuitIndex>> = BTreeMap::new();
+    for &(index, day) in circuits {
+        days.entry(day).or_default().push(index);
+    }
+
+    let mut days: Vec<Vec<CircuitIndex>> = days.into_values().collect();
+    for indices in days.iter_mut() {
+        indices.sort();
+        rng.shuffle(indices);
+    }
+    rng.shuffle(&mut days);
+
+    let mut sample = Vec::with_capacity(n.min(circuits.len()));
+    let mut round = 0;
+    while sample.len() < n && days.iter().any(|d| round < d.len()) {
+        for indices in days.iter() {
+            if sample.len() == n {
+                break;
+            }
+            if let Some(&index) = indices.get(round) {
+                sample.push(index);
+            }
+        }
+        round += 1;
+    }
+
+    sample.sort();
+    sample
+}
+
+/// Keeps a uniform random sample of at most `n` items from a stream of unknown
+/// length using reservoir sampling, so that the stream need not be stored.
+#[derive(Clone, Debug)]
+pub struct Reservoir<T> {
+    n: usize,
+    seen: u64,
+    items: Vec<(u64, T)>,
+}
+
+impl<T> Reservoir<T> {
+    /// Creates an empty reservoir that keeps at most `n` items. The items are
+    /// allocated as they are offered rather than up front, since `n` may be
+    /// much larger than the stream.
+    pub fn new(n: usize) -> Self {
+        Self {
+            n,
+            seen: 0,
+            items: Vec::new(),
+        }
+    }
+
+    /// Offers the next item of the stream, which is kept with probability
+    /// `n / seen` (replacing a random kept item if the reservoir is full).
+    pub fn offer(&mut self, item: T, rng: &mut Rng) {
+        if self.items.len() < self.n {
+            self.items.push((self.seen, item));
+        } else if self.n > 0 {
+            let j = rng.below(self.seen + 1) as usize;
+            if j < self.n {
+                self.items[j] = (self.seen, item);
+            }
+        }
+        self.seen += 1;
+    }
+
+    /// The number of items offered so far.
+    pub fn seen(&self) -> u64 {
+        self.seen
+    }
+
+    /// Returns the kept items in the order in which they were offered.
+    pub fn into_items(mut self) -> Vec<T> {
+        self.items.sort_by_key(|(seq, _)| *seq);
+        self.items.into_iter().map(|(_, item)| item).collect()
+    }
+}
+
+/// The circuits drawn by `PerLabelSample`: the original circuits to keep, the
+/// circuits drawn again when sampling with replacement, and the number of
+/// labels that were skipped for having too few circuits.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PerLabelPlan {
+    /// The indices of the circuits to keep, sorted and distinct.
+    pub keep: Vec<CircuitIndex>,
+    /// The indices of the circuits drawn again, sorted, with an index repeated
+    /// once per extra draw of its circuit. Since a circuit's uuid must be
+    /// unique in a circuits dataset, these are meant to be written as
+    /// augmented copies.
+    pub duplicates: Vec<CircuitIndex>,
+    /// The number of labels skipped for having fewer than `n` circuits.
+    pub skipped: usize,
+}
+
+/// Draws exactly `n` circuits of every label, spread across days as evenly as
+/// possible. Labels with fewer than `n` circuits are skipped, unless sampling
+/// with replacement, in which case all of their circuits are kept and the
+/// remainder is drawn again uniformly at random as duplicates.
+#[derive(Clone, Debug)]
+pub struct PerLabelSample {
+    n: usize,
+    replace: bool,
+    labels: HashMap<FixedAscii<44>, Vec<(CircuitIndex, u8)>>,
+}
+
+impl PerLabelSample {
+    /// Creates a sample of `n` circuits per label, with replacement for small
+    /// labels if `replace` is set.
+    pub fn new(n: usize, replace: bool) -> Self {
+        Self {
+            n,
+            replace,
+            labels: HashMap::new(),
+        }
+    }
+
+    /// Adds the circuit at position `index` in the dataset as a candidate.
+    pub fn add(&mut self, index: CircuitIndex, meta: &CircuitMeta) {
+        self.labels
+            .entry(meta.label())
+            .or_default()
+            .push((index, meta.day));
+    }
+
+    /// Draws the sample. The result depends only on `rng` and the set of
+    /// added circuits.
+    pub fn select(&self, rng: &mut Rng) -> PerLabelPlan {
+        let mut labels: Vec<(&FixedAscii<44>, &Vec<(CircuitIndex, u8)>)> =
+            self.labels.iter().collect();
+        labels.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+
+        let mut plan = PerLabelPlan::default();
+
+        for (_, circuits) in labels {
+            let mut circuits = circuits.clone();
+            circuits.sort();
+
+            if circuits.len() >= self.n {
+                plan.keep.extend(spread_across_days(&circuits, self.n, rng));
+            } else if self.replace {
+                plan.keep.extend(circuits.iter().map(|c| c.0));
+                for _ in circuits.len()..self.n {
+                    let i = rng.below(circuits.len() as u64) as usize;
+                    plan.duplicates.push(circuits[i].0);
+                }
+            } else {
+                plan.skipped += 1;
+            }
+        }
+
+        plan.keep.sort();
+        plan.duplicates.sort();
+        plan
+    }
+}
+
+/// Selects at most `cap` circuits of each label, either uniformly at random or
+/// spread across days as evenly as possible. Labels with at most `cap`
+/// circuits are kept whole.
+#[derive(Clone, Debug)]
+pub struct LabelCapSample {
+    cap: usize,
+    spread_days: bool,
+    labels: HashMap<FixedAscii<44>, Vec<(CircuitIndex, u8)>>,
+}
+
+impl LabelCapSample {
+    /// Creates a sample of at most `cap` circuits per label, drawn uniformly at
+    /// random, or spread across days if `spread_days` is set.
+    pub fn new(cap: usize, spread_days: bool) -> Self {
+        Self {
+            cap,
+            spread_days,
+            labels: HashMap::new(),
+        }
+    }
+
+    /// Adds the circuit at position `index` in the dataset as a candidate.
+    pub fn add(&mut self, index: CircuitIndex, meta: &CircuitMeta) {
+        self.labels
+            .entry(meta.label())
+            .or_default()
+            .push((index, meta.day));
+    }
+
+    /// Draws the sample, returning the selected circuit indices in increasing
+    /// order. The result depends only on `rng` and the set of added circuits.
+    pub fn select(&self, rng: &mut Rng) -> Vec<CircuitIndex> {
+        let mut labels: Vec<(&FixedAscii<44>, &Vec<(CircuitIndex, u8)>)> =
+            self.labels.iter().collect();
+        labels.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+
+        let mut sample = Vec::new();
+        for (_, circuits) in labels {
+            let mut circuits = circuits.clone();
+            circuits.sort();
+
+            if self.spread_days {
+                sample.extend(spread_across_days(&circuits, self.cap, rng));
+            } else {
+                let indices: Vec<CircuitIndex> = circuits.iter().map(|c| c.0).collect();
+                sample.extend(uniform_from(&indices, self.cap, rng));
+            }
+        }
+
+        sample.sort();
+        sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_returns_all_when_n_covers_size() {
+        let mut rng = Rng::new(1);
+        assert_eq!(uniform(5, 5, &mut rng), vec![0, 1, 2, 3, 4]);
+        assert_eq!(uniform(3, 10, &mut rng), vec![0, 1, 2]);
+        assert!(uniform(0, 0, &mut rng).is_empty());
+        assert!(uniform(10, 0, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn uniform_draws_distinct_sorted_positions() {
+        let mut rng = Rng::new(2);
+        for _ in 0..100 {
+            let sample = uniform(50, 20, &mut rng);
+            assert_eq!(sample.len(), 20);
+            assert!(sample.windows(2).all(|w| w[0] < w[1]));
+            assert!(sample.iter().all(|&i| i < 50));
+        }
+    }
+
+    #[test]
+    fn uniform_is_reproducible() {
+        let a = uniform(1_000, 10, &mut Rng::new(3));
+        let b = uniform(1_000, 10, &mut Rng::new(3));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn uniform_is_unbiased() {
+        let mut rng = Rng::new(4);
+        let mut counts = [0usize; 10];
+        let trials = 20_000;
+        for _ in 0..trials {
+            for i in uniform(10, 3, &mut rng) {
+                counts[i as usize] += 1;
+            }
+        }
+        // Each position is drawn with probability 0.3, i.e., 6,000 times in
+        // expectation with a standard deviation of about 65.
+        for count in counts {
+            assert!((5_700..6_300).contains(&count), "{counts:?}");
+        }
+    }
+
+    fn meta(i: usize, label: &str, day: u8) -> CircuitMeta {
+        CircuitMeta {
+            uuid: crate::fixedascii_from_str(&format!("{i:032x}")).unwrap(),
+            domain: crate::fixedascii_from_str(label).unwrap(),
+            shortest_private_suffix: crate::fixedascii_from_str(label).unwrap(),
+            day,
+            port: 443,
+            len: 0,
+        }
+    }
+
+    #[test]
+    fn per_label_with_replacement_separates_duplicates() {
+        let mut sample = PerLabelSample::new(4, true);
+        for i in 0..10 {
+            sample.add(i as CircuitIndex, &meta(i, "big.com", (i % 3) as u8));
+        }
+        for i in 10..12 {
+            sample.add(i as CircuitIndex, &meta(i, "small.com", 0));
+        }
+
+        let plan = sample.select(&mut Rng::new(9));
+        assert_eq!(plan.keep.len(), 6);
+        assert!(plan.keep.windows(2).all(|w| w[0] < w[1]), "{plan:?}");
+        assert!(plan.keep.contains(&10) && plan.keep.contains(&11));
+        assert_eq!(plan.duplicates.len(), 2);
+        assert!(plan.duplicates.iter().all(|&i| i == 10 || i == 11));
+        assert_eq!(plan.skipped, 0);
+    }
+
+    #[test]
+    fn per_label_without_replacement_skips_small_labels() {
+        let mut sample = PerLabelSample::new(4, false);
+        for i in 0..10 {
+            sample.add(i as CircuitIndex, &meta(i, "big.com", 0));
+        }
+        sample.add(10, &meta(10, "small.com", 0));
+
+        let plan = sample.select(&mut Rng::new(10));
+        assert_eq!(plan.keep.len(), 4);
+        assert!(plan.keep.iter().all(|&i| i < 10));
+        assert!(plan.duplicates.is_empty());
+        assert_eq!(plan.skipped, 1);
+    }
+
+    #[test]
+    fn reservoir_keeps_all_until_full() {
+        let mut rng = Rng::new(5);
+        let mut reservoir = Reservoir::new(5);
+        for i in 0..3 {
+            reservoir.offer(i, &mut rng);
+        }
+        assert_eq!(reservoir.seen(), 3);
+        assert_eq!(reservoir.into_items(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn reservoir_replaces_once_full() {
+        let mut rng = Rng::new(6);
+        let mut reservoir = Reservoir::new(10);
+        for i in 0..1_000 {
+            reservoir.offer(i, &mut rng);
+        }
+        assert_eq!(reservoir.seen(), 1_000);
+        let items = reservoir.into_items();
+        assert_eq!(items.len(), 10);
+        // With 1,000 items offered, keeping only the first 10 is vanishingly
+        // unlikely.
+        assert!(items.iter().any(|&i| i >= 10), "{items:?}");
+    }
+
+    #[test]
+    fn reservoir_returns_items_in_offered_order() {
+        let mut rng = Rng::new(7);
+        for _ in 0..100 {
+            let mut reservoir = Reservoir::new(20);
+            for i in 0..200 {
+                reservoir.offer(i, &mut rng);
+            }
+            let items = reservoir.into_items();
+            assert_eq!(items.len(), 20);
+            assert!(items.windows(2).all(|w| w[0] < w[1]), "{items:?}");
+        }
+    }
+
+    #[test]
+    fn reservoir_of_zero_keeps_nothing() {
+        let mut rng = Rng::new(8);
+        let mut reservoir = Reservoir::new(0);
+        for i in 0..10 {
+            reservoir.offer(i, &mut rng);
+        }
+        assert_eq!(reservoir.seen(), 10);
+        assert!(reservoir.into_items().is_empty());
+    }
+}
@@ -0,0 +1,138 @@
+//! Versioning of the record type of a circuits dataset.
+//!
+//! The `schema_version` attribute of a circuits dataset names the type of its
+//! records: `SCHEMA_V1` for `Circuit`, which is assumed if the attribute is
+//! missing as in the original GTT23 files, and `SCHEMA_V2` for
+//! `ExtendedCircuit`. Since `ExtendedCircuit` keeps the field names of
+//! `Circuit`, tools that read `Circuit` or `CircuitMeta` records work on either
+//! version, and only tools that need the extended fields must check it.
+
+use std::ops::Range;
+
+use hdf5::File;
+
+use crate::writer::CircuitWriter;
+use crate::{Circuit, ExtendedCircuit, ExtendedFields};
+
+/// The name of the attribute holding the schema version of a dataset.
+pub const SCHEMA_VERSION_ATTR: &str = "schema_version";
+
+/// The schema version of datasets of `Circuit` records.
+pub const SCHEMA_V1: u32 = 1;
+
+/// The schema version of datasets of `ExtendedCircuit` records.
+pub const SCHEMA_V2: u32 = 2;
+
+/// The number of circuits read at a time when converting.
+const READ_BATCH: usize = 1_000;
+
+/// The schema version of `dataset`, which is `SCHEMA_V1` if it has no
+/// `schema_version` attribute. Errors reading an existing attribute are
+/// returned rather than taken to mean `SCHEMA_V1`.
+pub fn schema_version(dataset: &hdf5::Dataset) -> hdf5::Result<u32> {
+    if !has_schema_version(dataset)? {
+        return Ok(SCHEMA_V1);
+    }
+    dataset.attr(SCHEMA_VERSION_ATTR)?.read_scalar::<u32>()
+}
+
+/// Whether `dataset` has a `schema_version` attribute.
+fn has_schema_version(dataset: &hdf5::Dataset) -> hdf5::Result<bool> {
+    Ok(dataset
+        .attr_names()?
+        .iter()
+        .any(|name| name == SCHEMA_VERSION_ATTR))
+}
+
+/// Writes `version` to the `schema_version` attribute of `dataset`, replacing
+/// any existing version.
+pub fn write_schema_version(dataset: &hdf5::Dataset, version: u32) -> hdf5::Result<()> {
+    if has_schema_version(dataset)? {
+        dataset.attr(SCHEMA_VERSION_ATTR)?.write_scalar(&version)
+    } else {
+        dataset
+            .new_attr::<u32>()
+            .create(SCHEMA_VERSION_ATTR)?
+            .write_scalar(&version)
+    }
+}
+
+/// Reads the records in `range` of a circuits dataset of either schema version
+/// as `ExtendedCircuit`s. The extended fields of `SCHEMA_V1` records are
+/// unknown. Returns an error for unsupported versions.
+pub fn read_extended(
+    dataset: &hdf5::Dataset,
+    range: Range<usize>,
+) -> hdf5::Result<Vec<ExtendedCircuit>> {
+    match schema_version(dataset)? {
+        SCHEMA_V1 => Ok(dataset
+            .read_slice_1d::<Circuit, _>(range)?
+            .iter()
+            .map(ExtendedCircuit::from)
+            .collect()),
+        SCHEMA_V2 => Ok(dataset.read_slice_1d::<ExtendedCircuit, _>(range)?.to_vec()),
+        version => Err(hdf5::Error::from(format!(
+            "Unsupported circuits schema version {version}"
+        ))),
+    }
+}
+
+/// Copies the `/circuits` dataset of `src` to a `SCHEMA_V2` `/circuits`
+/// dataset of `dst` with the same chunking and compression, setting the
+/// extended fields of each circuit to those returned by `fields`, which is
+/// passed the circuit and its current fields. `progress` is called with the
+/// number of circuits copied after each batch. Returns the number of circuits
+/// written.
+pub fn write_extended<F, P>(
+    src: &File,
+    dst: &File,
+    mut fields: F,
+    mut progress: P,
+) -> hdf5::Result<usize>
+where
+    F: FnMut(&ExtendedCircuit) -> ExtendedFields,
+    P: FnMut(usize),
+{
+    let circuits = src.dataset("/circuits")?;
+    let size = circuits.size();
+    let mut writer = CircuitWriter::<ExtendedCircuit>::create_like(dst, "/circuits", &circuits)?;
+
+    for begin in (0..size).step_by(READ_BATCH) {
+        let end = std::cmp::min(begin + READ_BATCH, size);
+        for mut circuit in read_extended(&circuits, begin..end)? {
+            circuit.set_fields(fields(&circuit));
+            writer.push(circuit)?;
+        }
+        progress(end - begin);
+    }
+
+    let dataset = writer.finish()?;
+    write_schema_version(&dataset, SCHEMA_V2)?;
+    Ok(size)
+}
+
+/// Copies the `/circuits` dataset of `src`, of either schema version, to a
+/// `SCHEMA_V1` `/circuits` dataset of `dst` with the same chunking and
+/// compression, dropping any extended fields. `progress` is called with the
+/// number of circuits copied after each batch. Returns the number of circuits
+/// written.
+pub fn write_v1<P>(src: &File, dst: &File, mut progress: P) -> hdf5::Result<usize>
+where
+    P: FnMut(usize),
+{
+    let circuits = src.dataset("/circuits")?;
+    let size = circuits.size();
+    let mut writer = CircuitWriter::<Circuit>::create_like(dst, "/circuits", &circuits)?;
+
+    for begin in (0..size).step_by(READ_BATCH) {
+        let end = std::cmp::min(begin + READ_BATCH, size);
+        for circuit in circuits.read_slice_1d::<Circuit, _>(begin..end)?.iter() {
+            writer.push(*circuit)?;
+        }
+        progress(end - begin);
+    }
+
+    let dataset = writer.finish()?;
+    write_schema_version(&dataset, SCHEMA_V1)?;
+    Ok(size)
+}
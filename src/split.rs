@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+
+use hdf5::types::FixedAscii;
+use hdf5::File;
+
+use crate::rng::Rng;
+use crate::{CircuitIndex, CircuitMeta, IndexArrayEntry};
+
+/// Partitions circuits into parts (e.g., train/validation/test) with the given
+/// ratios, separately for each label so that every part has the same label
+/// distribution.
+#[derive(Clone, Debug)]
+pub struct StratifiedSplit {
+    ratios: Vec<u32>,
+    seed: u64,
+    labels: HashMap<FixedAscii<44>, Vec<CircuitIndex>>,
+}
+
+impl StratifiedSplit {
+    /// Creates a split into `ratios.len()` parts, where part `i` receives
+    /// `ratios[i] / sum(ratios)` of the circuits of each label.
+    pub fn new(ratios: &[u32], seed: u64) -> Self {
+        Self {
+            ratios: ratios.to_vec(),
+            seed,
+            labels: HashMap::new(),
+        }
+    }
+
+    /// Adds the circuit at position `index` in the dataset to the split.
+    pub fn add(&mut self, index: CircuitIndex, meta: &CircuitMeta) {
+        self.labels.entry(meta.label()).or_default().push(index);
+    }
+
+    /// Assigns the added circuits to parts. Returns the sorted circuit indices
+    /// of each part, in the order of the ratios.
+    ///
+    /// The assignment depends only on the seed and on the set of added
+    /// circuits, not on the order in which they were added.
+    pub fn assign(&self) -> Vec<Vec<CircuitIndex>> {
+        let mut parts = vec![Vec::new(); self.ratios.len()];
+        let mut rng = Rng::new(self.seed);
+
+        let mut labels: Vec<(&FixedAscii<44>, &Vec<CircuitIndex>)> = self.labels.iter().collect();
+        labels.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+
+        // Carry the leftovers of each label over to the next, so that the many
+        // labels with few circuits do not all favor the largest part.
+        let mut sizer = PartSizer::new(&self.ratios);
+        for (_, indices) in labels {
+            let mut indices = indices.clone();
+            indices.sort();
+            rng.shuffle(&mut indices);
+
+            let mut begin = 0;
+            for (part, count) in parts.iter_mut().zip(sizer.divide(indices.len())) {
+                part.extend_from_slice(&indices[begin..begin + count]);
+                begin += count;
+            }
+        }
+
+        for part in parts.iter_mut() {
+            part.sort();
+        }
+        parts
+    }
+}
+
+/// Partitions circuits into parts with the given ratios of labels, so that
+/// every circuit of a label lands in the same part and no label appears in
+/// more than one part (e.g., for zero-shot or open-set experiments).
+#[derive(Clone, Debug)]
+pub struct LabelSplit {
+    ratios: Vec<u32>,
+    seed: u64,
+    labels: HashMap<String, Vec<CircuitIndex>>,
+}
+
+impl LabelSplit {
+    /// Creates a split into `ratios.len()` parts, where part `i` receives
+    /// `ratios[i] / sum(ratios)` of the labels.
+    pub fn new(ratios: &[u32], seed: u64) -> Self {
+        Self {
+            ratios: ratios.to_vec(),
+            seed,
+            labels: HashMap::new(),
+        }
+    }
+
+    /// Adds the circuit at position `index` in the dataset to the split.
+    pub fn add(&mut self, index: CircuitIndex, meta: &CircuitMeta) {
+        self.add_label(meta.label().as_str(), &[index]);
+    }
+
+    /// Adds the circuits at positions `indices` in the dataset, which all have
+    /// `label`.
+    pub fn add_label(&mut self, label: &str, indices: &[CircuitIndex]) {
+        match self.labels.get_mut(label) {
+            Some(v) => v.extend_from_slice(indices),
+            None => {
+                self.labels.insert(label.to_string(), indices.to_vec());
+            }
+        }
+    }
+
+    /// Adds all circuits using the `/index/label` dataset of `file`, which
+    /// avoids reading the circuits dataset. Returns false without adding
+    /// anything if the file has no label index.
+    pub fn add_from_index(&mut self, file: &File) -> hdf5::Result<bool> {
+        if !file.link_exists("/index/label") {
+            return Ok(false);
+        }
+
+        for entry in file
+            .dataset("/index/label")?
+            .read_raw::<IndexArrayEntry<FixedAscii<44>>>()?
+        {
+            self.add_label(entry.value.as_str(), entry.indexarr.as_slice());
+        }
+        Ok(true)
+    }
+
+    /// Assigns each label to a part. Returns the labels in sorted order along
+    /// with the part they were assigned to.
+    ///
+    /// The assignment depends only on the seed and on the set of added labels.
+    pub fn label_parts(&self) -> Vec<(String, usize)> {
+        let mut rng = Rng::new(self.seed);
+
+        let mut labels: Vec<&String> = self.labels.keys().collect();
+        labels.sort();
+        rng.shuffle(&mut labels);
+
+        let mut label_parts = Vec::with_capacity(labels.len());
+        let mut begin = 0;
+        for (part, count) in part_sizes(labels.len(), &self.ratios)
+            .into_iter()
+            .enumerate()
+        {
+            for label in &labels[begin..begin + count] {
+                label_parts.push((label.to_string(), part));
+            }
+            begin += count;
+        }
+
+        label_parts.sort();
+        label_parts
+    }
+
+    /// Assigns the added circuits to parts by label as `label_parts` does.
+    /// Returns the sorted circuit indices of each part, in the order of the
+    /// ratios.
+    pub fn assign(&self) -> Vec<Vec<CircuitIndex>> {
+        let mut parts = vec![Vec::new(); self.ratios.len()];
+        for (label, part) in self.label_parts() {
+            parts[part].extend_from_slice(&self.labels[&label]);
+        }
+
+        for part in parts.iter_mut() {
+            part.sort();
+        }
+        parts
+    }
+}
+
+/// Partitions circuits into parts by their day of measurement, so that every
+/// circuit of a day lands in the same part (e.g., days 1-6 for training and
+/// days 7-8 for testing). Circuits of days not assigned to any part are left
+/// out.
+#[derive(Clone, Debug)]
+pub struct DaySplit {
+    part_of_day: HashMap<u8, usize>,
+    parts: Vec<Vec<CircuitIndex>>,
+    unassigned: usize,
+}
+
+impl DaySplit {
+    /// Creates a split into `days.len()` parts, where part `i` receives the
+    /// circuits of the days in `days[i]`. Returns an error if a day is listed
+    /// in more than one part.
+    pub fn new(days: &[Vec<u8>]) -> Result<Self, String> {
+        let mut part_of_day = HashMap::new();
+        for (part, days) in days.iter().enumerate() {
+            for &day in days {
+                match part_of_day.insert(day, part) {
+                    Some(other) if other != part => {
+                        return Err(format!("Day {day} is assigned to parts {other} and {part}"));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(Self {
+            part_of_day,
+            parts: vec![Vec::new(); days.len()],
+            unassigned: 0,
+        })
+    }
+
+    /// Adds the circuit at position `index` in the dataset to the split.
+    pub fn add(&mut self, index: CircuitIndex, meta: &CircuitMeta) {
+        self.add_day(meta.day, &[index]);
+    }
+
+    /// Adds the circuits at positions `indices` in the dataset, which were all
+    /// measured on `day`.
+    pub fn add_day(&mut self, day: u8, indices: &[CircuitIndex]) {
+        match self.part_of_day.get(&day) {
+            Some(&part) => self.parts[part].extend_from_slice(indices),
+            None => self.unassigned += indices.len(),
+        }
+    }
+
+    /// Adds all circuits using the `/index/day` dataset of `file`, which avoids
+    /// reading the circuits dataset. Returns false without adding anything if
+    /// the file has no day index.
+    pub fn add_from_index(&mut self, file: &File) -> hdf5::Result<bool> {
+        if !file.link_exists("/index/day") {
+            return Ok(false);
+        }
+
+        for entry in file
+            .dataset("/index/day")?
+            .read_raw::<IndexArrayEntry<u8>>()?
+        {
+            self.add_day(entry.value, entry.indexarr.as_slice());
+        }
+        Ok(true)
+    }
+
+    /// The number of added circuits whose day is not assigned to any part.
+    pub fn unassigned(&self) -> usize {
+        self.unassigned
+    }
+
+    /// Returns the sorted circuit indices of each part, in the order of the
+    /// days given to `new`.
+    pub fn assign(&self) -> Vec<Vec<CircuitIndex>> {
+        let mut parts = self.parts.clone();
+        for part in parts.iter_mut() {
+            part.sort();
+        }
+        parts
+    }
+}
+
+/// Parses groups of days separated by colons, where each group is a comma
+/// separated list of days or inclusive day ranges, e.g., `1-6:7,8`.
+pub fn parse_day_groups(s: &str) -> Result<Vec<Vec<u8>>, String> {
+    s.split(':')
+        .map(|group| {
+            let mut days = Vec::new();
+            for item in group.split(',').map(|item| item.trim()) {
+                let parse = |d: &str| {
+                    d.trim()
+                        .parse::<u8>()
+                        .map_err(|e| format!("invalid day '{d}': {e}"))
+                };
+                match item.split_once('-') {
+                    Some((first, last)) => {
+                        let (first, last) = (parse(first)?, parse(last)?);
+                        if first > last {
+                            return Err(format!("invalid day range '{item}'"));
+                        }
+                        days.extend(first..=last);
+                    }
+                    None => days.push(parse(item)?),
+                }
+            }
+            Ok(days)
+        })
+        .collect()
+}
+
+/// Divides successive groups of items (e.g., the circuits of each label) into
+/// parts proportional to `ratios`. Each part receives at least the floor of its
+/// share of every group, and the leftover items of a group go to the parts that
+/// are furthest below their share of all items divided so far, so the part
+/// sizes stay proportional over many small groups.
+#[derive(Clone, Debug)]
+pub struct PartSizer {
+    ratios: Vec<u64>,
+    total: u64,
+    /// The number of items assigned to each part so far.
+    assigned: Vec<u64>,
+    /// The number of items divided so far.
+    items: u64,
+}
+
+impl PartSizer {
+    /// Creates a sizer into `ratios.len()` parts that has not divided any
+    /// items.
+    pub fn new(ratios: &[u32]) -> Self {
+        let ratios: Vec<u64> = ratios.iter().map(|&r| r as u64).collect();
+        Self {
+            total: ratios.iter().sum(),
+            assigned: vec![0; ratios.len()],
+            ratios,
+            items: 0,
+        }
+    }
+
+    /// Divides the next group of `n` items, returning the size of each part,
+    /// which sum to `n`.
+    pub fn divide(&mut self, n: usize) -> Vec<usize> {
+        if self.total == 0 {
+            return vec![0; self.ratios.len()];
+        }
+
+        let mut sizes: Vec<usize> = self
+            .ratios
+            .iter()
+            .map(|&r| (n as u64 * r / self.total) as usize)
+            .collect();
+        for (assigned, &size) in self.assigned.iter_mut().zip(sizes.iter()) {
+            *assigned += size as u64;
+        }
+        self.items += sizes.iter().sum::<usize>() as u64;
+
+        // Each leftover item goes to the part with the largest deficit
+        // `items * ratio / total - assigned`, scaled by `total`, breaking ties
+        // in favor of earlier parts.
+        let leftover = n - sizes.iter().sum::<usize>();
+        for _ in 0..leftover {
+            self.items += 1;
+            let deficit = |i: usize| {
+                (self.items * self.ratios[i]) as i128 - (self.assigned[i] * self.total) as i128
+            };
+            let best = (0..self.ratios.len())
+                .filter(|&i| self.ratios[i] > 0)
+                .max_by(|&a, &b| deficit(a).cmp(&deficit(b)).then(b.cmp(&a)))
+                .unwrap_or(0);
+            sizes[best] += 1;
+            self.assigned[best] += 1;
+        }
+
+        sizes
+    }
+}
+
+/// Divides `n` items into parts proportional to `ratios` using the largest
+/// remainder method, so the sizes always sum to `n`.
+pub fn part_sizes(n: usize, ratios: &[u32]) -> Vec<usize> {
+    let total: u64 = ratios.iter().map(|&r| r as u64).sum();
+    if total == 0 {
+        return vec![0; ratios.len()];
+    }
+
+    let mut sizes: Vec<usize> = ratios
+        .iter()
+        .map(|&r| (n as u64 * r as u64 / total) as usize)
+        .collect();
+
+    // Hand out the leftover items to the parts with the largest remainders,
+    // breaking ties in favor of earlier parts.
+    let mut order: Vec<usize> = (0..ratios.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(n as u64 * ratios[i] as u64 % total));
+
+    let leftover = n - sizes.iter().sum::<usize>();
+    for &i in order.iter().take(leftover) {
+        sizes[i] += 1;
+    }
+
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn part_sizer_rounds_each_group_to_its_size() {
+        let mut sizer = PartSizer::new(&[8, 1, 1]);
+        assert_eq!(sizer.divide(10), vec![8, 1, 1]);
+        for n in [0, 1, 2, 3, 7, 11, 100] {
+            assert_eq!(sizer.divide(n).iter().sum::<usize>(), n);
+        }
+    }
+
+    #[test]
+    fn part_sizer_stays_proportional_over_small_groups() {
+        // Dividing single items one at a time must not favor the first part.
+        let mut sizer = PartSizer::new(&[8, 1, 1]);
+        let mut totals = [0; 3];
+        for _ in 0..100 {
+            for (total, size) in totals.iter_mut().zip(sizer.divide(1)) {
+                *total += size;
+            }
+        }
+        assert_eq!(totals, [80, 10, 10]);
+
+        let mut sizer = PartSizer::new(&[1, 1, 1]);
+        let mut totals = [0; 3];
+        for _ in 0..10 {
+            for (total, size) in totals.iter_mut().zip(sizer.divide(2)) {
+                *total += size;
+            }
+        }
+        assert_eq!(totals.iter().sum::<usize>(), 20);
+        assert!(totals.iter().all(|&t| (6..=7).contains(&t)), "{totals:?}");
+    }
+
+    #[test]
+    fn part_sizer_matches_percentages_summing_to_one() {
+        // Ratios given as percentages, i.e., fractions 0.7, 0.2, and 0.1.
+        let mut sizer = PartSizer::new(&[70, 20, 10]);
+        let mut totals = [0; 3];
+        for n in (1..=40).cycle().take(200) {
+            for (total, size) in totals.iter_mut().zip(sizer.divide(n)) {
+                *total += size;
+            }
+        }
+        // 200 groups of 1 to 40 items, 4,100 items in all.
+        assert_eq!(totals, [2_870, 820, 410]);
+    }
+
+    #[test]
+    fn part_sizer_skips_parts_with_zero_ratio() {
+        let mut sizer = PartSizer::new(&[0, 1, 1]);
+        for _ in 0..10 {
+            assert_eq!(sizer.divide(3)[0], 0);
+        }
+        assert_eq!(PartSizer::new(&[0, 0]).divide(5), vec![0, 0]);
+    }
+
+    #[test]
+    fn part_sizes_sum_to_n() {
+        assert_eq!(part_sizes(10, &[8, 1, 1]), vec![8, 1, 1]);
+        assert_eq!(part_sizes(3, &[1, 1]), vec![2, 1]);
+        assert_eq!(part_sizes(7, &[3, 3, 4]), vec![2, 2, 3]);
+        for n in 0..50 {
+            assert_eq!(part_sizes(n, &[7, 2, 1]).iter().sum::<usize>(), n);
+        }
+    }
+}
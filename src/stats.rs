@@ -0,0 +1,1668 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::File as FsFile;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use hdf5::types::FixedAscii;
+use hdf5::{File, H5Type};
+use ndarray::Array1;
+
+use crate::export::csv_field;
+use crate::features::{mean, median, std_dev};
+use crate::index::write_index;
+use crate::{
+    CellCommand, Circuit, CircuitIndex, CircuitMeta, Direction, IndexArrayEntry, MAX_CELLS,
+    RelayCommand, ServiceCategory,
+};
+
+/// The name of the dataset holding the `LabelStats` of each label.
+pub const LABEL_STATS_NAME: &str = "/stats/labels";
+
+/// The name of the dataset holding the `PortDayCount` of each port and day.
+pub const PORT_STATS_NAME: &str = "/stats/ports";
+
+/// The name of the group holding the length distributions written by
+/// `write_len_stats`.
+pub const LEN_STATS_NAME: &str = "/stats/len";
+
+/// The percentiles of circuit lengths stored in `LenPercentiles`.
+pub const LEN_PERCENTILES: [f64; 7] = [1.0, 5.0, 25.0, 50.0, 75.0, 95.0, 99.0];
+
+/// The service categories, indexed by their values.
+const SERVICES: [ServiceCategory; 5] = [
+    ServiceCategory::OTHER,
+    ServiceCategory::HTTP,
+    ServiceCategory::HTTPS,
+    ServiceCategory::SMTP,
+    ServiceCategory::IMAP,
+];
+
+/// The number of circuits read at a time when computing statistics.
+const READ_BATCH: usize = 10_000;
+
+/// Summary statistics accumulated while streaming through a circuits dataset.
+#[derive(Clone, Debug)]
+pub struct Stats {
+    total: usize,
+    days: BTreeMap<u8, usize>,
+    labels: HashMap<FixedAscii<44>, usize>,
+    ports: BTreeMap<u16, usize>,
+    /// The number of circuits with each port on each day.
+    port_days: BTreeMap<(u16, u8), usize>,
+    /// The number of circuits of each length, indexed by length.
+    len_hist: Vec<usize>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            total: 0,
+            days: BTreeMap::new(),
+            labels: HashMap::new(),
+            ports: BTreeMap::new(),
+            port_days: BTreeMap::new(),
+            len_hist: vec![0; MAX_CELLS + 1],
+        }
+    }
+}
+
+impl Stats {
+    /// Creates a `Stats` that has not yet seen any circuits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accounts for `circuit` in the statistics.
+    pub fn add<const N: usize>(&mut self, circuit: &Circuit<N>) {
+        self.add_meta(&CircuitMeta::from(circuit));
+    }
+
+    /// Accounts for the circuit with `meta` in the statistics, which only
+    /// depend on the meta-data of circuits.
+    pub fn add_meta(&mut self, meta: &CircuitMeta) {
+        self.total += 1;
+        *self.days.entry(meta.day).or_default() += 1;
+        *self.labels.entry(meta.label()).or_default() += 1;
+        *self.ports.entry(meta.port).or_default() += 1;
+        *self.port_days.entry((meta.port, meta.day)).or_default() += 1;
+
+        let len = meta.len as usize;
+        if len >= self.len_hist.len() {
+            self.len_hist.resize(len + 1, 0);
+        }
+        self.len_hist[len] += 1;
+    }
+
+    /// The total number of circuits.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// The number of circuits observed on each day.
+    pub fn days(&self) -> &BTreeMap<u8, usize> {
+        &self.days
+    }
+
+    /// The number of circuits with each port.
+    pub fn ports(&self) -> &BTreeMap<u16, usize> {
+        &self.ports
+    }
+
+    /// The number of circuits with each port on each day, keyed by port and
+    /// then day.
+    pub fn port_days(&self) -> &BTreeMap<(u16, u8), usize> {
+        &self.port_days
+    }
+
+    /// The number of circuits with each port on each day, sorted by port and
+    /// then day.
+    pub fn port_day_counts(&self) -> Vec<PortDayCount> {
+        self.port_days
+            .iter()
+            .map(|(&(port, day), &count)| PortDayCount {
+                port,
+                day,
+                count: count as u64,
+            })
+            .collect()
+    }
+
+    /// The number of circuits in each service category, in the order of the
+    /// categories' values, omitting categories without circuits.
+    pub fn services(&self) -> Vec<(ServiceCategory, usize)> {
+        let mut counts = [0; SERVICES.len()];
+        for (&port, &count) in self.ports.iter() {
+            counts[ServiceCategory::from_port(port) as usize] += count;
+        }
+        SERVICES
+            .into_iter()
+            .zip(counts)
+            .filter(|&(_, count)| count > 0)
+            .collect()
+    }
+
+    /// The number of circuits in each service category on each day, in the
+    /// order of the categories' values and then days, omitting categories
+    /// without circuits on a day.
+    pub fn service_days(&self) -> Vec<(ServiceCategory, u8, usize)> {
+        let mut counts: BTreeMap<(u8, u8), usize> = BTreeMap::new();
+        for (&(port, day), &count) in self.port_days.iter() {
+            let service = ServiceCategory::from_port(port) as u8;
+            *counts.entry((service, day)).or_default() += count;
+        }
+        counts
+            .into_iter()
+            .map(|((service, day), count)| (SERVICES[service as usize], day, count))
+            .collect()
+    }
+
+    /// The number of distinct labels.
+    pub fn num_labels(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// The `k` labels with the most circuits and their circuit counts, sorted
+    /// by decreasing count and then by label.
+    pub fn top_labels(&self, k: usize) -> Vec<(String, usize)> {
+        top_k(&self.labels, k)
+    }
+
+    /// The imbalance of the number of circuits of each label.
+    pub fn imbalance(&self) -> Imbalance {
+        Imbalance::from_counts(self.labels.values().copied())
+    }
+
+    /// The nearest-rank `p`th percentile (0-100) of circuit lengths, or `None`
+    /// if no circuits were seen.
+    pub fn len_percentile(&self, p: f64) -> Option<u16> {
+        if self.total == 0 {
+            return None;
+        }
+
+        let counts = self.len_hist.iter().map(|&c| c as u64);
+        hist_percentile(counts, self.total as u64, p).map(|len| len as u16)
+    }
+
+    /// The fraction of circuits with each length or shorter, indexed by
+    /// length.
+    fn len_cdf(&self, max_len: usize) -> Vec<f64> {
+        let mut sum = 0;
+        (0..=max_len)
+            .map(|len| {
+                sum += self.len_hist.get(len).copied().unwrap_or(0);
+                sum as f64 / self.total.max(1) as f64
+            })
+            .collect()
+    }
+}
+
+/// Computes the `Stats` of the circuits in the `/circuits` dataset of `file`,
+/// reading only their meta-data. `progress` is called with the number of
+/// circuits read after each batch.
+pub fn summary_stats<F>(file: &File, mut progress: F) -> hdf5::Result<Stats>
+where
+    F: FnMut(usize),
+{
+    let circuits = file.dataset("/circuits")?;
+    let size = circuits.size();
+    let mut stats = Stats::new();
+
+    for begin in (0..size).step_by(READ_BATCH) {
+        let end = std::cmp::min(begin + READ_BATCH, size);
+        let metas: Array1<CircuitMeta> = circuits.read_slice(ndarray::s![begin..end])?;
+        metas.iter().for_each(|m| stats.add_meta(m));
+        progress(end - begin);
+    }
+
+    Ok(stats)
+}
+
+/// The side-by-side `Stats` of two datasets `a` and `b`, e.g., a converted or
+/// filtered dataset and the source it should match. Each count is given as a
+/// pair of the count in `a` and the count in `b`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatsDiff {
+    pub totals: (usize, usize),
+    /// The number of circuits of each label in either dataset, sorted by
+    /// decreasing absolute difference and then by label.
+    pub labels: Vec<(String, usize, usize)>,
+    /// The number of circuits on each day in either dataset, sorted by day.
+    pub days: Vec<(u8, usize, usize)>,
+    /// The number of circuits with each port in either dataset, sorted by
+    /// port.
+    pub ports: Vec<(u16, usize, usize)>,
+    /// The `LEN_PERCENTILES` of circuit lengths in each dataset.
+    pub len_percentiles: Vec<(f64, Option<u16>, Option<u16>)>,
+    /// The largest difference between the distribution functions of circuit
+    /// lengths, i.e., the Kolmogorov-Smirnov statistic of the shift in the
+    /// length distribution, from 0 for identical distributions to 1 for
+    /// disjoint ones.
+    pub len_ks: f64,
+}
+
+impl StatsDiff {
+    /// Compares the statistics `a` and `b`.
+    pub fn new(a: &Stats, b: &Stats) -> Self {
+        let mut labels: Vec<(String, usize, usize)> = a
+            .labels
+            .keys()
+            .chain(b.labels.keys().filter(|l| !a.labels.contains_key(*l)))
+            .map(|label| {
+                let count = |s: &Stats| s.labels.get(label).copied().unwrap_or(0);
+                (label.to_string(), count(a), count(b))
+            })
+            .collect();
+        labels.sort_by(|x, y| {
+            x.1.abs_diff(x.2)
+                .cmp(&y.1.abs_diff(y.2))
+                .reverse()
+                .then_with(|| x.0.cmp(&y.0))
+        });
+
+        let max_len = std::cmp::max(a.len_hist.len(), b.len_hist.len());
+        let len_ks = if a.total > 0 && b.total > 0 {
+            a.len_cdf(max_len)
+                .iter()
+                .zip(b.len_cdf(max_len))
+                .map(|(x, y)| (x - y).abs())
+                .fold(0.0, f64::max)
+        } else {
+            0.0
+        };
+
+        Self {
+            totals: (a.total, b.total),
+            labels,
+            days: side_by_side(&a.days, &b.days),
+            ports: side_by_side(&a.ports, &b.ports),
+            len_percentiles: LEN_PERCENTILES
+                .iter()
+                .map(|&p| (p, a.len_percentile(p), b.len_percentile(p)))
+                .collect(),
+            len_ks,
+        }
+    }
+
+    /// The labels whose number of circuits differs, in the order of `labels`.
+    pub fn changed_labels(&self) -> impl Iterator<Item = &(String, usize, usize)> {
+        self.labels.iter().take_while(|(_, a, b)| a != b)
+    }
+
+    /// The number of labels only in `a`, and the number only in `b`.
+    pub fn unique_labels(&self) -> (usize, usize) {
+        let only_a = self.labels.iter().filter(|(_, _, b)| *b == 0).count();
+        let only_b = self.labels.iter().filter(|(_, a, _)| *a == 0).count();
+        (only_a, only_b)
+    }
+
+    /// Returns true if the statistics of `a` and `b` are the same.
+    pub fn is_identical(&self) -> bool {
+        self.totals.0 == self.totals.1
+            && self.changed_labels().next().is_none()
+            && self.days.iter().all(|(_, a, b)| a == b)
+            && self.ports.iter().all(|(_, a, b)| a == b)
+            && self.len_ks == 0.0
+    }
+}
+
+/// Pairs the counts of every key of `a` or `b`, sorted by key.
+fn side_by_side<K: Copy + Ord>(
+    a: &BTreeMap<K, usize>,
+    b: &BTreeMap<K, usize>,
+) -> Vec<(K, usize, usize)> {
+    let keys: BTreeSet<K> = a.keys().chain(b.keys()).copied().collect();
+    keys.into_iter()
+        .map(|k| {
+            let count = |m: &BTreeMap<K, usize>| m.get(&k).copied().unwrap_or(0);
+            (k, count(a), count(b))
+        })
+        .collect()
+}
+
+/// The name of the dataset holding the `Imbalance` of the labels.
+pub const IMBALANCE_NAME: &str = "/stats/imbalance";
+
+/// The fractions of all circuits for which `Imbalance` counts the most frequent
+/// labels holding them.
+pub const IMBALANCE_HEAD_FRACTIONS: [f64; 4] = [0.5, 0.8, 0.9, 0.99];
+
+/// The numbers of circuits for which `Imbalance` counts the labels with at
+/// most that many circuits.
+pub const IMBALANCE_TAIL_COUNTS: [u64; 4] = [1, 10, 100, 1_000];
+
+/// Measures of how unevenly the circuits are spread over the labels, computed
+/// the same way wherever they are reported.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct Imbalance {
+    pub labels: u64,
+    pub circuits: u64,
+    /// The number of circuits of the least frequent label.
+    pub min_count: u64,
+    /// The number of circuits of the most frequent label.
+    pub max_count: u64,
+    /// The Gini coefficient of the label counts, from 0 if every label has
+    /// the same number of circuits toward 1 if one label has them all.
+    pub gini: f64,
+    /// The Shannon entropy in bits of the label distribution.
+    pub entropy: f64,
+    /// The number of equally frequent labels with the same entropy, i.e.,
+    /// `2^entropy`, which equals `labels` for balanced labels.
+    pub effective_classes: f64,
+    /// The smallest number of most frequent labels holding each fraction of
+    /// `IMBALANCE_HEAD_FRACTIONS` of the circuits.
+    pub head: [u64; 4],
+    /// The number of labels with at most each number of
+    /// `IMBALANCE_TAIL_COUNTS` circuits.
+    pub tail: [u64; 4],
+}
+
+impl Imbalance {
+    /// Computes the imbalance of labels with the number of circuits `counts`,
+    /// ignoring labels without circuits.
+    pub fn from_counts<I: IntoIterator<Item = usize>>(counts: I) -> Self {
+        let mut counts: Vec<u64> = counts
+            .into_iter()
+            .filter(|&c| c > 0)
+            .map(|c| c as u64)
+            .collect();
+        counts.sort_unstable();
+        let n = counts.len() as f64;
+        let total: u64 = counts.iter().sum();
+
+        let gini = if total > 0 {
+            let weighted: f64 = counts
+                .iter()
+                .enumerate()
+                .map(|(i, &c)| (i + 1) as f64 * c as f64)
+                .sum();
+            2.0 * weighted / (n * total as f64) - (n + 1.0) / n
+        } else {
+            0.0
+        };
+
+        let entropy: f64 = counts
+            .iter()
+            .map(|&c| c as f64 / total as f64)
+            .map(|p| -p * p.log2())
+            .sum();
+
+        let head = IMBALANCE_HEAD_FRACTIONS.map(|fraction| {
+            let target = fraction * total as f64;
+            let mut sum = 0;
+            let covering = counts.iter().rev().position(|&c| {
+                sum += c;
+                sum as f64 >= target
+            });
+            covering.map_or(counts.len(), |i| i + 1) as u64
+        });
+        let tail = IMBALANCE_TAIL_COUNTS.map(|max| counts.partition_point(|&c| c <= max) as u64);
+
+        Self {
+            labels: counts.len() as u64,
+            circuits: total,
+            min_count: counts.first().copied().unwrap_or(0),
+            max_count: counts.last().copied().unwrap_or(0),
+            gini,
+            entropy,
+            effective_classes: if counts.is_empty() {
+                0.0
+            } else {
+                entropy.exp2()
+            },
+            head,
+            tail,
+        }
+    }
+}
+
+/// The number of circuits with a port on a day.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct PortDayCount {
+    pub port: u16,
+    pub day: u8,
+    pub count: u64,
+}
+
+/// Returns the nearest-rank `p`th percentile (0-100) of the values counted by
+/// the histogram `counts` with `total` values, or `None` if it is empty.
+fn hist_percentile<I>(counts: I, total: u64, p: f64) -> Option<usize>
+where
+    I: IntoIterator<Item = u64>,
+{
+    if total == 0 {
+        return None;
+    }
+
+    let rank = ((p.clamp(0.0, 100.0) / 100.0) * total as f64).ceil() as u64;
+    let rank = rank.max(1);
+    let mut seen = 0;
+
+    for (value, count) in counts.into_iter().enumerate() {
+        seen += count;
+        if seen >= rank {
+            return Some(value);
+        }
+    }
+
+    None
+}
+
+/// The number of circuits with a label, the days they appear on, and
+/// statistics of their lengths, e.g., to pick closed-world label sets.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct LabelStats {
+    pub label: FixedAscii<44>,
+    pub count: u32,
+    /// The number of distinct days with a circuit with the label.
+    pub days: u16,
+    pub first_day: u8,
+    pub last_day: u8,
+    pub min_len: u16,
+    pub max_len: u16,
+    pub mean_len: f64,
+    pub median_len: f64,
+    /// The population standard deviation of the lengths.
+    pub std_len: f64,
+}
+
+/// The days and lengths of the circuits with one label.
+#[derive(Clone, Debug, Default)]
+struct LabelCircuits {
+    /// A bit per day.
+    days: [u64; 4],
+    lens: Vec<u16>,
+}
+
+/// Accumulates the `LabelStats` of every label while streaming through a
+/// circuits dataset.
+#[derive(Clone, Debug, Default)]
+pub struct LabelStatsBuilder {
+    labels: HashMap<FixedAscii<44>, LabelCircuits>,
+}
+
+impl LabelStatsBuilder {
+    /// Creates a builder that has not yet seen any circuits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accounts for `circuit` in the statistics of its label.
+    pub fn add(&mut self, circuit: &Circuit) {
+        self.add_circuit(circuit.label(), circuit.day, circuit.len);
+    }
+
+    /// Like `add`, but for the meta-data of a circuit.
+    pub fn add_meta(&mut self, meta: &CircuitMeta) {
+        self.add_circuit(meta.label(), meta.day, meta.len);
+    }
+
+    fn add_circuit(&mut self, label: FixedAscii<44>, day: u8, len: u16) {
+        let circuits = self.labels.entry(label).or_default();
+        circuits.days[day as usize / 64] |= 1 << (day % 64);
+        circuits.lens.push(len);
+    }
+
+    /// The statistics of every label seen, sorted by label.
+    pub fn finish(self) -> Vec<LabelStats> {
+        let mut stats: Vec<LabelStats> = self
+            .labels
+            .into_iter()
+            .map(|(label, circuits)| {
+                let bits = &circuits.days;
+                let day_set =
+                    (0..=u8::MAX).filter(|&d| bits[d as usize / 64] & (1 << (d % 64)) != 0);
+                let lens: Vec<f64> = circuits.lens.iter().map(|&l| f64::from(l)).collect();
+                LabelStats {
+                    label,
+                    count: circuits.lens.len() as u32,
+                    days: bits.iter().map(|b| b.count_ones() as u16).sum(),
+                    first_day: day_set.clone().next().unwrap_or(0),
+                    last_day: day_set.last().unwrap_or(0),
+                    min_len: circuits.lens.iter().copied().min().unwrap_or(0),
+                    max_len: circuits.lens.iter().copied().max().unwrap_or(0),
+                    mean_len: mean(&lens),
+                    median_len: median(&lens),
+                    std_len: std_dev(&lens),
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| a.label.as_str().cmp(b.label.as_str()));
+        stats
+    }
+}
+
+/// Computes the `LabelStats` of every label of the circuits in the `/circuits`
+/// dataset of `file`, reading only their meta-data, and writes them to
+/// `/stats/labels`, replacing any existing dataset. Returns the statistics.
+pub fn write_label_stats(file: &File) -> hdf5::Result<Vec<LabelStats>> {
+    let circuits = file.dataset("/circuits")?;
+    let size = circuits.size();
+    let mut builder = LabelStatsBuilder::new();
+
+    for begin in (0..size).step_by(READ_BATCH) {
+        let end = std::cmp::min(begin + READ_BATCH, size);
+        let metas: Array1<CircuitMeta> = circuits.read_slice(ndarray::s![begin..end])?;
+        metas.iter().for_each(|m| builder.add_meta(m));
+    }
+
+    let stats = builder.finish();
+    write_index(file, LABEL_STATS_NAME, &stats)?;
+    Ok(stats)
+}
+
+/// Writes `stats` to a CSV file at `path` with a header row.
+pub fn write_label_stats_csv<P: AsRef<Path>>(path: P, stats: &[LabelStats]) -> io::Result<()> {
+    let mut out = BufWriter::new(FsFile::create(path)?);
+    writeln!(
+        out,
+        "label,count,days,first_day,last_day,min_len,max_len,mean_len,median_len,std_len"
+    )?;
+    for s in stats {
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{},{}",
+            csv_field(s.label.as_str()),
+            s.count,
+            s.days,
+            s.first_day,
+            s.last_day,
+            s.min_len,
+            s.max_len,
+            s.mean_len,
+            s.median_len,
+            s.std_len
+        )?;
+    }
+    out.flush()
+}
+
+/// The number of circuits in a group and the `LEN_PERCENTILES` of their
+/// lengths.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct LenPercentiles {
+    pub count: u64,
+    pub percentiles: [u16; 7],
+}
+
+impl LenPercentiles {
+    /// The percentiles of the lengths counted by the histogram `hist`.
+    fn from_hist(hist: &[u64]) -> Self {
+        let count = hist.iter().sum();
+        Self {
+            count,
+            percentiles: LEN_PERCENTILES
+                .map(|p| hist_percentile(hist.iter().copied(), count, p).unwrap_or(0) as u16),
+        }
+    }
+}
+
+/// The length percentiles of the circuits of one day.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct DayLenPercentiles {
+    pub day: u8,
+    pub len: LenPercentiles,
+}
+
+/// The length percentiles of the circuits with one label.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct LabelLenPercentiles {
+    pub label: FixedAscii<44>,
+    pub len: LenPercentiles,
+}
+
+/// Length histograms of the circuits of a dataset overall, per day, and per
+/// label, accumulated from only their meta-data.
+#[derive(Clone, Debug, Default)]
+pub struct LenStats {
+    /// The number of circuits of each length, indexed by length.
+    overall: Vec<u64>,
+    days: BTreeMap<u8, Vec<u64>>,
+    /// Label histograms are sparse, as most labels have few circuits.
+    labels: HashMap<FixedAscii<44>, BTreeMap<u16, u64>>,
+}
+
+impl LenStats {
+    /// Creates statistics that have not yet seen any circuits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accounts for the circuit with `meta`.
+    pub fn add_meta(&mut self, meta: &CircuitMeta) {
+        let len = meta.len as usize;
+        for hist in [&mut self.overall, self.days.entry(meta.day).or_default()] {
+            if len >= hist.len() {
+                hist.resize(std::cmp::max(len + 1, MAX_CELLS + 1), 0);
+            }
+            hist[len] += 1;
+        }
+        *self
+            .labels
+            .entry(meta.label())
+            .or_default()
+            .entry(meta.len)
+            .or_default() += 1;
+    }
+
+    /// The number of circuits of each length, indexed by length.
+    pub fn hist(&self) -> &[u64] {
+        &self.overall
+    }
+
+    /// The length percentiles of all circuits.
+    pub fn percentiles(&self) -> LenPercentiles {
+        LenPercentiles::from_hist(&self.overall)
+    }
+
+    /// The length percentiles of the circuits of each day, sorted by day.
+    pub fn day_percentiles(&self) -> Vec<DayLenPercentiles> {
+        self.days
+            .iter()
+            .map(|(&day, hist)| DayLenPercentiles {
+                day,
+                len: LenPercentiles::from_hist(hist),
+            })
+            .collect()
+    }
+
+    /// The length percentiles of the circuits with each label, sorted by
+    /// label.
+    pub fn label_percentiles(&self) -> Vec<LabelLenPercentiles> {
+        let mut labels: Vec<LabelLenPercentiles> = self
+            .labels
+            .iter()
+            .map(|(&label, sparse)| {
+                let max = sparse.keys().last().map_or(0, |&l| l as usize);
+                let mut hist = vec![0u64; max + 1];
+                sparse.iter().for_each(|(&l, &c)| hist[l as usize] = c);
+                LabelLenPercentiles {
+                    label,
+                    len: LenPercentiles::from_hist(&hist),
+                }
+            })
+            .collect();
+        labels.sort_by(|a, b| a.label.as_str().cmp(b.label.as_str()));
+        labels
+    }
+
+    /// Writes the statistics under `/stats/len` in `file`, replacing any
+    /// existing datasets: the overall histogram to `hist`, the histogram of
+    /// each day to the rows of `day_hist`, and the percentiles to
+    /// `percentiles`, `days`, and `labels`, where row `k` of `day_hist` belongs
+    /// to row `k` of `days`.
+    pub fn write(&self, file: &File) -> hdf5::Result<()> {
+        let width = std::cmp::max(MAX_CELLS + 1, self.overall.len());
+        let mut overall = self.overall.clone();
+        overall.resize(width, 0);
+        let mut day_hist = ndarray::Array2::<u64>::zeros((self.days.len(), width));
+        for (k, hist) in self.days.values().enumerate() {
+            for (len, &count) in hist.iter().enumerate() {
+                day_hist[[k, len]] = count;
+            }
+        }
+
+        write_index(file, &format!("{LEN_STATS_NAME}/hist"), &overall)?;
+        write_index(
+            file,
+            &format!("{LEN_STATS_NAME}/percentiles"),
+            &[self.percentiles()],
+        )?;
+        write_index(
+            file,
+            &format!("{LEN_STATS_NAME}/days"),
+            &self.day_percentiles(),
+        )?;
+        write_index(
+            file,
+            &format!("{LEN_STATS_NAME}/labels"),
+            &self.label_percentiles(),
+        )?;
+
+        let name = format!("{LEN_STATS_NAME}/day_hist");
+        if file.link_exists(&name) {
+            // Note this unlinks but does not reclaim its storage space.
+            file.unlink(&name)?;
+        }
+        file.new_dataset_builder()
+            .with_data(&day_hist)
+            .create(name.as_str())?;
+        Ok(())
+    }
+}
+
+/// Computes the `LenStats` of the circuits in the `/circuits` dataset of
+/// `file`, reading only their meta-data, and writes them under `/stats/len`.
+/// Returns the statistics.
+pub fn write_len_stats(file: &File) -> hdf5::Result<LenStats> {
+    let circuits = file.dataset("/circuits")?;
+    let size = circuits.size();
+    let mut stats = LenStats::new();
+
+    for begin in (0..size).step_by(READ_BATCH) {
+        let end = std::cmp::min(begin + READ_BATCH, size);
+        let metas: Array1<CircuitMeta> = circuits.read_slice(ndarray::s![begin..end])?;
+        metas.iter().for_each(|m| stats.add_meta(m));
+    }
+
+    stats.write(file)?;
+    Ok(stats)
+}
+
+/// The name of the dataset holding the `CommandCount` of each combination of
+/// day, direction, and commands.
+pub const COMMAND_STATS_NAME: &str = "/stats/commands";
+
+/// The number of cells with a cell command and relay command sent in a
+/// direction on a day.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct CommandCount {
+    pub day: u8,
+    pub direction: Direction,
+    pub cell_cmd: CellCommand,
+    pub relay_cmd: RelayCommand,
+    pub count: u64,
+}
+
+/// Counts of the cell and relay commands of the valid cells of circuits, per
+/// direction and day, which quantify how much of a dataset is flow control
+/// (`SENDME`), long-range padding (`DROP`), or link padding (`PADDING`).
+#[derive(Clone, Debug, Default)]
+pub struct CommandStats {
+    /// Counts keyed by day, direction, cell command, and relay command, stored
+    /// as their raw values since the enums are not ordered.
+    counts: BTreeMap<(u8, i8, u8, u8), u64>,
+}
+
+impl CommandStats {
+    /// Creates statistics that have not yet seen any circuits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accounts for the valid cells of `circuit`.
+    pub fn add(&mut self, circuit: &Circuit) {
+        for cell in circuit.valid_cells() {
+            let key = (
+                circuit.day,
+                cell.direction as i8,
+                cell.cell_cmd as u8,
+                cell.relay_cmd as u8,
+            );
+            *self.counts.entry(key).or_default() += 1;
+        }
+    }
+
+    /// The non-zero counts, sorted by day, direction, cell command, and relay
+    /// command.
+    pub fn counts(&self) -> Vec<CommandCount> {
+        self.counts
+            .iter()
+            .filter_map(|(&(day, direction, cell_cmd, relay_cmd), &count)| {
+                Some(CommandCount {
+                    day,
+                    direction: Direction::try_from(direction).ok()?,
+                    cell_cmd: CellCommand::try_from(cell_cmd).ok()?,
+                    relay_cmd: RelayCommand::try_from(relay_cmd).ok()?,
+                    count,
+                })
+            })
+            .collect()
+    }
+
+    /// The counts summed over all days, sorted by direction, cell command, and
+    /// relay command.
+    pub fn totals(&self) -> Vec<CommandCount> {
+        let mut totals: BTreeMap<(i8, u8, u8), CommandCount> = BTreeMap::new();
+        for c in self.counts() {
+            let key = (c.direction as i8, c.cell_cmd as u8, c.relay_cmd as u8);
+            totals
+                .entry(key)
+                .and_modify(|t| t.count += c.count)
+                .or_insert(CommandCount { day: 0, ..c });
+        }
+        totals.into_values().collect()
+    }
+
+    /// The number of cells sent in `direction` on `day`, or on every day if
+    /// `day` is `None`.
+    pub fn cells(&self, direction: Direction, day: Option<u8>) -> u64 {
+        self.counts
+            .iter()
+            .filter(|((d, dir, _, _), _)| {
+                *dir == direction as i8 && day.is_none_or(|day| *d == day)
+            })
+            .map(|(_, count)| count)
+            .sum()
+    }
+}
+
+/// Counts the commands of the valid cells of the circuits in the `/circuits`
+/// dataset of `file`, writes the `CommandCount`s to `/stats/commands`, and
+/// returns the statistics. `progress` is called with the number of circuits
+/// read after each batch.
+pub fn write_command_stats<F>(file: &File, mut progress: F) -> hdf5::Result<CommandStats>
+where
+    F: FnMut(usize),
+{
+    let circuits = file.dataset("/circuits")?;
+    let size = circuits.size();
+    let step = 1_000; // cells are large, so read fewer circuits at a time
+    let mut stats = CommandStats::new();
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+        let batch: Array1<Circuit> = circuits.read_slice(ndarray::s![begin..end])?;
+        batch.iter().for_each(|c| stats.add(c));
+        progress(end - begin);
+    }
+
+    write_index(file, COMMAND_STATS_NAME, &stats.counts())?;
+    Ok(stats)
+}
+
+/// The name of the group holding the inter-arrival time distributions written
+/// by `write_iat_stats`.
+pub const IAT_STATS_NAME: &str = "/stats/iat";
+
+/// The percentiles of inter-arrival times stored in `IatSummary`.
+pub const IAT_PERCENTILES: [f64; 7] = [1.0, 5.0, 25.0, 50.0, 75.0, 95.0, 99.0];
+
+/// The smallest inter-arrival time in seconds resolved by `IatHistogram`.
+pub const IAT_MIN: f64 = 1e-6;
+
+/// The largest inter-arrival time in seconds resolved by `IatHistogram`.
+pub const IAT_MAX: f64 = 100.0;
+
+/// A histogram of inter-arrival times with logarithmically spaced bins from
+/// `IAT_MIN` to `IAT_MAX`. Shorter times, including zero, are counted in an
+/// underflow bin before them, and longer times in an overflow bin after them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IatHistogram {
+    bins_per_decade: usize,
+    /// The underflow bin, the logarithmic bins, and the overflow bin.
+    counts: Vec<u64>,
+}
+
+impl IatHistogram {
+    /// Creates an empty histogram with `bins_per_decade` bins for each factor of
+    /// 10 between `IAT_MIN` and `IAT_MAX`.
+    pub fn new(bins_per_decade: usize) -> Self {
+        let bins_per_decade = bins_per_decade.max(1);
+        let decades = (IAT_MAX / IAT_MIN).log10().round() as usize;
+        Self {
+            bins_per_decade,
+            counts: vec![0; decades * bins_per_decade + 2],
+        }
+    }
+
+    /// Counts the inter-arrival time `gap` in seconds.
+    pub fn add(&mut self, gap: f64) {
+        let last = self.counts.len() - 1;
+        let bin = match gap {
+            g if g.is_nan() || g < IAT_MIN => 0,
+            g if g >= IAT_MAX => last,
+            g => {
+                let bin = ((g / IAT_MIN).log10() * self.bins_per_decade as f64).floor();
+                (bin as usize + 1).min(last - 1)
+            }
+        };
+        self.counts[bin] += 1;
+    }
+
+    /// The edges of the logarithmic bins in seconds, from `IAT_MIN` to
+    /// `IAT_MAX`, so that bin `k` of `counts` after the underflow bin spans
+    /// `edges[k - 1]..edges[k]`.
+    pub fn edges(&self) -> Vec<f64> {
+        (0..self.counts.len() - 1)
+            .map(|k| IAT_MIN * 10f64.powf(k as f64 / self.bins_per_decade as f64))
+            .collect()
+    }
+
+    /// The number of times in the underflow bin, each logarithmic bin, and the
+    /// overflow bin.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// The number of times counted.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns the `p`th percentile (0-100) of the counted times, resolved to
+    /// the upper edge of its bin, or `None` if the histogram is empty. Times in
+    /// the underflow bin are reported as `IAT_MIN`, and times in the overflow
+    /// bin as infinity.
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        let bin = hist_percentile(self.counts.iter().copied(), self.total(), p)?;
+        match bin {
+            0 => Some(IAT_MIN),
+            b if b == self.counts.len() - 1 => Some(f64::INFINITY),
+            b => Some(self.edges()[b]),
+        }
+    }
+}
+
+/// The number of inter-arrival times in a distribution and their
+/// `IAT_PERCENTILES`.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct IatSummary {
+    pub count: u64,
+    pub percentiles: [f64; 7],
+}
+
+impl IatSummary {
+    fn from_hist(hist: &IatHistogram) -> Self {
+        Self {
+            count: hist.total(),
+            percentiles: IAT_PERCENTILES.map(|p| hist.percentile(p).unwrap_or(0.0)),
+        }
+    }
+}
+
+/// The distributions of the times between the valid cells of circuits: between
+/// each cell and the cell before it regardless of direction, and between each
+/// cell sent in a direction and the cell before it in the same direction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IatStats {
+    pub all: IatHistogram,
+    pub client_to_server: IatHistogram,
+    pub server_to_client: IatHistogram,
+    /// The number of circuits accounted for.
+    pub circuits: u64,
+}
+
+impl IatStats {
+    /// Creates statistics that have not yet seen any circuits, with histograms
+    /// of `bins_per_decade` bins per factor of 10.
+    pub fn new(bins_per_decade: usize) -> Self {
+        Self {
+            all: IatHistogram::new(bins_per_decade),
+            client_to_server: IatHistogram::new(bins_per_decade),
+            server_to_client: IatHistogram::new(bins_per_decade),
+            circuits: 0,
+        }
+    }
+
+    /// Accounts for the valid cells of `circuit`.
+    pub fn add(&mut self, circuit: &Circuit) {
+        let cells = circuit.valid_cells();
+        let (mut prev_out, mut prev_in) = (None, None);
+
+        for w in cells.windows(2) {
+            self.all.add(w[1].time - w[0].time);
+        }
+        for cell in cells {
+            let (hist, prev) = match cell.direction {
+                Direction::CLIENT_TO_SERVER => (&mut self.client_to_server, &mut prev_out),
+                Direction::SERVER_TO_CLIENT => (&mut self.server_to_client, &mut prev_in),
+                Direction::PADDING => continue,
+            };
+            if let Some(prev) = *prev {
+                hist.add(cell.time - prev);
+            }
+            *prev = Some(cell.time);
+        }
+        self.circuits += 1;
+    }
+
+    /// The summaries of the `all`, `client_to_server`, and `server_to_client`
+    /// distributions, in that order.
+    pub fn summaries(&self) -> [IatSummary; 3] {
+        [&self.all, &self.client_to_server, &self.server_to_client].map(IatSummary::from_hist)
+    }
+
+    /// Writes the statistics under `/stats/iat` in `file`, replacing any
+    /// existing datasets: the bin edges to `edges`, the counts of the `all`,
+    /// `client_to_server`, and `server_to_client` histograms to datasets of
+    /// those names, and their summaries to the rows of `percentiles` in that
+    /// order. The number of circuits is stored in the `circuits` attribute of
+    /// `percentiles`.
+    pub fn write(&self, file: &File) -> hdf5::Result<()> {
+        write_index(file, &format!("{IAT_STATS_NAME}/edges"), &self.all.edges())?;
+        for (name, hist) in [
+            ("all", &self.all),
+            ("client_to_server", &self.client_to_server),
+            ("server_to_client", &self.server_to_client),
+        ] {
+            write_index(file, &format!("{IAT_STATS_NAME}/{name}"), hist.counts())?;
+        }
+
+        let name = format!("{IAT_STATS_NAME}/percentiles");
+        write_index(file, &name, &self.summaries())?;
+        file.dataset(&name)?
+            .new_attr::<u64>()
+            .create("circuits")?
+            .write_scalar(&self.circuits)?;
+        Ok(())
+    }
+}
+
+/// Computes the `IatStats` of the circuits in the `/circuits` dataset of `file`
+/// with `bins_per_decade` bins per factor of 10, and writes them under
+/// `/stats/iat`. Only the circuits at the sorted positions `sample` are read
+/// if given, e.g., as drawn by `sample::uniform`. `progress` is called with
+/// the number of circuits read after each batch. Returns the statistics.
+pub fn write_iat_stats<F>(
+    file: &File,
+    bins_per_decade: usize,
+    sample: Option<&[CircuitIndex]>,
+    mut progress: F,
+) -> hdf5::Result<IatStats>
+where
+    F: FnMut(usize),
+{
+    let circuits = file.dataset("/circuits")?;
+    let step = 1_000; // cells are large, so read fewer circuits at a time
+    let mut stats = IatStats::new(bins_per_decade);
+
+    match sample {
+        Some(sample) => {
+            for batch in sample.chunks(step) {
+                let indices: Vec<usize> = batch.iter().map(|&i| i as usize).collect();
+                let batch = circuits.read_slice_1d::<Circuit, _>(indices)?;
+                batch.iter().for_each(|c| stats.add(c));
+                progress(batch.len());
+            }
+        }
+        None => {
+            let size = circuits.size();
+            for begin in (0..size).step_by(step) {
+                let end = std::cmp::min(begin + step, size);
+                let batch: Array1<Circuit> = circuits.read_slice(ndarray::s![begin..end])?;
+                batch.iter().for_each(|c| stats.add(c));
+                progress(end - begin);
+            }
+        }
+    }
+
+    stats.write(file)?;
+    Ok(stats)
+}
+
+/// The name of the dataset holding the `DayVolume` of each day.
+pub const DAY_VOLUME_NAME: &str = "/stats/days";
+
+/// The volume of the circuits measured on one day.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct DayVolume {
+    pub day: u8,
+    /// The number of circuits.
+    pub circuits: u64,
+    /// The total number of valid cells of the circuits.
+    pub cells: u64,
+    /// The mean number of valid cells per second of the circuits, each taken
+    /// over the time from its first to its last valid cell, excluding circuits
+    /// without such time.
+    pub mean_rate: f64,
+    /// The mean time in seconds from the first to the last valid cell of the
+    /// circuits.
+    pub mean_duration: f64,
+}
+
+/// Accumulates the `DayVolume` of each day while streaming through circuits.
+#[derive(Clone, Debug, Default)]
+pub struct DayVolumeBuilder {
+    /// The circuits, cells, rate sum, timed circuits, and duration sum of each
+    /// day.
+    days: BTreeMap<u8, (u64, u64, f64, u64, f64)>,
+}
+
+impl DayVolumeBuilder {
+    /// Creates a builder that has not yet seen any circuits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accounts for `circuit` on its day.
+    pub fn add(&mut self, circuit: &Circuit) {
+        let len = circuit.valid_cells().len();
+        let duration = circuit.duration();
+
+        let day = self.days.entry(circuit.day).or_default();
+        day.0 += 1;
+        day.1 += len as u64;
+        if duration > 0.0 {
+            day.2 += len as f64 / duration;
+            day.3 += 1;
+        }
+        day.4 += duration;
+    }
+
+    /// Returns the volume of each day, sorted by day.
+    pub fn finish(&self) -> Vec<DayVolume> {
+        self.days
+            .iter()
+            .map(
+                |(&day, &(circuits, cells, rates, timed, durations))| DayVolume {
+                    day,
+                    circuits,
+                    cells,
+                    mean_rate: match timed {
+                        0 => 0.0,
+                        n => rates / n as f64,
+                    },
+                    mean_duration: durations / circuits as f64,
+                },
+            )
+            .collect()
+    }
+}
+
+/// Returns the days of `volumes` whose number of circuits or cells is below
+/// `fraction` of the median over all days, which may indicate degraded
+/// collection.
+pub fn low_volume_days(volumes: &[DayVolume], fraction: f64) -> Vec<u8> {
+    let median_of = |f: fn(&DayVolume) -> u64| {
+        let values: Vec<f64> = volumes.iter().map(|v| f(v) as f64).collect();
+        median(&values)
+    };
+    let circuits = median_of(|v| v.circuits);
+    let cells = median_of(|v| v.cells);
+    volumes
+        .iter()
+        .filter(|v| {
+            (v.circuits as f64) < fraction * circuits || (v.cells as f64) < fraction * cells
+        })
+        .map(|v| v.day)
+        .collect()
+}
+
+/// Computes the `DayVolume` of each day of the circuits in the `/circuits`
+/// dataset of `file` and writes them to `/stats/days`. `progress` is called
+/// with the number of circuits read after each batch. Returns the volumes.
+pub fn write_day_volumes<F>(file: &File, mut progress: F) -> hdf5::Result<Vec<DayVolume>>
+where
+    F: FnMut(usize),
+{
+    let circuits = file.dataset("/circuits")?;
+    let size = circuits.size();
+    let step = 1_000; // cells are large, so read fewer circuits at a time
+    let mut builder = DayVolumeBuilder::new();
+
+    for begin in (0..size).step_by(step) {
+        let end = std::cmp::min(begin + step, size);
+        let batch: Array1<Circuit> = circuits.read_slice(ndarray::s![begin..end])?;
+        batch.iter().for_each(|c| builder.add(c));
+        progress(end - begin);
+    }
+
+    let volumes = builder.finish();
+    write_index(file, DAY_VOLUME_NAME, &volumes)?;
+    Ok(volumes)
+}
+
+/// The name of the dataset holding the `LabelDay` of each label and day it
+/// appears on.
+pub const LABEL_DAYS_NAME: &str = "/stats/label_days";
+
+/// The name of the dataset holding the labels that appear on a single day.
+pub const SINGLE_DAY_LABELS_NAME: &str = "/stats/single_day_labels";
+
+/// The number of circuits with a label on a day it appears on.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct LabelDay {
+    pub label: FixedAscii<44>,
+    pub day: u8,
+    pub count: u32,
+}
+
+/// The days on which each label appears. A label that appears on a single day
+/// has all of its circuits on one side of a split by day, and a split of its
+/// circuits within the day may leak the conditions of that day from training
+/// into testing.
+#[derive(Clone, Debug, Default)]
+pub struct LabelChurn {
+    /// The label and day pairs, sorted by label and then day.
+    pub label_days: Vec<LabelDay>,
+}
+
+/// Accumulates the `LabelChurn` of the circuits while streaming through a
+/// circuits dataset.
+#[derive(Clone, Debug, Default)]
+pub struct LabelChurnBuilder {
+    counts: HashMap<FixedAscii<44>, BTreeMap<u8, u32>>,
+}
+
+impl LabelChurnBuilder {
+    /// Creates a builder that has not yet seen any circuits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accounts for the circuit with `meta` on its day.
+    pub fn add_meta(&mut self, meta: &CircuitMeta) {
+        *self
+            .counts
+            .entry(meta.label())
+            .or_default()
+            .entry(meta.day)
+            .or_default() += 1;
+    }
+
+    /// Returns the days of every label seen.
+    pub fn finish(self) -> LabelChurn {
+        let mut label_days: Vec<LabelDay> = self
+            .counts
+            .into_iter()
+            .flat_map(|(label, days)| {
+                days.into_iter()
+                    .map(move |(day, count)| LabelDay { label, day, count })
+            })
+            .collect();
+        label_days.sort_by(|a, b| (a.label.as_str(), a.day).cmp(&(b.label.as_str(), b.day)));
+        LabelChurn { label_days }
+    }
+}
+
+impl LabelChurn {
+    /// The days on which each label appears, in the order of `label_days`.
+    pub fn days(&self) -> Vec<(FixedAscii<44>, Vec<u8>)> {
+        let mut days: Vec<(FixedAscii<44>, Vec<u8>)> = Vec::new();
+        for ld in self.label_days.iter() {
+            match days.last_mut() {
+                Some((label, days)) if *label == ld.label => days.push(ld.day),
+                _ => days.push((ld.label, vec![ld.day])),
+            }
+        }
+        days
+    }
+
+    /// The labels that appear on a single day, sorted by label.
+    pub fn single_day_labels(&self) -> Vec<FixedAscii<44>> {
+        self.days()
+            .into_iter()
+            .filter(|(_, days)| days.len() == 1)
+            .map(|(label, _)| label)
+            .collect()
+    }
+
+    /// The number of labels that appear only on each day, sorted by day.
+    pub fn single_day_counts(&self) -> BTreeMap<u8, usize> {
+        let mut counts = BTreeMap::new();
+        for (_, days) in self.days() {
+            if let [day] = days[..] {
+                *counts.entry(day).or_default() += 1;
+            }
+        }
+        counts
+    }
+
+    /// Writes the label and day pairs to `/stats/label_days` and the labels
+    /// that appear on a single day to `/stats/single_day_labels` in `file`,
+    /// replacing any existing datasets.
+    pub fn write(&self, file: &File) -> hdf5::Result<()> {
+        write_index(file, LABEL_DAYS_NAME, &self.label_days)?;
+        write_index(file, SINGLE_DAY_LABELS_NAME, &self.single_day_labels())
+    }
+}
+
+/// Computes the `LabelChurn` of the circuits in the `/circuits` dataset of
+/// `file`, reading only their meta-data, and writes it to `/stats/label_days`
+/// and `/stats/single_day_labels`. Returns the churn.
+pub fn write_label_churn(file: &File) -> hdf5::Result<LabelChurn> {
+    let circuits = file.dataset("/circuits")?;
+    let size = circuits.size();
+    let mut builder = LabelChurnBuilder::new();
+
+    for begin in (0..size).step_by(READ_BATCH) {
+        let end = std::cmp::min(begin + READ_BATCH, size);
+        let metas: Array1<CircuitMeta> = circuits.read_slice(ndarray::s![begin..end])?;
+        metas.iter().for_each(|m| builder.add_meta(m));
+    }
+
+    let churn = builder.finish();
+    churn.write(file)?;
+    Ok(churn)
+}
+
+/// Counts the circuits of each label, registrable domain (shortest private
+/// suffix), and raw domain, to show how much labeling by shortest private
+/// suffix collapses distinct domains.
+#[derive(Clone, Debug, Default)]
+pub struct DomainStats {
+    total: usize,
+    /// The number of circuits without a shortest private suffix, which are
+    /// labeled by their domain.
+    unsuffixed: usize,
+    labels: HashMap<FixedAscii<44>, usize>,
+    suffixes: HashMap<FixedAscii<44>, usize>,
+    domains: HashMap<FixedAscii<44>, usize>,
+    /// The distinct domains of the circuits with each label.
+    label_domains: HashMap<FixedAscii<44>, HashSet<FixedAscii<44>>>,
+}
+
+impl DomainStats {
+    /// Creates statistics that have not yet seen any circuits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accounts for the circuit with `meta`.
+    pub fn add_meta(&mut self, meta: &CircuitMeta) {
+        let label = meta.label();
+        self.total += 1;
+        if meta.shortest_private_suffix.is_empty() {
+            self.unsuffixed += 1;
+        } else {
+            *self
+                .suffixes
+                .entry(meta.shortest_private_suffix)
+                .or_default() += 1;
+        }
+        *self.labels.entry(label).or_default() += 1;
+        *self.domains.entry(meta.domain).or_default() += 1;
+        self.label_domains
+            .entry(label)
+            .or_default()
+            .insert(meta.domain);
+    }
+
+    /// The total number of circuits.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// The number of circuits without a shortest private suffix.
+    pub fn unsuffixed(&self) -> usize {
+        self.unsuffixed
+    }
+
+    /// The number of distinct labels, registrable domains, and raw domains.
+    pub fn distinct(&self) -> (usize, usize, usize) {
+        (self.labels.len(), self.suffixes.len(), self.domains.len())
+    }
+
+    /// The `k` labels with the most circuits and their circuit counts, sorted
+    /// by decreasing count and then by label.
+    pub fn top_labels(&self, k: usize) -> Vec<(String, usize)> {
+        top_k(&self.labels, k)
+    }
+
+    /// Like `top_labels`, but for registrable domains.
+    pub fn top_suffixes(&self, k: usize) -> Vec<(String, usize)> {
+        top_k(&self.suffixes, k)
+    }
+
+    /// Like `top_labels`, but for raw domains.
+    pub fn top_domains(&self, k: usize) -> Vec<(String, usize)> {
+        top_k(&self.domains, k)
+    }
+
+    /// The `k` labels that collapse the most distinct domains, with their
+    /// number of distinct domains and circuits, sorted by decreasing number of
+    /// domains and then by label.
+    pub fn top_collapsed(&self, k: usize) -> Vec<(String, usize, usize)> {
+        let mut top: Vec<(String, usize, usize)> = self
+            .label_domains
+            .iter()
+            .map(|(label, domains)| {
+                let circuits = self.labels.get(label).copied().unwrap_or(0);
+                (label.to_string(), domains.len(), circuits)
+            })
+            .collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top.truncate(k);
+        top
+    }
+}
+
+/// The `k` keys of `counts` with the largest counts, sorted by decreasing count
+/// and then by key.
+fn top_k(counts: &HashMap<FixedAscii<44>, usize>, k: usize) -> Vec<(String, usize)> {
+    let mut top: Vec<(String, usize)> = counts
+        .iter()
+        .map(|(key, count)| (key.to_string(), *count))
+        .collect();
+    top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    top.truncate(k);
+    top
+}
+
+/// Computes the `DomainStats` of the circuits in the `/circuits` dataset of
+/// `file`, reading only their meta-data.
+pub fn domain_stats(file: &File) -> hdf5::Result<DomainStats> {
+    let circuits = file.dataset("/circuits")?;
+    let size = circuits.size();
+    let mut stats = DomainStats::new();
+
+    for begin in (0..size).step_by(READ_BATCH) {
+        let end = std::cmp::min(begin + READ_BATCH, size);
+        let metas: Array1<CircuitMeta> = circuits.read_slice(ndarray::s![begin..end])?;
+        metas.iter().for_each(|m| stats.add_meta(m));
+    }
+
+    Ok(stats)
+}
+
+/// The name of the dataset holding the `PortLabelCount` of each label and port
+/// other than 443 it is reached over.
+pub const PORT_LABELS_NAME: &str = "/stats/port_labels";
+
+/// The HTTPS port, over which the circuits of websites are expected to be
+/// reached.
+pub const HTTPS_PORT: u16 = 443;
+
+/// The number of circuits with a label that are reached over a port other than
+/// 443, and the number of circuits with the label over any port.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct PortLabelCount {
+    pub label: FixedAscii<44>,
+    pub port: u16,
+    pub count: u32,
+    pub label_count: u32,
+}
+
+/// The ports other than 443 over which the circuits of a label are reached.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LabelPorts {
+    pub label: FixedAscii<44>,
+    /// The number of circuits with the label over any port.
+    pub circuits: u32,
+    /// The number of circuits with the label over each port other than 443,
+    /// sorted by port.
+    pub ports: Vec<(u16, u32)>,
+}
+
+impl LabelPorts {
+    /// The number of circuits with the label over ports other than 443.
+    pub fn non_https(&self) -> u32 {
+        self.ports.iter().map(|(_, count)| count).sum()
+    }
+
+    /// The fraction of the circuits with the label over ports other than 443.
+    pub fn non_https_fraction(&self) -> f64 {
+        self.non_https() as f64 / self.circuits.max(1) as f64
+    }
+}
+
+/// The contingency of port and label, restricted to ports other than 443. The
+/// circuits of a label that are mostly reached over other ports are likely not
+/// web traffic, but are labeled as websites by their domain.
+#[derive(Clone, Debug, Default)]
+pub struct PortLabels {
+    /// The label and port pairs, sorted by label and then port.
+    pub counts: Vec<PortLabelCount>,
+    /// The number of labels, including those only reached over port 443.
+    pub labels: usize,
+}
+
+/// Accumulates the `PortLabels` of the circuits while streaming through a
+/// circuits dataset.
+#[derive(Clone, Debug, Default)]
+pub struct PortLabelsBuilder {
+    totals: HashMap<FixedAscii<44>, u32>,
+    counts: HashMap<FixedAscii<44>, BTreeMap<u16, u32>>,
+}
+
+impl PortLabelsBuilder {
+    /// Creates a builder that has not yet seen any circuits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accounts for the circuit with `meta` on its port.
+    pub fn add_meta(&mut self, meta: &CircuitMeta) {
+        let label = meta.label();
+        *self.totals.entry(label).or_default() += 1;
+        if meta.port != HTTPS_PORT {
+            *self
+                .counts
+                .entry(label)
+                .or_default()
+                .entry(meta.port)
+                .or_default() += 1;
+        }
+    }
+
+    /// Returns the port and label pairs of every circuit seen.
+    pub fn finish(self) -> PortLabels {
+        let totals = self.totals;
+        let mut counts: Vec<PortLabelCount> = self
+            .counts
+            .into_iter()
+            .flat_map(|(label, ports)| {
+                let label_count = totals.get(&label).copied().unwrap_or(0);
+                ports.into_iter().map(move |(port, count)| PortLabelCount {
+                    label,
+                    port,
+                    count,
+                    label_count,
+                })
+            })
+            .collect();
+        counts.sort_by(|a, b| (a.label.as_str(), a.port).cmp(&(b.label.as_str(), b.port)));
+        PortLabels {
+            counts,
+            labels: totals.len(),
+        }
+    }
+}
+
+impl PortLabels {
+    /// The ports of every label reached over a port other than 443, in the
+    /// order of `counts`.
+    pub fn label_ports(&self) -> Vec<LabelPorts> {
+        let mut labels: Vec<LabelPorts> = Vec::new();
+        for c in self.counts.iter() {
+            match labels.last_mut() {
+                Some(lp) if lp.label == c.label => lp.ports.push((c.port, c.count)),
+                _ => labels.push(LabelPorts {
+                    label: c.label,
+                    circuits: c.label_count,
+                    ports: vec![(c.port, c.count)],
+                }),
+            }
+        }
+        labels
+    }
+
+    /// The labels with at least `min_fraction` of their circuits reached over
+    /// ports other than 443, which are likely not websites, sorted by
+    /// decreasing fraction and then by decreasing number of circuits.
+    pub fn flagged(&self, min_fraction: f64) -> Vec<LabelPorts> {
+        let mut flagged: Vec<LabelPorts> = self
+            .label_ports()
+            .into_iter()
+            .filter(|lp| lp.non_https_fraction() >= min_fraction)
+            .collect();
+        flagged.sort_by(|a, b| {
+            b.non_https_fraction()
+                .total_cmp(&a.non_https_fraction())
+                .then_with(|| b.circuits.cmp(&a.circuits))
+        });
+        flagged
+    }
+
+    /// The number of circuits reached over each port other than 443, and the
+    /// number of labels reached over it, sorted by port.
+    pub fn ports(&self) -> BTreeMap<u16, (u64, usize)> {
+        let mut ports: BTreeMap<u16, (u64, usize)> = BTreeMap::new();
+        for c in self.counts.iter() {
+            let entry = ports.entry(c.port).or_default();
+            entry.0 += c.count as u64;
+            entry.1 += 1;
+        }
+        ports
+    }
+
+    /// Writes the label and port pairs to `/stats/port_labels` in `file`,
+    /// replacing any existing dataset, with the number of labels in its
+    /// `labels` attribute.
+    pub fn write(&self, file: &File) -> hdf5::Result<()> {
+        write_index(file, PORT_LABELS_NAME, &self.counts)?;
+        file.dataset(PORT_LABELS_NAME)?
+            .new_attr::<u64>()
+            .create("labels")?
+            .write_scalar(&(self.labels as u64))
+    }
+}
+
+/// Computes the `PortLabels` of the circuits in `file` and writes them to
+/// `/stats/port_labels`. Joins the `/index/port` and `/index/label` datasets
+/// if present, and otherwise reads the meta-data of the circuits. Returns the
+/// contingency.
+pub fn write_port_labels(file: &File) -> hdf5::Result<PortLabels> {
+    let port_labels = if file.link_exists("/index/port") && file.link_exists("/index/label") {
+        join_port_labels(file)?
+    } else {
+        let circuits = file.dataset("/circuits")?;
+        let size = circuits.size();
+        let mut builder = PortLabelsBuilder::new();
+        for begin in (0..size).step_by(READ_BATCH) {
+            let end = std::cmp::min(begin + READ_BATCH, size);
+            let metas: Array1<CircuitMeta> = circuits.read_slice(ndarray::s![begin..end])?;
+            metas.iter().for_each(|m| builder.add_meta(m));
+        }
+        builder.finish()
+    };
+    port_labels.write(file)?;
+    Ok(port_labels)
+}
+
+/// Computes the `PortLabels` of the circuits in `file` from its port and label
+/// indexes, holding only the indices of circuits reached over ports other than
+/// 443 in memory.
+fn join_port_labels(file: &File) -> hdf5::Result<PortLabels> {
+    let mut ports: HashMap<CircuitIndex, u16> = HashMap::new();
+    for entry in file
+        .dataset("/index/port")?
+        .read_raw::<IndexArrayEntry<u16>>()?
+        .into_iter()
+        .filter(|e| e.value != HTTPS_PORT)
+    {
+        ports.extend(entry.indexarr.iter().map(|&i| (i, entry.value)));
+    }
+
+    let mut builder = PortLabelsBuilder::new();
+    for entry in file
+        .dataset("/index/label")?
+        .read_raw::<IndexArrayEntry<FixedAscii<44>>>()?
+    {
+        builder
+            .totals
+            .insert(entry.value, entry.indexarr.len() as u32);
+        for i in entry.indexarr.iter() {
+            if let Some(&port) = ports.get(i) {
+                *builder
+                    .counts
+                    .entry(entry.value)
+                    .or_default()
+                    .entry(port)
+                    .or_default() += 1;
+            }
+        }
+    }
+    Ok(builder.finish())
+}
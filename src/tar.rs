@@ -0,0 +1,111 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// The size of a tar header and of the blocks that hold file contents.
+const BLOCK: usize = 512;
+
+/// Writes an uncompressed POSIX ustar archive of regular files, one file at a
+/// time.
+pub struct TarWriter {
+    out: BufWriter<File>,
+}
+
+impl TarWriter {
+    /// Creates a writer for the archive at `path`, replacing any existing file.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self {
+            out: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    /// Appends a regular file named `name` holding `contents`. `name` must be
+    /// at most 100 bytes.
+    pub fn append(&mut self, name: &str, contents: &[u8]) -> io::Result<()> {
+        self.out.write_all(&header(name, contents.len())?)?;
+        self.out.write_all(contents)?;
+        let padding = (BLOCK - contents.len() % BLOCK) % BLOCK;
+        self.out.write_all(&[0u8; BLOCK][..padding])
+    }
+
+    /// Writes the end-of-archive marker and flushes the archive.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.write_all(&[0u8; 2 * BLOCK])?;
+        self.out.flush()
+    }
+}
+
+/// The ustar header of a regular file named `name` with `size` bytes.
+fn header(name: &str, size: usize) -> io::Result<[u8; BLOCK]> {
+    if name.len() > 100 {
+        return Err(io::Error::other(format!(
+            "Tar member name '{name}' is longer than 100 bytes"
+        )));
+    }
+
+    let mut header = [0u8; BLOCK];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    octal(&mut header[100..108], 0o644); // mode
+    octal(&mut header[108..116], 0); // uid
+    octal(&mut header[116..124], 0); // gid
+    octal(&mut header[124..136], size as u64);
+    octal(&mut header[136..148], 0); // mtime
+    header[156] = b'0'; // regular file
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // The checksum is computed with its own field filled with spaces.
+    header[148..156].fill(b' ');
+    let checksum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+    octal(&mut header[148..155], u64::from(checksum));
+
+    Ok(header)
+}
+
+/// Writes `value` to `field` as zero-padded octal digits followed by a NUL.
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{value:0width$o}", width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[digits.len()] = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_matches_ustar_layout() {
+        let header = header("a.txt", 3).unwrap();
+        assert_eq!(&header[..6], b"a.txt\0");
+        assert_eq!(&header[100..108], b"0000644\0");
+        assert_eq!(&header[124..136], b"00000000003\0");
+        assert_eq!(header[156], b'0');
+        assert_eq!(&header[257..265], b"ustar\x0000");
+        // Checked against Python's tarfile, which reads the member back.
+        assert_eq!(&header[148..156], b"006717\0 ");
+    }
+
+    #[test]
+    fn header_rejects_long_names() {
+        assert!(header(&"a".repeat(100), 0).is_ok());
+        assert!(header(&"a".repeat(101), 0).is_err());
+    }
+
+    #[test]
+    fn archive_pads_contents_to_blocks() {
+        let path = std::env::temp_dir().join(format!("gtt23-{}-tar-test.tar", std::process::id()));
+        let mut tar = TarWriter::create(&path).unwrap();
+        tar.append("a.txt", b"abc").unwrap();
+        tar.append("empty", b"").unwrap();
+        tar.finish().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(bytes.len(), 2 * BLOCK + BLOCK + 2 * BLOCK);
+        assert_eq!(&bytes[..BLOCK], &header("a.txt", 3).unwrap());
+        assert_eq!(&bytes[BLOCK..BLOCK + 3], b"abc");
+        assert!(bytes[BLOCK + 3..2 * BLOCK].iter().all(|&b| b == 0));
+        assert_eq!(&bytes[2 * BLOCK..3 * BLOCK], &header("empty", 0).unwrap());
+        assert!(bytes[3 * BLOCK..].iter().all(|&b| b == 0));
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use hdf5::types::FixedAscii;
+use ndarray::Array1;
+
+use crate::index::LabelVocabulary;
+use crate::npz::npy_header;
+use crate::rng::Rng;
+use crate::{Circuit, CircuitIndex, CircuitMeta, MAX_CELLS};
+
+/// The number of cells in each row of the `x` field.
+pub const TENSOR_LENGTH: usize = MAX_CELLS;
+
+/// The NumPy dtype of each record written by `write_tensor`.
+pub fn tensor_descr() -> String {
+    format!("[('x', '<f4', ({TENSOR_LENGTH}, 2)), ('y', '<i8')]")
+}
+
+/// The size in bytes of each record written by `write_tensor`.
+const RECORD_BYTES: usize = TENSOR_LENGTH * 2 * 4 + 8;
+
+/// The number of circuits read at a time.
+const READ_BATCH: usize = 1_000;
+
+/// Options for `write_tensor`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TensorOptions {
+    /// The seed of the shuffle.
+    pub seed: u64,
+    /// If set, drop the circuits shuffled past the last multiple of this many
+    /// records, so that the file splits into full batches of this size.
+    pub batch_size: Option<usize>,
+    /// The class ids, typically read from the `/labels` dataset of the source
+    /// file.
+    pub vocabulary: Option<LabelVocabulary>,
+}
+
+/// Writes the circuits of `circuits` at `indices` in shuffled order to a single
+/// `.npy` file at `path` that can be memory-mapped for training, e.g., with
+/// `np.load(path, mmap_mode="r")`. Returns the number of records written and
+/// the labels, where the label of class id `k` is at position `k`.
+///
+/// Each record has the structured dtype `tensor_descr()`: `x` is a float32
+/// `[TENSOR_LENGTH, 2]` array holding the direction of each valid cell (+1 toward the
+/// server, -1 toward the client) in channel 0 and its time in seconds since
+/// the first valid cell in channel 1, padded with zeros, and `y` is the int64
+/// class id of the circuit's label. Class ids are taken from
+/// `options.vocabulary` if given, and are otherwise assigned to the labels of
+/// the written circuits in sorted order.
+///
+/// The shuffle is determined by `options.seed` and the set of `indices`. The
+/// circuits are read in order, once, and each is written directly to its
+/// shuffled position in the file.
+pub fn write_tensor<P: AsRef<Path>>(
+    circuits: &hdf5::Dataset,
+    indices: &[CircuitIndex],
+    path: P,
+    options: &TensorOptions,
+) -> hdf5::Result<(usize, Vec<FixedAscii<44>>)> {
+    let to_hdf5 = |e: io::Error| hdf5::Error::from(e.to_string());
+
+    let mut order = indices.to_vec();
+    order.sort_unstable();
+    order.dedup();
+    Rng::new(options.seed).shuffle(&mut order);
+    if let Some(batch) = options.batch_size.filter(|&b| b > 0) {
+        order.truncate(order.len() - order.len() % batch);
+    }
+
+    // The shuffled position of each circuit, in the order they are read.
+    let mut positions: Vec<(CircuitIndex, usize)> =
+        order.iter().enumerate().map(|(pos, &i)| (i, pos)).collect();
+    positions.sort_unstable();
+
+    let classes = match &options.vocabulary {
+        Some(vocabulary) => vocabulary.entries().iter().map(|e| e.label).collect(),
+        None => {
+            let mut classes = read_labels(circuits, &positions)?;
+            classes.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+            classes.dedup();
+            classes
+        }
+    };
+    let class_ids: HashMap<FixedAscii<44>, i64> = classes
+        .iter()
+        .enumerate()
+        .map(|(id, label)| (*label, id as i64))
+        .collect();
+
+    let header = npy_header(&tensor_descr(), order.len(), &[], 0);
+    let mut out = File::create(path).map_err(to_hdf5)?;
+    out.write_all(&header).map_err(to_hdf5)?;
+    out.set_len((header.len() + order.len() * RECORD_BYTES) as u64)
+        .map_err(to_hdf5)?;
+
+    let mut record = Vec::with_capacity(RECORD_BYTES);
+    let mut next = positions.iter().peekable();
+    let size = circuits.size();
+
+    for begin in (0..size).step_by(READ_BATCH) {
+        let end = std::cmp::min(begin + READ_BATCH, size);
+
+        // Skip batches that contain no selected circuits.
+        if next.peek().is_none_or(|&&(i, _)| i as usize >= end) {
+            continue;
+        }
+
+        let batch: Array1<Circuit> = circuits.read_slice(ndarray::s![begin..end])?;
+
+        while let Some(&(i, pos)) = next.next_if(|&&(i, _)| (i as usize) < end) {
+            let circuit = &batch[i as usize - begin];
+            let label = circuit.label();
+            let class = class_ids.get(&label).ok_or_else(|| {
+                hdf5::Error::from(format!("Label '{label}' is not in the label vocabulary"))
+            })?;
+            encode_record(circuit, *class, &mut record);
+
+            let offset = header.len() + pos * RECORD_BYTES;
+            out.seek(SeekFrom::Start(offset as u64)).map_err(to_hdf5)?;
+            out.write_all(&record).map_err(to_hdf5)?;
+        }
+    }
+
+    Ok((order.len(), classes))
+}
+
+/// Reads the label of each circuit in `positions`, which is sorted by index.
+fn read_labels(
+    circuits: &hdf5::Dataset,
+    positions: &[(CircuitIndex, usize)],
+) -> hdf5::Result<Vec<FixedAscii<44>>> {
+    let mut labels = Vec::with_capacity(positions.len());
+    let mut next = positions.iter().peekable();
+    let size = circuits.size();
+
+    for begin in (0..size).step_by(READ_BATCH) {
+        let end = std::cmp::min(begin + READ_BATCH, size);
+        if next.peek().is_none_or(|&&(i, _)| i as usize >= end) {
+            continue;
+        }
+
+        let metas: Array1<CircuitMeta> = circuits.read_slice(ndarray::s![begin..end])?;
+        while let Some(&(i, _)) = next.next_if(|&&(i, _)| (i as usize) < end) {
+            labels.push(metas[i as usize - begin].label());
+        }
+    }
+
+    Ok(labels)
+}
+
+/// Replaces `record` with the little-endian bytes of the record of `circuit`
+/// with class id `class`.
+fn encode_record(circuit: &Circuit, class: i64, record: &mut Vec<u8>) {
+    record.clear();
+
+    let len = std::cmp::min(circuit.len as usize, TENSOR_LENGTH);
+    let cells = &circuit.cells[..len];
+    let start = cells.first().map_or(0.0, |c| c.time);
+
+    for cell in cells {
+        let time = (cell.time - start).max(0.0) as f32;
+        record.extend_from_slice(&(cell.direction as i8 as f32).to_le_bytes());
+        record.extend_from_slice(&time.to_le_bytes());
+    }
+    record.resize(TENSOR_LENGTH * 2 * 4, 0);
+    record.extend_from_slice(&class.to_le_bytes());
+}
@@ -0,0 +1,213 @@
+use crate::{AugmentedCircuit, Cell, CellCommand, Circuit, Direction, RelayCommand};
+
+/// A transformation of the cells of a circuit, such as removing or truncating
+/// cells, that can be applied when deriving a new dataset.
+pub trait CircuitTransform {
+    /// Transforms the valid cells `cells[..*len]` in place, updating `len` to
+    /// the new number of valid cells and zeroing the cells beyond it.
+    fn apply_cells(&self, cells: &mut [Cell], len: &mut u16);
+
+    /// Transforms the cells of `circuit` in place.
+    fn apply(&self, circuit: &mut Circuit) {
+        self.apply_cells(&mut circuit.cells, &mut circuit.len);
+    }
+
+    /// Transforms the cells of `circuit` in place.
+    fn apply_augmented(&self, circuit: &mut AugmentedCircuit) {
+        self.apply_cells(&mut circuit.cells, &mut circuit.len);
+    }
+}
+
+/// Applies each transform in turn.
+impl CircuitTransform for Vec<Box<dyn CircuitTransform>> {
+    fn apply_cells(&self, cells: &mut [Cell], len: &mut u16) {
+        for t in self.iter() {
+            t.apply_cells(cells, len);
+        }
+    }
+}
+
+/// Removes the cells whose cell command or relay command is in the given sets,
+/// e.g., to compare classifiers on data-only traces against full traces.
+#[derive(Clone, Debug, Default)]
+pub struct DropCells {
+    /// Remove cells with any of these cell commands.
+    pub cell_cmds: Vec<CellCommand>,
+    /// Remove cells with any of these relay commands.
+    pub relay_cmds: Vec<RelayCommand>,
+}
+
+impl DropCells {
+    /// Creates a transform that removes cells with any of `cell_cmds` or any of
+    /// `relay_cmds`.
+    pub fn new(cell_cmds: Vec<CellCommand>, relay_cmds: Vec<RelayCommand>) -> Self {
+        Self {
+            cell_cmds,
+            relay_cmds,
+        }
+    }
+
+    /// Returns true if `cell` is removed by this transform.
+    pub fn drops(&self, cell: &Cell) -> bool {
+        self.cell_cmds.contains(&cell.cell_cmd) || self.relay_cmds.contains(&cell.relay_cmd)
+    }
+}
+
+impl CircuitTransform for DropCells {
+    fn apply_cells(&self, cells: &mut [Cell], len: &mut u16) {
+        retain_cells(cells, len, |cell| !self.drops(cell));
+    }
+}
+
+/// Truncates circuits to their first `n` cells, preserving the cell times, to
+/// study how much of a trace is needed for classification.
+#[derive(Clone, Copy, Debug)]
+pub struct TrimCells {
+    pub n: u16,
+}
+
+impl TrimCells {
+    /// Creates a transform that keeps at most the first `n` cells.
+    pub fn new(n: u16) -> Self {
+        Self { n }
+    }
+}
+
+impl CircuitTransform for TrimCells {
+    fn apply_cells(&self, cells: &mut [Cell], len: &mut u16) {
+        truncate_cells(cells, len, self.n as usize);
+    }
+}
+
+/// Keeps only the cells observed within `seconds` of the first cell, to
+/// evaluate early classification.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeWindow {
+    pub seconds: f64,
+}
+
+impl TimeWindow {
+    /// Creates a transform that keeps the cells with a time of at most
+    /// `seconds` after the time of the first cell.
+    pub fn new(seconds: f64) -> Self {
+        Self { seconds }
+    }
+}
+
+impl CircuitTransform for TimeWindow {
+    fn apply_cells(&self, cells: &mut [Cell], len: &mut u16) {
+        if *len == 0 || cells.is_empty() {
+            return;
+        }
+        let last = cells[0].time + self.seconds;
+        retain_cells(cells, len, |cell| cell.time <= last);
+    }
+}
+
+/// Rescales the time between consecutive cells to simulate slower or faster
+/// network paths, e.g., to test the sensitivity of classifiers to client
+/// bandwidth. The gap before each cell is multiplied by the factor of the
+/// cell's direction, and the first cell keeps its time.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeScale {
+    /// The factor for the gaps before client-to-server cells.
+    pub client: f64,
+    /// The factor for the gaps before server-to-client cells.
+    pub server: f64,
+}
+
+impl TimeScale {
+    /// Creates a transform that multiplies every gap by `factor`, so that a
+    /// factor of 2 simulates a path that is twice as slow.
+    pub fn new(factor: f64) -> Self {
+        Self::per_direction(factor, factor)
+    }
+
+    /// Creates a transform that multiplies the gaps before client-to-server
+    /// cells by `client` and before server-to-client cells by `server`.
+    pub fn per_direction(client: f64, server: f64) -> Self {
+        Self { client, server }
+    }
+
+    /// Returns the factor for the gap before a cell in `direction`. Cells in
+    /// neither direction, i.e., padding, use the mean of the two factors.
+    pub fn factor(&self, direction: Direction) -> f64 {
+        match direction {
+            Direction::CLIENT_TO_SERVER => self.client,
+            Direction::SERVER_TO_CLIENT => self.server,
+            Direction::PADDING => (self.client + self.server) / 2.0,
+        }
+    }
+}
+
+impl CircuitTransform for TimeScale {
+    fn apply_cells(&self, cells: &mut [Cell], len: &mut u16) {
+        let valid = std::cmp::min(*len as usize, cells.len());
+        if valid == 0 {
+            return;
+        }
+
+        let mut prev = cells[0].time;
+        for i in 1..valid {
+            let gap = cells[i].time - prev;
+            prev = cells[i].time;
+            cells[i].time = cells[i - 1].time + gap * self.factor(cells[i].direction);
+        }
+    }
+}
+
+/// Keeps only the valid cells `cells[..*len]` for which `keep` returns true,
+/// preserving their order, and then updates `len` and zeroes the rest.
+pub fn retain_cells<F>(cells: &mut [Cell], len: &mut u16, mut keep: F)
+where
+    F: FnMut(&Cell) -> bool,
+{
+    let valid = std::cmp::min(*len as usize, cells.len());
+    let mut kept = 0;
+
+    for i in 0..valid {
+        if keep(&cells[i]) {
+            cells[kept] = cells[i];
+            kept += 1;
+        }
+    }
+
+    cells[kept..].fill(Cell::empty());
+    *len = kept as u16;
+}
+
+/// Keeps only the first `n` valid cells, and then updates `len` and zeroes the
+/// rest.
+pub fn truncate_cells(cells: &mut [Cell], len: &mut u16, n: usize) {
+    let kept = std::cmp::min(n, std::cmp::min(*len as usize, cells.len()));
+    cells[kept..].fill(Cell::empty());
+    *len = kept as u16;
+}
+
+/// Parses a cell command from its name (e.g., `PADDING`, case insensitive) or
+/// its integer value.
+pub fn parse_cell_command(name: &str) -> Result<CellCommand, String> {
+    parse_command(name, |v| CellCommand::try_from(v).ok())
+        .ok_or_else(|| format!("unknown cell command '{name}'"))
+}
+
+/// Parses a relay command from its name (e.g., `SENDME`, case insensitive) or
+/// its integer value.
+pub fn parse_relay_command(name: &str) -> Result<RelayCommand, String> {
+    parse_command(name, |v| RelayCommand::try_from(v).ok())
+        .ok_or_else(|| format!("unknown relay command '{name}'"))
+}
+
+fn parse_command<T, F>(name: &str, decode: F) -> Option<T>
+where
+    T: std::fmt::Debug,
+    F: Fn(u8) -> Option<T>,
+{
+    let name = name.trim();
+    match name.parse::<u8>() {
+        Ok(v) => decode(v),
+        Err(_) => (0..=u8::MAX)
+            .filter_map(decode)
+            .find(|cmd| format!("{cmd:?}").eq_ignore_ascii_case(name)),
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use hdf5::types::{FixedAscii, VarLenArray};
+use hdf5::{File, H5Type};
+
+use crate::index::INDEX_NAMES;
+use crate::{CellCommand, CircuitIndex, Direction, MAX_CELLS, RelayCommand};
+
+/// A `Cell` whose enum fields have not been decoded, so that invalid values
+/// stored in a file can be detected instead of being read as enum variants.
+#[derive(H5Type, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct RawCell {
+    pub time: f64,
+    pub direction: i8,
+    pub cell_cmd: u8,
+    pub relay_cmd: u8,
+}
+
+/// A `Circuit` whose cells have not been decoded. The field names match
+/// `Circuit`, so it can be read directly from a circuits dataset of circuits
+/// with `N` cells.
+#[derive(H5Type, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct RawCircuit<const N: usize = MAX_CELLS> {
+    pub uuid: FixedAscii<32>,
+    pub domain: FixedAscii<44>,
+    pub shortest_private_suffix: FixedAscii<44>,
+    pub day: u8,
+    pub port: u16,
+    pub len: u16,
+    pub cells: [RawCell; N],
+}
+
+/// The kind of invariant that a violation breaks.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum ViolationKind {
+    /// The circuit's `len` exceeds the size of the cells array.
+    LenTooLong,
+    /// A cell beyond the circuit's `len` is not zeroed.
+    NonZeroPadding,
+    /// A cell has a negative time.
+    NegativeTime,
+    /// A cell's time is less than the time of the previous cell.
+    DecreasingTime,
+    /// The uuid is not 32 hex characters.
+    BadUuid,
+    /// A cell has a direction that does not decode.
+    BadDirection,
+    /// A cell has a cell command that does not decode.
+    BadCellCommand,
+    /// A cell has a relay command that does not decode.
+    BadRelayCommand,
+    /// An index entry references a circuit beyond the end of the dataset.
+    IndexOutOfRange,
+}
+
+impl ViolationKind {
+    /// A short name for the kind of violation, suitable for reports.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ViolationKind::LenTooLong => "len_too_long",
+            ViolationKind::NonZeroPadding => "nonzero_padding",
+            ViolationKind::NegativeTime => "negative_time",
+            ViolationKind::DecreasingTime => "decreasing_time",
+            ViolationKind::BadUuid => "bad_uuid",
+            ViolationKind::BadDirection => "bad_direction",
+            ViolationKind::BadCellCommand => "bad_cell_cmd",
+            ViolationKind::BadRelayCommand => "bad_relay_cmd",
+            ViolationKind::IndexOutOfRange => "index_out_of_range",
+        }
+    }
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A single broken invariant.
+#[derive(Clone, Debug)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    /// The index of the offending circuit, or for index violations, the
+    /// position of the offending entry in the index dataset.
+    pub index: usize,
+    /// A human-readable description of the problem.
+    pub detail: String,
+}
+
+/// Checks the invariants of the circuit at position `index` in the dataset,
+/// returning the violations found. At most one violation of each kind is
+/// reported per circuit.
+pub fn check_circuit<const N: usize>(index: usize, circuit: &RawCircuit<N>) -> Vec<Violation> {
+    let mut found: BTreeMap<ViolationKind, String> = BTreeMap::new();
+    let mut note = |kind: ViolationKind, detail: String| {
+        found.entry(kind).or_insert(detail);
+    };
+
+    let uuid = circuit.uuid.as_str();
+    if uuid.len() != 32 || !uuid.bytes().all(|b| b.is_ascii_hexdigit()) {
+        note(ViolationKind::BadUuid, format!("uuid '{uuid}'"));
+    }
+
+    let len = circuit.len as usize;
+    if len > circuit.cells.len() {
+        note(ViolationKind::LenTooLong, format!("len {len}"));
+    }
+
+    let mut prev_time = f64::NEG_INFINITY;
+    for (i, cell) in circuit.cells.iter().enumerate() {
+        if i >= len {
+            if cell.time != 0.0 || cell.direction != 0 || cell.cell_cmd != 0 || cell.relay_cmd != 0
+            {
+                note(ViolationKind::NonZeroPadding, format!("cell {i}"));
+            }
+            continue;
+        }
+
+        if cell.time < 0.0 {
+            note(
+                ViolationKind::NegativeTime,
+                format!("cell {i} time {}", cell.time),
+            );
+        }
+        if cell.time < prev_time {
+            note(
+                ViolationKind::DecreasingTime,
+                format!("cell {i} time {} after {prev_time}", cell.time),
+            );
+        }
+        prev_time = cell.time;
+
+        if let Err(e) = Direction::try_from(cell.direction) {
+            note(ViolationKind::BadDirection, format!("cell {i}: {e}"));
+        }
+        if let Err(e) = CellCommand::try_from(cell.cell_cmd) {
+            note(ViolationKind::BadCellCommand, format!("cell {i}: {e}"));
+        }
+        if let Err(e) = RelayCommand::try_from(cell.relay_cmd) {
+            note(ViolationKind::BadRelayCommand, format!("cell {i}: {e}"));
+        }
+    }
+
+    found
+        .into_iter()
+        .map(|(kind, detail)| Violation {
+            kind,
+            index,
+            detail,
+        })
+        .collect()
+}
+
+#[derive(H5Type, Clone, Copy, Debug)]
+#[repr(C)]
+struct IndexOffset {
+    index: CircuitIndex,
+}
+
+#[derive(H5Type, Clone, Debug)]
+#[repr(C)]
+struct IndexArrayOffsets {
+    indexarr: VarLenArray<CircuitIndex>,
+}
+
+/// Checks that every entry of each index in `/index` that exists in `file`
+/// references a circuit in a dataset of `size` circuits.
+pub fn check_indices(file: &File, size: usize) -> hdf5::Result<Vec<Violation>> {
+    let mut violations = Vec::new();
+
+    for name in INDEX_NAMES {
+        let path = format!("/index/{name}");
+        if !file.link_exists(&path) {
+            continue;
+        }
+
+        let dataset = file.dataset(&path)?;
+        let offsets: Vec<Vec<CircuitIndex>> = if name == "uuid" {
+            dataset
+                .read_raw::<IndexOffset>()?
+                .into_iter()
+                .map(|e| vec![e.index])
+                .collect()
+        } else {
+            dataset
+                .read_raw::<IndexArrayOffsets>()?
+                .into_iter()
+                .map(|e| e.indexarr.to_vec())
+                .collect()
+        };
+
+        for (pos, indices) in offsets.iter().enumerate() {
+            if let Some(bad) = indices.iter().find(|&&i| i as usize >= size) {
+                violations.push(Violation {
+                    kind: ViolationKind::IndexOutOfRange,
+                    index: pos,
+                    detail: format!("{path} references circuit {bad} of {size}"),
+                });
+            }
+        }
+    }
+
+    Ok(violations)
+}
+
+/// Counts violations by kind, keeping the first few of each kind as examples.
+#[derive(Clone, Debug)]
+pub struct Report {
+    max_examples: usize,
+    counts: BTreeMap<ViolationKind, usize>,
+    examples: BTreeMap<ViolationKind, Vec<Violation>>,
+}
+
+impl Report {
+    /// Creates an empty report keeping up to `max_examples` of each kind.
+    pub fn new(max_examples: usize) -> Self {
+        Self {
+            max_examples,
+            counts: BTreeMap::new(),
+            examples: BTreeMap::new(),
+        }
+    }
+
+    /// Adds `violations` to the report.
+    pub fn extend(&mut self, violations: impl IntoIterator<Item = Violation>) {
+        for v in violations {
+            *self.counts.entry(v.kind).or_default() += 1;
+            let examples = self.examples.entry(v.kind).or_default();
+            if examples.len() < self.max_examples {
+                examples.push(v);
+            }
+        }
+    }
+
+    /// Returns true if no violations were added.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// The number of violations of each kind.
+    pub fn counts(&self) -> &BTreeMap<ViolationKind, usize> {
+        &self.counts
+    }
+
+    /// The kept examples of violations of `kind`.
+    pub fn examples(&self, kind: ViolationKind) -> &[Violation] {
+        self.examples.get(&kind).map_or(&[], |v| v.as_slice())
+    }
+}
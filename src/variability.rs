@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use hdf5::types::FixedAscii;
+use hdf5::{File, H5Type};
+use ndarray::Array1;
+
+use crate::edit;
+use crate::features::{bursts, mean, par_map, std_dev};
+use crate::index::write_index;
+use crate::rng::Rng;
+use crate::sample::Reservoir;
+use crate::{Circuit, CircuitIndex, CircuitMeta};
+
+/// The name of the dataset holding the `LabelVariability` of each label.
+pub const VARIABILITY_NAME: &str = "/stats/variability";
+
+/// The number of circuits whose meta-data is read at a time.
+const META_BATCH: usize = 10_000;
+
+/// The number of sampled circuits read at a time.
+const READ_BATCH: usize = 1_000;
+
+/// The number of labels whose circuits are held in memory at a time.
+const LABEL_BATCH: usize = 256;
+
+/// How much the circuits of a label differ from each other. Labels whose
+/// circuits vary a lot are intrinsically harder to fingerprint.
+#[derive(H5Type, Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
+pub struct LabelVariability {
+    pub label: FixedAscii<44>,
+    /// The number of circuits with the label.
+    pub count: u32,
+    /// The number of circuits sampled to compute the metrics.
+    pub sampled: u32,
+    /// The mean normalized edit distance between the direction sequences of
+    /// every pair of sampled circuits, from 0 if all are identical to 1.
+    pub mean_distance: f64,
+    /// The standard deviation of the pairwise distances.
+    pub std_distance: f64,
+    /// The Shannon entropy in bits of the `burst_pattern`s of the sampled
+    /// circuits.
+    pub burst_entropy: f64,
+    /// `burst_entropy` divided by its largest possible value for the number of
+    /// sampled circuits, from 0 if all patterns are equal to 1 if all differ.
+    pub normalized_burst_entropy: f64,
+}
+
+/// Options of `write_label_variability`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VariabilityOptions {
+    /// Sample at most this many circuits of each label.
+    pub max_circuits: usize,
+    /// Only compute the metrics of labels with at least this many circuits.
+    pub min_circuits: usize,
+    /// Compare only this many of the first cells of each circuit.
+    pub max_cells: Option<usize>,
+    /// The number of bursts in each `burst_pattern`.
+    pub pattern_bursts: usize,
+    /// The number of threads computing the metrics.
+    pub threads: usize,
+}
+
+impl Default for VariabilityOptions {
+    fn default() -> Self {
+        Self {
+            max_circuits: 50,
+            min_circuits: 10,
+            max_cells: Some(500),
+            pattern_bursts: 8,
+            threads: 1,
+        }
+    }
+}
+
+/// Returns the coarse shape of the first `n` bursts of `circuit`: the size of
+/// each burst rounded up to a power of two, as its base 2 logarithm plus one,
+/// signed by its direction. Circuits with the same pattern load similarly.
+pub fn burst_pattern(circuit: &Circuit, n: usize) -> Vec<i8> {
+    bursts(circuit)
+        .iter()
+        .take(n)
+        .map(|b| b.direction * (b.count.next_power_of_two().ilog2() as i8 + 1))
+        .collect()
+}
+
+/// Returns the Shannon entropy in bits of the distribution of `items`.
+pub fn entropy<T: Eq + std::hash::Hash>(items: &[T]) -> f64 {
+    let mut counts: HashMap<&T, usize> = HashMap::new();
+    for item in items {
+        *counts.entry(item).or_default() += 1;
+    }
+    let n = items.len() as f64;
+    counts
+        .values()
+        .map(|&c| {
+            let p = c as f64 / n;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// The direction sequence and burst pattern of a sampled circuit.
+type Shape = (Vec<i8>, Vec<i8>);
+
+/// Computes the metrics of the circuits of `label`, of which there are
+/// `count`, from the `shapes` of a sample of them.
+fn variability(label: FixedAscii<44>, count: u32, shapes: &[Shape]) -> LabelVariability {
+    let mut distances = Vec::new();
+    for (i, a) in shapes.iter().enumerate() {
+        for b in shapes[i + 1..].iter() {
+            distances.push(edit::normalized_distance(&a.0, &b.0));
+        }
+    }
+    let patterns: Vec<&Vec<i8>> = shapes.iter().map(|s| &s.1).collect();
+    let burst_entropy = entropy(&patterns);
+    let max_entropy = (shapes.len() as f64).log2();
+
+    LabelVariability {
+        label,
+        count,
+        sampled: shapes.len() as u32,
+        mean_distance: mean(&distances),
+        std_distance: std_dev(&distances),
+        burst_entropy,
+        normalized_burst_entropy: if max_entropy > 0.0 {
+            burst_entropy / max_entropy
+        } else {
+            0.0
+        },
+    }
+}
+
+/// Computes the `LabelVariability` of every label of the circuits in the
+/// `/circuits` dataset of `file` with enough circuits, from a sample of its
+/// circuits drawn with a random number generator seeded with `seed`, and
+/// writes them to `/stats/variability`, replacing any existing dataset. The
+/// meta-data is read twice, to count and then sample the circuits of each
+/// label, and then only the sampled circuits are read. `progress` is called
+/// with the number of labels computed so far and the number of labels to
+/// compute after each batch of labels. Returns the metrics, sorted by label.
+pub fn write_label_variability<F>(
+    file: &File,
+    options: &VariabilityOptions,
+    seed: u64,
+    mut progress: F,
+) -> hdf5::Result<Vec<LabelVariability>>
+where
+    F: FnMut(usize, usize),
+{
+    let circuits = file.dataset("/circuits")?;
+    let size = circuits.size();
+    let read_metas = |begin: usize| -> hdf5::Result<Array1<CircuitMeta>> {
+        let end = std::cmp::min(begin + META_BATCH, size);
+        circuits.read_slice(ndarray::s![begin..end])
+    };
+
+    let mut counts: HashMap<FixedAscii<44>, u32> = HashMap::new();
+    for begin in (0..size).step_by(META_BATCH) {
+        for meta in read_metas(begin)?.iter() {
+            *counts.entry(meta.label()).or_default() += 1;
+        }
+    }
+
+    let mut rng = Rng::new(seed);
+    let mut samples: HashMap<FixedAscii<44>, Reservoir<CircuitIndex>> = counts
+        .iter()
+        .filter(|&(_, &count)| count as usize >= options.min_circuits.max(2))
+        .map(|(&label, _)| (label, Reservoir::new(options.max_circuits)))
+        .collect();
+    for begin in (0..size).step_by(META_BATCH) {
+        for (i, meta) in read_metas(begin)?.iter().enumerate() {
+            if let Some(reservoir) = samples.get_mut(&meta.label()) {
+                reservoir.offer((begin + i) as CircuitIndex, &mut rng);
+            }
+        }
+    }
+
+    let mut labels: Vec<(FixedAscii<44>, Vec<CircuitIndex>)> = samples
+        .into_iter()
+        .map(|(label, reservoir)| (label, reservoir.into_items()))
+        .collect();
+    labels.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+
+    let mut metrics = Vec::with_capacity(labels.len());
+    for batch in labels.chunks(LABEL_BATCH) {
+        // Read the sampled circuits of the batch in order of index.
+        let mut indices: Vec<(CircuitIndex, usize)> = batch
+            .iter()
+            .enumerate()
+            .flat_map(|(k, (_, sample))| sample.iter().map(move |&i| (i, k)))
+            .collect();
+        indices.sort();
+
+        let mut shapes: Vec<Vec<Shape>> = vec![Vec::new(); batch.len()];
+        for chunk in indices.chunks(READ_BATCH) {
+            let points: Vec<usize> = chunk.iter().map(|&(i, _)| i as usize).collect();
+            let read = circuits.read_slice_1d::<Circuit, _>(points)?;
+            for (circuit, &(_, k)) in read.iter().zip(chunk) {
+                shapes[k].push((
+                    edit::sequence(circuit, options.max_cells),
+                    burst_pattern(circuit, options.pattern_bursts),
+                ));
+            }
+        }
+
+        let work: Vec<(FixedAscii<44>, Vec<Shape>)> =
+            batch.iter().map(|(label, _)| *label).zip(shapes).collect();
+        metrics.extend(par_map(&work, options.threads, |(label, shapes)| {
+            variability(*label, counts[label], shapes)
+        }));
+        progress(metrics.len(), labels.len());
+    }
+
+    write_index(file, VARIABILITY_NAME, &metrics)?;
+    Ok(metrics)
+}
@@ -0,0 +1,111 @@
+//! Storage of circuits with only their valid cells, as `CircuitVarLen`.
+//!
+//! Every `Circuit` record holds a fixed array of `MAX_CELLS` cells, about
+//! 100KB, even though most circuits have far fewer valid cells. A `CircuitVarLen` record
+//! holds only its valid cells in a variable-length array, so an uncompressed
+//! dataset of them is typically an order of magnitude smaller.
+//!
+//! The tradeoffs are:
+//!
+//! - HDF5 stores the cells of variable-length arrays in a global heap outside
+//!   of the dataset's chunks, so compression filters only apply to the fixed
+//!   fields and not to the cells. A compressed fixed-length dataset, where the
+//!   padding compresses well, may be about as small.
+//! - Reading a variable-length record follows a pointer into the heap and
+//!   allocates its cells, so full scans are typically slower per cell than
+//!   reading compressed fixed-length chunks, while reading few circuits by
+//!   index is faster since whole fixed-length records need not be decompressed.
+//! - The rest of the crate works on `Circuit`, so records are converted with
+//!   `CircuitVarLen::to_circuit` when read, e.g., by `read_circuits`.
+//!
+//! Meta-data scans are unaffected: `CircuitMeta` can be read from a dataset of
+//! either type.
+
+use std::ops::Range;
+
+use hdf5::File;
+
+use crate::writer::CircuitWriter;
+use crate::{Circuit, CircuitVarLen};
+
+/// The name of the dataset of `CircuitVarLen` records written by
+/// `write_varlen`.
+pub const VARLEN_NAME: &str = "/circuits_varlen";
+
+/// The number of records per chunk of the variable-length dataset. Chunks only
+/// hold references into the heap, so they can be larger than those of the
+/// fixed-length dataset.
+const VARLEN_CHUNK: usize = 1_000;
+
+/// The number of circuits read at a time when converting.
+const READ_BATCH: usize = 1_000;
+
+/// Creates an empty resizable dataset of `CircuitVarLen` records named `name` in
+/// `file`.
+pub fn create_varlen(file: &File, name: &str) -> hdf5::Result<CircuitWriter<CircuitVarLen>> {
+    let dataset = file
+        .new_dataset_builder()
+        .chunk(VARLEN_CHUNK)
+        .empty::<CircuitVarLen>()
+        .shape(0..)
+        .create(name)?;
+    Ok(CircuitWriter::new(dataset))
+}
+
+/// Reads the records in `range` of a dataset of `CircuitVarLen` records and
+/// converts them to `Circuit`s.
+pub fn read_circuits(dataset: &hdf5::Dataset, range: Range<usize>) -> hdf5::Result<Vec<Circuit>> {
+    Ok(dataset
+        .read_slice_1d::<CircuitVarLen, _>(range)?
+        .iter()
+        .map(|c| c.to_circuit())
+        .collect())
+}
+
+/// Converts every circuit of the `/circuits` dataset of `src` to a
+/// `CircuitVarLen` and writes it to the `/circuits_varlen` dataset of `dst`.
+/// `progress` is called with the number of circuits converted after each
+/// batch. Returns the number of circuits written.
+pub fn write_varlen<F>(src: &File, dst: &File, mut progress: F) -> hdf5::Result<usize>
+where
+    F: FnMut(usize),
+{
+    let circuits = src.dataset("/circuits")?;
+    let size = circuits.size();
+    let mut writer = create_varlen(dst, VARLEN_NAME)?;
+
+    for begin in (0..size).step_by(READ_BATCH) {
+        let end = std::cmp::min(begin + READ_BATCH, size);
+        for circuit in circuits.read_slice_1d::<Circuit, _>(begin..end)?.iter() {
+            writer.push(CircuitVarLen::from(circuit))?;
+        }
+        progress(end - begin);
+    }
+
+    writer.finish()?;
+    Ok(size)
+}
+
+/// Converts every record of the `/circuits_varlen` dataset of `src` back to a
+/// `Circuit` and writes it to the `/circuits` dataset of `dst`, uncompressed.
+/// `progress` is called with the number of circuits converted after each
+/// batch. Returns the number of circuits written.
+pub fn write_fixed<F>(src: &File, dst: &File, mut progress: F) -> hdf5::Result<usize>
+where
+    F: FnMut(usize),
+{
+    let records = src.dataset(VARLEN_NAME)?;
+    let size = records.size();
+    let mut writer = CircuitWriter::<Circuit>::create(dst, "/circuits")?;
+
+    for begin in (0..size).step_by(READ_BATCH) {
+        let end = std::cmp::min(begin + READ_BATCH, size);
+        for circuit in read_circuits(&records, begin..end)? {
+            writer.push(circuit)?;
+        }
+        progress(end - begin);
+    }
+
+    writer.finish()?;
+    Ok(size)
+}
@@ -0,0 +1,97 @@
+use hdf5::types::FixedAscii;
+
+use crate::{AugmentedCircuit, Cell, Circuit};
+
+/// Slices circuits into overlapping windows of consecutive cells, so that
+/// attacks can be trained and evaluated on partial traces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlidingWindow {
+    /// The number of cells in each window.
+    width: u16,
+    /// The number of cells between the starts of consecutive windows.
+    stride: u16,
+    /// Whether a final window ends at the last valid cell.
+    tail: bool,
+}
+
+impl SlidingWindow {
+    /// Creates windows of `width` cells that start every `stride` cells. Panics
+    /// if `width` or `stride` is zero.
+    pub fn new(width: u16, stride: u16) -> Self {
+        assert!(width > 0, "window width must be positive");
+        assert!(stride > 0, "window stride must be positive");
+        Self {
+            width,
+            stride,
+            tail: false,
+        }
+    }
+
+    /// Adds a final window that ends at the last valid cell of each circuit
+    /// whose cells would otherwise not all be covered, so that no cells are
+    /// left out.
+    pub fn with_tail(mut self) -> Self {
+        self.tail = true;
+        self
+    }
+
+    /// The number of cells in each window.
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// The number of cells between the starts of consecutive windows.
+    pub fn stride(&self) -> u16 {
+        self.stride
+    }
+
+    /// Returns the number of windows of a circuit with `len` valid cells.
+    ///
+    /// Window `k` holds the cells `k * stride .. k * stride + width`, for every
+    /// window that fits within the valid cells; cells after the last such
+    /// window are not covered when `len - width` is not a multiple of
+    /// `stride`, unless the windows were created `with_tail`, in which case a
+    /// final window holds the last `width` cells. A circuit with fewer than
+    /// `width` (but at least one) valid cells has one window holding all of
+    /// them.
+    pub fn count(&self, len: u16) -> usize {
+        let (len, width, stride) = (len as usize, self.width as usize, self.stride as usize);
+        match len {
+            0 => 0,
+            len if len <= width => 1,
+            len if self.tail && (len - width) % stride != 0 => (len - width) / stride + 2,
+            len => (len - width) / stride + 1,
+        }
+    }
+
+    /// Returns window number `k` of `circuit` as an augmented circuit with
+    /// `uuid`, linked to `circuit` with `aug_index` set to `k`. The cells keep
+    /// their original times, and the cells after the window are zeroed.
+    pub fn window(&self, circuit: &Circuit, k: usize, uuid: FixedAscii<32>) -> AugmentedCircuit {
+        let valid = circuit.valid_cells().len();
+        let width = self.width as usize;
+        // Only the tail window starts past the last window that fits.
+        let begin = std::cmp::min(k * self.stride as usize, valid.saturating_sub(width));
+        let end = std::cmp::min(begin + width, valid);
+
+        let mut augmented = AugmentedCircuit::from_circuit(circuit, uuid, k as u16);
+        augmented.cells.copy_within(begin..end, 0);
+        augmented.cells[end - begin..].fill(Cell::empty());
+        augmented.len = (end - begin) as u16;
+        augmented
+    }
+
+    /// Returns an iterator over the windows of `circuit`, in order, where
+    /// `uuid` is called to create the uuid of each window.
+    pub fn windows<'a, F>(
+        &'a self,
+        circuit: &'a Circuit,
+        mut uuid: F,
+    ) -> impl Iterator<Item = AugmentedCircuit> + 'a
+    where
+        F: FnMut() -> FixedAscii<32> + 'a,
+    {
+        let len = circuit.valid_cells().len() as u16;
+        (0..self.count(len)).map(move |k| self.window(circuit, k, uuid()))
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use hdf5::types::{FixedAscii, VarLenAscii};
+
+use crate::rng::Rng;
+use crate::sample;
+use crate::{CircuitIndex, CircuitMeta};
+
+/// The name of the attribute on a circuits dataset that lists its labels.
+pub const LABELS_ATTR: &str = "labels";
+
+/// The circuits and labels chosen for a closed- or open-world dataset.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Selection {
+    /// The chosen labels, sorted.
+    pub labels: Vec<String>,
+    /// The indices of the chosen circuits, sorted.
+    pub indices: Vec<CircuitIndex>,
+}
+
+/// Groups circuits by label to choose the labels and circuits of a
+/// closed-world dataset: the `k` most frequent labels that have at least
+/// `min_circuits` circuits each, with the same number of circuits per label.
+#[derive(Clone, Debug, Default)]
+pub struct ClosedWorld {
+    labels: HashMap<FixedAscii<44>, Vec<CircuitIndex>>,
+}
+
+impl ClosedWorld {
+    /// Creates a builder that has not yet seen any circuits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the circuit at position `index` in the dataset.
+    pub fn add(&mut self, index: CircuitIndex, meta: &CircuitMeta) {
+        self.labels.entry(meta.label()).or_default().push(index);
+    }
+
+    /// Chooses the `k` labels with the most circuits among those with at least
+    /// `min_circuits` circuits (ties broken by label), and draws `per_label`
+    /// circuits of each chosen label uniformly at random. `per_label` is
+    /// capped at `min_circuits` so that every label is equally represented.
+    pub fn select(
+        &self,
+        k: usize,
+        min_circuits: usize,
+        per_label: usize,
+        rng: &mut Rng,
+    ) -> Selection {
+        let mut eligible: Vec<(String, &Vec<CircuitIndex>)> = self
+            .labels
+            .iter()
+            .filter(|(_, indices)| indices.len() >= min_circuits.max(1))
+            .map(|(label, indices)| (label.to_string(), indices))
+            .collect();
+        eligible.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(&b.0)));
+        eligible.truncate(k);
+        eligible.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let per_label = per_label.min(min_circuits.max(1));
+        let mut selection = Selection::default();
+
+        for (label, indices) in eligible {
+            let mut indices = indices.clone();
+            indices.sort();
+            selection
+                .indices
+                .extend(sample::uniform_from(&indices, per_label, rng));
+            selection.labels.push(label);
+        }
+
+        selection.indices.sort();
+        selection
+    }
+}
+
+/// Chooses the labels and circuits of an open-world dataset: a closed world of
+/// monitored labels, plus a background of circuits whose labels are never
+/// monitored.
+#[derive(Clone, Debug, Default)]
+pub struct OpenWorld {
+    closed: ClosedWorld,
+}
+
+impl OpenWorld {
+    /// Creates a builder that has not yet seen any circuits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the circuit at position `index` in the dataset.
+    pub fn add(&mut self, index: CircuitIndex, meta: &CircuitMeta) {
+        self.closed.add(index, meta);
+    }
+
+    /// Chooses the monitored set as `ClosedWorld::select` does, and then the
+    /// unmonitored set of up to `n_unmonitored` circuits drawn from the other
+    /// labels in a random order, taking at most `unmonitored_per_label`
+    /// circuits of each. No label appears in both sets.
+    pub fn select(
+        &self,
+        k: usize,
+        min_circuits: usize,
+        per_label: usize,
+        n_unmonitored: usize,
+        unmonitored_per_label: usize,
+        rng: &mut Rng,
+    ) -> (Selection, Selection) {
+        let monitored = self.closed.select(k, min_circuits, per_label, rng);
+
+        let mut background: Vec<(String, &Vec<CircuitIndex>)> = self
+            .closed
+            .labels
+            .iter()
+            .map(|(label, indices)| (label.to_string(), indices))
+            .filter(|(label, _)| monitored.labels.binary_search(label).is_err())
+            .collect();
+        background.sort_by(|a, b| a.0.cmp(&b.0));
+        rng.shuffle(&mut background);
+
+        let mut unmonitored = Selection::default();
+        for (label, indices) in background {
+            let remaining = n_unmonitored - unmonitored.indices.len();
+            if remaining == 0 {
+                break;
+            }
+
+            let mut indices = indices.clone();
+            indices.sort();
+            let n = unmonitored_per_label.min(remaining);
+            unmonitored
+                .indices
+                .extend(sample::uniform_from(&indices, n, rng));
+            unmonitored.labels.push(label);
+        }
+
+        unmonitored.labels.sort();
+        unmonitored.indices.sort();
+        (monitored, unmonitored)
+    }
+}
+
+/// Writes `labels` as the label vocabulary attribute of `dataset`, so that a
+/// label's position in the list can serve as its class id.
+pub fn write_labels(dataset: &hdf5::Dataset, labels: &[String]) -> hdf5::Result<()> {
+    let mut values = Vec::with_capacity(labels.len());
+    for label in labels {
+        values.push(VarLenAscii::from_ascii(label).map_err(|e| e.to_string())?);
+    }
+
+    if dataset.attr_names()?.iter().any(|n| n == LABELS_ATTR) {
+        dataset.delete_attr(LABELS_ATTR)?;
+    }
+    dataset
+        .new_attr::<VarLenAscii>()
+        .shape(values.len())
+        .create(LABELS_ATTR)?
+        .write(&values)
+}
+
+/// Reads the label vocabulary attribute written by `write_labels`.
+pub fn read_labels(dataset: &hdf5::Dataset) -> hdf5::Result<Vec<String>> {
+    Ok(dataset
+        .attr(LABELS_ATTR)?
+        .read_raw::<VarLenAscii>()?
+        .iter()
+        .map(|l| l.to_string())
+        .collect())
+}
@@ -0,0 +1,170 @@
+use hdf5::{File, H5Type};
+
+use crate::index::{self, AugmentedIndexBuilder};
+use crate::{AugmentedCircuit, Circuit, CircuitIndex};
+
+/// The number of records per chunk used when there is no template dataset,
+/// matching the chunking of the circuits dataset written by `writecircuits`.
+pub const DEFAULT_CHUNK: usize = 25;
+
+/// The number of records buffered in memory before they are written.
+const WRITE_BATCH: usize = 1_000;
+
+/// Appends records (circuits by default) to a resizable HDF5 dataset, writing
+/// them in batches. Call `finish` to write any buffered records.
+pub struct CircuitWriter<T: H5Type = Circuit> {
+    dataset: hdf5::Dataset,
+    buffer: Vec<T>,
+    written: usize,
+}
+
+impl<T: H5Type> CircuitWriter<T> {
+    /// Creates an empty resizable dataset `name` in `file` that uses the same
+    /// chunk size and compression filters as `template`.
+    pub fn create_like(file: &File, name: &str, template: &hdf5::Dataset) -> hdf5::Result<Self> {
+        let chunk = template
+            .chunk()
+            .and_then(|c| c.first().copied())
+            .unwrap_or(DEFAULT_CHUNK);
+
+        let dataset = file
+            .new_dataset_builder()
+            .chunk(chunk)
+            .set_filters(&template.filters())
+            .empty::<T>()
+            .shape(0..)
+            .create(name)?;
+
+        Ok(Self::new(dataset))
+    }
+
+    /// Creates an empty resizable and uncompressed dataset `name` in `file`.
+    pub fn create(file: &File, name: &str) -> hdf5::Result<Self> {
+        let dataset = file
+            .new_dataset_builder()
+            .chunk(DEFAULT_CHUNK)
+            .empty::<T>()
+            .shape(0..)
+            .create(name)?;
+
+        Ok(Self::new(dataset))
+    }
+
+    /// Appends to an existing resizable dataset, starting after its last
+    /// record.
+    pub fn new(dataset: hdf5::Dataset) -> Self {
+        let written = dataset.size();
+        Self {
+            dataset,
+            buffer: Vec::with_capacity(WRITE_BATCH),
+            written,
+        }
+    }
+
+    /// Appends `record` to the dataset.
+    pub fn push(&mut self, record: T) -> hdf5::Result<()> {
+        self.buffer.push(record);
+        if self.buffer.len() >= WRITE_BATCH {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// The number of records in the dataset, including buffered records.
+    pub fn len(&self) -> usize {
+        self.written + self.buffer.len()
+    }
+
+    /// Returns true if no records have been written or buffered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes all buffered records to the dataset.
+    pub fn flush(&mut self) -> hdf5::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let end = self.written + self.buffer.len();
+        self.dataset.resize(end)?;
+        self.dataset
+            .write_slice(&self.buffer[..], self.written..end)?;
+
+        self.written = end;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Writes all buffered records and returns the dataset.
+    pub fn finish(mut self) -> hdf5::Result<hdf5::Dataset> {
+        self.flush()?;
+        Ok(self.dataset)
+    }
+}
+
+/// The name of the augmented circuits dataset written by `AugmentedWriter`.
+pub const AUGMENTED_NAME: &str = "/augmented";
+
+/// The name of the index from GTT23 uuids to augmented circuits written by
+/// `AugmentedWriter`.
+pub const UUID_GTT23_NAME: &str = "/index/uuid_gtt23";
+
+/// Appends augmented circuits to the `/augmented` dataset of a file, setting
+/// the `aug_index` of each to the number of augmentations of the same GTT23
+/// circuit written before it, so that the augmentations of each circuit are
+/// numbered from 0. Builds the `/index/uuid_gtt23` index in the same pass,
+/// which `finish` writes.
+pub struct AugmentedWriter {
+    file: File,
+    writer: CircuitWriter<AugmentedCircuit>,
+    index: AugmentedIndexBuilder,
+}
+
+impl AugmentedWriter {
+    /// Creates an empty `/augmented` dataset in `file` that uses the same
+    /// chunk size and compression filters as the GTT23 circuits dataset
+    /// `template`.
+    pub fn create_like(file: &File, template: &hdf5::Dataset) -> hdf5::Result<Self> {
+        Ok(Self {
+            file: file.clone(),
+            writer: CircuitWriter::create_like(file, AUGMENTED_NAME, template)?,
+            index: AugmentedIndexBuilder::new(),
+        })
+    }
+
+    /// Appends `circuit`, numbering it by `aug_index` after the augmentations
+    /// of the same GTT23 circuit already written. Returns an error if that
+    /// circuit already has as many augmentations as `aug_index` can number.
+    pub fn push(&mut self, mut circuit: AugmentedCircuit) -> hdf5::Result<()> {
+        let written = self.index.augmentations(&circuit.uuid_gtt23);
+        circuit.aug_index = u16::try_from(written).map_err(|_| {
+            hdf5::Error::from(format!(
+                "Circuit {} has more than {} augmentations",
+                circuit.uuid_gtt23,
+                u16::MAX as usize + 1
+            ))
+        })?;
+        self.index.add(self.writer.len() as CircuitIndex, &circuit);
+        self.writer.push(circuit)
+    }
+
+    /// The number of augmented circuits in the dataset, including buffered
+    /// circuits.
+    pub fn len(&self) -> usize {
+        self.writer.len()
+    }
+
+    /// Returns true if no circuits have been written or buffered.
+    pub fn is_empty(&self) -> bool {
+        self.writer.is_empty()
+    }
+
+    /// Writes all buffered circuits and the `/index/uuid_gtt23` index, and
+    /// returns the augmented dataset.
+    pub fn finish(self) -> hdf5::Result<hdf5::Dataset> {
+        let dataset = self.writer.finish()?;
+        index::write_index(&self.file, UUID_GTT23_NAME, &self.index.uuid_gtt23_index())?;
+        Ok(dataset)
+    }
+}